@@ -0,0 +1,115 @@
+//! Measures the circuit on fixed workloads and reports it next to other keccak circuits.
+//!
+//! Every workload is proven at the smallest `k` fitting it, for a few `rows_per_round`. The
+//! measurements are printed as a markdown table and written to `target/keccak-comparison.json`
+//! for regression tracking. Measurements of other circuits on the same workloads, e.g. PSE's
+//! zkevm keccak or Axiom's, are merged into the table from the JSON file at
+//! `$KECCAK_BENCH_BASELINES`, in the same format. They are measured in their own repositories,
+//! as each pins its own halo2 fork.
+
+use std::time::{Duration, Instant};
+
+use halo2_keccak_256::planner::plan_batches;
+use halo2_keccak_256::proof_system::{KzgShplonk, ProofSystem};
+use halo2_keccak_256::{InstanceMode, KeccakCircuit, KeccakConfigParams, KeccakProver};
+use halo2_proofs::halo2curves::bn256::Bn256;
+use serde::{Deserialize, Serialize};
+
+const ROWS_PER_ROUND: [usize; 3] = [5, 12, 28];
+const MIN_K: u32 = 12;
+const MAX_K: u32 = 18;
+
+/// Workloads: a name, a number of messages and the length of each in bytes.
+const WORKLOADS: [(&str, usize, usize); 3] =
+    [("32 x 32 B", 32, 32), ("4 x 1 KiB", 4, 1024), ("1 x 4 KiB", 1, 4096)];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Measurement {
+    circuit: String,
+    workload: String,
+    k: u32,
+    keygen_ms: u128,
+    prove_ms: u128,
+    verify_ms: u128,
+    proof_bytes: usize,
+}
+
+fn measure(workload: &str, inputs: &[Vec<u8>], rows_per_round: usize) -> Option<Measurement> {
+    let sizes = inputs.iter().map(Vec::len).collect::<Vec<_>>();
+    // The smallest `k` proving the whole workload at once
+    let config = (MIN_K..=MAX_K)
+        .map(|k| KeccakConfigParams {
+            k,
+            rows_per_round,
+            instance_mode: InstanceMode::Input,
+            ..Default::default()
+        })
+        .find(|config| {
+            config.validate().is_ok()
+                && plan_batches(&sizes, *config).map_or(false, |batches| batches.len() == 1)
+        })?;
+
+    let circuit = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k))
+        .build()
+        .unwrap();
+    let srs = KzgShplonk::<Bn256>::setup(config.k);
+    let (pk, keygen) = timed(|| KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap());
+    let vk = pk.get_vk().clone();
+    let prover = KeccakProver::new(srs.clone(), pk, config);
+
+    let ((instances, proof), prove) = timed(|| prover.prove(inputs.to_vec()).unwrap());
+    let instances = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let (_, verify) = timed(|| KzgShplonk::<Bn256>::verify(&srs, &vk, &proof, &instances).unwrap());
+
+    Some(Measurement {
+        circuit: format!("halo2-keccak-256 rows_per_round = {rows_per_round}"),
+        workload: workload.to_string(),
+        k: config.k,
+        keygen_ms: keygen.as_millis(),
+        prove_ms: prove.as_millis(),
+        verify_ms: verify.as_millis(),
+        proof_bytes: proof.len(),
+    })
+}
+
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn main() {
+    let mut measurements = vec![];
+    for (workload, count, len) in WORKLOADS {
+        let inputs = (0..count).map(|i| vec![i as u8; len]).collect::<Vec<_>>();
+        for rows_per_round in ROWS_PER_ROUND {
+            match measure(workload, &inputs, rows_per_round) {
+                Some(measurement) => measurements.push(measurement),
+                None => println!("{workload} doesn't fit k <= {MAX_K} at rows_per_round = {rows_per_round}"),
+            }
+        }
+    }
+
+    std::fs::create_dir_all("target").unwrap();
+    std::fs::write("target/keccak-comparison.json", serde_json::to_string_pretty(&measurements).unwrap())
+        .unwrap();
+
+    if let Ok(path) = std::env::var("KECCAK_BENCH_BASELINES") {
+        let baselines = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Failed to read {path}: {e}"));
+        let baselines: Vec<Measurement> =
+            serde_json::from_str(&baselines).unwrap_or_else(|e| panic!("Invalid baselines in {path}: {e}"));
+        measurements.extend(baselines);
+    }
+    measurements.sort_by(|a, b| a.workload.cmp(&b.workload).then(a.prove_ms.cmp(&b.prove_ms)));
+
+    println!("| workload | circuit | k | keygen (ms) | prove (ms) | verify (ms) | proof (bytes) |");
+    println!("|---|---|---|---|---|---|---|");
+    for m in &measurements {
+        println!(
+            "| {} | {} | {} | {} | {} | {} | {} |",
+            m.workload, m.circuit, m.k, m.keygen_ms, m.prove_ms, m.verify_ms, m.proof_bytes
+        );
+    }
+}
@@ -0,0 +1,54 @@
+//! Compares binding every absorbed word to the instance against binding packed chunks of
+//! words: instance length, proof size, proving and verification time.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use halo2_keccak_256::proof_system::{KzgShplonk, ProofSystem};
+use halo2_keccak_256::{InputBinding, InstanceMode, KeccakCircuit, KeccakConfigParams, KeccakProver};
+use halo2_proofs::halo2curves::bn256::Bn256;
+
+const K: u32 = 14;
+const ROWS_PER_ROUND: usize = 28;
+
+fn bench_input_binding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("input_binding");
+    group.sample_size(10);
+    let inputs: Vec<Vec<u8>> = (0..4).map(|i| vec![i as u8; 500]).collect();
+
+    for input_binding in [InputBinding::Word, InputBinding::Packed, InputBinding::Bytes31] {
+        let config = KeccakConfigParams {
+            k: K,
+            rows_per_round: ROWS_PER_ROUND,
+            instance_mode: InstanceMode::Input,
+            input_binding,
+            ..Default::default()
+        };
+        let circuit = KeccakCircuit::builder()
+            .config(config)
+            .capacity_rows(2usize.pow(K))
+            .build()
+            .unwrap();
+        let srs = KzgShplonk::<Bn256>::setup(K);
+        let pk = KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap();
+        let vk = pk.get_vk().clone();
+        let prover = KeccakProver::new(srs.clone(), pk, config);
+
+        let (instances, proof) = prover.prove(inputs.clone()).unwrap();
+        let instances = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        println!(
+            "{input_binding:?}: {} input cells, proof of {} bytes",
+            instances[0].len(),
+            proof.len()
+        );
+
+        group.bench_function(format!("{input_binding:?}/prove"), |b| {
+            b.iter(|| prover.prove(inputs.clone()).unwrap())
+        });
+        group.bench_function(format!("{input_binding:?}/verify"), |b| {
+            b.iter(|| KzgShplonk::<Bn256>::verify(&srs, &vk, &proof, &instances).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_input_binding);
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+//! Compares the split and tagged lookup layouts: fixed columns, verifying key size, keygen and
+//! proving time at the same `k`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use halo2_keccak_256::proof_system::{KzgShplonk, ProofSystem};
+use halo2_keccak_256::{KeccakCircuit, KeccakConfigParams, KeccakProver, LookupLayout};
+use halo2_proofs::halo2curves::bn256::Bn256;
+use halo2_proofs::SerdeFormat::RawBytes;
+
+const K: u32 = 14;
+const ROWS_PER_ROUND: usize = 28;
+
+fn bench_lookup_layout(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookup_layout");
+    group.sample_size(10);
+    let inputs: Vec<Vec<u8>> = (0..4).map(|i| vec![i as u8; 200]).collect();
+
+    for layout in [LookupLayout::Split, LookupLayout::Tagged] {
+        let config = KeccakConfigParams {
+            k: K,
+            rows_per_round: ROWS_PER_ROUND,
+            lookup_layout: layout,
+            ..Default::default()
+        };
+        let circuit = KeccakCircuit::builder()
+            .config(config)
+            .capacity_rows(2usize.pow(K))
+            .build()
+            .unwrap();
+        let srs = KzgShplonk::<Bn256>::setup(K);
+        let pk = KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap();
+
+        let mut vk = vec![];
+        pk.get_vk().write(&mut vk, RawBytes).unwrap();
+        println!(
+            "{layout:?}: {} fixed columns, {} advice columns, verifying key of {} bytes",
+            pk.get_vk().cs().num_fixed_columns(),
+            pk.get_vk().cs().num_advice_columns(),
+            vk.len()
+        );
+
+        group.bench_function(format!("{layout:?}/keygen"), |b| {
+            b.iter(|| KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap())
+        });
+        let prover = KeccakProver::new(srs, pk, config);
+        group.bench_function(format!("{layout:?}/prove"), |b| {
+            b.iter(|| prover.prove(inputs.clone()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lookup_layout);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+/// Bounds the witness size, 3 keccak_f per input at most.
+const MAX_INPUTS: usize = 8;
+const MAX_INPUT_LEN: usize = 3 * 136;
+
+fuzz_target!(|inputs: Vec<Vec<u8>>| {
+    let inputs = inputs
+        .into_iter()
+        .take(MAX_INPUTS)
+        .map(|mut input| {
+            input.truncate(MAX_INPUT_LEN);
+            input
+        })
+        .collect::<Vec<_>>();
+    halo2_keccak_256::fuzz::check_witness(&inputs);
+});
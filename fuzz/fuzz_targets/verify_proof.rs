@@ -0,0 +1,7 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|mutation: Vec<(usize, u8)>| {
+    halo2_keccak_256::fuzz::verify_mutated_proof(&mutation);
+});
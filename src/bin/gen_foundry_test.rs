@@ -0,0 +1,77 @@
+use clap::{App, Arg};
+use std::env;
+use std::path::Path;
+
+use halo2_keccak_256::encoding::from_hex;
+use halo2_keccak_256::solidity::foundry_test;
+use halo2_keccak_256::{KeccakConfigParams, KeccakProver, TranscriptKind, DEFAULT_CONFIG};
+
+pub fn main() {
+    // Setup command-line argument parsing
+    let matches = App::new("Keccak Foundry Test Generator")
+        .about("Proves the given inputs and embeds the proof in a Foundry test of the EVM verifier")
+        .arg(Arg::with_name("config")
+            .long("config")
+            .short('c')
+            .help("TOML config the keys were generated for, the defaults if omitted")
+            .takes_value(true))
+        .arg(Arg::with_name("input")
+            .long("input")
+            .short('i')
+            .help("Hex encoded message, may be repeated")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .required(true))
+        .arg(Arg::with_name("name")
+            .long("name")
+            .help("Name of the test contract")
+            .takes_value(true)
+            .default_value("KeccakVerifierTest"))
+        .get_matches();
+
+    let mut config = match matches.value_of("config") {
+        Some(path) => KeccakConfigParams::from_file(path),
+        None => Ok(DEFAULT_CONFIG),
+    }
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    // The EVM verifier reads the proof with the keccak256 transcript
+    config.transcript = TranscriptKind::Keccak256;
+
+    let inputs = matches
+        .values_of("input")
+        .unwrap()
+        .map(from_hex)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+
+    let project_root = env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is not set");
+    let out_dir = Path::new(&project_root).join("out");
+    let circuit_name = "keccak256";
+
+    let prover = KeccakProver::from_files(
+        &out_dir.join(format!("{}_srs", circuit_name)),
+        &out_dir.join(format!("{}_pk", circuit_name)),
+        config,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let (instances, proof) = prover.prove(inputs.clone()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+
+    let name = matches.value_of("name").unwrap();
+    let test_path = out_dir.join(format!("{}.t.sol", name));
+    std::fs::write(&test_path, foundry_test(name, &config, &inputs, &instances, &proof))
+        .expect("Unable to write the Foundry test");
+
+    println!("Foundry test stored in {}", test_path.display());
+}
@@ -3,12 +3,12 @@ use std::env;
 use std::path::Path;
 
 use halo2_proofs::halo2curves::bn256::Bn256;
-use halo2_proofs::plonk::{keygen_pk, keygen_vk};
-use halo2_proofs::poly::commitment::ParamsProver;
-use halo2_proofs::poly::kzg::commitment::ParamsKZG;
 
-use halo2_keccak_256::{DEFAULT_CONFIG, KeccakCircuit, KeccakConfigParams};
+use halo2_keccak_256::{DEFAULT_CONFIG, KeccakCircuit, KeccakConfigParams, SanityLevel};
 use halo2_keccak_256::io::{write_keys, write_srs};
+use halo2_keccak_256::progress::{ProgressEvent, Stage};
+use halo2_keccak_256::proof_system::{KzgShplonk, ProofSystem};
+use halo2_keccak_256::solidity::layout_library;
 
 pub fn main() {
     // Setup command-line argument parsing
@@ -40,31 +40,54 @@ pub fn main() {
         std::fs::create_dir(&out_dir).expect("Unable to create out directory");
     }
 
+    let config = KeccakConfigParams {
+        k,
+        rows_per_round,
+        ..DEFAULT_CONFIG
+    };
+
     let circuit_name = "keccak256";
-    let circuit = KeccakCircuit::new(
-        KeccakConfigParams {
-            k,
-            rows_per_round,
-        },
-        Some(2usize.pow(k)),
-        vec![],
-        false,
-        false,
-    );
-
-    let srs = ParamsKZG::<Bn256>::new(k);
+    let circuit = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(k))
+        .sanity(SanityLevel::Off)
+        .build()
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+
+    let progress = |event: ProgressEvent| match event {
+        ProgressEvent::Started(stage) => println!("{}...", stage_name(stage)),
+        ProgressEvent::Finished { stage, elapsed } => println!("{} done in {:.1?}", stage_name(stage), elapsed),
+    };
+
+    let srs = KzgShplonk::<Bn256>::setup(k);
     let srs_path = out_dir.join(format!("{}_srs", circuit_name));
     write_srs(&srs, srs_path.as_path());
 
-    let vk = keygen_vk(&srs, &circuit).expect("keygen_vk should not fail");
+    let pk = KzgShplonk::<Bn256>::keygen_with_progress(&srs, &circuit, &progress).expect("keygen should not fail");
     let vk_path = out_dir.join(format!("{}_vk", circuit_name));
-    let pk = keygen_pk(&srs, vk, &circuit).expect("keygen_pk should not fail");
     let pk_path = out_dir.join(format!("{}_pk", circuit_name));
 
-    write_keys(&pk, pk_path.as_path(), vk_path.as_path());
+    write_keys(&pk, pk_path.as_path(), vk_path.as_path(), config);
+
+    let layout_path = out_dir.join(format!("{}_layout.sol", circuit_name));
+    std::fs::write(&layout_path, layout_library("KeccakCircuitLayout", config, false, pk.get_vk()))
+        .expect("Unable to write the layout library");
 
     println!("Circuit file preparation finished successfully.");
     println!("SRS stored in {}", srs_path.display());
     println!("Proving key stored in {}", pk_path.display());
     println!("Verification key stored in {}", vk_path.display());
-}
\ No newline at end of file
+    println!("Solidity instance layout stored in {}", layout_path.display());
+}
+
+fn stage_name(stage: Stage) -> &'static str {
+    match stage {
+        Stage::Setup => "Generating the SRS",
+        Stage::VerifyingKey => "Generating the verifying key (fixed columns, permutation, commitments)",
+        Stage::ProvingKey => "Generating the proving key (extended domain evaluations)",
+        Stage::Proof => "Proving",
+    }
+}
@@ -0,0 +1,35 @@
+use clap::{App, Arg};
+
+use halo2_keccak_256::encoding::from_hex;
+use halo2_keccak_256::{inspect_witness, KeccakConfigParams, DEFAULT_CONFIG};
+
+pub fn main() {
+    // Setup command-line argument parsing
+    let matches = App::new("Keccak Witness Inspector")
+        .about("Prints the witness trace of hashing the given input, round by round")
+        .arg(Arg::with_name("config")
+            .long("config")
+            .short('c')
+            .help("TOML config to generate the witness for, the defaults if omitted")
+            .takes_value(true))
+        .arg(Arg::with_name("input")
+            .long("input")
+            .short('i')
+            .help("Hex encoded message")
+            .takes_value(true)
+            .required(true))
+        .get_matches();
+
+    let config = match matches.value_of("config") {
+        Some(path) => KeccakConfigParams::from_file(path).map_err(|e| e.to_string()),
+        None => Ok(DEFAULT_CONFIG),
+    };
+    let input = from_hex(matches.value_of("input").unwrap()).map_err(|e| e.to_string());
+    match config.and_then(|config| input.map(|input| (config, input))) {
+        Ok((config, input)) => print!("{}", inspect_witness(&input, config)),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,721 @@
+//! Gadgets moving the keccak circuit's assigned cells into a shared column, as [`SharedCell`]s
+//! and range checked [`DigestBytes`], for host circuits to consume.
+//!
+//! Values are moved into a single shared advice column through copy constraints. Digest bytes
+//! are range checked against a byte table and recomposed into `hash_hi`/`hash_lo`, so they are
+//! safe to consume as bytes.
+//!
+//! These are plain halo2 cells, not halo2-lib's `AssignedValue`/`SafeBytes32`: those live in
+//! the virtual cells of a halo2-base `Context`, and the crate doesn't depend on halo2-base (see
+//! the README), so there are no conversions. A halo2-lib host copies the shared cells into the
+//! advice cells of its context itself, once the context is laid out.
+//!
+//! On top of the bytes, [`KeccakBridgeConfig::constrain_prefix`] and
+//! [`KeccakBridgeConfig::constrain_hi_below`] bind the digest to public predicates, e.g. for
+//! proof-of-work or vanity hash statements, and [`KeccakBridgeConfig::assign_scalar_limbs`]
+//! hands the digest to ECDSA gadgets as the limbs of the message scalar.
+//!
+//! For hash DAGs, where a message is the concatenation of the digests of earlier messages of
+//! the same proof, [`resolve_digest_inputs`] builds the messages and
+//! [`KeccakBridgeConfig::constrain_absorbs_digests`] wires the digests into the absorbed words.
+
+use halo2_proofs::circuit::{Layouter, Value};
+use halo2_proofs::plonk::{Advice, Column, ConstraintSystem, Error, Instance, Selector, TableColumn};
+use halo2_proofs::poly::Rotation;
+use sha3::{Digest, Keccak256};
+
+use crate::util::assign_value::{assigned_value, constrain_equal, constrain_instance, raw_assign_advice};
+use crate::util::eth_types::Field;
+use crate::util::expression::{from_bytes, Expr};
+use crate::util::Halo2AssignedCell;
+use crate::vanilla::param::NUM_BYTES_PER_WORD;
+use crate::Keccak256Error;
+
+/// Number of bytes in each of `hash_hi` and `hash_lo`.
+const NUM_BYTES_PER_HALF: usize = 16;
+
+/// A field element assigned in the shared column.
+pub type SharedCell<'v, F> = Halo2AssignedCell<'v, F>;
+
+/// Limb layout of the ECDSA message scalar, the big-endian digest as an integer, in the format
+/// expected by common halo2 ECDSA gadgets. Limbs are little-endian, the least significant first.
+/// The digest isn't reduced modulo the curve order, which those gadgets do themselves.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LimbFormat {
+    /// 3 limbs of 88 bits, as in halo2-ecc's CRT integers.
+    Limbs3x88,
+    /// 4 limbs of 64 bits.
+    Limbs4x64,
+}
+
+impl LimbFormat {
+    /// Number of bytes in every limb but possibly the most significant one.
+    pub fn limb_bytes(&self) -> usize {
+        match self {
+            LimbFormat::Limbs3x88 => 11,
+            LimbFormat::Limbs4x64 => 8,
+        }
+    }
+
+    /// Number of limbs.
+    pub fn num_limbs(&self) -> usize {
+        match self {
+            LimbFormat::Limbs3x88 => 3,
+            LimbFormat::Limbs4x64 => 4,
+        }
+    }
+
+    /// The big-endian digest bytes making up each limb, least significant limb first.
+    fn limb_ranges(&self) -> impl Iterator<Item = std::ops::Range<usize>> {
+        let limb_bytes = self.limb_bytes();
+        (0..self.num_limbs()).map(move |i| 32usize.saturating_sub((i + 1) * limb_bytes)..32 - i * limb_bytes)
+    }
+
+    /// The limbs of `digest`, computed natively.
+    pub fn limbs(&self, digest: &[u8; 32]) -> Vec<u128> {
+        self.limb_ranges()
+            .map(|range| digest[range].iter().fold(0u128, |acc, byte| acc << 8 | *byte as u128))
+            .collect()
+    }
+}
+
+/// A message of a hash DAG.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DigestInput {
+    /// A message given as bytes.
+    Bytes(Vec<u8>),
+    /// The concatenation of the digests of the earlier messages at these indices.
+    Digests(Vec<usize>),
+}
+
+/// The messages of a hash DAG in order, with their digests, computed natively. Fails if a
+/// message references itself or a later message.
+pub fn resolve_digest_inputs(inputs: &[DigestInput]) -> Result<(Vec<Vec<u8>>, Vec<[u8; 32]>), Keccak256Error> {
+    let mut messages = Vec::with_capacity(inputs.len());
+    let mut digests: Vec<[u8; 32]> = Vec::with_capacity(inputs.len());
+    for (idx, input) in inputs.iter().enumerate() {
+        let message = match input {
+            DigestInput::Bytes(bytes) => bytes.clone(),
+            DigestInput::Digests(parents) => parents
+                .iter()
+                .map(|parent| {
+                    digests.get(*parent).ok_or_else(|| {
+                        Keccak256Error(format!(
+                            "Message {idx} absorbs the digest of message {parent}, which isn't an earlier one"
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .concat(),
+        };
+        digests.push(Keccak256::digest(&message).into());
+        messages.push(message);
+    }
+    Ok((messages, digests))
+}
+
+/// The range checked bytes of a digest, in the shared column.
+#[derive(Clone, Debug)]
+pub struct DigestBytes<'v, F: Field> {
+    /// The digest bytes in big-endian order, as returned by `Keccak256::digest`.
+    pub bytes: [SharedCell<'v, F>; 32],
+}
+
+/// Configuration of the bridge gadget.
+#[derive(Clone, Debug)]
+pub struct KeccakBridgeConfig {
+    /// Shared advice column, equality enabled.
+    pub shared: Column<Advice>,
+    q_decompose: Selector,
+    q_byte: Selector,
+    q_below: Selector,
+    q_horner: Selector,
+    q_word: Selector,
+    byte_table: TableColumn,
+}
+
+impl KeccakBridgeConfig {
+    /// Configures the bridge gadget.
+    pub fn configure<F: Field>(meta: &mut ConstraintSystem<F>) -> Self {
+        let shared = meta.advice_column();
+        meta.enable_equality(shared);
+        let q_decompose = meta.selector();
+        let q_byte = meta.complex_selector();
+        let q_below = meta.selector();
+        let q_horner = meta.selector();
+        let q_word = meta.selector();
+        let byte_table = meta.lookup_table_column();
+
+        // The 16 little-endian bytes followed by the 128 bit half they recompose to.
+        meta.create_gate("bridge byte decomposition", |meta| {
+            let q = meta.query_selector(q_decompose);
+            let bytes = (0..NUM_BYTES_PER_HALF)
+                .map(|i| meta.query_advice(shared, Rotation(i as i32)))
+                .collect::<Vec<_>>();
+            let half = meta.query_advice(shared, Rotation(NUM_BYTES_PER_HALF as i32));
+            vec![q * (from_bytes::expr(&bytes) - half)]
+        });
+
+        // `hash_hi`, the threshold, then the decomposition of `threshold - 1 - hash_hi`, which
+        // only fits in 16 bytes if `hash_hi < threshold`.
+        meta.create_gate("bridge below threshold", |meta| {
+            let q = meta.query_selector(q_below);
+            let hash_hi = meta.query_advice(shared, Rotation::cur());
+            let threshold = meta.query_advice(shared, Rotation::next());
+            let diff = meta.query_advice(shared, Rotation(2 + NUM_BYTES_PER_HALF as i32));
+            vec![q * (threshold - 1.expr() - hash_hi - diff)]
+        });
+
+        // Alternating byte and accumulator cells, most significant byte first.
+        meta.create_gate("bridge limb composition", |meta| {
+            let q = meta.query_selector(q_horner);
+            let prev_acc = meta.query_advice(shared, Rotation(-2));
+            let byte = meta.query_advice(shared, Rotation::prev());
+            let acc = meta.query_advice(shared, Rotation::cur());
+            vec![q * (prev_acc * 256.expr() + byte - acc)]
+        });
+
+        // The little-endian bytes of an input word followed by the word, as in `word_value`.
+        meta.create_gate("bridge word decomposition", |meta| {
+            let q = meta.query_selector(q_word);
+            let bytes = (0..NUM_BYTES_PER_WORD)
+                .map(|i| meta.query_advice(shared, Rotation(i as i32)))
+                .collect::<Vec<_>>();
+            let word = meta.query_advice(shared, Rotation(NUM_BYTES_PER_WORD as i32));
+            vec![q * (from_bytes::expr(&bytes) - word)]
+        });
+
+        meta.lookup("bridge byte range check", |meta| {
+            let q = meta.query_selector(q_byte);
+            vec![(q * meta.query_advice(shared, Rotation::cur()), byte_table)]
+        });
+
+        KeccakBridgeConfig { shared, q_decompose, q_byte, q_below, q_horner, q_word, byte_table }
+    }
+
+    /// Loads the byte table used by the range checks.
+    pub fn load_byte_table<F: Field>(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
+        layouter.assign_table(
+            || "bridge byte table",
+            |mut table| {
+                for byte in 0..256u64 {
+                    table.assign_cell(
+                        || "byte",
+                        self.byte_table,
+                        byte as usize,
+                        || Value::known(F::from(byte)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Copies keccak cells (e.g. `word_value` or `bytes_left`) into the shared column.
+    pub fn assign_values<'v, F: Field>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        cells: &[Halo2AssignedCell<'v, F>],
+    ) -> Result<Vec<SharedCell<'v, F>>, Error> {
+        layouter.assign_region(
+            || "bridge values",
+            |mut region| {
+                cells
+                    .iter()
+                    .enumerate()
+                    .map(|(offset, cell)| {
+                        let assigned =
+                            raw_assign_advice(&mut region, self.shared, offset, assigned_value(cell));
+                        constrain_equal(&mut region, cell.cell(), assigned.cell())?;
+                        Ok(assigned)
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    /// Copies the `hash_hi`/`hash_lo` cells of a final row into the shared column and
+    /// decomposes them into range checked bytes.
+    pub fn assign_digest<'v, F: Field>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        hash_hi: &Halo2AssignedCell<'v, F>,
+        hash_lo: &Halo2AssignedCell<'v, F>,
+    ) -> Result<DigestBytes<'v, F>, Error> {
+        layouter.assign_region(
+            || "bridge digest",
+            |mut region| {
+                let mut bytes = Vec::with_capacity(32);
+                for (idx, half) in [hash_hi, hash_lo].into_iter().enumerate() {
+                    let base = idx * (NUM_BYTES_PER_HALF + 1);
+                    let value = assigned_value(half);
+                    self.q_decompose.enable(&mut region, base)?;
+                    let mut le_bytes = Vec::with_capacity(NUM_BYTES_PER_HALF);
+                    for i in 0..NUM_BYTES_PER_HALF {
+                        self.q_byte.enable(&mut region, base + i)?;
+                        le_bytes.push(raw_assign_advice(
+                            &mut region,
+                            self.shared,
+                            base + i,
+                            value.map(|v: F| F::from(v.to_repr()[i] as u64)),
+                        ));
+                    }
+                    let assigned =
+                        raw_assign_advice(&mut region, self.shared, base + NUM_BYTES_PER_HALF, value);
+                    constrain_equal(&mut region, half.cell(), assigned.cell())?;
+                    bytes.extend(le_bytes.into_iter().rev());
+                }
+                Ok(DigestBytes { bytes: bytes.try_into().unwrap() })
+            },
+        )
+    }
+
+    /// Copies input words (`word_value` cells) into the shared column and decomposes them into
+    /// range checked bytes, in message order.
+    pub fn assign_word_bytes<'v, F: Field>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        words: &[Halo2AssignedCell<'v, F>],
+    ) -> Result<Vec<SharedCell<'v, F>>, Error> {
+        layouter.assign_region(
+            || "bridge word bytes",
+            |mut region| {
+                let mut bytes = Vec::with_capacity(words.len() * NUM_BYTES_PER_WORD);
+                for (idx, word) in words.iter().enumerate() {
+                    let base = idx * (NUM_BYTES_PER_WORD + 1);
+                    let value = assigned_value(word);
+                    self.q_word.enable(&mut region, base)?;
+                    for i in 0..NUM_BYTES_PER_WORD {
+                        self.q_byte.enable(&mut region, base + i)?;
+                        bytes.push(raw_assign_advice(
+                            &mut region,
+                            self.shared,
+                            base + i,
+                            value.map(|v: F| F::from(v.to_repr()[i] as u64)),
+                        ));
+                    }
+                    let assigned =
+                        raw_assign_advice(&mut region, self.shared, base + NUM_BYTES_PER_WORD, value);
+                    constrain_equal(&mut region, word.cell(), assigned.cell())?;
+                }
+                Ok(bytes)
+            },
+        )
+    }
+
+    /// Recomposes the digest bytes into the limbs of the ECDSA message scalar in `format`, ready
+    /// to be copied into an ECDSA gadget.
+    pub fn assign_scalar_limbs<'v, F: Field>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        digest: &DigestBytes<'v, F>,
+        format: LimbFormat,
+    ) -> Result<Vec<SharedCell<'v, F>>, Error> {
+        layouter.assign_region(
+            || "bridge scalar limbs",
+            |mut region| {
+                let mut offset = 0;
+                let mut limbs = Vec::with_capacity(format.num_limbs());
+                for range in format.limb_ranges() {
+                    let mut acc: Option<SharedCell<'v, F>> = None;
+                    for byte in &digest.bytes[range] {
+                        let copied = raw_assign_advice(&mut region, self.shared, offset, assigned_value(byte));
+                        constrain_equal(&mut region, byte.cell(), copied.cell())?;
+                        let value = match &acc {
+                            Some(acc) => assigned_value(acc)
+                                .zip(assigned_value(byte))
+                                .map(|(acc, byte)| acc * F::from(256) + byte),
+                            None => assigned_value(byte),
+                        };
+                        let next = raw_assign_advice(&mut region, self.shared, offset + 1, value);
+                        match acc {
+                            Some(_) => self.q_horner.enable(&mut region, offset + 1)?,
+                            None => constrain_equal(&mut region, copied.cell(), next.cell())?,
+                        }
+                        acc = Some(next);
+                        offset += 2;
+                    }
+                    limbs.push(acc.unwrap());
+                }
+                Ok(limbs)
+            },
+        )
+    }
+
+    /// Constrains the message absorbed through `words`, its `word_value` cells in message order,
+    /// to be the concatenation of `digests`, e.g. the digests of earlier messages of the same
+    /// proof as returned by [`Self::assign_digest`]. Fails with `Error::Synthesis` if the
+    /// lengths differ.
+    pub fn constrain_absorbs_digests<'v, F: Field>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        words: &[Halo2AssignedCell<'v, F>],
+        digests: &[DigestBytes<'v, F>],
+    ) -> Result<(), Error> {
+        if words.len() * NUM_BYTES_PER_WORD != digests.len() * 32 {
+            return Err(Error::Synthesis);
+        }
+        let bytes = self.assign_word_bytes(layouter.namespace(|| "message bytes"), words)?;
+        layouter.assign_region(
+            || "bridge digest input",
+            |mut region| {
+                for (byte, digest_byte) in bytes.iter().zip(digests.iter().flat_map(|digest| &digest.bytes)) {
+                    constrain_equal(&mut region, byte.cell(), digest_byte.cell())?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Constrains the digest to start with the public bytes at rows `offset..offset + len` of
+    /// `instance`, which needs equality enabled. Fails with `Error::Synthesis` if `len` exceeds
+    /// the 32 digest bytes.
+    pub fn constrain_prefix<F: Field>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        digest: &DigestBytes<'_, F>,
+        instance: Column<Instance>,
+        offset: usize,
+        len: usize,
+    ) -> Result<(), Error> {
+        if len > digest.bytes.len() {
+            return Err(Error::Synthesis);
+        }
+        for (i, byte) in digest.bytes[..len].iter().enumerate() {
+            constrain_instance(&mut layouter, byte.cell(), instance, offset + i)?;
+        }
+        Ok(())
+    }
+
+    /// Constrains `hash_hi < threshold`, where `threshold` is at most 2^128 and is the public
+    /// value at row `offset` of `instance`, which needs equality enabled. A threshold of
+    /// 2^(128 - n) proves n leading zero bits.
+    pub fn constrain_hi_below<F: Field>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        hash_hi: &Halo2AssignedCell<'_, F>,
+        threshold: Value<F>,
+        instance: Column<Instance>,
+        offset: usize,
+    ) -> Result<(), Error> {
+        let threshold_cell = layouter.assign_region(
+            || "bridge below threshold",
+            |mut region| {
+                self.q_below.enable(&mut region, 0)?;
+                let hi = raw_assign_advice(&mut region, self.shared, 0, assigned_value(hash_hi));
+                constrain_equal(&mut region, hash_hi.cell(), hi.cell())?;
+                let threshold_cell = raw_assign_advice(&mut region, self.shared, 1, threshold);
+
+                let base = 2;
+                let diff = threshold.zip(assigned_value(hash_hi)).map(|(t, h)| t - F::ONE - h);
+                self.q_decompose.enable(&mut region, base)?;
+                for i in 0..NUM_BYTES_PER_HALF {
+                    self.q_byte.enable(&mut region, base + i)?;
+                    raw_assign_advice(
+                        &mut region,
+                        self.shared,
+                        base + i,
+                        diff.map(|v: F| F::from(v.to_repr()[i] as u64)),
+                    );
+                }
+                raw_assign_advice(&mut region, self.shared, base + NUM_BYTES_PER_HALF, diff);
+                Ok(threshold_cell)
+            },
+        )?;
+        constrain_instance(&mut layouter, threshold_cell.cell(), instance, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::circuit::SimpleFloorPlanner;
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use halo2_proofs::halo2curves::ff::PrimeField;
+    use halo2_proofs::plonk::Circuit;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct BridgeTestCircuit {
+        hash_hi: u128,
+        hash_lo: u128,
+        expected: [u8; 32],
+    }
+
+    impl Circuit<Fr> for BridgeTestCircuit {
+        type Config = (Column<Advice>, KeccakBridgeConfig);
+        type FloorPlanner = SimpleFloorPlanner;
+        type Params = ();
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let digest = meta.advice_column();
+            meta.enable_equality(digest);
+            (digest, KeccakBridgeConfig::configure(meta))
+        }
+
+        fn synthesize(
+            &self,
+            (digest, bridge): Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            bridge.load_byte_table(&mut layouter)?;
+            let (hi, lo) = layouter.assign_region(
+                || "digest",
+                |mut region| {
+                    let hi = raw_assign_advice(
+                        &mut region,
+                        digest,
+                        0,
+                        Value::known(Fr::from_u128(self.hash_hi)),
+                    );
+                    let lo = raw_assign_advice(
+                        &mut region,
+                        digest,
+                        1,
+                        Value::known(Fr::from_u128(self.hash_lo)),
+                    );
+                    Ok((hi, lo))
+                },
+            )?;
+            let bytes = bridge.assign_digest(layouter.namespace(|| "bridge"), &hi, &lo)?;
+            for (cell, expected) in bytes.bytes.iter().zip(self.expected.iter()) {
+                assigned_value(cell).assert_if_known(|v| *v == Fr::from(*expected as u64));
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct PredicateTestCircuit {
+        digest: [u8; 32],
+        threshold: u128,
+        prefix_len: usize,
+    }
+
+    impl Circuit<Fr> for PredicateTestCircuit {
+        type Config = (Column<Advice>, Column<Instance>, KeccakBridgeConfig);
+        type FloorPlanner = SimpleFloorPlanner;
+        type Params = ();
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let digest = meta.advice_column();
+            meta.enable_equality(digest);
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+            (digest, instance, KeccakBridgeConfig::configure(meta))
+        }
+
+        fn synthesize(
+            &self,
+            (digest, instance, bridge): Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            bridge.load_byte_table(&mut layouter)?;
+            let hash_hi = u128::from_be_bytes(self.digest[..16].try_into().unwrap());
+            let hash_lo = u128::from_be_bytes(self.digest[16..].try_into().unwrap());
+            let (hi, lo) = layouter.assign_region(
+                || "digest",
+                |mut region| {
+                    let hi = raw_assign_advice(&mut region, digest, 0, Value::known(Fr::from_u128(hash_hi)));
+                    let lo = raw_assign_advice(&mut region, digest, 1, Value::known(Fr::from_u128(hash_lo)));
+                    Ok((hi, lo))
+                },
+            )?;
+            let bytes = bridge.assign_digest(layouter.namespace(|| "bridge"), &hi, &lo)?;
+            bridge.constrain_prefix(layouter.namespace(|| "prefix"), &bytes, instance, 0, self.prefix_len)?;
+            bridge.constrain_hi_below(
+                layouter.namespace(|| "below"),
+                &hi,
+                Value::known(Fr::from_u128(self.threshold)),
+                instance,
+                self.prefix_len,
+            )
+        }
+    }
+
+    #[test]
+    fn test_digest_predicates() {
+        let digest: [u8; 32] = Keccak256::digest(b"predicate").into();
+        let hash_hi = u128::from_be_bytes(digest[..16].try_into().unwrap());
+        let run = |prefix: &[u8], threshold: u128| {
+            let circuit = PredicateTestCircuit { digest, threshold, prefix_len: prefix.len() };
+            let mut instance = prefix.iter().map(|b| Fr::from(*b as u64)).collect::<Vec<_>>();
+            instance.push(Fr::from_u128(threshold));
+            MockProver::run(9, &circuit, vec![instance]).unwrap().verify()
+        };
+
+        assert!(run(&digest[..3], hash_hi + 1).is_ok());
+        assert!(run(&[], u128::MAX).is_ok());
+        assert!(run(&[digest[0] ^ 1], hash_hi + 1).is_err());
+        assert!(run(&digest[..1], hash_hi).is_err());
+
+        let too_long = PredicateTestCircuit { digest, threshold: hash_hi + 1, prefix_len: 33 };
+        assert!(matches!(MockProver::run(9, &too_long, vec![vec![Fr::from(0); 34]]), Err(Error::Synthesis)));
+    }
+
+    struct LimbTestCircuit {
+        digest: [u8; 32],
+        format: LimbFormat,
+    }
+
+    impl Circuit<Fr> for LimbTestCircuit {
+        type Config = (Column<Advice>, KeccakBridgeConfig);
+        type FloorPlanner = SimpleFloorPlanner;
+        type Params = ();
+
+        fn without_witnesses(&self) -> Self {
+            LimbTestCircuit { digest: [0; 32], format: self.format }
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            BridgeTestCircuit::configure(meta)
+        }
+
+        fn synthesize(
+            &self,
+            (digest, bridge): Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            bridge.load_byte_table(&mut layouter)?;
+            let hash_hi = u128::from_be_bytes(self.digest[..16].try_into().unwrap());
+            let hash_lo = u128::from_be_bytes(self.digest[16..].try_into().unwrap());
+            let (hi, lo) = layouter.assign_region(
+                || "digest",
+                |mut region| {
+                    let hi = raw_assign_advice(&mut region, digest, 0, Value::known(Fr::from_u128(hash_hi)));
+                    let lo = raw_assign_advice(&mut region, digest, 1, Value::known(Fr::from_u128(hash_lo)));
+                    Ok((hi, lo))
+                },
+            )?;
+            let bytes = bridge.assign_digest(layouter.namespace(|| "bridge"), &hi, &lo)?;
+            let limbs = bridge.assign_scalar_limbs(layouter.namespace(|| "limbs"), &bytes, self.format)?;
+            assert_eq!(limbs.len(), self.format.num_limbs());
+            for (cell, expected) in limbs.iter().zip(self.format.limbs(&self.digest)) {
+                assigned_value(cell).assert_if_known(|v| *v == Fr::from_u128(expected));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_assign_scalar_limbs() {
+        let digest: [u8; 32] = Keccak256::digest(b"ecdsa").into();
+        for format in [LimbFormat::Limbs3x88, LimbFormat::Limbs4x64] {
+            let limbs = format.limbs(&digest);
+            let bits = format.limb_bytes() * 8;
+            let recomposed = limbs.iter().rev().fold(num_bigint::BigUint::default(), |acc, limb| {
+                (acc << bits) + num_bigint::BigUint::from(*limb)
+            });
+            assert_eq!(recomposed, num_bigint::BigUint::from_bytes_be(&digest));
+
+            let circuit = LimbTestCircuit { digest, format };
+            MockProver::run(9, &circuit, vec![]).unwrap().assert_satisfied();
+        }
+    }
+
+    /// Word cells of a message standing for its schedule in the keccak circuit, and the
+    /// digest cells of the messages it should absorb.
+    #[derive(Default)]
+    struct DigestInputTestCircuit {
+        message: Vec<u8>,
+        digests: Vec<[u8; 32]>,
+    }
+
+    impl Circuit<Fr> for DigestInputTestCircuit {
+        type Config = (Column<Advice>, KeccakBridgeConfig);
+        type FloorPlanner = SimpleFloorPlanner;
+        type Params = ();
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            BridgeTestCircuit::configure(meta)
+        }
+
+        fn synthesize(
+            &self,
+            (keccak, bridge): Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            bridge.load_byte_table(&mut layouter)?;
+            let (words, halves) = layouter.assign_region(
+                || "keccak",
+                |mut region| {
+                    let words = self
+                        .message
+                        .chunks(NUM_BYTES_PER_WORD)
+                        .enumerate()
+                        .map(|(row, word)| {
+                            let word = u64::from_le_bytes(word.try_into().unwrap());
+                            raw_assign_advice(&mut region, keccak, row, Value::known(Fr::from(word)))
+                        })
+                        .collect::<Vec<_>>();
+                    let offset = words.len();
+                    let halves = self
+                        .digests
+                        .iter()
+                        .flat_map(|digest| digest.chunks(16))
+                        .enumerate()
+                        .map(|(row, half)| {
+                            let half = u128::from_be_bytes(half.try_into().unwrap());
+                            raw_assign_advice(&mut region, keccak, offset + row, Value::known(Fr::from_u128(half)))
+                        })
+                        .collect::<Vec<_>>();
+                    Ok((words, halves))
+                },
+            )?;
+            let digests = halves
+                .chunks(2)
+                .map(|half| bridge.assign_digest(layouter.namespace(|| "digest"), &half[0], &half[1]))
+                .collect::<Result<Vec<_>, _>>()?;
+            bridge.constrain_absorbs_digests(layouter.namespace(|| "digest input"), &words, &digests)
+        }
+    }
+
+    #[test]
+    fn test_constrain_absorbs_digests() {
+        let inputs = [
+            DigestInput::Bytes(b"left".to_vec()),
+            DigestInput::Bytes(b"right".to_vec()),
+            DigestInput::Digests(vec![0, 1]),
+        ];
+        let (messages, digests) = resolve_digest_inputs(&inputs).unwrap();
+        assert_eq!(messages[2], [digests[0], digests[1]].concat());
+        assert_eq!(digests[2], <[u8; 32]>::from(Keccak256::digest(&messages[2])));
+        assert!(resolve_digest_inputs(&[DigestInput::Digests(vec![0])]).is_err());
+
+        let run = |digests: Vec<[u8; 32]>| {
+            let circuit = DigestInputTestCircuit { message: messages[2].clone(), digests };
+            MockProver::run(10, &circuit, vec![]).unwrap().verify()
+        };
+        assert!(run(vec![digests[0], digests[1]]).is_ok());
+        assert!(run(vec![digests[1], digests[0]]).is_err());
+
+        // A message that isn't as long as the digests is rejected rather than panicking
+        let circuit = DigestInputTestCircuit { message: messages[2].clone(), digests: vec![digests[0]] };
+        assert!(matches!(MockProver::run(10, &circuit, vec![]), Err(Error::Synthesis)));
+    }
+
+    #[test]
+    fn test_assign_digest() {
+        let digest = Keccak256::digest(b"bridge");
+        let circuit = BridgeTestCircuit {
+            hash_hi: u128::from_be_bytes(digest[..16].try_into().unwrap()),
+            hash_lo: u128::from_be_bytes(digest[16..].try_into().unwrap()),
+            expected: digest.into(),
+        };
+        let prover = MockProver::run(9, &circuit, vec![]).unwrap();
+        prover.assert_satisfied();
+    }
+}
@@ -1,52 +1,491 @@
 use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::Write;
 use std::marker::PhantomData;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
-use halo2_proofs::halo2curves::bn256::{Bn256, Fr, G1Affine};
-use halo2_proofs::halo2curves::ff::PrimeField;
-use halo2_proofs::plonk::{Circuit, Column, ConstraintSystem, create_proof, Error, Instance, ProvingKey, verify_proof, VerifyingKey};
-use halo2_proofs::poly::commitment::ParamsProver;
-use halo2_proofs::poly::kzg::commitment::{KZGCommitmentScheme, ParamsKZG};
-use halo2_proofs::poly::kzg::multiopen::{ProverSHPLONK, VerifierSHPLONK};
-use halo2_proofs::poly::kzg::strategy::SingleStrategy;
-use halo2_proofs::transcript::{Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer};
-use itertools::Itertools;
-use rand::thread_rng;
-use sha3::{Digest, Keccak256};
-
-use crate::DEFAULT_CONFIG;
-use crate::util::{SKIP_FIRST_PASS, value_to_option};
+use halo2_proofs::halo2curves::bn256::Bn256;
+use halo2_proofs::halo2curves::ff::{FromUniformBytes, PrimeField, WithSmallOrderMulGroup};
+use halo2_proofs::halo2curves::pairing::MultiMillerLoop;
+use halo2_proofs::halo2curves::serde::SerdeObject;
+use halo2_proofs::plonk::{Circuit, Column, ConstraintSystem, Error, Instance, ProvingKey, VerifyingKey};
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use halo2_proofs::transcript::{Blake2bWrite, Challenge255, Keccak256Write};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{Keccak256Error, KeccakCircuitError, DEFAULT_CONFIG};
+use crate::config::{DigestSize, InputBinding, InstanceMode, SanityLevel, TranscriptKind};
+use crate::disclosure::{block_flags, mask, DisclosureConfig, DISCLOSURE_HEADER_LEN};
+use crate::schedule::{hash_schedules, AbsorbScheduleIter, AbsorbStep, HashSchedule};
+use crate::proof_system::{
+    Blake2bTranscriptRead, KeccakTranscriptRead, KzgShplonk, ProofSystem,
+};
+use crate::util::{assign_value, assign_value::constrain_instance, SKIP_FIRST_PASS, value_to_option};
 use crate::util::eth_types::Field;
 use crate::vanilla::{KeccakAssignedRow, KeccakCircuitConfig, KeccakConfigParams};
-use crate::vanilla::keccak_packed_multi::{get_keccak_capacity, KeccakAssignedValue};
+use crate::vanilla::keccak_packed_multi::{get_keccak_capacity, input_cell_word, KeccakAssignedValue};
 use crate::vanilla::param::{NUM_BYTES_PER_WORD, NUM_ROUNDS, NUM_WORDS_TO_ABSORB};
 use crate::vanilla::witness::multi_keccak;
 
 #[derive(Clone, Debug)]
 pub struct CircuitConfig<F> {
-    pub input: Column<Instance>,
+    /// Input words, empty unless the instance mode exposes inputs, spread over
+    /// [`KeccakConfigParams::num_input_columns`] columns.
+    pub inputs: Vec<Column<Instance>>,
+    /// Digests as 128 bit cells, most significant first, only present if the instance mode
+    /// exposes outputs.
+    pub output: Option<Column<Instance>>,
     pub keccak_config: KeccakCircuitConfig<F>,
+    /// Masks the words of the fixed instance layout, only present with
+    /// [`selective_disclosure`](KeccakConfigParams::selective_disclosure).
+    pub disclosure: Option<DisclosureConfig>,
     _marker: PhantomData<F>,
 }
 
+impl<F> CircuitConfig<F> {
+    /// The column and row of logical input row `row`.
+    fn input_cell(&self, row: usize) -> (Column<Instance>, usize) {
+        (self.inputs[row % self.inputs.len()], row / self.inputs.len())
+    }
+}
+
 /// KeccakCircuit
 #[derive(Default, Clone, Debug)]
 pub struct KeccakCircuit<F: Field> {
     config: KeccakConfigParams,
-    inputs: Vec<Vec<u8>>,
+    inputs: Arc<Vec<Vec<u8>>>,
     num_rows: Option<usize>,
-    verify_output: bool,
-    use_instance: bool,
+    /// Range of `inputs` hashed in each region. With `split_regions`, every region spans
+    /// exactly `num_rows` rows.
+    regions: Vec<Range<usize>>,
+    split_regions: bool,
+    public_bytes: PublicBytes,
+    accumulator: bool,
+    fixed_instance: bool,
+    deadline: Option<Deadline>,
+    _marker: PhantomData<F>,
+}
+
+/// The deadline of a proof, checked by the synthesis once the witness is generated and once it
+/// is assigned, so that a proof over budget is aborted before its commitments. It records when
+/// the witness was done, for the timings of the proof.
+#[derive(Clone, Debug)]
+pub(crate) struct Deadline {
+    at: Instant,
+    witness_done: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Deadline {
+    pub(crate) fn new(at: Instant) -> Self {
+        Deadline { at, witness_done: Arc::default() }
+    }
+
+    pub(crate) fn expired(&self) -> bool {
+        Instant::now() >= self.at
+    }
+
+    /// When the witness was assigned, if it was.
+    pub(crate) fn witness_done(&self) -> Option<Instant> {
+        *self.witness_done.lock().unwrap()
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.expired() {
+            return Err(Error::Synthesis);
+        }
+        Ok(())
+    }
+}
+
+/// Which bytes of every input are exposed through the `input` instance column, if the instance
+/// mode exposes inputs at all. Words are exposed whole, so boundaries are multiples of
+/// `NUM_BYTES_PER_WORD`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PublicBytes {
+    /// The whole input.
+    #[default]
+    All,
+    /// Only the first `n` bytes, e.g. the revealed value of `keccak(value || salt)`.
+    Prefix(usize),
+    /// Everything after the first `n` bytes, e.g. the message of `keccak(key || message)`.
+    /// The number of public bytes, i.e. the `bytes_left` of the first public word, is exposed
+    /// right before the words, which pins the private prefix to exactly `n` bytes. `Suffix(0)`
+    /// exposes the length of every non-empty input before its words.
+    Suffix(usize),
+    /// Everything but the bytes `start..end`, e.g. the key blocks of a KMAC between its
+    /// customization block and its message. As with `Suffix(end)`, the number of bytes after
+    /// them is exposed right before their words, which pins the private bytes in between.
+    Except { start: usize, end: usize },
+}
+
+impl PublicBytes {
+    /// The public bytes of an input of `len` bytes, those after the private ones with
+    /// [`PublicBytes::Except`].
+    pub fn range(&self, len: usize) -> Range<usize> {
+        match *self {
+            PublicBytes::All => 0..len,
+            PublicBytes::Prefix(n) => 0..n.min(len),
+            PublicBytes::Suffix(n) | PublicBytes::Except { end: n, .. } => n.min(len)..len,
+        }
+    }
+
+    /// The public bytes before the private ones with [`PublicBytes::Except`], empty otherwise.
+    pub fn leading_range(&self, len: usize) -> Range<usize> {
+        match *self {
+            PublicBytes::Except { start, .. } => 0..start.min(len),
+            _ => 0..0,
+        }
+    }
+
+    /// Whether the word at `byte_offset` of an input of `len` bytes is disclosed by the fixed
+    /// instance layout with [`selective_disclosure`](KeccakConfigParams::selective_disclosure):
+    /// if it is public, or past the end of the input, where it is zero.
+    pub fn discloses(&self, len: usize, byte_offset: usize) -> bool {
+        byte_offset >= len || self.range(len).contains(&byte_offset) || self.leading_range(len).contains(&byte_offset)
+    }
+
+    /// Whether the number of public bytes of [`Self::range`] is exposed before their words.
+    pub fn exposes_len(&self) -> bool {
+        matches!(self, PublicBytes::Suffix(_) | PublicBytes::Except { .. })
+    }
+
+    fn validate(&self) -> Result<(), Keccak256Error> {
+        match *self {
+            PublicBytes::All => Ok(()),
+            PublicBytes::Prefix(n) | PublicBytes::Suffix(n) if n % NUM_BYTES_PER_WORD != 0 => {
+                Err(Keccak256Error(format!(
+                    "{:?} is not a multiple of the {} byte word",
+                    self, NUM_BYTES_PER_WORD
+                )))
+            }
+            PublicBytes::Except { start, end }
+                if start % NUM_BYTES_PER_WORD != 0 || end % NUM_BYTES_PER_WORD != 0 || start > end =>
+            {
+                Err(Keccak256Error(format!(
+                    "{:?} is not an ordered range of multiples of the {} byte word",
+                    self, NUM_BYTES_PER_WORD
+                )))
+            }
+            PublicBytes::Prefix(_) | PublicBytes::Suffix(_) | PublicBytes::Except { .. } => Ok(()),
+        }
+    }
+}
+
+/// Builder for [`KeccakCircuit`]. Parameters are checked in [`KeccakCircuitBuilder::build`].
+#[derive(Clone, Debug)]
+pub struct KeccakCircuitBuilder<F: Field> {
+    config: KeccakConfigParams,
+    inputs: Arc<Vec<Vec<u8>>>,
+    instance_mode: Option<InstanceMode>,
+    sanity: Option<SanityLevel>,
+    capacity_rows: Option<usize>,
+    split_regions: bool,
+    public_bytes: PublicBytes,
+    accumulator: bool,
+    fixed_instance: Option<bool>,
+    deadline: Option<Deadline>,
     _marker: PhantomData<F>,
 }
 
+impl<F: Field> Default for KeccakCircuitBuilder<F> {
+    fn default() -> Self {
+        KeccakCircuitBuilder {
+            config: DEFAULT_CONFIG,
+            inputs: Arc::default(),
+            instance_mode: None,
+            sanity: None,
+            capacity_rows: None,
+            split_regions: false,
+            public_bytes: PublicBytes::All,
+            accumulator: false,
+            fixed_instance: None,
+            deadline: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<F: Field> KeccakCircuitBuilder<F> {
+    /// Sets the circuit parameters, [`DEFAULT_CONFIG`] by default.
+    pub fn config(mut self, config: KeccakConfigParams) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the messages to hash. The circuit never reorders them: they are hashed, and their
+    /// input cells and digests laid out in the instance, in the given order, so digest `i` of
+    /// the `output` column is always the one of `inputs[i]`.
+    pub fn inputs(mut self, inputs: Vec<Vec<u8>>) -> Self {
+        self.inputs = Arc::new(inputs);
+        self
+    }
+
+    /// Sets the messages to hash without copying them, sharing the caller's allocation.
+    pub fn shared_inputs(mut self, inputs: Arc<Vec<Vec<u8>>>) -> Self {
+        self.inputs = inputs;
+        self
+    }
+
+    /// Sets the messages to hash from any collection of byte slices.
+    pub fn inputs_from_iter<I, B>(self, inputs: I) -> Self
+    where
+        I: IntoIterator<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        self.inputs(inputs.into_iter().map(|input| input.as_ref().to_vec()).collect())
+    }
+
+    /// Overrides the instance mode of the config.
+    pub fn instance_mode(mut self, instance_mode: InstanceMode) -> Self {
+        self.instance_mode = Some(instance_mode);
+        self
+    }
+
+    /// Overrides the sanity level of the config.
+    pub fn sanity(mut self, sanity: SanityLevel) -> Self {
+        self.sanity = Some(sanity);
+        self
+    }
+
+    /// Sets the number of rows to fill with keccak_f permutations, padding with dummy hashes.
+    /// Without it, only the rows needed by the inputs are assigned.
+    pub fn capacity_rows(mut self, capacity_rows: usize) -> Self {
+        self.capacity_rows = Some(capacity_rows);
+        self
+    }
+
+    /// Splits the inputs over as many regions of `capacity_rows` rows as fit in the circuit,
+    /// instead of failing when they don't fit in a single one. Every region is padded to its
+    /// full capacity, so the layout and the verifying key only depend on the parameters.
+    /// The instance keeps the inputs in order across regions.
+    pub fn split_regions(mut self, split_regions: bool) -> Self {
+        self.split_regions = split_regions;
+        self
+    }
+
+    /// Restricts which bytes of every input are exposed, all of them by default. Only valid
+    /// with an instance mode exposing the inputs.
+    pub fn public_bytes(mut self, public_bytes: PublicBytes) -> Self {
+        self.public_bytes = public_bytes;
+        self
+    }
+
+    /// Reserves the first [`ACCUMULATOR_INSTANCE_LEN`] cells of the `input` column for a KZG
+    /// accumulator of previously aggregated proofs, carried through untouched so that the
+    /// circuit can be aggregated by snark-verifier as part of a proof-carrying-data chain.
+    pub fn accumulator(mut self, accumulator: bool) -> Self {
+        self.accumulator = accumulator;
+        self
+    }
+
+    /// Overrides the [`fixed_instance`](KeccakConfigParams::fixed_instance) of the config,
+    /// which only applies with `capacity_rows` otherwise: without it the assigned rows, and so
+    /// the keys, depend on the inputs anyway.
+    ///
+    /// The fixed layout lays out the instance by keccak_f slot over the whole capacity instead
+    /// of by input, so that the instance length, and the cell every instance row is bound to,
+    /// only depend on the parameters, and keys generated without inputs bind the inputs of any
+    /// proof. Every slot exposes the words it absorbs, zero past the end of its input, and the
+    /// digest on its last round, zero unless it finishes its input. The padding slots hash the
+    /// empty input. Requires `capacity_rows`, and exposing whole inputs unless
+    /// [`selective_disclosure`](KeccakConfigParams::selective_disclosure) masks the private
+    /// words, see [`disclosure`](crate::disclosure). Without it, keys only bind messages of the
+    /// lengths they were generated with.
+    pub fn fixed_instance(mut self, fixed_instance: bool) -> Self {
+        self.fixed_instance = Some(fixed_instance);
+        self
+    }
+
+    /// Aborts the synthesis of the witness once `deadline` has passed.
+    pub(crate) fn deadline(mut self, deadline: Deadline) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Validates the parameters and builds the circuit.
+    pub fn build(self) -> Result<KeccakCircuit<F>, Keccak256Error> {
+        let mut config = self.config;
+        if let Some(instance_mode) = self.instance_mode {
+            config.instance_mode = instance_mode;
+        }
+        if let Some(sanity) = self.sanity {
+            config.sanity = sanity;
+        }
+        config.validate()?;
+        self.public_bytes.validate()?;
+        if self.public_bytes != PublicBytes::All && !config.instance_mode.exposes_input() {
+            return Err(Keccak256Error(format!(
+                "public_bytes = {:?} requires an instance mode exposing the inputs, got {:?}",
+                self.public_bytes, config.instance_mode
+            )));
+        }
+        if self.accumulator && config.num_input_columns() > 1 {
+            return Err(Keccak256Error(format!(
+                "accumulator requires a single input column, got input_columns = {}",
+                config.input_columns
+            )));
+        }
+        let fixed_instance = self
+            .fixed_instance
+            .unwrap_or(config.fixed_instance && self.capacity_rows.is_some());
+        if fixed_instance
+            && (self.capacity_rows.is_none()
+                || (self.public_bytes != PublicBytes::All && !config.selective_disclosure))
+        {
+            return Err(Keccak256Error(format!(
+                "fixed_instance requires capacity_rows, and public_bytes = All without selective_disclosure, \
+                 got {:?} and {:?}; set fixed_instance(false) for an instance laid out by input",
+                self.capacity_rows, self.public_bytes
+            )));
+        }
+        if config.selective_disclosure && !fixed_instance {
+            return Err(Keccak256Error(format!(
+                "selective_disclosure masks the fixed instance layout, it requires capacity_rows and fixed_instance, \
+                 got {:?} and {:?}",
+                self.capacity_rows, self.fixed_instance
+            )));
+        }
+        // Laid out by input, the instance of a circuit without inputs is bound to no row, and
+        // keys generated from it would take any digests
+        if !fixed_instance
+            && self.capacity_rows.is_some()
+            && self.inputs.is_empty()
+            && config.instance_mode != InstanceMode::None
+        {
+            return Err(Keccak256Error(format!(
+                "{:?} laid out by input needs the inputs the keys are for, or fixed_instance",
+                config.instance_mode
+            )));
+        }
+        // Without sanity checks a longer message is left to fail the max input len gate
+        if let Some(max_input_len) = config.max_input_len.filter(|_| config.sanity != SanityLevel::Off) {
+            if let Some((idx, input)) = self.inputs.iter().enumerate().find(|(_, input)| input.len() > max_input_len) {
+                return Err(Keccak256Error(format!(
+                    "input {idx} has {} bytes, more than max_input_len = {max_input_len}",
+                    input.len()
+                )));
+            }
+        }
+        if self.public_bytes != PublicBytes::All && config.input_binding != InputBinding::Word {
+            return Err(Keccak256Error(format!(
+                "public_bytes = {:?} is not supported with {:?} input binding",
+                self.public_bytes, config.input_binding
+            )));
+        }
+
+        let regions = match (self.capacity_rows, self.split_regions) {
+            (Some(capacity_rows), split_regions) => {
+                if capacity_rows > 1 << config.k {
+                    return Err(Keccak256Error(format!(
+                        "capacity_rows = {} exceeds the 2^{} rows of the circuit",
+                        capacity_rows, config.k
+                    )));
+                }
+                let capacity = (capacity_rows / config.rows_per_round)
+                    .checked_sub(1 + NUM_WORDS_TO_ABSORB)
+                    .map_or(0, |rounds| rounds / (NUM_ROUNDS + 1));
+                if split_regions {
+                    let num_regions = config.usable_rows() / capacity_rows;
+                    split_into_regions(&self.inputs, &config, capacity, num_regions).map_err(|e| {
+                        Keccak256Error(format!(
+                            "{}, with {} regions of capacity_rows = {} fitting {} keccak_f permutations each",
+                            e, num_regions, capacity_rows, capacity
+                        ))
+                    })?
+                } else {
+                    let required = self.inputs.iter().map(|input| config.num_keccak_f(input.len())).sum::<usize>();
+                    if required > capacity {
+                        return Err(Keccak256Error(format!(
+                            "inputs need {} keccak_f permutations, but capacity_rows = {} only fits {}",
+                            required, capacity_rows, capacity
+                        )));
+                    }
+                    vec![0..self.inputs.len()]
+                }
+            }
+            (None, true) => {
+                return Err(Keccak256Error(
+                    "split_regions requires capacity_rows to be set".to_string(),
+                ))
+            }
+            (None, false) => vec![0..self.inputs.len()],
+        };
+
+        Ok(KeccakCircuit {
+            config,
+            inputs: self.inputs,
+            num_rows: self.capacity_rows,
+            regions,
+            split_regions: self.split_regions,
+            public_bytes: self.public_bytes,
+            accumulator: self.accumulator,
+            fixed_instance,
+            deadline: self.deadline,
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// Greedily assigns consecutive inputs to `num_regions` regions fitting `capacity` keccak_f
+/// permutations each. Unused regions get an empty range.
+fn split_into_regions(
+    inputs: &[Vec<u8>],
+    config: &KeccakConfigParams,
+    capacity: usize,
+    num_regions: usize,
+) -> Result<Vec<Range<usize>>, String> {
+    if num_regions == 0 {
+        return Err("not even one region fits in the circuit".to_string());
+    }
+    let mut regions = Vec::with_capacity(num_regions);
+    let mut start = 0;
+    let mut used = 0;
+    for (idx, input) in inputs.iter().enumerate() {
+        let num_keccak_f = config.num_keccak_f(input.len());
+        if num_keccak_f > capacity {
+            return Err(format!(
+                "input {} needs {} keccak_f permutations and can't be split across regions",
+                idx, num_keccak_f
+            ));
+        }
+        if used + num_keccak_f > capacity {
+            regions.push(start..idx);
+            start = idx;
+            used = 0;
+        }
+        used += num_keccak_f;
+    }
+    if start < inputs.len() {
+        regions.push(start..inputs.len());
+    }
+    if regions.len() > num_regions {
+        return Err(format!("inputs need {} regions", regions.len()));
+    }
+    regions.resize(num_regions, inputs.len()..inputs.len());
+    Ok(regions)
+}
+
 impl<F: Field> Circuit<F> for KeccakCircuit<F> {
     type Config = CircuitConfig<F>;
     type FloorPlanner = SimpleFloorPlanner;
     type Params = KeccakConfigParams;
 
     fn without_witnesses(&self) -> Self {
-        Self::default()
+        Self {
+            config: self.config,
+            inputs: Arc::default(),
+            num_rows: self.num_rows,
+            regions: vec![0..0; self.regions.len()],
+            split_regions: self.split_regions,
+            public_bytes: self.public_bytes,
+            accumulator: self.accumulator,
+            fixed_instance: self.fixed_instance,
+            deadline: None,
+            _marker: PhantomData,
+        }
     }
 
     fn params(&self) -> Self::Params {
@@ -57,12 +496,26 @@ impl<F: Field> Circuit<F> for KeccakCircuit<F> {
         // MockProver complains if you only have columns in SecondPhase, so let's just make an empty column in FirstPhase
         meta.advice_column();
 
-        let input = meta.instance_column();
+        let inputs = (0..params.num_input_columns())
+            .map(|_| {
+                let input = meta.instance_column();
+                meta.enable_equality(input);
+                input
+            })
+            .collect();
+        let output = params.instance_mode.exposes_output().then(|| {
+            let output = meta.instance_column();
+            meta.enable_equality(output);
+            output
+        });
         let keccak_config = KeccakCircuitConfig::new(meta, params);
+        let disclosure = params.selective_disclosure.then(|| DisclosureConfig::configure(meta));
 
         CircuitConfig {
-            input,
+            inputs,
+            output,
             keccak_config,
+            disclosure,
             _marker: PhantomData,
         }
     }
@@ -77,243 +530,537 @@ impl<F: Field> Circuit<F> for KeccakCircuit<F> {
         mut layouter: impl Layouter<F>,
     ) -> Result<(), Error> {
         let params = config.keccak_config.parameters;
-        config
-            .keccak_config
-            .load_aux_tables(&mut layouter, params.k)?;
-        let mut first_pass = SKIP_FIRST_PASS;
-        let mut cache = vec![];
-        layouter.assign_region(
-            || "keccak circuit",
-            |mut region| {
-                if first_pass {
-                    first_pass = false;
-                    return Ok(());
+        config.keccak_config.load_aux_tables(&mut layouter)?;
+        // Regions are independent, so their witnesses are generated in parallel up front and
+        // only the assignment, which goes through the layouter, is sequential.
+        let capacity = self.num_rows.map(|nr| get_keccak_capacity(nr, params.rows_per_round));
+        let witnesses = self
+            .regions
+            .par_iter()
+            .map(|range| multi_keccak::<F, _>(&self.inputs[range.clone()], capacity, params).0)
+            .collect::<Vec<_>>();
+        if let Some(deadline) = &self.deadline {
+            deadline.check()?;
+        }
+
+        let slots = if self.fixed_instance { self.slots() } else { vec![] };
+        let mut instance_offset = if self.accumulator { ACCUMULATOR_INSTANCE_LEN } else { 0 };
+        let mut output_offset = 0;
+        for (region_idx, (range, witness)) in self.regions.iter().zip(witnesses).enumerate() {
+            let inputs = &self.inputs[range.clone()];
+            let mut first_pass = SKIP_FIRST_PASS;
+            let mut cache = vec![];
+            layouter.assign_region(
+                || format!("keccak circuit {}", region_idx),
+                |mut region| {
+                    // Split regions are placed one after the other, so their full height has to
+                    // be known from the first pass.
+                    let reserved_rows = self.num_rows.filter(|_| self.split_regions);
+                    if first_pass {
+                        first_pass = false;
+                        if let Some(num_rows) = reserved_rows {
+                            config.keccak_config.reserve_rows(&mut region, num_rows);
+                        }
+                        return Ok(());
+                    }
+                    let assigned_rows = config.keccak_config.assign(&mut region, &witness);
+                    if let Some(num_rows) = reserved_rows {
+                        config.keccak_config.reserve_rows(&mut region, num_rows);
+                    }
+                    cache.push(assigned_rows.clone());
+                    if self.config.sanity == SanityLevel::Full {
+                        // Exposed digests are bound to the instance, whose values are computed
+                        // natively, and the squeeze gate ties them to the permutation, so
+                        // recomputing them here would only repeat what the proof checks. With
+                        // modified round constants there is no native digest to compare to.
+                        let mut verified = self.verify_input_witnesses(&assigned_rows, inputs);
+                        if !self.config.instance_mode.exposes_output() && self.config.round_constants.is_none() {
+                            verified = verified.and_then(|_| self.verify_output_witnesses(&assigned_rows, inputs));
+                        }
+                        // halo2 errors carry no message, so the mismatch is logged
+                        verified.map_err(|e| {
+                            log::error!("{}", e);
+                            Error::Synthesis
+                        })?;
+                    }
+
+                    Ok(())
+                },
+            )?;
+
+            if self.fixed_instance {
+                for assigned_row in cache.iter() {
+                    (instance_offset, output_offset) = self.constraint_fixed_instance(
+                        layouter.namespace(|| "fixed instance"),
+                        assigned_row,
+                        &slots[region_idx],
+                        (instance_offset, output_offset),
+                        &config,
+                    )?;
                 }
-                let (witness, _) = multi_keccak(
-                    &self.inputs,
-                    self.num_rows
-                        .map(|nr| get_keccak_capacity(nr, params.rows_per_round)),
-                    params,
-                );
-                let assigned_rows = config.keccak_config.assign(&mut region, &witness);
-                cache.push(assigned_rows.clone());
-                if self.verify_output {
-                    self.verify_output_witnesses(&assigned_rows);
-                    self.verify_input_witnesses(&assigned_rows);
+                continue;
+            }
+            if self.config.instance_mode.exposes_input() {
+                for assigned_row in cache.iter() {
+                    instance_offset = self.constraint_public_inputs(
+                        layouter.namespace(|| "public inputs"),
+                        assigned_row,
+                        inputs,
+                        instance_offset,
+                        &config,
+                    )?;
+                }
+            }
+            if let Some(output) = config.output {
+                for assigned_row in cache.iter() {
+                    output_offset = self.constraint_public_outputs(
+                        layouter.namespace(|| "public outputs"),
+                        assigned_row,
+                        inputs,
+                        output_offset,
+                        output,
+                    )?;
                 }
-
-                Ok(())
-            },
-        )?;
-
-        if self.use_instance {
-            for assigned_row in cache.iter() {
-                self.constraint_public_inputs(
-                    layouter.namespace(|| "public inputs"),
-                    assigned_row,
-                    &config,
-                );
             }
         }
 
+        if let Some(deadline) = &self.deadline {
+            deadline.check()?;
+            *deadline.witness_done.lock().unwrap() = Some(Instant::now());
+        }
         Ok(())
     }
 }
 
 impl<F: Field> KeccakCircuit<F> {
-    /// Creates a new circuit instance
-    pub fn new(
-        config: KeccakConfigParams,
-        num_rows: Option<usize>,
-        inputs: Vec<Vec<u8>>,
-        verify_output: bool,
-        use_instance: bool,
-    ) -> Self {
-        KeccakCircuit {
-            config,
-            inputs,
-            num_rows,
-            _marker: PhantomData,
-            verify_output,
-            use_instance,
-        }
+    /// Returns a builder for the circuit
+    pub fn builder() -> KeccakCircuitBuilder<F> {
+        KeccakCircuitBuilder::default()
     }
 
-    fn verify_output_witnesses(&self, assigned_rows: &[KeccakAssignedRow<F>]) {
-        let mut input_offset = 0;
-        // only look at last row in each round
-        // first round is dummy, so ignore
-        // only look at last round per absorb of RATE_IN_BITS
-        for assigned_row in assigned_rows
-            .iter()
-            .step_by(self.config.rows_per_round)
-            .step_by(NUM_ROUNDS + 1)
-            .skip(1)
-        {
-            let KeccakAssignedRow {
-                is_final,
-                hash_lo,
-                hash_hi,
-                ..
-            } = assigned_row.clone();
-            let is_final_val = extract_value(is_final).ne(&F::ZERO);
-            let hash_lo_val = extract_u128(hash_lo);
-            let hash_hi_val = extract_u128(hash_hi);
-
-            if input_offset < self.inputs.len() && is_final_val {
+    /// Where every one of `inputs` lands in the trace of a region hashing them, in order, for
+    /// tooling navigating the assigned rows. Rows are relative to the start of the region.
+    pub fn schedule<B: AsRef<[u8]>>(&self, inputs: &[B]) -> Vec<HashSchedule> {
+        hash_schedules(
+            inputs.iter().map(|input| input.as_ref().len()),
+            self.config.rows_per_round,
+            self.config.digest_size,
+        )
+    }
+
+    fn absorb_schedule(&self, inputs: &[Vec<u8>]) -> AbsorbScheduleIter {
+        AbsorbScheduleIter::for_inputs(inputs, self.config.rows_per_round, self.config.digest_size)
+    }
+
+    fn verify_output_witnesses(
+        &self,
+        assigned_rows: &[KeccakAssignedRow<F>],
+        inputs: &[Vec<u8>],
+    ) -> Result<(), KeccakCircuitError> {
+        for step in self.absorb_schedule(inputs) {
+            if let AbsorbStep::Final { input, row } = step {
+                if extract_value(assigned_rows[row].is_final.clone())? == F::ZERO {
+                    return Err(mismatch(format!("is_final unset on row {} of input {}", row, input)));
+                }
                 // out is in big endian.
-                let out = Keccak256::digest(&self.inputs[input_offset]);
-                let lo = u128::from_be_bytes(out[16..].try_into().unwrap());
-                let hi = u128::from_be_bytes(out[..16].try_into().unwrap());
-                assert_eq!(lo, hash_lo_val);
-                assert_eq!(hi, hash_hi_val);
-                input_offset += 1;
+                let out = self.config.digest(&inputs[input]);
+                for (half, cell) in out.chunks(16).zip(assigned_rows[row].digest_cells()) {
+                    if u128::from_be_bytes(half.try_into().unwrap()) != extract_u128(cell.clone())? {
+                        return Err(mismatch(format!("digest of input {} on row {}", input, row)));
+                    }
+                }
             }
         }
+        Ok(())
+    }
+
+    fn constraint_public_outputs(
+        &self,
+        mut layouter: impl Layouter<F>,
+        assigned_rows: &[KeccakAssignedRow<F>],
+        inputs: &[Vec<u8>],
+        output_offset: usize,
+        output: Column<Instance>,
+    ) -> Result<usize, Error> {
+        let mut total_offset = output_offset;
+        for step in self.absorb_schedule(inputs) {
+            if let AbsorbStep::Final { row, .. } = step {
+                for cell in assigned_rows[row].digest_cells() {
+                    constrain_instance(&mut layouter, cell.cell(), output, total_offset)?;
+                    total_offset += 1;
+                }
+            }
+        }
+        Ok(total_offset)
     }
 
     fn constraint_public_inputs(
         &self,
         mut layouter: impl Layouter<F>,
         assigned_rows: &[KeccakAssignedRow<F>],
+        inputs: &[Vec<u8>],
+        instance_offset: usize,
         config: &<KeccakCircuit<F> as Circuit<F>>::Config,
-    ) {
+    ) -> Result<usize, Error> {
+        let mut total_offset = instance_offset;
+        for public_cell in self.public_input_cells(inputs) {
+            let cell = match public_cell {
+                PublicCell::WordValue(row) => &assigned_rows[row].word_value,
+                PublicCell::BytesLeft(row) => &assigned_rows[row].bytes_left,
+                PublicCell::InputChunk(row) => assigned_rows[row]
+                    .input_acc
+                    .as_ref()
+                    .expect("packed input bindings assign input_acc"),
+            };
+            let (column, row) = config.input_cell(total_offset);
+            constrain_instance(&mut layouter, cell.cell(), column, row)?;
+            total_offset += 1;
+        }
+        Ok(total_offset)
+    }
+
+    /// Binds the words and the digest of every keccak_f slot of a region with
+    /// [`fixed_instance`](KeccakCircuitBuilder::fixed_instance), whatever it hashes, the
+    /// `(input, block)` of every slot of the region telling which words it discloses with
+    /// [`selective_disclosure`](KeccakConfigParams::selective_disclosure). Returns the next
+    /// `(input, output)` offsets.
+    fn constraint_fixed_instance(
+        &self,
+        mut layouter: impl Layouter<F>,
+        assigned_rows: &[KeccakAssignedRow<F>],
+        slots: &[Option<(usize, usize)>],
+        (mut input_offset, mut output_offset): (usize, usize),
+        config: &<KeccakCircuit<F> as Circuit<F>>::Config,
+    ) -> Result<(usize, usize), Error> {
         let rows_per_round = self.config.rows_per_round;
-        let mut input_offset = 0;
-        let mut total_offset = 0;
-        let mut input_byte_offset = 0;
+        // first round is dummy
+        let start = |slot: usize| rows_per_round * (1 + slot * (NUM_ROUNDS + 1));
+        let disclosed = match &config.disclosure {
+            Some(disclosure) => {
+                let words = (0..slots.len())
+                    .map(|slot| {
+                        self.slot_words()
+                            .map(|word| assigned_rows[start(slot) + word * rows_per_round].word_value.clone())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+                let flags = slots.iter().map(|slot| self.slot_flags(*slot)).collect::<Vec<_>>();
+                Some(disclosure.assign_slots(layouter.namespace(|| "disclosure"), &words, &flags)?)
+            }
+            None => None,
+        };
+        for slot in 0..slots.len() {
+            let start = start(slot);
+            if let Some(disclosed) = &disclosed {
+                let disclosed = &disclosed[slot];
+                let header = [&assigned_rows[start].bytes_left, &disclosed.mask];
+                for cell in header.into_iter().chain(&disclosed.words) {
+                    let (column, row) = config.input_cell(input_offset);
+                    constrain_instance(&mut layouter, cell.cell(), column, row)?;
+                    input_offset += 1;
+                }
+            } else if self.config.instance_mode.exposes_input() {
+                for word in self.slot_words() {
+                    let row = &assigned_rows[start + word * rows_per_round];
+                    let cell = match self.config.input_binding {
+                        InputBinding::Word => &row.word_value,
+                        InputBinding::Packed | InputBinding::Bytes31 => {
+                            row.input_acc.as_ref().expect("packed input bindings assign input_acc")
+                        }
+                    };
+                    let (column, row) = config.input_cell(input_offset);
+                    constrain_instance(&mut layouter, cell.cell(), column, row)?;
+                    input_offset += 1;
+                }
+            }
+            if let Some(output) = config.output {
+                let row = &assigned_rows[start + NUM_ROUNDS * rows_per_round];
+                for cell in row.digest_cells() {
+                    constrain_instance(&mut layouter, cell.cell(), output, output_offset)?;
+                    output_offset += 1;
+                }
+            }
+        }
+        Ok((input_offset, output_offset))
+    }
 
-        // first round is dummy, so ignore
-        for absorb_chunk in &assigned_rows
-            .chunks(rows_per_round)
-            .skip(1)
-            .chunks(NUM_ROUNDS + 1)
-        {
-            let mut absorbed = false;
-            for (round_idx, assigned_rows) in absorb_chunk.enumerate() {
-                for (row_idx, assigned_row) in assigned_rows.iter().enumerate() {
-                    let KeccakAssignedRow {
-                        is_final,
-                        word_value,
-                        ..
-                    } = assigned_row.clone();
-                    let is_final_val = extract_value(is_final).ne(&F::ZERO);
+    /// The words of a keccak_f whose row holds an `input` cell with a fixed instance: every
+    /// word, or the [`input_cell_word`] of every cell with the packed input bindings.
+    fn slot_words(&self) -> impl Iterator<Item = usize> {
+        (0..self.config.digest_size.rate())
+            .step_by(self.config.input_binding.bytes_per_input_cell())
+            .map(input_cell_word)
+    }
 
-                    // If we reached to the end of this chunk, skip it
-                    if input_offset >= self.inputs.len() {
-                        continue;
-                    }
+    /// The disclosure flags of the words of a keccak_f slot hashing `(input, block)`, or of a
+    /// padding slot, see [`block_flags`].
+    fn slot_flags(&self, slot: Option<(usize, usize)>) -> Vec<bool> {
+        let (len, block) = slot.map_or((0, 0), |(input, block)| (self.inputs[input].len(), block));
+        block_flags(self.public_bytes, len, block, self.config.digest_size.rate())
+    }
 
-                    let input_len = self.inputs[input_offset].len();
+    /// The `(input, block)` hashed by every keccak_f slot of every region, `None` for the
+    /// padding slots. Only meaningful with `capacity_rows`.
+    fn slots(&self) -> Vec<Vec<Option<(usize, usize)>>> {
+        let capacity = self
+            .num_rows
+            .map_or(0, |num_rows| get_keccak_capacity(num_rows, self.config.rows_per_round));
+        self.regions
+            .iter()
+            .map(|range| {
+                let mut slots = range
+                    .clone()
+                    .flat_map(|input| {
+                        (0..self.config.num_keccak_f(self.inputs[input].len())).map(move |block| Some((input, block)))
+                    })
+                    .collect::<Vec<_>>();
+                slots.resize(capacity, None);
+                slots
+            })
+            .collect()
+    }
 
-                    if input_byte_offset >= input_len {
-                        continue;
-                    }
-                    if round_idx == NUM_ROUNDS && row_idx == 0 && is_final_val {
-                        absorbed = true;
-                    }
-                    if row_idx == 0 {
-                        // Only these rows could contain inputs.
-                        let end = if round_idx < NUM_WORDS_TO_ABSORB {
-                            std::cmp::min(input_byte_offset + NUM_BYTES_PER_WORD, input_len)
-                        } else {
-                            input_byte_offset
-                        };
-
-                        layouter
-                            .constrain_instance(word_value.cell(), config.input, total_offset)
-                            .unwrap();
-                        total_offset += 1;
-
-                        input_byte_offset = end;
+    /// The values of each instance column, without the accumulator cells: the `input` columns,
+    /// followed by the `output` column if the instance mode exposes the digests.
+    pub fn instances(&self) -> Vec<Vec<F>> {
+        let columns = if self.fixed_instance {
+            self.fixed_instance_columns()
+        } else {
+            instance_columns(&self.inputs, &self.config, self.public_bytes)
+        };
+        spread_input_column(columns, self.config.num_input_columns())
+    }
+
+    fn fixed_instance_columns(&self) -> Vec<Vec<F>> {
+        let num_cells = self.slot_words().count();
+        let rate = self.config.digest_size.rate();
+        let mut input = vec![];
+        let mut output = vec![];
+        for slot in self.slots().into_iter().flatten() {
+            let (words, digest) = match slot {
+                Some((idx, block)) => {
+                    let bytes = &self.inputs[idx];
+                    let block_bytes = bytes.chunks(rate).nth(block).unwrap_or_default().to_vec();
+                    let words = pack_input_cells_to_instance(
+                        &[block_bytes],
+                        self.config.input_binding.bytes_per_input_cell(),
+                        rate,
+                    );
+                    let digest = if block + 1 == self.config.num_keccak_f(bytes.len()) {
+                        pack_output_to_instance(&[bytes.clone()], &self.config)
+                    } else {
+                        vec![F::ZERO; self.config.digest_size.num_digest_cells()]
+                    };
+                    (words, digest)
+                }
+                None => (vec![], pack_output_to_instance(&[vec![]], &self.config)),
+            };
+            let words = words.into_iter().chain(std::iter::repeat(F::ZERO)).take(num_cells);
+            if self.config.selective_disclosure {
+                let flags = self.slot_flags(slot);
+                let bytes_left = slot.map_or(0, |(idx, block)| self.inputs[idx].len() - block * rate);
+                input.push(F::from(bytes_left as u64));
+                input.push(mask(&flags));
+                input.extend(words.zip(flags).map(|(word, flag)| if flag { word } else { F::ZERO }));
+            } else {
+                input.extend(words);
+            }
+            output.extend(digest);
+        }
+        let mut columns = vec![if self.config.instance_mode.exposes_input() { input } else { vec![] }];
+        if self.config.instance_mode.exposes_output() {
+            columns.push(output);
+        }
+        columns
+    }
+
+    /// The cells of a region hashing `inputs` that are exposed through the `input` column, in
+    /// instance order. [`instance_columns`] has to lay out the same cells on the verifier side.
+    pub(crate) fn public_input_cells(&self, inputs: &[Vec<u8>]) -> Vec<PublicCell> {
+        self.public_input_layout(inputs).into_iter().map(|(cell, _, _)| cell).collect()
+    }
+
+    /// The [`public_input_cells`](Self::public_input_cells), each with the input and the range
+    /// of its bytes the cell carries, empty for the number of public bytes.
+    fn public_input_layout(&self, inputs: &[Vec<u8>]) -> Vec<(PublicCell, usize, Range<usize>)> {
+        let mut cells = vec![];
+        for step in self.absorb_schedule(inputs) {
+            if let AbsorbStep::Word { input, row, byte_offset } = step {
+                let len = inputs[input].len();
+                if self.config.input_binding != InputBinding::Word {
+                    // The cells starting in this word, on the row of their `input_cell_word`
+                    let bytes_per_cell = self.config.input_binding.bytes_per_input_cell();
+                    let rate = self.config.digest_size.rate();
+                    let block_offset = byte_offset / rate * rate;
+                    let word = byte_offset % rate / NUM_BYTES_PER_WORD;
+                    for start in (0..rate).step_by(bytes_per_cell) {
+                        if start / NUM_BYTES_PER_WORD != word || block_offset + start >= len {
+                            continue;
+                        }
+                        let cell_row =
+                            row + (input_cell_word(start) - word) * self.config.rows_per_round;
+                        let end = len.min(block_offset + rate.min(start + bytes_per_cell));
+                        cells.push((PublicCell::InputChunk(cell_row), input, block_offset + start..end));
                     }
+                    continue;
+                }
+                let public = self.public_bytes.range(len);
+                if self.public_bytes.exposes_len() && byte_offset == public.start {
+                    cells.push((PublicCell::BytesLeft(row), input, byte_offset..byte_offset));
+                }
+                if public.contains(&byte_offset) || self.public_bytes.leading_range(len).contains(&byte_offset) {
+                    let end = len.min(byte_offset + NUM_BYTES_PER_WORD);
+                    cells.push((PublicCell::WordValue(row), input, byte_offset..end));
                 }
             }
-            if absorbed {
-                input_offset += 1;
-                input_byte_offset = 0;
+        }
+        cells
+    }
+
+    /// The row of the `input` instance cell carrying byte `byte_offset` of input `input_index`,
+    /// counting the accumulator cells, or `None` if the byte isn't public. With several input
+    /// columns this is the logical row, see [`KeccakConfigParams::num_input_columns`]. A cell holds a word,
+    /// or a chunk of words with packed input binding, little-endian.
+    pub fn instance_row_for(&self, input_index: usize, byte_offset: usize) -> Option<usize> {
+        if !self.config.instance_mode.exposes_input() {
+            return None;
+        }
+        let offset = if self.accumulator { ACCUMULATOR_INSTANCE_LEN } else { 0 };
+        if self.fixed_instance {
+            let len = self.inputs.get(input_index)?.len();
+            if byte_offset >= len {
+                return None;
+            }
+            let rate = self.config.digest_size.rate();
+            let slot = self
+                .slots()
+                .concat()
+                .iter()
+                .position(|slot| *slot == Some((input_index, byte_offset / rate)))?;
+            let cell = byte_offset % rate / self.config.input_binding.bytes_per_input_cell();
+            if !self.config.selective_disclosure {
+                return Some(offset + slot * self.slot_words().count() + cell);
             }
+            // Private words are zero in the instance
+            if !self.public_bytes.discloses(len, byte_offset) {
+                return None;
+            }
+            let stride = DISCLOSURE_HEADER_LEN + self.slot_words().count();
+            return Some(offset + slot * stride + DISCLOSURE_HEADER_LEN + cell);
         }
+        // Regions keep the inputs in order, so the instance is laid out as a single region's
+        self.public_input_layout(&self.inputs)
+            .into_iter()
+            .position(|(_, input, bytes)| input == input_index && bytes.contains(&byte_offset))
+            .map(|row| offset + row)
     }
 
-    fn verify_input_witnesses(&self, assigned_rows: &[KeccakAssignedRow<F>]) {
-        let rows_per_round = self.config.rows_per_round;
-        let mut input_offset = 0;
-        let mut input_byte_offset = 0;
+    /// Prover-side check of the words and `bytes_left` of every input. The `word_value`,
+    /// `bytes_left` and `padding` gates enforce the same relations, this only fails earlier
+    /// and with a readable message.
+    fn verify_input_witnesses(
+        &self,
+        assigned_rows: &[KeccakAssignedRow<F>],
+        inputs: &[Vec<u8>],
+    ) -> Result<(), KeccakCircuitError> {
         // first round is dummy, so ignore
-        for absorb_chunk in &assigned_rows
-            .chunks(rows_per_round)
-            .skip(1)
-            .chunks(NUM_ROUNDS + 1)
-        {
-            let mut absorbed = false;
-            for (round_idx, assigned_rows) in absorb_chunk.enumerate() {
-                for (row_idx, assigned_row) in assigned_rows.iter().enumerate() {
+        let mut end_row = self.config.rows_per_round;
+        for step in self.absorb_schedule(inputs) {
+            match step {
+                AbsorbStep::Word { input, row, byte_offset } => {
                     let KeccakAssignedRow {
-                        is_final,
                         word_value,
                         bytes_left,
                         ..
-                    } = assigned_row.clone();
-                    let is_final_val = extract_value(is_final).ne(&F::ZERO);
-                    let word_value_val = extract_u128(word_value);
-                    let bytes_left_val = extract_u128(bytes_left);
-                    // Padded inputs - all empty.
-                    if input_offset >= self.inputs.len() {
-                        assert_eq!(word_value_val, 0);
-                        assert_eq!(bytes_left_val, 0);
-                        continue;
-                    }
-                    let input_len = self.inputs[input_offset].len();
-                    if round_idx == NUM_ROUNDS && row_idx == 0 && is_final_val {
-                        absorbed = true;
+                    } = assigned_rows[row].clone();
+                    let idx = input;
+                    let input = &inputs[input];
+                    if extract_u128(bytes_left)? != input.len() as u128 - byte_offset as u128 {
+                        return Err(mismatch(format!("bytes_left of input {} at byte {}", idx, byte_offset)));
                     }
-                    if row_idx == 0 {
-                        assert_eq!(
-                            bytes_left_val,
-                            input_len as u128 - input_byte_offset as u128
-                        );
-                        // Only these rows could contain inputs.
-                        let end = if round_idx < NUM_WORDS_TO_ABSORB {
-                            std::cmp::min(input_byte_offset + NUM_BYTES_PER_WORD, input_len)
-                        } else {
-                            input_byte_offset
-                        };
-                        let mut expected_val_le_bytes = self.inputs[input_offset]
-                            [input_byte_offset..end]
-                            .to_vec()
-                            .clone();
-                        expected_val_le_bytes.resize(NUM_BYTES_PER_WORD, 0);
-                        assert_eq!(
-                            word_value_val,
-                            u64::from_le_bytes(expected_val_le_bytes.try_into().unwrap()) as u128,
-                        );
-                        input_byte_offset = end;
+                    let end = std::cmp::min(byte_offset + NUM_BYTES_PER_WORD, input.len());
+                    let mut expected_val_le_bytes = input[byte_offset..end].to_vec();
+                    expected_val_le_bytes.resize(NUM_BYTES_PER_WORD, 0);
+                    if extract_u128(word_value)? != u64::from_le_bytes(expected_val_le_bytes.try_into().unwrap()) as u128 {
+                        return Err(mismatch(format!("word_value of input {} at byte {}", idx, byte_offset)));
                     }
                 }
+                AbsorbStep::Final { row, .. } => end_row = row + self.config.rows_per_round,
             }
-            if absorbed {
-                input_offset += 1;
-                input_byte_offset = 0;
+        }
+        // Padded inputs - all empty, which the gates enforce as well.
+        for (row, assigned_row) in assigned_rows.iter().enumerate().skip(end_row) {
+            if extract_u128(assigned_row.word_value.clone())? != 0 || extract_u128(assigned_row.bytes_left.clone())? != 0 {
+                return Err(mismatch(format!("non-empty padding row {}", row)));
             }
         }
+        Ok(())
     }
 }
 
-fn extract_value<F: Field>(assigned_value: KeccakAssignedValue<F>) -> F {
-    let assigned = *value_to_option(assigned_value.value()).unwrap();
-    match assigned {
-        halo2_proofs::plonk::Assigned::Zero => F::ZERO,
-        halo2_proofs::plonk::Assigned::Trivial(f) => f,
-        _ => panic!("value should be trival"),
+fn mismatch(what: String) -> KeccakCircuitError {
+    KeccakCircuitError::WitnessMismatch(what)
+}
+
+/// A cell exposed through the `input` column, by its row in the region.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum PublicCell {
+    WordValue(usize),
+    BytesLeft(usize),
+    /// The accumulated chunk starting at the word of the row, with packed input binding.
+    InputChunk(usize),
+}
+
+/// The assigned cells of a single input, for host circuits wiring hashes together.
+#[derive(Clone)]
+pub struct InputCells<'v, F: Field> {
+    /// The `bytes_left` cell of the first absorb row, i.e. the length of the message in bytes,
+    /// which the gates tie to the absorbed words and padding.
+    pub length: KeccakAssignedValue<'v, F>,
+    /// The `word_value` cell of every absorbed word, in message order.
+    pub words: Vec<KeccakAssignedValue<'v, F>>,
+    pub hash_hi: KeccakAssignedValue<'v, F>,
+    pub hash_lo: KeccakAssignedValue<'v, F>,
+}
+
+/// Collects the cells of every input from the rows [`KeccakCircuitConfig::assign`] returns for
+/// a region hashing `inputs` into 256 bit digests, e.g. for a host circuit to range check or
+/// bind the length of a message. `PublicBytes::Suffix(0)` exposes the lengths through the
+/// instance instead.
+pub fn input_cells<'v, F: Field>(
+    assigned_rows: &[KeccakAssignedRow<'v, F>],
+    inputs: &[Vec<u8>],
+    rows_per_round: usize,
+) -> Vec<InputCells<'v, F>> {
+    let schedules = hash_schedules(inputs.iter().map(Vec::len), rows_per_round, DigestSize::Bits256);
+    let mut cells = Vec::with_capacity(inputs.len());
+    let mut words = vec![];
+    for step in AbsorbScheduleIter::for_inputs(inputs, rows_per_round, DigestSize::Bits256) {
+        match step {
+            AbsorbStep::Word { row, .. } => words.push(assigned_rows[row].word_value.clone()),
+            AbsorbStep::Final { input, row } => cells.push(InputCells {
+                length: assigned_rows[schedules[input].first_round_row].bytes_left.clone(),
+                words: std::mem::take(&mut words),
+                hash_hi: assigned_rows[row].hash_hi.clone(),
+                hash_lo: assigned_rows[row].hash_lo.clone(),
+            }),
+        }
     }
+    cells
+}
+
+fn extract_value<F: Field>(assigned_value: KeccakAssignedValue<F>) -> Result<F, KeccakCircuitError> {
+    value_to_option(assigned_value::assigned_value(&assigned_value))
+        .ok_or_else(|| mismatch("unknown value of an assigned cell".to_string()))
 }
 
-fn extract_u128<F: Field>(assigned_value: KeccakAssignedValue<F>) -> u128 {
-    let le_bytes = extract_value(assigned_value).to_bytes_le();
+fn extract_u128<F: Field>(assigned_value: KeccakAssignedValue<F>) -> Result<u128, KeccakCircuitError> {
+    let le_bytes = extract_value(assigned_value)?.to_bytes_le();
     let hi = u128::from_le_bytes(le_bytes[16..].try_into().unwrap());
-    assert_eq!(hi, 0);
-    u128::from_le_bytes(le_bytes[..16].try_into().unwrap())
+    if hi != 0 {
+        return Err(mismatch("assigned cell doesn't fit in 128 bits".to_string()));
+    }
+    Ok(u128::from_le_bytes(le_bytes[..16].try_into().unwrap()))
 }
 
 /// Packs each input byte array into field elements for use in cryptographic computations,
@@ -321,7 +1068,7 @@ fn extract_u128<F: Field>(assigned_value: KeccakAssignedValue<F>) -> u128 {
 /// Each high-level vector's bytes are combined into a single field element up to `NUM_BYTES_PER_WORD`.
 /// Bytes arrays shorter than `NUM_BYTES_PER_WORD` are zero-padded to this length.
 /// The field element is derived from these bytes interpreted as a little-endian u64.
-fn pack_input_to_instance<F: PrimeField>(input: &[Vec<u8>]) -> Vec<F> {
+pub(crate) fn pack_input_to_instance<F: PrimeField>(input: &[Vec<u8>]) -> Vec<F> {
     input
         .iter()
         .flat_map(|input_vec| {
@@ -335,89 +1082,404 @@ fn pack_input_to_instance<F: PrimeField>(input: &[Vec<u8>]) -> Vec<F> {
         .collect()
 }
 
+/// Packs the bytes of every keccak_f of every input, `rate` bytes each, in cells of
+/// `bytes_per_cell` bytes, each a little-endian field element, as constrained with
+/// [`InputBinding::bytes_per_input_cell`]. With a word per cell, this is
+/// [`pack_input_to_instance`].
+pub(crate) fn pack_input_cells_to_instance<F: PrimeField>(
+    inputs: &[Vec<u8>],
+    bytes_per_cell: usize,
+    rate: usize,
+) -> Vec<F> {
+    inputs
+        .iter()
+        .flat_map(|input| input.chunks(rate).flat_map(|block| block.chunks(bytes_per_cell)))
+        .map(|cell| {
+            cell.iter().rev().fold(F::ZERO, |acc, byte| acc * F::from(256) + F::from(*byte as u64))
+        })
+        .collect()
+}
+
+/// Number of `output` instance cells per 256 bit digest: its high and its low 128 bits. The
+/// digest of keccak_f slot `s` of the fixed instance layout is at rows
+/// `DIGEST_INSTANCE_STRIDE * s` and `DIGEST_INSTANCE_STRIDE * s + 1`, a message's on the slot
+/// it finishes on, see [`message_digest_from_instance`]. With other digest sizes the stride is
+/// [`DigestSize::num_digest_cells`].
+pub const DIGEST_INSTANCE_STRIDE: usize = DigestSize::Bits256.num_digest_cells();
+
+/// Number of limbs of every accumulator coordinate, snark-verifier's `LIMBS`.
+pub const ACCUMULATOR_LIMBS: usize = 3;
+
+/// Number of bits of every accumulator limb, snark-verifier's `BITS`.
+pub const ACCUMULATOR_LIMB_BITS: usize = 88;
+
+/// Number of `input` cells reserved by [`KeccakCircuitBuilder::accumulator`]: the limbs of the
+/// x and y coordinates of the two G1 points of the accumulator.
+pub const ACCUMULATOR_INSTANCE_LEN: usize = 4 * ACCUMULATOR_LIMBS;
+
+/// The `(column, row)` of every accumulator cell, what snark-verifier's
+/// `CircuitExt::accumulator_indices` returns for a circuit built with an accumulator.
+pub fn accumulator_indices() -> Vec<(usize, usize)> {
+    (0..ACCUMULATOR_INSTANCE_LEN).map(|row| (0, row)).collect()
+}
+
+/// Prepends the `accumulator` limbs to the `input` column of `instances`, for a circuit built
+/// with [`KeccakCircuitBuilder::accumulator`].
+pub fn with_accumulator<F: Field>(
+    mut instances: Vec<Vec<F>>,
+    accumulator: &[F],
+) -> Result<Vec<Vec<F>>, Keccak256Error> {
+    if accumulator.len() != ACCUMULATOR_INSTANCE_LEN {
+        return Err(Keccak256Error(format!(
+            "accumulator has {} limbs, expected {}",
+            accumulator.len(),
+            ACCUMULATOR_INSTANCE_LEN
+        )));
+    }
+    instances[0].splice(0..0, accumulator.iter().copied());
+    Ok(instances)
+}
+
+/// Reads the big-endian 256 bit digest at `slot` of the `output` instance column of a config
+/// with [`DigestSize::Bits256`], see [`digest_bytes_from_instance`] for the others. Slots are
+/// the keccak_f of the fixed instance layout, or the messages of a layout by input.
+pub fn digest_from_instance<F: Field>(output: &[F], slot: usize) -> Option<[u8; 32]> {
+    digest_bytes_from_instance(output, slot, DigestSize::Bits256)?.try_into().ok()
+}
+
+/// Reads the big-endian digest at `slot` of the `output` instance column of a config with
+/// `digest_size`, a digest every [`DigestSize::num_digest_cells`] cells.
+pub fn digest_bytes_from_instance<F: Field>(output: &[F], slot: usize, digest_size: DigestSize) -> Option<Vec<u8>> {
+    let stride = digest_size.num_digest_cells();
+    let cells = output.get(stride * slot..stride * (slot + 1))?;
+    let mut digest = vec![0u8; 16 * stride];
+    for (limb, cell) in digest.chunks_mut(16).zip(cells) {
+        let le_bytes = cell.to_bytes_le();
+        if le_bytes[16..].iter().any(|b| *b != 0) {
+            return None;
+        }
+        limb.copy_from_slice(&u128::from_le_bytes(le_bytes[..16].try_into().unwrap()).to_be_bytes());
+    }
+    Some(digest)
+}
+
+/// Reads the big-endian digest of message `i` of a proof of messages of `input_lengths` from
+/// its `output` instance column: at the keccak_f slot the message finishes on with the fixed
+/// instance layout of `config`, at slot `i` with a layout by input.
+pub fn message_digest_from_instance<F: Field>(
+    output: &[F],
+    input_lengths: &[usize],
+    i: usize,
+    config: &KeccakConfigParams,
+) -> Option<Vec<u8>> {
+    let slot = match config.fixed_instance {
+        true => *config.final_slots(input_lengths).get(i)?,
+        false => i,
+    };
+    digest_bytes_from_instance(output, slot, config.digest_size)
+}
+
+/// Lays out the digest of each input under the config's padding and digest size as field
+/// elements of 128 bits each, the most significant first, e.g. the high and the low 128 bits of
+/// a 256 bit big-endian digest.
+pub(crate) fn pack_output_to_instance<F: PrimeField>(inputs: &[Vec<u8>], config: &KeccakConfigParams) -> Vec<F> {
+    let digests = inputs.iter().map(|input| config.digest(input)).collect::<Vec<_>>();
+    pack_digests_to_instance(&digests)
+}
+
+/// Lays out big-endian digests as [`pack_output_to_instance`] does.
+pub(crate) fn pack_digests_to_instance<F: PrimeField, D: AsRef<[u8]>>(digests: &[D]) -> Vec<F> {
+    digests
+        .iter()
+        .flat_map(|digest| digest.as_ref().chunks(16))
+        .map(|half| F::from_u128(u128::from_be_bytes(half.try_into().unwrap())))
+        .collect()
+}
+
+/// The values of each instance column of the circuit proving `inputs` with `config`: the
+/// public input words, or their packed cells with the other input bindings, empty if not
+/// exposed, followed by the digests if exposed.
+pub(crate) fn instance_columns<F: PrimeField>(
+    inputs: &[Vec<u8>],
+    config: &KeccakConfigParams,
+    public_bytes: PublicBytes,
+) -> Vec<Vec<F>> {
+    let KeccakConfigParams { instance_mode, input_binding, .. } = *config;
+    let mut columns = vec![match (instance_mode.exposes_input(), input_binding) {
+        (false, _) => vec![],
+        (true, InputBinding::Packed | InputBinding::Bytes31) => pack_input_cells_to_instance(
+            inputs,
+            input_binding.bytes_per_input_cell(),
+            config.digest_size.rate(),
+        ),
+        (true, InputBinding::Word) => inputs
+            .iter()
+            .flat_map(|input| {
+                let leading = pack_input_to_instance(&[input[public_bytes.leading_range(input.len())].to_vec()]);
+                let public = public_bytes.range(input.len());
+                let len = (public_bytes.exposes_len() && !public.is_empty())
+                    .then(|| F::from(public.len() as u64));
+                leading.into_iter().chain(len).chain(pack_input_to_instance(&[input[public].to_vec()]))
+            })
+            .collect(),
+    }];
+    if instance_mode.exposes_output() {
+        columns.push(pack_output_to_instance(inputs, config));
+    }
+    columns
+}
+
+/// Spreads the single `input` column of `columns` over `num_input_columns` columns, logical
+/// row `r` landing in row `r / n` of column `r % n`.
+pub(crate) fn spread_input_column<F: Copy>(mut columns: Vec<Vec<F>>, num_input_columns: usize) -> Vec<Vec<F>> {
+    let input = columns.remove(0);
+    let mut inputs = vec![vec![]; num_input_columns];
+    for (row, value) in input.into_iter().enumerate() {
+        inputs[row % num_input_columns].push(value);
+    }
+    inputs.extend(columns);
+    inputs
+}
+
 /// Converts field elements to a vector of bytes.
-/// Currently converts each field element to a single byte.
+/// Currently converts each field element to a single byte, rejecting elements of 256 and up
+/// rather than hashing their low byte.
 /// TODO - optimize by packing multiple bytes into field elements
-fn unpack_input<F: Field>(instance: &[F]) -> Vec<u8> {
+fn unpack_input<F: Field>(instance: &[F]) -> Result<Vec<u8>, KeccakCircuitError> {
     instance
         .iter()
-        .map(|x| x.to_bytes_le()[0])
-        .collect::<Vec<u8>>()
+        .enumerate()
+        .map(|(idx, x)| {
+            let le_bytes = x.to_bytes_le();
+            if le_bytes[1..].iter().any(|b| *b != 0) {
+                return Err(KeccakCircuitError::NotAByte { index: idx });
+            }
+            Ok(le_bytes[0])
+        })
+        .collect()
 }
 
 
-pub(crate) fn generate_halo2_proof(
-    inputs: HashMap<String, Vec<Fr>>,
-    srs: &ParamsKZG<Bn256>,
-    pk: &ProvingKey<G1Affine>,
+/// Engine used when none is specified.
+pub type DefaultEngine = Bn256;
+
+pub(crate) fn generate_halo2_proof<E>(
+    inputs: HashMap<String, Vec<E::Scalar>>,
+    srs: &ParamsKZG<E>,
+    pk: &ProvingKey<E::G1Affine>,
     config: Option<KeccakConfigParams>,
-) -> Result<(Vec<Fr>, Vec<u8>), String> {
+) -> Result<(Vec<E::Scalar>, Vec<u8>), KeccakCircuitError>
+where
+    E: MultiMillerLoop + Debug,
+    E::Scalar: Field + Ord + FromUniformBytes<64> + WithSmallOrderMulGroup<3>,
+    E::G1Affine: SerdeObject,
+    E::G2Affine: SerdeObject,
+{
     // Get inputs by name "input" from the inputs hashmap
-    let raw_inputs = inputs
-        .get("in")
-        .ok_or("`in` value not found in proof input".to_string())?;
+    let raw_inputs = inputs.get("in").ok_or(KeccakCircuitError::MissingInput("in"))?;
 
     // Convert the raw inputs to a vector of u8
     // TODO - can be optimized by packing multiple bytes into field elements
-    let inputs = vec![unpack_input(raw_inputs)];
+    let inputs = vec![unpack_input(raw_inputs)?];
+
+    // This interface has a single instance vector, so the columns are concatenated
+    let (instances, proof) =
+        generate_halo2_proof_for_inputs(inputs, srs, pk, config.unwrap_or(DEFAULT_CONFIG))?;
+    Ok((instances.concat(), proof))
+}
 
-    let instance = pack_input_to_instance::<Fr>(&inputs);
+/// Proves the keccak hashes of the raw byte `inputs`, returning the instance columns and the
+/// proof.
+pub(crate) fn generate_halo2_proof_for_inputs<E>(
+    inputs: Vec<Vec<u8>>,
+    srs: &ParamsKZG<E>,
+    pk: &ProvingKey<E::G1Affine>,
+    config: KeccakConfigParams,
+) -> Result<(Vec<Vec<E::Scalar>>, Vec<u8>), KeccakCircuitError>
+where
+    E: MultiMillerLoop + Debug,
+    E::Scalar: Field + Ord + FromUniformBytes<64> + WithSmallOrderMulGroup<3>,
+    E::G1Affine: SerdeObject,
+    E::G2Affine: SerdeObject,
+{
+    let writer = Vec::with_capacity(config.estimate_proof_size());
+    write_halo2_proof_for_inputs(inputs, srs, pk, config, PublicBytes::All, writer, None)
+}
+
+/// Proves the keccak hashes of the raw byte `inputs`, exposing their `public_bytes`, streaming
+/// the proof to `writer` as the transcript is written, and aborting once `deadline` has passed,
+/// before the proof or within its synthesis. Returns the instance columns and the writer.
+pub(crate) fn write_halo2_proof_for_inputs<E, W>(
+    inputs: Vec<Vec<u8>>,
+    srs: &ParamsKZG<E>,
+    pk: &ProvingKey<E::G1Affine>,
+    config: KeccakConfigParams,
+    public_bytes: PublicBytes,
+    writer: W,
+    deadline: Option<Deadline>,
+) -> Result<(Vec<Vec<E::Scalar>>, W), KeccakCircuitError>
+where
+    E: MultiMillerLoop + Debug,
+    E::Scalar: Field + Ord + FromUniformBytes<64> + WithSmallOrderMulGroup<3>,
+    E::G1Affine: SerdeObject,
+    E::G2Affine: SerdeObject,
+    W: Write,
+{
+    // The keys are generated without inputs, so they only bind the inputs with a fixed layout
+    config.check_fixed_instance()?;
 
-    let config = config.unwrap_or(DEFAULT_CONFIG);
     // Set up the circuit
-    let circuit = KeccakCircuit::new(
-        config,
-        Some(2usize.pow(config.k)),
-        inputs,
-        true, // Prover side-check to verify the circuit correctly computes the hash
-        true, // Use the instance column for the input
-    );
-
-    let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
-
-    create_proof::<
-        KZGCommitmentScheme<Bn256>,
-        ProverSHPLONK<'_, Bn256>,
-        Challenge255<G1Affine>,
-        _,
-        Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>,
-        _,
-    >(
-        &srs,
-        &pk,
-        &[circuit],
-        &[&[&instance[..]]],
-        thread_rng(),
-        &mut transcript,
-    )
-        .unwrap();
-
-    let proof = transcript.finalize();
-    Ok((instance, proof))
-}
-
-
-pub(crate) fn verify_halo2_proof(
+    let mut builder = KeccakCircuit::<E::Scalar>::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k))
+        .public_bytes(public_bytes)
+        .inputs(inputs);
+    if let Some(deadline) = deadline.clone() {
+        builder = builder.deadline(deadline);
+    }
+    let circuit = builder.build()?;
+    let instances = circuit.instances();
+    let instance_refs = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    if deadline.as_ref().map_or(false, Deadline::expired) {
+        return Err(KeccakCircuitError::DeadlinePassed);
+    }
+
+    let writer = match config.transcript {
+        TranscriptKind::Blake2b => KzgShplonk::<E>::prove_to_writer_with_transcript::<
+            _,
+            Blake2bWrite<W, E::G1Affine, Challenge255<E::G1Affine>>,
+        >(srs, pk, circuit, &instance_refs, writer),
+        TranscriptKind::Keccak256 => KzgShplonk::<E>::prove_to_writer_with_transcript::<
+            _,
+            Keccak256Write<W, E::G1Affine, Challenge255<E::G1Affine>>,
+        >(srs, pk, circuit, &instance_refs, writer),
+    }?;
+    Ok((instances, writer))
+}
+
+
+/// Verifies a proof from [`generate_halo2_proof`], whose instance holds the concatenated
+/// columns for a single message.
+pub(crate) fn verify_halo2_proof<E>(
     proof: Vec<u8>,
-    inputs: &Vec<Fr>,
-    srs: &ParamsKZG<Bn256>,
-    vk: &VerifyingKey<G1Affine>,
-) -> Result<bool, ()> {
-    let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(&proof[..]);
-    let proof_verified = verify_proof::<
-        KZGCommitmentScheme<Bn256>,
-        VerifierSHPLONK<'_, Bn256>,
-        Challenge255<G1Affine>,
-        Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
-        _,
-    >(
-        srs.verifier_params(),
-        &vk,
-        SingleStrategy::new(&srs),
-        &[&[&inputs[..]]],
-        &mut transcript,
-    )
-        .is_ok();
+    inputs: &Vec<E::Scalar>,
+    srs: &ParamsKZG<E>,
+    vk: &VerifyingKey<E::G1Affine>,
+    config: KeccakConfigParams,
+) -> Result<bool, KeccakCircuitError>
+where
+    E: MultiMillerLoop + Debug,
+    E::Scalar: Field + Ord + FromUniformBytes<64> + WithSmallOrderMulGroup<3>,
+    E::G1Affine: SerdeObject,
+    E::G2Affine: SerdeObject,
+{
+    config.check_fixed_instance()?;
+    // The fixed layout sizes every column from the parameters alone
+    let lengths = KeccakCircuit::<E::Scalar>::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k))
+        .build()?
+        .instances()
+        .iter()
+        .map(Vec::len)
+        .collect::<Vec<_>>();
+    let expected = lengths.iter().sum::<usize>();
+    if inputs.len() < expected {
+        return Err(KeccakCircuitError::InstanceTooShort { expected, got: inputs.len() });
+    }
+    if inputs.len() > expected {
+        return Err(KeccakCircuitError::InstanceTooLong { expected, got: inputs.len() });
+    }
+    let mut rest = &inputs[..];
+    let instances = lengths
+        .iter()
+        .map(|len| {
+            let (column, tail) = rest.split_at(*len);
+            rest = tail;
+            column
+        })
+        .collect::<Vec<_>>();
+
+    let proof_verified = match config.transcript {
+        TranscriptKind::Blake2b => KzgShplonk::<E>::verify_with_transcript::<
+            Blake2bTranscriptRead<E::G1Affine>,
+        >(srs, vk, &proof, &instances),
+        TranscriptKind::Keccak256 => KzgShplonk::<E>::verify_with_transcript::<
+            KeccakTranscriptRead<E::G1Affine>,
+        >(srs, vk, &proof, &instances),
+    }
+    .is_ok();
+    Ok(proof_verified)
+}
+
+/// Verifies a proof of a circuit in [`InstanceMode::Output`] against the digests it should
+/// expose, in input order, building the fixed `output` instance from them: the digest of an
+/// input sits at the slot of its last keccak_f, found from `input_lengths`. Fails if the config
+/// exposes anything else or isn't laid out by slot, as keys generated without inputs don't
+/// bind the digests of an instance laid out by input.
+pub fn verify_digest<E>(
+    proof: &[u8],
+    input_lengths: &[usize],
+    expected_digests: &[[u8; 32]],
+    srs: &ParamsKZG<E>,
+    vk: &VerifyingKey<E::G1Affine>,
+    config: KeccakConfigParams,
+) -> Result<bool, Keccak256Error>
+where
+    E: MultiMillerLoop + Debug,
+    E::Scalar: Field + Ord + FromUniformBytes<64> + WithSmallOrderMulGroup<3>,
+    E::G1Affine: SerdeObject,
+    E::G2Affine: SerdeObject,
+{
+    if config.instance_mode != InstanceMode::Output {
+        return Err(Keccak256Error(format!(
+            "verify_digest requires the Output instance mode, got {:?}",
+            config.instance_mode
+        )));
+    }
+    if config.digest_size != DigestSize::Bits256 {
+        return Err(Keccak256Error(format!(
+            "verify_digest takes 256 bit digests, got a config with {:?}",
+            config.digest_size
+        )));
+    }
+    config.check_fixed_instance()?;
+    if input_lengths.len() != expected_digests.len() {
+        return Err(Keccak256Error(format!(
+            "{} input lengths for {} digests",
+            input_lengths.len(),
+            expected_digests.len()
+        )));
+    }
+    // Starting from the empty circuit, whose slots all hash the empty input
+    let mut columns = KeccakCircuit::<E::Scalar>::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k))
+        .build()?
+        .instances();
+    let output = columns.last_mut().expect("the Output instance mode has an output column");
+    let num_slots = output.len() / DIGEST_INSTANCE_STRIDE;
+    let final_slots = config.final_slots(input_lengths);
+    let end = final_slots.last().map_or(0, |slot| slot + 1);
+    if end > num_slots {
+        return Err(Keccak256Error(format!("the inputs exceed the {num_slots} keccak_f of the layout")));
+    }
+    // Slots not finishing a message expose zero
+    output[..end * DIGEST_INSTANCE_STRIDE].fill(E::Scalar::ZERO);
+    for (slot, digest) in final_slots.into_iter().zip(expected_digests) {
+        output[slot * DIGEST_INSTANCE_STRIDE..(slot + 1) * DIGEST_INSTANCE_STRIDE]
+            .copy_from_slice(&pack_digests_to_instance::<E::Scalar>(&[*digest]));
+    }
+    let instances = columns.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let proof_verified = match config.transcript {
+        TranscriptKind::Blake2b => KzgShplonk::<E>::verify_with_transcript::<
+            Blake2bTranscriptRead<E::G1Affine>,
+        >(srs, vk, proof, &instances),
+        TranscriptKind::Keccak256 => KzgShplonk::<E>::verify_with_transcript::<
+            KeccakTranscriptRead<E::G1Affine>,
+        >(srs, vk, proof, &instances),
+    }
+    .is_ok();
     Ok(proof_verified)
 }
 
@@ -433,8 +1495,17 @@ mod test {
     use rand_core::OsRng;
     use test_case::test_case;
 
-    use crate::{DEFAULT_CONFIG, KeccakCircuit};
-    use crate::circuit::{generate_halo2_proof, pack_input_to_instance, unpack_input, verify_halo2_proof};
+    use crate::{
+        DigestSize, InstanceMode, KeccakCircuit, KeccakCircuitError, KeccakConfigParams, SanityLevel, DEFAULT_CONFIG,
+    };
+    use crate::proof_system::{KzgShplonk, ProofSystem};
+    use sha3::{Digest, Keccak256};
+
+    use crate::circuit::{
+        digest_bytes_from_instance, digest_from_instance, generate_halo2_proof, message_digest_from_instance, generate_halo2_proof_for_inputs, pack_input_to_instance,
+        pack_output_to_instance,
+        unpack_input, verify_digest, verify_halo2_proof, DIGEST_INSTANCE_STRIDE,
+    };
 
     #[test_case(vec ! [0u8, 151u8, 200u8, 255u8]; "4 Different Elements")]
     #[test_case(vec ! []; "Empty case")]
@@ -446,10 +1517,19 @@ mod test {
             .collect::<Vec<Fr>>();
 
         // Convert the field elements back to bytes
-        let output = unpack_input(&f_input);
+        let output = unpack_input(&f_input).unwrap();
         assert_eq!(input, output);
     }
 
+    #[test_case(Fr::from(256); "Just above a byte")]
+    #[test_case(-Fr::from(1); "Low byte 0")]
+    fn test_unpack_input_rejects_non_bytes(element: Fr) {
+        assert!(matches!(
+            unpack_input(&[Fr::from(7), element]),
+            Err(KeccakCircuitError::NotAByte { index: 1 })
+        ));
+    }
+
     #[test_case(vec ! [0u8, 0u8, 0u8, 0u8], vec ! [Fr::from(0u64)]; "Zero to Zero")]
     #[test_case(
         vec ! [1u8, 0u8, 0u8, 0u8, 1u8, 0u8, 0u8, 0u8], vec ! [Fr::from(4294967297u64)]; "Max size single element"
@@ -487,13 +1567,12 @@ mod test {
         );
 
         // Generate the keys
-        let circuit = KeccakCircuit::new(
-            config,
-            Some(2usize.pow(config.k)),
-            vec![],
-            false,
-            false,
-        );
+        let circuit = KeccakCircuit::builder()
+            .config(config)
+            .capacity_rows(2usize.pow(config.k))
+            .sanity(SanityLevel::Off)
+            .build()
+            .unwrap();
 
         let vk = keygen_vk(&srs, &circuit).unwrap();
         let pk = keygen_pk(&srs, vk.clone(), &circuit).unwrap();
@@ -525,13 +1604,12 @@ mod test {
         );
 
         // Generate the keys
-        let circuit = KeccakCircuit::new(
-            config,
-            Some(2usize.pow(config.k)),
-            vec![],
-            false,
-            false,
-        );
+        let circuit = KeccakCircuit::builder()
+            .config(config)
+            .capacity_rows(2usize.pow(config.k))
+            .sanity(SanityLevel::Off)
+            .build()
+            .unwrap();
 
         let vk = keygen_vk(&srs, &circuit).unwrap();
         let pk = keygen_pk(&srs, vk.clone(), &circuit).unwrap();
@@ -540,9 +1618,108 @@ mod test {
             .map_err(|_| "Failed to prove")
             .unwrap();
         let verifier_srs: ParamsVerifierKZG<Bn256> = srs.verifier_params().clone();
-        let result = verify_halo2_proof(proof, &public_input, &verifier_srs, &vk)
+        let result = verify_halo2_proof(proof.clone(), &public_input, &verifier_srs, &vk, config)
             .map_err(|_| "Failed to verify")
             .unwrap();
         assert!(result, "Proof verification failed");
+
+        let mut too_long = public_input.clone();
+        too_long.push(Fr::from(0));
+        match verify_halo2_proof(proof.clone(), &too_long, &verifier_srs, &vk, config) {
+            Err(KeccakCircuitError::InstanceTooLong { expected, got }) => {
+                assert_eq!(expected, public_input.len());
+                assert_eq!(got, public_input.len() + 1);
+            }
+            other => panic!("expected InstanceTooLong, got {:?}", other.map_err(|e| e.to_string())),
+        }
+
+        // Keys generated without inputs don't bind an instance laid out by input
+        let by_input = KeccakConfigParams { fixed_instance: false, ..config };
+        assert!(verify_halo2_proof(proof, &public_input, &verifier_srs, &vk, by_input).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_verify_digest() {
+        let config = KeccakConfigParams {
+            k: 12,
+            rows_per_round: 5,
+            instance_mode: InstanceMode::Output,
+            ..Default::default()
+        };
+        let inputs = vec![b"abc".to_vec(), vec![]];
+        let srs = ParamsKZG::<Bn256>::setup(config.k, OsRng);
+        let circuit = KeccakCircuit::builder()
+            .config(config)
+            .capacity_rows(2usize.pow(config.k))
+            .build()
+            .unwrap();
+        let vk = keygen_vk(&srs, &circuit).unwrap();
+        let pk = keygen_pk(&srs, vk.clone(), &circuit).unwrap();
+        let (instances, proof) = generate_halo2_proof_for_inputs(inputs.clone(), &srs, &pk, config).unwrap();
+
+        let lengths = inputs.iter().map(Vec::len).collect::<Vec<_>>();
+        let mut digests = inputs.iter().map(|input| Keccak256::digest(input).into()).collect::<Vec<[u8; 32]>>();
+        assert!(verify_digest(&proof, &lengths, &digests, &srs, &vk, config).unwrap());
+        assert!(!verify_digest(&proof, &[200, 0], &digests, &srs, &vk, config).unwrap());
+        assert!(verify_digest(&proof, &[3], &digests, &srs, &vk, config).is_err());
+        digests.swap(0, 1);
+        assert!(!verify_digest(&proof, &lengths, &digests, &srs, &vk, config).unwrap());
+        digests.swap(0, 1);
+        let input_mode = KeccakConfigParams { instance_mode: InstanceMode::Input, ..config };
+        assert!(verify_digest(&proof, &lengths, &digests, &srs, &vk, input_mode).is_err());
+        let by_input = KeccakConfigParams { fixed_instance: false, ..config };
+        assert!(verify_digest(&proof, &lengths, &digests, &srs, &vk, by_input).is_err());
+
+        // A proof hashing other messages of the same lengths, with the digests forced into its instance
+        let forged = KeccakCircuit::builder()
+            .config(config)
+            .capacity_rows(2usize.pow(config.k))
+            .inputs(vec![b"abd".to_vec(), vec![]])
+            .build()
+            .unwrap();
+        let instance_refs = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let forged_proof = KzgShplonk::<Bn256>::prove(&srs, &pk, forged, &instance_refs).unwrap();
+        assert!(!verify_digest(&forged_proof, &lengths, &digests, &srs, &vk, config).unwrap());
+    }
+
+    #[test]
+    fn test_digest_from_instance() {
+        let inputs = vec![vec![], vec![1u8; 300], vec![7u8]];
+        let output = pack_output_to_instance::<Fr>(&inputs, &DEFAULT_CONFIG);
+        assert_eq!(output.len(), DIGEST_INSTANCE_STRIDE * inputs.len());
+        for (i, input) in inputs.iter().enumerate() {
+            let expected: [u8; 32] = Keccak256::digest(input).into();
+            assert_eq!(digest_from_instance(&output, i), Some(expected));
+        }
+        assert_eq!(digest_from_instance(&output, inputs.len()), None);
+
+        let config = KeccakConfigParams { digest_size: DigestSize::Bits512, ..DEFAULT_CONFIG };
+        let output = pack_output_to_instance::<Fr>(&inputs, &config);
+        for (i, input) in inputs.iter().enumerate() {
+            assert_eq!(digest_bytes_from_instance(&output, i, config.digest_size), Some(config.digest(input)));
+        }
+    }
+
+    #[test]
+    fn test_message_digest_from_instance() {
+        let config =
+            KeccakConfigParams { k: 12, rows_per_round: 5, instance_mode: InstanceMode::Output, ..Default::default() };
+        // Message 1 spans three keccak_f, so message 2 finishes on slot 4
+        let inputs = vec![vec![], vec![1u8; 300], vec![7u8]];
+        let lengths = inputs.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(config.final_slots(&lengths), vec![0, 3, 4]);
+        let circuit = KeccakCircuit::<Fr>::builder()
+            .config(config)
+            .capacity_rows(2usize.pow(config.k))
+            .inputs(inputs.clone())
+            .build()
+            .unwrap();
+        let instances = circuit.instances();
+        let output = instances.last().unwrap();
+        for (i, input) in inputs.iter().enumerate() {
+            assert_eq!(message_digest_from_instance(output, &lengths, i, &config), Some(config.digest(input)));
+        }
+        assert_eq!(digest_from_instance(output, 2), Some([0; 32]));
+        assert_eq!(message_digest_from_instance(output, &lengths, inputs.len(), &config), None);
+    }
+}
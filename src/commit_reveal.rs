@@ -0,0 +1,188 @@
+//! Commit–reveal statements on top of the keccak circuit, for `commitment = keccak(value || salt)`.
+//!
+//! At commit time only the commitment is public, through [`InstanceMode::Output`]. At reveal
+//! time `value` is exposed as well, as the [`PublicBytes::Prefix`] of the input, while `salt`
+//! stays private. Words are exposed whole, so the length of `value` has to be a multiple of
+//! `NUM_BYTES_PER_WORD` for a word never to mix value and salt bytes.
+//!
+//! Both circuits lay the instance out with
+//! [`fixed_instance`](crate::KeccakCircuitBuilder::fixed_instance), the reveal one masking the
+//! salt with [`selective_disclosure`](KeccakConfigParams::selective_disclosure), so keys
+//! generated without inputs for [`commit_config`] or [`reveal_config`] serve every opening. The
+//! verifier learns the length of the preimage, and the slot of its digest, either way.
+
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::PublicBytes;
+use crate::disclosure::{disclosure_config, masked_instances};
+use crate::util::eth_types::Field;
+use crate::{InstanceMode, Keccak256Error, KeccakCircuit, KeccakCircuitBuilder, KeccakConfigParams};
+
+/// The private witness of a commitment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitReveal {
+    pub value: Vec<u8>,
+    pub salt: Vec<u8>,
+}
+
+impl CommitReveal {
+    pub fn new(value: Vec<u8>, salt: Vec<u8>) -> Self {
+        CommitReveal { value, salt }
+    }
+
+    /// `value || salt`, the message hashed by the circuit.
+    pub fn preimage(&self) -> Vec<u8> {
+        [self.value.as_slice(), self.salt.as_slice()].concat()
+    }
+
+    /// The big-endian commitment `keccak(value || salt)`.
+    pub fn commitment(&self) -> [u8; 32] {
+        Keccak256::digest(self.preimage()).into()
+    }
+
+    /// Builder of the circuit proving knowledge of an opening of the commitment, with both
+    /// `value` and `salt` private, under the [`commit_config`] of `config`. Needs
+    /// `capacity_rows`, whose keys bind openings of any length.
+    pub fn commit_builder<F: Field>(&self, config: KeccakConfigParams) -> KeccakCircuitBuilder<F> {
+        KeccakCircuit::builder().config(commit_config(config)).inputs(vec![self.preimage()])
+    }
+
+    /// Builder of the circuit opening the commitment to `value`, keeping `salt` private, under
+    /// the [`reveal_config`] of `config`. As with [`Self::commit_builder`], it needs
+    /// `capacity_rows`, and one key serves every opening.
+    pub fn reveal_builder<F: Field>(&self, config: KeccakConfigParams) -> KeccakCircuitBuilder<F> {
+        KeccakCircuit::builder()
+            .config(reveal_config(config))
+            .inputs(vec![self.preimage()])
+            .public_bytes(PublicBytes::Prefix(self.value.len()))
+    }
+}
+
+/// `config` exposing the commitment alone, with the fixed instance layout.
+pub fn commit_config(config: KeccakConfigParams) -> KeccakConfigParams {
+    KeccakConfigParams {
+        instance_mode: InstanceMode::Output,
+        fixed_instance: true,
+        selective_disclosure: false,
+        ..config
+    }
+}
+
+/// `config` exposing the value and the commitment, with the fixed instance layout masking the
+/// salt, its [`disclosure_config`].
+pub fn reveal_config(config: KeccakConfigParams) -> KeccakConfigParams {
+    disclosure_config(config)
+}
+
+/// The instance columns of the circuit from [`CommitReveal::commit_builder`] with `config` and
+/// `capacity_rows`, for a preimage of `preimage_len` bytes.
+pub fn commit_instances<F: Field>(
+    config: KeccakConfigParams,
+    capacity_rows: usize,
+    preimage_len: usize,
+    commitment: [u8; 32],
+) -> Result<Vec<Vec<F>>, Keccak256Error> {
+    let preimage = vec![0; preimage_len];
+    masked_instances(commit_config(config), capacity_rows, PublicBytes::All, vec![preimage], &[commitment.to_vec()])
+}
+
+/// The instance columns of the circuit from [`CommitReveal::reveal_builder`] with `config` and
+/// `capacity_rows`, computed from the revealed `value`, the length of the salt and the
+/// commitment only.
+pub fn reveal_instances<F: Field>(
+    config: KeccakConfigParams,
+    capacity_rows: usize,
+    value: &[u8],
+    salt_len: usize,
+    commitment: [u8; 32],
+) -> Result<Vec<Vec<F>>, Keccak256Error> {
+    let preimage = [value, &vec![0; salt_len]].concat();
+    masked_instances(
+        reveal_config(config),
+        capacity_rows,
+        PublicBytes::Prefix(value.len()),
+        vec![preimage],
+        &[commitment.to_vec()],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::{Bn256, Fr};
+    use halo2_proofs::plonk::{keygen_pk, keygen_vk};
+    use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::{verify_keccak, KeccakProver};
+
+    const CONFIG: KeccakConfigParams = KeccakConfigParams { k: 12, rows_per_round: 5, ..crate::DEFAULT_CONFIG };
+    const CAPACITY_ROWS: usize = (1 << CONFIG.k) - 109;
+
+    fn run(builder: KeccakCircuitBuilder<Fr>, instances: Vec<Vec<Fr>>) -> bool {
+        let circuit = builder.capacity_rows(CAPACITY_ROWS).build().unwrap();
+        MockProver::run(CONFIG.k, &circuit, instances).unwrap().verify().is_ok()
+    }
+
+    #[test]
+    fn test_commit_reveal() {
+        let opening = CommitReveal::new(b"vote:yes".repeat(2), b"some private salt".to_vec());
+        let commitment = opening.commitment();
+        let len = opening.preimage().len();
+        let salt_len = opening.salt.len();
+
+        let commit = |commitment| commit_instances(CONFIG, CAPACITY_ROWS, len, commitment).unwrap();
+        let reveal = |value: &[u8]| reveal_instances(CONFIG, CAPACITY_ROWS, value, salt_len, commitment).unwrap();
+        assert!(run(opening.commit_builder(CONFIG), commit(commitment)));
+        assert!(run(opening.reveal_builder(CONFIG), reveal(&opening.value)));
+
+        let other = b"vote:no!".repeat(2);
+        assert!(!run(opening.reveal_builder(CONFIG), reveal(&other)));
+        assert!(!run(opening.commit_builder(CONFIG), commit(Keccak256::digest(&other).into())));
+    }
+
+    #[test]
+    fn test_reveal_proof() {
+        let config = reveal_config(CONFIG);
+        let srs = ParamsKZG::<Bn256>::setup(config.k, OsRng);
+        let keygen = KeccakCircuit::<Fr>::builder().config(config).capacity_rows(1 << config.k).build().unwrap();
+        let vk = keygen_vk(&srs, &keygen).unwrap();
+        let pk = keygen_pk(&srs, vk.clone(), &keygen).unwrap();
+
+        let opening = CommitReveal::new(b"vote:yes".repeat(2), b"some private salt".to_vec());
+        let prover = KeccakProver::new(srs.clone(), pk, config)
+            .with_public_bytes(PublicBytes::Prefix(opening.value.len()));
+        let (instances, proof) = prover.prove(vec![opening.preimage()]).unwrap();
+        let reveal = |value: &[u8], salt_len| {
+            reveal_instances::<Fr>(CONFIG, 1 << CONFIG.k, value, salt_len, opening.commitment()).unwrap()
+        };
+        let expected = reveal(&opening.value, opening.salt.len());
+        assert_eq!(instances, expected);
+        assert!(verify_keccak(&proof, &expected, &srs, &vk, config).is_ok());
+        // The salt is not in the instance
+        let salt_word = Fr::from(u64::from_le_bytes(opening.salt[..8].try_into().unwrap()));
+        assert!(!instances[0].contains(&salt_word));
+
+        assert!(verify_keccak(&proof, &reveal(&b"vote:no!".repeat(2), opening.salt.len()), &srs, &vk, config).is_err());
+        assert!(verify_keccak(&proof, &reveal(&opening.value, opening.salt.len() + 8), &srs, &vk, config).is_err());
+        // The word after the value can't be passed off as private
+        let shorter = reveal_instances::<Fr>(CONFIG, 1 << CONFIG.k, &opening.value[..8], 25, opening.commitment());
+        assert!(verify_keccak(&proof, &shorter.unwrap(), &srs, &vk, config).is_err());
+
+        // The same keys prove an opening of another length
+        let longer = CommitReveal::new(b"vote:abstain".repeat(2), vec![7; 200]);
+        let prover = prover.with_public_bytes(PublicBytes::Prefix(longer.value.len()));
+        let (_, proof) = prover.prove(vec![longer.preimage()]).unwrap();
+        let expected =
+            reveal_instances::<Fr>(CONFIG, 1 << CONFIG.k, &longer.value, longer.salt.len(), longer.commitment());
+        assert!(verify_keccak(&proof, &expected.unwrap(), &srs, &vk, config).is_ok());
+    }
+
+    #[test]
+    fn test_unaligned_value() {
+        let opening = CommitReveal::new(b"yes".to_vec(), b"salt".to_vec());
+        assert!(opening.reveal_builder::<Fr>(CONFIG).capacity_rows(CAPACITY_ROWS).build().is_err());
+        assert!(reveal_instances::<Fr>(CONFIG, CAPACITY_ROWS, &opening.value, 4, opening.commitment()).is_err());
+    }
+}
@@ -0,0 +1,944 @@
+//! Deployment level configuration of the keccak circuit, loadable from a TOML file with
+//! environment variable overrides.
+//!
+//! ```toml
+//! k = 14
+//! rows_per_round = 28
+//! instance_mode = "input"
+//! transcript = "blake2b"
+//! sanity = "full"
+//! max_degree = 0
+//! lookup_layout = "split"
+//! input_binding = "word"
+//! padding = "keccak"
+//! digest_size = "bits256"
+//! fixed_instance = true
+//! selective_disclosure = false
+//! ```
+//!
+//! `max_input_len = 1024` additionally bounds the length of every message in the circuit.
+//!
+//! Every key but `round_constants` can be overridden by the upper-cased variable with a `KECCAK_` prefix, e.g.
+//! `KECCAK_K=16` or `KECCAK_INSTANCE_MODE=none`.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use halo2_proofs::plonk::{Circuit, ConstraintSystem};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256, Keccak512, Sha3_256, Sha3_512};
+
+use crate::vanilla::param::{
+    CHI_BASE_LOOKUP_TABLE, NUM_BYTES_PER_INPUT_ELEMENT, NUM_BYTES_PER_WORD, NUM_ROUNDS,
+    NUM_WORDS_PER_INPUT_CHUNK, NUM_WORDS_TO_ABSORB, NUM_WORDS_TO_ABSORB_512, NUM_WORDS_TO_SQUEEZE,
+    NUM_WORDS_TO_SQUEEZE_512, ROUND_CST,
+};
+use crate::vanilla::padding::{DomainPadding, CSHAKE_PADDING, KECCAK_PADDING, SHA3_PADDING};
+use crate::vanilla::table::get_num_bits_per_lookup;
+use crate::{Keccak256Error, KeccakCircuit, KeccakConfigParams, DEFAULT_CONFIG};
+
+/// Environment variable prefix for configuration overrides.
+pub const ENV_PREFIX: &str = "KECCAK_";
+
+/// Largest circuit degree considered when suggesting a valid `k`.
+const MAX_SUGGESTED_K: u32 = 28;
+
+/// Number of rounds a single keccak_f occupies, including the dummy first round and the
+/// `NUM_WORDS_TO_ABSORB` rounds queried ahead of the absorb row.
+const MIN_ROUNDS: usize = 1 + NUM_WORDS_TO_ABSORB + NUM_ROUNDS + 1;
+
+lazy_static! {
+    /// [`KeccakConfigParams::unusable_rows`] by config fingerprint. Every circuit build
+    /// validates its config, and the suggestions of `validate` try many nearby ones, each of
+    /// which would otherwise configure a whole constraint system.
+    static ref UNUSABLE_ROWS: Mutex<HashMap<[u8; 32], usize>> = Mutex::new(HashMap::new());
+}
+
+/// Which values of the circuit are exposed through the instance column.
+///
+/// There is deliberately no mode exposing an RLC of the inputs under a second phase challenge:
+/// the instance is absorbed into the transcript before any challenge is squeezed, so the prover
+/// would have to know the challenge before it exists, and an RLC under a challenge supplied
+/// through the instance is not binding.
+///
+/// halo2 fixes the copy constraints to the instance at keygen. Laid out by input, i.e. without
+/// [`fixed_instance`](KeccakConfigParams::fixed_instance), the instance cells are bound to the
+/// rows of the messages the keys were generated with, so the keys only bind messages of those
+/// lengths, and keys generated without inputs bind none at all. The proving and verification
+/// functions of the crate only take configs passing
+/// [`check_fixed_instance`](KeccakConfigParams::check_fixed_instance).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceMode {
+    /// Nothing is exposed.
+    None,
+    /// Every absorbed input word is exposed.
+    #[default]
+    Input,
+    /// Every absorbed input word is exposed, and the digests through a separate column. The
+    /// digests are then only checked through the instance, as for [`InstanceMode::Output`].
+    InputOutput,
+    /// Only the digests are exposed, the inputs stay private. The prover no longer recomputes
+    /// the exposed digests natively, so nothing but the copy constraints to the `output` column
+    /// ties them to the hashed messages: their keys have to be generated with the fixed layout,
+    /// or with messages of the very lengths proven.
+    Output,
+}
+
+impl InstanceMode {
+    /// Whether the input words are exposed through the `input` instance column.
+    pub fn exposes_input(&self) -> bool {
+        matches!(self, InstanceMode::Input | InstanceMode::InputOutput)
+    }
+
+    /// Whether the digests are exposed through the `output` instance column.
+    pub fn exposes_output(&self) -> bool {
+        matches!(self, InstanceMode::InputOutput | InstanceMode::Output)
+    }
+}
+
+/// Transcript used to make the proof non-interactive.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptKind {
+    #[default]
+    Blake2b,
+    /// Cheaper to verify in the EVM.
+    Keccak256,
+}
+
+/// How much the prover checks its own witness before proving.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SanityLevel {
+    /// No prover side checks.
+    Off,
+    /// Compare the assigned inputs against the raw inputs, and recompute the digests natively
+    /// unless the instance mode exposes them.
+    #[default]
+    Full,
+}
+
+/// How the fixed lookup tables of the keccak_f are laid out.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LookupLayout {
+    /// Every table in its own pair of table columns.
+    #[default]
+    Split,
+    /// All tables in a single set of table columns, told apart by a tag column. Saves 7 fixed
+    /// columns, and with them verifying key size, but the tables share the rows, so each looks up
+    /// fewer bits at a time and the keccak_f needs more advice cells.
+    Tagged,
+}
+
+/// How the absorbed words are bound to the `input` instance column.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputBinding {
+    /// One copy constraint per word.
+    #[default]
+    Word,
+    /// The words of every keccak_f are accumulated in chunks of `NUM_WORDS_PER_INPUT_CHUNK`,
+    /// and one copy constraint binds each chunk. Costs an advice and a fixed column, but cuts
+    /// the instance, and with it the verifier's work and calldata, about threefold. Only
+    /// supports exposing the whole inputs.
+    Packed,
+    /// The bytes of every keccak_f are split in elements of `NUM_BYTES_PER_INPUT_ELEMENT`
+    /// bytes, decomposed in-circuit into the absorbed bytes, and one copy constraint binds each
+    /// element. Costs an advice column, and cuts the instance about fourfold. Only supports
+    /// exposing the whole inputs.
+    Bytes31,
+}
+
+impl InputBinding {
+    /// Number of message bytes carried by a single `input` instance cell, the last cell of
+    /// every keccak_f holding the rest of its `RATE` bytes.
+    pub fn bytes_per_input_cell(self) -> usize {
+        match self {
+            InputBinding::Word => NUM_BYTES_PER_WORD,
+            InputBinding::Packed => NUM_WORDS_PER_INPUT_CHUNK * NUM_BYTES_PER_WORD,
+            InputBinding::Bytes31 => NUM_BYTES_PER_INPUT_ELEMENT,
+        }
+    }
+}
+
+/// The padding of the messages, which picks the hash function the circuit proves. Both pad
+/// with `pad10*1` and share the keccak_f permutation.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Padding {
+    /// Keccak-256, as used by Ethereum.
+    #[default]
+    Keccak,
+    /// The NIST SHA3-256, whose padding starts with the `01` domain separation bits.
+    Sha3,
+    /// The NIST cSHAKE256 with a 256 bit output, whose padding starts with the `00` domain
+    /// separation bits. Messages start with their customization block, see [`crate::kmac`].
+    #[serde(rename = "cshake")]
+    CShake,
+}
+
+impl Padding {
+    /// The digest of `input` under this padding, big-endian.
+    pub fn digest(self, input: &[u8]) -> [u8; 32] {
+        match self {
+            Padding::Keccak => Keccak256::digest(input).into(),
+            Padding::Sha3 => Sha3_256::digest(input).into(),
+            Padding::CShake => {
+                CSHAKE_PADDING.digest(input, DigestSize::Bits256.rate(), 32).try_into().unwrap()
+            }
+        }
+    }
+
+    /// The padding rule of the witness generation.
+    pub(crate) fn rule(self) -> DomainPadding {
+        match self {
+            Padding::Keccak => KECCAK_PADDING,
+            Padding::Sha3 => SHA3_PADDING,
+            Padding::CShake => CSHAKE_PADDING,
+        }
+    }
+}
+
+/// The size of the digests, which sets the rate of the sponge: the capacity is twice the
+/// digest, so 512 bit digests absorb fewer words per keccak_f. Together with [`Padding`] it
+/// picks Keccak-256, SHA3-256, Keccak-512 or SHA3-512.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestSize {
+    /// 256 bit digests, exposed as a high and a low 128 bit cell.
+    #[default]
+    Bits256,
+    /// 512 bit digests, exposed as four 128 bit cells, most significant first. Only supports
+    /// `InputBinding::Word`, with Keccak or SHA3 padding.
+    Bits512,
+}
+
+impl DigestSize {
+    /// Number of words absorbed per keccak_f.
+    pub fn words_to_absorb(self) -> usize {
+        match self {
+            DigestSize::Bits256 => NUM_WORDS_TO_ABSORB,
+            DigestSize::Bits512 => NUM_WORDS_TO_ABSORB_512,
+        }
+    }
+
+    /// Number of message bytes absorbed per keccak_f.
+    pub fn rate(self) -> usize {
+        self.words_to_absorb() * NUM_BYTES_PER_WORD
+    }
+
+    /// Number of words squeezed out as the digest.
+    pub const fn words_to_squeeze(self) -> usize {
+        match self {
+            DigestSize::Bits256 => NUM_WORDS_TO_SQUEEZE,
+            DigestSize::Bits512 => NUM_WORDS_TO_SQUEEZE_512,
+        }
+    }
+
+    /// Number of 128 bit `output` instance cells per digest.
+    pub const fn num_digest_cells(self) -> usize {
+        self.words_to_squeeze() * NUM_BYTES_PER_WORD / 16
+    }
+}
+
+/// The FFTs and MSMs a single proof of a config costs, as counted by
+/// [`KeccakConfigParams::estimate_prover_cost`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProverCost {
+    /// Size of the domain, `2^k`.
+    pub fft_size: usize,
+    /// Size of the extended domain the quotient is evaluated over.
+    pub extended_fft_size: usize,
+    /// FFTs over the domain, turning the committed polynomials into coefficients.
+    pub ffts: usize,
+    /// FFTs over the extended domain, moving the witness polynomials to the coset and the
+    /// quotient back.
+    pub extended_ffts: usize,
+    /// MSMs of `fft_size` points, one per commitment.
+    pub msms: usize,
+}
+
+/// `k` is lowered by this much when sizing the tables of the tagged layout, so that the four
+/// large tables and the pack table fit together.
+const TAGGED_LOOKUP_K_REDUCTION: u32 = 3;
+
+macro_rules! impl_from_str {
+    ($ty:ty, $($name:literal => $variant:expr),+) => {
+        impl FromStr for $ty {
+            type Err = Keccak256Error;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s.to_ascii_lowercase().as_str() {
+                    $($name => Ok($variant),)+
+                    _ => Err(Keccak256Error(format!(
+                        "Unknown {} `{}`, expected one of: {}",
+                        stringify!($ty),
+                        s,
+                        [$($name),+].join(", ")
+                    ))),
+                }
+            }
+        }
+    };
+}
+
+impl_from_str!(
+    InstanceMode,
+    "none" => InstanceMode::None,
+    "input" => InstanceMode::Input,
+    "input_output" => InstanceMode::InputOutput,
+    "output" => InstanceMode::Output
+);
+impl_from_str!(
+    TranscriptKind,
+    "blake2b" => TranscriptKind::Blake2b,
+    "keccak256" => TranscriptKind::Keccak256
+);
+impl_from_str!(SanityLevel, "off" => SanityLevel::Off, "full" => SanityLevel::Full);
+impl_from_str!(LookupLayout, "split" => LookupLayout::Split, "tagged" => LookupLayout::Tagged);
+impl_from_str!(
+    InputBinding,
+    "word" => InputBinding::Word,
+    "packed" => InputBinding::Packed,
+    "bytes31" => InputBinding::Bytes31
+);
+impl_from_str!(Padding, "keccak" => Padding::Keccak, "sha3" => Padding::Sha3, "cshake" => Padding::CShake);
+impl_from_str!(DigestSize, "bits256" => DigestSize::Bits256, "bits512" => DigestSize::Bits512);
+
+impl Default for KeccakConfigParams {
+    fn default() -> Self {
+        DEFAULT_CONFIG
+    }
+}
+
+impl KeccakConfigParams {
+    /// Loads the parameters from a TOML file, applies the `KECCAK_*` environment overrides and
+    /// validates the result.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Keccak256Error> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            Keccak256Error(format!("Failed to read config file {}: {}", path.display(), e))
+        })?;
+        let config = Self::parse_toml(&contents)?.with_env_overrides()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parses and validates the parameters from a TOML string. Missing keys take their value
+    /// in [`DEFAULT_CONFIG`].
+    pub fn from_toml_str(contents: &str) -> Result<Self, Keccak256Error> {
+        let config = Self::parse_toml(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn parse_toml(contents: &str) -> Result<Self, Keccak256Error> {
+        toml::from_str(contents)
+            .map_err(|e| Keccak256Error(format!("Failed to parse config: {}", e)))
+    }
+
+    /// Applies the `KECCAK_*` environment variable overrides.
+    pub fn with_env_overrides(self) -> Result<Self, Keccak256Error> {
+        self.with_overrides(|key| std::env::var(format!("{ENV_PREFIX}{key}")).ok())
+    }
+
+    /// Checks that the parameters describe a circuit that can be built, i.e. that at least one
+    /// keccak_f and all lookup tables fit in the usable rows. The error suggests the nearest
+    /// valid `k` and `rows_per_round`.
+    pub fn validate(&self) -> Result<(), Keccak256Error> {
+        if self.rows_per_round == 0 {
+            return Err(Keccak256Error(
+                "rows_per_round must be at least 1, the default is 28".to_string(),
+            ));
+        }
+        if self.round_constants.is_some() && self.instance_mode.exposes_output() {
+            return Err(Keccak256Error(format!(
+                "Invalid keccak config: the digests of modified round constants can't be computed natively, use an instance mode not exposing them instead of {:?}",
+                self.instance_mode
+            )));
+        }
+        if self.digest_size != DigestSize::Bits256 && self.input_binding != InputBinding::Word {
+            return Err(Keccak256Error(format!(
+                "Invalid keccak config: {:?} input binding is only supported with 256 bit digests",
+                self.input_binding
+            )));
+        }
+        if self.digest_size == DigestSize::Bits512 && self.padding == Padding::CShake {
+            return Err(Keccak256Error(
+                "Invalid keccak config: cSHAKE256 absorbs at the 256 bit rate, 512 bit digests are only supported with Keccak or SHA3 padding".to_string(),
+            ));
+        }
+        if self.selective_disclosure
+            && (!self.fixed_instance || !self.instance_mode.exposes_input() || self.input_binding != InputBinding::Word)
+        {
+            return Err(Keccak256Error(format!(
+                "Invalid keccak config: selective_disclosure masks the words of the fixed instance layout, it needs fixed_instance, an instance mode exposing the inputs and word input binding, got {:?}, {:?} and {:?}",
+                self.fixed_instance, self.instance_mode, self.input_binding
+            )));
+        }
+        self.check_degree()?;
+        self.check_rows().map_err(|reason| {
+            let min_k = (self.k + 1..=MAX_SUGGESTED_K)
+                .find(|&k| KeccakConfigParams { k, ..*self }.check_rows().is_ok());
+            let max_rows_per_round = (1..self.rows_per_round)
+                .rev()
+                .find(|&rows_per_round| {
+                    KeccakConfigParams { rows_per_round, ..*self }.check_rows().is_ok()
+                });
+            let suggestion = match (min_k, max_rows_per_round) {
+                (Some(k), Some(rows_per_round)) => format!(
+                    "use k >= {k}, or rows_per_round <= {rows_per_round} at k = {}",
+                    self.k
+                ),
+                (Some(k), None) => format!("use k >= {k}"),
+                (None, Some(rows_per_round)) => {
+                    format!("use rows_per_round <= {rows_per_round}")
+                }
+                (None, None) => "no nearby valid configuration was found".to_string(),
+            };
+            Keccak256Error(format!("Invalid keccak config: {reason}; {suggestion}"))
+        })
+    }
+
+    /// The iota round constants of every round including the absorb round, for which it is
+    /// zero: the override if set, the standard ones otherwise.
+    pub fn round_cst_table(&self) -> [u64; NUM_ROUNDS + 1] {
+        match self.round_constants {
+            Some(constants) => array_init::array_init(|round| constants.get(round).copied().unwrap_or(0)),
+            None => ROUND_CST,
+        }
+    }
+
+    /// The digest of `input` under the config's padding and digest size, big-endian.
+    pub fn digest(&self, input: &[u8]) -> Vec<u8> {
+        match (self.digest_size, self.padding) {
+            (DigestSize::Bits256, padding) => padding.digest(input).to_vec(),
+            (DigestSize::Bits512, Padding::Keccak) => Keccak512::digest(input).to_vec(),
+            (DigestSize::Bits512, Padding::Sha3) => Sha3_512::digest(input).to_vec(),
+            (DigestSize::Bits512, Padding::CShake) => CSHAKE_PADDING.digest(input, DigestSize::Bits512.rate(), 64),
+        }
+    }
+
+    /// Number of keccak_f permutations hashing a message of `len` bytes, including the
+    /// padding, at the config's rate.
+    pub fn num_keccak_f(&self, len: usize) -> usize {
+        len / self.digest_size.rate() + 1
+    }
+
+    /// The keccak_f slot every message of `lengths` finishes on, in message order: where the
+    /// fixed instance layout exposes its digest.
+    pub fn final_slots(&self, lengths: &[usize]) -> Vec<usize> {
+        lengths
+            .iter()
+            .scan(0, |num_keccak_f, len| {
+                *num_keccak_f += self.num_keccak_f(*len);
+                Some(*num_keccak_f - 1)
+            })
+            .collect()
+    }
+
+    /// Number of byte cells `max_input_len - bytes_left` is decomposed into on every input
+    /// round, enough for `max_input_len` itself, none without a bound. A `bytes_left` above the
+    /// bound wraps around the field and doesn't fit them.
+    pub(crate) fn num_input_len_bytes(&self) -> usize {
+        match self.max_input_len {
+            Some(max_input_len) => (((usize::BITS - max_input_len.leading_zeros()) as usize + 7) / 8).max(1),
+            None => 0,
+        }
+    }
+
+    /// Number of `input` instance columns. Logical input row `r`, counting the accumulator
+    /// cells, is row `r / n` of column `r % n`, so the columns fill up evenly.
+    pub fn num_input_columns(&self) -> usize {
+        self.input_columns.max(1)
+    }
+
+    /// Checks that keys generated for the config without inputs bind the instance of every
+    /// proof, i.e. that it exposes nothing or lays the instance out with
+    /// [`fixed_instance`](Self::fixed_instance). Keys of a layout by input only bind messages
+    /// of the lengths they were generated with, which the verifier can't tell from the keys.
+    pub fn check_fixed_instance(&self) -> Result<(), Keccak256Error> {
+        if self.instance_mode != InstanceMode::None && !self.fixed_instance {
+            return Err(Keccak256Error(format!(
+                "{:?} without fixed_instance is laid out by input, its keys only bind messages of the keygen lengths",
+                self.instance_mode
+            )));
+        }
+        Ok(())
+    }
+
+    /// The `k` the lookup tables are sized for, i.e. the part sizes are derived from.
+    pub(crate) fn lookup_k(&self) -> u32 {
+        match self.lookup_layout {
+            LookupLayout::Split => self.k,
+            LookupLayout::Tagged => self.k.saturating_sub(TAGGED_LOOKUP_K_REDUCTION),
+        }
+    }
+
+    /// The number of rows usable for witnesses, i.e. without the blinding rows.
+    pub(crate) fn usable_rows(&self) -> usize {
+        (1usize << self.k).saturating_sub(self.unusable_rows())
+    }
+
+    /// The number of rows at the end of the circuit reserved by the backend for blinding.
+    ///
+    /// Both backends derive it from the largest number of rotations any advice column is
+    /// queried at and offer no way to lower it, so there is no non zero-knowledge mode that
+    /// reclaims these rows. Fewer `rows_per_round` means fewer rotations per column and hence
+    /// fewer blinding rows.
+    pub fn unusable_rows(&self) -> usize {
+        let fingerprint = self.fingerprint();
+        if let Some(unusable_rows) = UNUSABLE_ROWS.lock().unwrap().get(&fingerprint) {
+            return *unusable_rows;
+        }
+        let mut meta = ConstraintSystem::<Fr>::default();
+        KeccakCircuit::<Fr>::configure_with_params(&mut meta, *self);
+        let unusable_rows = meta.blinding_factors() + 1;
+        UNUSABLE_ROWS.lock().unwrap().insert(fingerprint, unusable_rows);
+        unusable_rows
+    }
+
+    /// The degree of the constraint system, i.e. of its highest degree gate or argument.
+    pub fn degree(&self) -> usize {
+        let mut meta = ConstraintSystem::<Fr>::default();
+        KeccakCircuit::<Fr>::configure_with_params(&mut meta, *self);
+        meta.degree()
+    }
+
+    /// Keccak256 of the constraint system: its columns, gates, lookups and permutation, but not
+    /// the fixed values nor the crate version. Unlike [`fingerprint`](Self::fingerprint) it only
+    /// changes with the constraints, which `tests/constraint_system.rs` pins for a few configs.
+    pub fn constraint_system_fingerprint(&self) -> [u8; 32] {
+        let mut meta = ConstraintSystem::<Fr>::default();
+        KeccakCircuit::<Fr>::configure_with_params(&mut meta, *self);
+        Keccak256::digest(format!("{:?}", meta.pinned())).into()
+    }
+
+    /// Fingerprint of the parameters that shape the proving and verifying keys, together with
+    /// the crate version. Keys are only valid for the config with the same fingerprint.
+    /// `transcript`, `sanity` and `max_degree` leave the keys unchanged and are not included.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let mut hasher = Keccak256::new();
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.update(self.k.to_le_bytes());
+        hasher.update((self.rows_per_round as u64).to_le_bytes());
+        hasher.update([self.instance_mode as u8]);
+        hasher.update([self.lookup_layout as u8]);
+        hasher.update([self.input_binding as u8]);
+        // Only hashed when set, so the fingerprints of existing single column keys still match
+        if self.num_input_columns() > 1 {
+            hasher.update((self.num_input_columns() as u64).to_le_bytes());
+        }
+        if let Some(constants) = self.round_constants {
+            constants.iter().for_each(|constant| hasher.update(constant.to_le_bytes()));
+        }
+        if self.padding != Padding::Keccak {
+            hasher.update([self.padding as u8]);
+        }
+        if self.digest_size != DigestSize::Bits256 {
+            // Tagged, so that it can't be mistaken for the padding byte
+            hasher.update(b"digest_size");
+            hasher.update([self.digest_size as u8]);
+        }
+        if let Some(max_input_len) = self.max_input_len {
+            hasher.update(b"max_input_len");
+            hasher.update((max_input_len as u64).to_le_bytes());
+        }
+        // Keys laid out by input are the ones from before the fixed layout was the default
+        if self.fixed_instance {
+            hasher.update(b"fixed_instance");
+        }
+        if self.selective_disclosure {
+            hasher.update(b"selective_disclosure");
+        }
+        hasher.finalize().into()
+    }
+
+    /// An estimate in bytes of the size of a KZG SHPLONK proof, with compressed 32 byte points:
+    /// the commitments to the advice, lookup, permutation and quotient polynomials, then the
+    /// evaluations at every queried rotation. Used to preallocate the transcript buffer.
+    pub fn estimate_proof_size(&self) -> usize {
+        let mut meta = ConstraintSystem::<Fr>::default();
+        KeccakCircuit::<Fr>::configure_with_params(&mut meta, *self);
+        let num_lookups = meta.lookups().len();
+        let chunk_len = (meta.degree() - 2).max(1);
+        let permutation_columns = meta.permutation().get_columns().len();
+        let permutation_chunks = (permutation_columns + chunk_len - 1) / chunk_len;
+
+        let commitments = meta.num_advice_columns()
+            + 3 * num_lookups
+            + permutation_chunks
+            // The quotient pieces and the random polynomial
+            + meta.degree() - 1 + 1
+            // The SHPLONK opening
+            + 2;
+        let evaluations = meta.advice_queries().len()
+            + meta.fixed_queries().len()
+            + permutation_columns
+            + 3 * permutation_chunks
+            + 5 * num_lookups
+            + 1;
+        (commitments + evaluations) * 32
+    }
+
+    /// The FFTs and MSMs of proving this config, counted like [`Self::estimate_proof_size`]
+    /// from the shape of the constraint system. The fixed and permutation polynomials come
+    /// precomputed with the proving key and cost nothing per proof.
+    pub fn estimate_prover_cost(&self) -> ProverCost {
+        let mut meta = ConstraintSystem::<Fr>::default();
+        KeccakCircuit::<Fr>::configure_with_params(&mut meta, *self);
+        let fft_size = 1 << self.k;
+        let extended_fft_size = fft_size * (meta.degree() - 1).next_power_of_two();
+        let num_lookups = meta.lookups().len();
+        let chunk_len = (meta.degree() - 2).max(1);
+        let permutation_chunks = (meta.permutation().get_columns().len() + chunk_len - 1) / chunk_len;
+
+        // Advice, the permuted input and table and the product of every lookup, and the
+        // permutation products, committed and turned into coefficients
+        let committed = meta.num_advice_columns() + 3 * num_lookups + permutation_chunks;
+        ProverCost {
+            fft_size,
+            extended_fft_size,
+            ffts: committed + meta.num_instance_columns(),
+            // Every witness polynomial to the coset, and the quotient back
+            extended_ffts: committed + meta.num_instance_columns() + 1,
+            // The quotient pieces, the random polynomial and the SHPLONK opening
+            msms: committed + meta.degree() - 1 + 1 + 2,
+        }
+    }
+
+    /// A rough estimate in bytes of the peak memory of the prover, counting the proving key, the
+    /// witness and lookup polynomials and the quotient, each in the representations the prover
+    /// holds at once. It ignores allocator overhead and the SRS, and is meant for sizing
+    /// machines, not as a bound.
+    pub fn estimate_prover_memory(&self) -> u64 {
+        let mut meta = ConstraintSystem::<Fr>::default();
+        KeccakCircuit::<Fr>::configure_with_params(&mut meta, *self);
+        let n = 1u64 << self.k;
+        // The quotient is evaluated over the extended domain, a power of two times larger
+        let extended_n = n * (meta.degree() as u64 - 1).next_power_of_two();
+        let field_bytes = std::mem::size_of::<Fr>() as u64;
+
+        // Fixed and permutation polynomials in values, coefficients and the extended coset,
+        // plus the l_0, l_last and l_active_row cosets
+        let key_columns = (meta.num_fixed_columns()
+            + meta.num_selectors()
+            + meta.permutation().get_columns().len()) as u64;
+        let pk = key_columns * (2 * n + extended_n) + 3 * extended_n;
+        // Advice and instance polynomials in values and coefficients
+        let witness = (meta.num_advice_columns() + meta.num_instance_columns()) as u64 * 2 * n;
+        // Per lookup the permuted input and table and the product, in values and coefficients
+        let lookups = meta.lookups().len() as u64 * 3 * 2 * n;
+        // Permutation products, one per chunk of `degree - 2` columns
+        let chunk_len = (meta.degree() - 2).max(1);
+        let permutation_chunks =
+            ((meta.permutation().get_columns().len() + chunk_len - 1) / chunk_len) as u64;
+        let permutation = permutation_chunks * 2 * n;
+        // The quotient over the extended domain
+        let quotient = extended_n;
+
+        (pk + witness + lookups + permutation + quotient) * field_bytes
+    }
+
+    /// There is a single formulation of the gates, all of degree at most `MAX_DEGREE`, so a
+    /// degree cap can only be checked, not met by reformulating. The lookup argument needs
+    /// degree 4 regardless of the gates, which bounds how far helper cells could lower it.
+    fn check_degree(&self) -> Result<(), Keccak256Error> {
+        if self.max_degree == 0 {
+            return Ok(());
+        }
+        let degree = self.degree();
+        if degree > self.max_degree {
+            return Err(Keccak256Error(format!(
+                "Invalid keccak config: the circuit has degree {degree}, but max_degree = {}; the lookup arguments alone need degree 4",
+                self.max_degree
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_rows(&self) -> Result<(), String> {
+        let usable_rows = self.usable_rows();
+        let required_rows = MIN_ROUNDS * self.rows_per_round;
+        if usable_rows < required_rows {
+            return Err(format!(
+                "k = {} leaves {usable_rows} usable rows, but a single keccak_f with rows_per_round = {} needs {required_rows}",
+                self.k, self.rows_per_round
+            ));
+        }
+        let tables = [
+            ("normalize_3", 3),
+            ("normalize_4", 4),
+            ("normalize_6", 6),
+            ("chi base", CHI_BASE_LOOKUP_TABLE.len()),
+        ];
+        // The tagged layout stacks all tables in the same rows
+        let mut tagged_rows = 256;
+        for (name, range) in tables {
+            let num_bits = get_num_bits_per_lookup(range, self.lookup_k());
+            let table_rows = range.pow(num_bits as u32);
+            if table_rows > usable_rows {
+                return Err(format!(
+                    "the {name} lookup table needs {table_rows} rows ({num_bits} bits per lookup), but k = {} leaves {usable_rows} usable rows",
+                    self.k
+                ));
+            }
+            tagged_rows += table_rows;
+        }
+        if 256 > usable_rows {
+            return Err(format!(
+                "the pack lookup table needs 256 rows, but k = {} leaves {usable_rows} usable rows",
+                self.k
+            ));
+        }
+        if self.lookup_layout == LookupLayout::Tagged && tagged_rows > usable_rows {
+            return Err(format!(
+                "the tagged lookup table needs {tagged_rows} rows, but k = {} leaves {usable_rows} usable rows",
+                self.k
+            ));
+        }
+        Ok(())
+    }
+
+    fn with_overrides(
+        mut self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, Keccak256Error> {
+        fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, Keccak256Error>
+        where
+            T::Err: Display,
+        {
+            value
+                .parse()
+                .map_err(|e| Keccak256Error(format!("Invalid value for {ENV_PREFIX}{key}: {e}")))
+        }
+
+        if let Some(value) = lookup("K") {
+            self.k = parse("K", &value)?;
+        }
+        if let Some(value) = lookup("ROWS_PER_ROUND") {
+            self.rows_per_round = parse("ROWS_PER_ROUND", &value)?;
+        }
+        if let Some(value) = lookup("INSTANCE_MODE") {
+            self.instance_mode = parse("INSTANCE_MODE", &value)?;
+        }
+        if let Some(value) = lookup("TRANSCRIPT") {
+            self.transcript = parse("TRANSCRIPT", &value)?;
+        }
+        if let Some(value) = lookup("SANITY") {
+            self.sanity = parse("SANITY", &value)?;
+        }
+        if let Some(value) = lookup("MAX_DEGREE") {
+            self.max_degree = parse("MAX_DEGREE", &value)?;
+        }
+        if let Some(value) = lookup("LOOKUP_LAYOUT") {
+            self.lookup_layout = parse("LOOKUP_LAYOUT", &value)?;
+        }
+        if let Some(value) = lookup("INPUT_BINDING") {
+            self.input_binding = parse("INPUT_BINDING", &value)?;
+        }
+        if let Some(value) = lookup("INPUT_COLUMNS") {
+            self.input_columns = parse("INPUT_COLUMNS", &value)?;
+        }
+        if let Some(value) = lookup("PADDING") {
+            self.padding = parse("PADDING", &value)?;
+        }
+        if let Some(value) = lookup("DIGEST_SIZE") {
+            self.digest_size = parse("DIGEST_SIZE", &value)?;
+        }
+        if let Some(value) = lookup("MAX_INPUT_LEN") {
+            self.max_input_len = Some(parse("MAX_INPUT_LEN", &value)?);
+        }
+        if let Some(value) = lookup("FIXED_INSTANCE") {
+            self.fixed_instance = parse("FIXED_INSTANCE", &value)?;
+        }
+        if let Some(value) = lookup("SELECTIVE_DISCLOSURE") {
+            self.selective_disclosure = parse("SELECTIVE_DISCLOSURE", &value)?;
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_from_toml_str() {
+        let config = KeccakConfigParams::from_toml_str(
+            "k = 12\nrows_per_round = 5\ninstance_mode = \"none\"\nsanity = \"off\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.k, 12);
+        assert_eq!(config.rows_per_round, 5);
+        assert_eq!(config.instance_mode, InstanceMode::None);
+        assert_eq!(config.transcript, TranscriptKind::Blake2b);
+        assert_eq!(config.sanity, SanityLevel::Off);
+
+        // Missing keys are the defaults, not zero
+        let config = KeccakConfigParams::from_toml_str("instance_mode = \"output\"").unwrap();
+        assert_eq!(config.k, DEFAULT_CONFIG.k);
+        assert_eq!(config.rows_per_round, DEFAULT_CONFIG.rows_per_round);
+        assert_eq!(config.input_columns, 1);
+        assert!(KeccakConfigParams::from_toml_str("k = 8").is_err());
+    }
+
+    #[test]
+    fn test_overrides() {
+        let overrides = HashMap::from([
+            ("K", "16"),
+            ("INSTANCE_MODE", "Input"),
+            ("PADDING", "sha3"),
+            ("MAX_INPUT_LEN", "1024"),
+        ]);
+        let config = KeccakConfigParams::from_toml_str("k = 12\nrows_per_round = 5\ninstance_mode = \"none\"")
+            .unwrap()
+            .with_overrides(|key| overrides.get(key).map(|v| v.to_string()))
+            .unwrap();
+        assert_eq!(config.k, 16);
+        assert_eq!(config.rows_per_round, 5);
+        assert_eq!(config.instance_mode, InstanceMode::Input);
+        assert_eq!(config.padding, Padding::Sha3);
+        assert_eq!(config.max_input_len, Some(1024));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(crate::DEFAULT_CONFIG.validate().is_ok());
+        assert!(KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() }
+            .validate()
+            .is_ok());
+
+        let zero_rows = KeccakConfigParams { k: 14, rows_per_round: 0, ..Default::default() };
+        assert!(zero_rows.validate().is_err());
+
+        let too_small = KeccakConfigParams { k: 8, rows_per_round: 28, ..Default::default() };
+        let err = too_small.validate().unwrap_err().to_string();
+        assert!(err.contains("use k >="), "{}", err);
+
+        let packed_512 = KeccakConfigParams {
+            k: 12,
+            rows_per_round: 5,
+            digest_size: DigestSize::Bits512,
+            input_binding: InputBinding::Packed,
+            ..Default::default()
+        };
+        assert!(packed_512.validate().is_err());
+
+        let cshake_512 = KeccakConfigParams {
+            k: 12,
+            rows_per_round: 5,
+            digest_size: DigestSize::Bits512,
+            padding: Padding::CShake,
+            ..Default::default()
+        };
+        let err = cshake_512.validate().unwrap_err().to_string();
+        assert!(err.contains("cSHAKE256"), "{}", err);
+        assert!(KeccakConfigParams { padding: Padding::Sha3, ..cshake_512 }.validate().is_ok());
+
+        let disclosure = KeccakConfigParams { k: 12, rows_per_round: 5, selective_disclosure: true, ..Default::default() };
+        assert!(disclosure.validate().is_ok());
+        assert!(KeccakConfigParams { fixed_instance: false, ..disclosure }.validate().is_err());
+        assert!(KeccakConfigParams { instance_mode: InstanceMode::Output, ..disclosure }.validate().is_err());
+        assert!(KeccakConfigParams { input_binding: InputBinding::Packed, ..disclosure }.validate().is_err());
+    }
+
+    #[test]
+    fn test_max_degree() {
+        let degree = crate::DEFAULT_CONFIG.degree();
+        assert!(KeccakConfigParams { max_degree: degree, ..crate::DEFAULT_CONFIG }.validate().is_ok());
+        assert!(KeccakConfigParams { max_degree: degree - 1, ..crate::DEFAULT_CONFIG }
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+        let unchanged =
+            KeccakConfigParams { transcript: TranscriptKind::Keccak256, sanity: SanityLevel::Off, ..config };
+        assert_eq!(config.fingerprint(), unchanged.fingerprint());
+        assert_ne!(config.fingerprint(), KeccakConfigParams { rows_per_round: 6, ..config }.fingerprint());
+        assert_ne!(
+            config.fingerprint(),
+            KeccakConfigParams { instance_mode: InstanceMode::Output, ..config }.fingerprint()
+        );
+        assert_ne!(
+            config.fingerprint(),
+            KeccakConfigParams { input_binding: InputBinding::Packed, ..config }.fingerprint()
+        );
+        assert_ne!(
+            KeccakConfigParams { input_binding: InputBinding::Packed, ..config }.fingerprint(),
+            KeccakConfigParams { input_binding: InputBinding::Bytes31, ..config }.fingerprint()
+        );
+        assert_eq!(config.fingerprint(), KeccakConfigParams { input_columns: 1, ..config }.fingerprint());
+        assert_ne!(config.fingerprint(), KeccakConfigParams { input_columns: 2, ..config }.fingerprint());
+        assert_ne!(
+            KeccakConfigParams { max_input_len: Some(0), ..config }.fingerprint(),
+            config.fingerprint()
+        );
+        assert_ne!(
+            KeccakConfigParams { max_input_len: Some(255), ..config }.fingerprint(),
+            KeccakConfigParams { max_input_len: Some(256), ..config }.fingerprint()
+        );
+        assert_ne!(config.fingerprint(), KeccakConfigParams { fixed_instance: false, ..config }.fingerprint());
+        assert_ne!(config.fingerprint(), KeccakConfigParams { selective_disclosure: true, ..config }.fingerprint());
+    }
+
+    #[test]
+    fn test_num_input_len_bytes() {
+        let config = |max_input_len| KeccakConfigParams { max_input_len, ..Default::default() };
+        assert_eq!(config(None).num_input_len_bytes(), 0);
+        assert_eq!(config(Some(0)).num_input_len_bytes(), 1);
+        assert_eq!(config(Some(255)).num_input_len_bytes(), 1);
+        assert_eq!(config(Some(256)).num_input_len_bytes(), 2);
+        assert_eq!(config(Some(1 << 20)).num_input_len_bytes(), 3);
+    }
+
+    #[test]
+    fn test_estimate_prover_memory() {
+        let small = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+        let large = KeccakConfigParams { k: 13, ..small };
+        assert!(large.estimate_prover_memory() > small.estimate_prover_memory());
+        assert!(small.estimate_prover_memory() > (1 << 12) * 32);
+    }
+
+    #[test]
+    fn test_estimate_prover_cost() {
+        let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+        let cost = config.estimate_prover_cost();
+        assert_eq!(cost.fft_size, 1 << 12);
+        assert!(cost.extended_fft_size > cost.fft_size);
+        assert_eq!(cost.extended_ffts, cost.ffts + 1);
+
+        // More rows per round fit a round in fewer advice columns
+        let narrow = KeccakConfigParams { rows_per_round: 10, ..config }.estimate_prover_cost();
+        assert!(narrow.msms < cost.msms);
+        assert_eq!(KeccakConfigParams { k: 13, ..config }.estimate_prover_cost().msms, cost.msms);
+    }
+
+    #[test]
+    fn test_tagged_lookup_layout() {
+        let config =
+            KeccakConfigParams { k: 14, lookup_layout: LookupLayout::Tagged, ..crate::DEFAULT_CONFIG };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.lookup_k(), 11);
+        assert_ne!(config.fingerprint(), crate::DEFAULT_CONFIG.fingerprint());
+    }
+
+    #[test]
+    fn test_invalid_override() {
+        let result = KeccakConfigParams::default()
+            .with_overrides(|key| (key == "SANITY").then(|| "paranoid".to_string()));
+        assert!(result.is_err());
+    }
+}
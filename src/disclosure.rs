@@ -0,0 +1,188 @@
+//! Selective disclosure of the input words with the fixed instance layout, see
+//! [`KeccakConfigParams::selective_disclosure`](crate::KeccakConfigParams::selective_disclosure).
+//!
+//! Every keccak_f slot exposes the `bytes_left` of its first word, a mask of the words it
+//! discloses and every word multiplied by its disclosure flag, so a private word is a zero in
+//! the instance. The flags are witnesses, but their mask is public: the verifier computes it
+//! from the lengths of the messages and the [`PublicBytes`], which pins which words are private.
+//! The mask has the flag of the first word as its most significant bit.
+//!
+//! The cells are the same whatever the inputs and the [`PublicBytes`], so keys generated
+//! without inputs serve every proof, e.g. of commit–reveal or keyed hashes. The price is that
+//! the lengths of the messages are public: the `bytes_left` of every slot is what ties the
+//! mask to the position of the private bytes in their message.
+
+use halo2_proofs::circuit::{Layouter, Value};
+use halo2_proofs::halo2curves::ff::PrimeField;
+use halo2_proofs::plonk::{Advice, Column, ConstraintSystem, Error, Selector};
+use halo2_proofs::poly::Rotation;
+
+use crate::circuit::pack_digests_to_instance;
+use crate::util::assign_value::{assigned_value, constrain_equal, raw_assign_advice};
+use crate::util::eth_types::Field;
+use crate::util::expression::Expr;
+use crate::util::Halo2AssignedCell;
+use crate::vanilla::param::NUM_BYTES_PER_WORD;
+use crate::{InputBinding, InstanceMode, Keccak256Error, KeccakCircuit, KeccakConfigParams, PublicBytes};
+
+/// Number of `input` instance cells of a slot before its words: the `bytes_left` of its first
+/// word and its mask.
+pub const DISCLOSURE_HEADER_LEN: usize = 2;
+
+/// The disclosure flags of the words of block `block` of a message of `len` bytes absorbing
+/// `rate` bytes per keccak_f. Words past the end of the message are zero and disclosed, so a
+/// padding slot, an empty message, discloses all of them.
+pub fn block_flags(public_bytes: PublicBytes, len: usize, block: usize, rate: usize) -> Vec<bool> {
+    (0..rate / NUM_BYTES_PER_WORD)
+        .map(|word| public_bytes.discloses(len, block * rate + word * NUM_BYTES_PER_WORD))
+        .collect()
+}
+
+/// The mask of `flags`, the first flag as its most significant bit.
+pub fn mask<F: PrimeField>(flags: &[bool]) -> F {
+    flags.iter().fold(F::ZERO, |acc, flag| acc.double() + F::from(*flag as u64))
+}
+
+/// `config` exposing the inputs and the digests with the fixed instance layout, masking the
+/// private words, the config of every statement keeping part of its messages private.
+pub fn disclosure_config(config: KeccakConfigParams) -> KeccakConfigParams {
+    KeccakConfigParams {
+        instance_mode: InstanceMode::InputOutput,
+        input_binding: InputBinding::Word,
+        fixed_instance: true,
+        selective_disclosure: true,
+        ..config
+    }
+}
+
+/// The instance columns of a proof of `messages` with the fixed instance layout of `config` over
+/// `capacity_rows`, exposing their `public_bytes` and their big-endian `digests`, for a verifier
+/// who only knows those: the private bytes of `messages` can be anything, e.g. zeros, as they
+/// are masked out, and so are their digests.
+pub fn masked_instances<F: Field>(
+    config: KeccakConfigParams,
+    capacity_rows: usize,
+    public_bytes: PublicBytes,
+    messages: Vec<Vec<u8>>,
+    digests: &[Vec<u8>],
+) -> Result<Vec<Vec<F>>, Keccak256Error> {
+    if digests.len() != messages.len() {
+        return Err(Keccak256Error(format!("{} digests for {} messages", digests.len(), messages.len())));
+    }
+    let lengths = messages.iter().map(Vec::len).collect::<Vec<_>>();
+    let mut columns = KeccakCircuit::<F>::builder()
+        .config(config)
+        .capacity_rows(capacity_rows)
+        .fixed_instance(true)
+        .public_bytes(public_bytes)
+        .inputs(messages)
+        .build()?
+        .instances();
+    if config.instance_mode.exposes_output() {
+        let stride = config.digest_size.num_digest_cells();
+        let output = columns.last_mut().expect("an instance mode exposing outputs has an output column");
+        for (slot, digest) in config.final_slots(&lengths).into_iter().zip(digests) {
+            if digest.len() != 16 * stride {
+                return Err(Keccak256Error(format!(
+                    "digest of {} bytes for a config with {:?}",
+                    digest.len(),
+                    config.digest_size
+                )));
+            }
+            output[slot * stride..(slot + 1) * stride].copy_from_slice(&pack_digests_to_instance::<F>(&[digest]));
+        }
+    }
+    Ok(columns)
+}
+
+/// The disclosure cells of a keccak_f slot, to be bound to the instance.
+#[derive(Clone)]
+pub struct DisclosedSlot<'v, F: Field> {
+    pub mask: Halo2AssignedCell<'v, F>,
+    /// Every word times its flag, in order.
+    pub words: Vec<Halo2AssignedCell<'v, F>>,
+}
+
+/// Config of the disclosure gadget: a row per word with the word, copied from its `word_value`,
+/// its flag, the disclosed word and the mask of the flags so far.
+#[derive(Clone, Debug)]
+pub struct DisclosureConfig {
+    word: Column<Advice>,
+    flag: Column<Advice>,
+    disclosed: Column<Advice>,
+    mask: Column<Advice>,
+    q_first: Selector,
+    q_rest: Selector,
+}
+
+impl DisclosureConfig {
+    pub fn configure<F: Field>(meta: &mut ConstraintSystem<F>) -> Self {
+        let [word, flag, disclosed, mask] = [(); 4].map(|_| meta.advice_column());
+        for column in [word, disclosed, mask] {
+            meta.enable_equality(column);
+        }
+        let q_first = meta.selector();
+        let q_rest = meta.selector();
+
+        meta.create_gate("disclosure", |meta| {
+            let q_first = meta.query_selector(q_first);
+            let q_rest = meta.query_selector(q_rest);
+            let mask_prev = meta.query_advice(mask, Rotation::prev());
+            let [word, flag, disclosed, mask] =
+                [word, flag, disclosed, mask].map(|column| meta.query_advice(column, Rotation::cur()));
+            let q_word = q_first.clone() + q_rest.clone();
+            vec![
+                q_word.clone() * flag.clone() * (1.expr() - flag.clone()),
+                q_word * (disclosed - word * flag.clone()),
+                q_first * (mask.clone() - flag.clone()),
+                q_rest * (mask - mask_prev * 2.expr() - flag),
+            ]
+        });
+
+        DisclosureConfig { word, flag, disclosed, mask, q_first, q_rest }
+    }
+
+    /// Assigns the disclosure of the `words` of every slot under its `flags`, one slot after
+    /// the other in a single region.
+    pub fn assign_slots<'v, F: Field>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        words: &[Vec<Halo2AssignedCell<'v, F>>],
+        flags: &[Vec<bool>],
+    ) -> Result<Vec<DisclosedSlot<'v, F>>, Error> {
+        layouter.assign_region(
+            || "disclosure",
+            |mut region| {
+                let mut offset = 0;
+                let mut slots = Vec::with_capacity(words.len());
+                for (words, flags) in words.iter().zip(flags) {
+                    let mut mask = Value::known(F::ZERO);
+                    let mut mask_cell = None;
+                    let mut disclosed_cells = Vec::with_capacity(words.len());
+                    for (idx, (word, flag)) in words.iter().zip(flags).enumerate() {
+                        if idx == 0 {
+                            self.q_first.enable(&mut region, offset)?;
+                        } else {
+                            self.q_rest.enable(&mut region, offset)?;
+                        }
+                        let flag = F::from(*flag as u64);
+                        let value = assigned_value(word);
+                        let copied = raw_assign_advice(&mut region, self.word, offset, value);
+                        constrain_equal(&mut region, word.cell(), copied.cell())?;
+                        raw_assign_advice(&mut region, self.flag, offset, Value::known(flag));
+                        let disclosed = value.map(|word| word * flag);
+                        disclosed_cells.push(raw_assign_advice(&mut region, self.disclosed, offset, disclosed));
+                        mask = mask.map(|mask| mask.double() + flag);
+                        mask_cell = Some(raw_assign_advice(&mut region, self.mask, offset, mask));
+                        offset += 1;
+                    }
+                    slots.push(DisclosedSlot {
+                        mask: mask_cell.expect("a slot has at least one word"),
+                        words: disclosed_cells,
+                    });
+                }
+                Ok(slots)
+            },
+        )
+    }
+}
@@ -0,0 +1,406 @@
+//! EIP-712 typed data hashing. Given a typed data schema with its values, builds the tree of
+//! `encodeType`/`hashStruct` keccaks leading to the signing digest
+//! `keccak(0x19 || 0x01 || domainSeparator || hashStruct(message))`, and proves it in a single
+//! circuit where every hash is copy constrained into the bytes of the message it is encoded in.
+//!
+//! Only the domain separator and the signing digest are public. Everything else, including the
+//! type strings, is private witness bound through the digest. Atomic values are passed already
+//! ABI encoded to 32 bytes, and arrays are not supported.
+//!
+//! The layout depends on the schema and on the lengths of the dynamic values, so a verifying key
+//! is only valid for messages of the same shape.
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::halo2curves::ff::PrimeField;
+use halo2_proofs::plonk::{Circuit, Column, ConstraintSystem, Error, Instance};
+use sha3::{Digest, Keccak256};
+
+use crate::bridge::KeccakBridgeConfig;
+use crate::circuit::input_cells;
+use crate::util::assign_value::{constrain_equal, constrain_instance};
+use crate::util::eth_types::Field;
+use crate::util::SKIP_FIRST_PASS;
+use crate::vanilla::param::NUM_BYTES_PER_WORD;
+use crate::vanilla::witness::multi_keccak;
+use crate::vanilla::{KeccakCircuitConfig, KeccakConfigParams};
+use crate::Keccak256Error;
+
+/// The value of a struct member.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TypedValue {
+    /// An atomic value (`uint256`, `address`, `bool`, `bytes32`, ...), ABI encoded to 32 bytes.
+    Atomic([u8; 32]),
+    /// A `bytes` or `string` value, encoded as its keccak.
+    Dynamic(Vec<u8>),
+    /// A nested struct, encoded as its `hashStruct`.
+    Struct(TypedStruct),
+}
+
+impl TypedValue {
+    /// An unsigned integer, left padded to 32 bytes.
+    pub fn uint(value: u128) -> Self {
+        let mut encoded = [0u8; 32];
+        encoded[16..].copy_from_slice(&value.to_be_bytes());
+        TypedValue::Atomic(encoded)
+    }
+
+    /// An address, left padded to 32 bytes.
+    pub fn address(address: [u8; 20]) -> Self {
+        let mut encoded = [0u8; 32];
+        encoded[12..].copy_from_slice(&address);
+        TypedValue::Atomic(encoded)
+    }
+
+    fn encode(&self) -> [u8; 32] {
+        match self {
+            TypedValue::Atomic(encoded) => *encoded,
+            TypedValue::Dynamic(bytes) => Keccak256::digest(bytes).into(),
+            TypedValue::Struct(value) => value.hash_struct(),
+        }
+    }
+}
+
+/// A named and typed struct member.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    /// The EIP-712 type, e.g. `uint256`, `string` or the name of a struct.
+    pub ty: String,
+    pub value: TypedValue,
+}
+
+/// A struct value together with its type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypedStruct {
+    pub name: String,
+    pub members: Vec<Member>,
+}
+
+impl TypedStruct {
+    pub fn new(name: &str) -> Self {
+        TypedStruct { name: name.to_string(), members: vec![] }
+    }
+
+    /// Appends a member.
+    pub fn member(mut self, name: &str, ty: &str, value: TypedValue) -> Self {
+        self.members.push(Member { name: name.to_string(), ty: ty.to_string(), value });
+        self
+    }
+
+    /// Appends a nested struct member, typed by the name of the struct.
+    pub fn struct_member(self, name: &str, value: TypedStruct) -> Self {
+        let ty = value.name.clone();
+        self.member(name, &ty, TypedValue::Struct(value))
+    }
+
+    fn definition(&self) -> String {
+        let members = self
+            .members
+            .iter()
+            .map(|member| format!("{} {}", member.ty, member.name))
+            .collect::<Vec<_>>();
+        format!("{}({})", self.name, members.join(","))
+    }
+
+    fn collect_dependencies(&self, dependencies: &mut BTreeMap<String, String>) {
+        for member in &self.members {
+            if let TypedValue::Struct(value) = &member.value {
+                dependencies.entry(value.name.clone()).or_insert_with(|| value.definition());
+                value.collect_dependencies(dependencies);
+            }
+        }
+    }
+
+    /// `encodeType`: the definition of the struct followed by those of the structs it
+    /// references, sorted by name.
+    pub fn encode_type(&self) -> String {
+        let mut dependencies = BTreeMap::new();
+        self.collect_dependencies(&mut dependencies);
+        dependencies.remove(&self.name);
+        self.definition() + &dependencies.into_values().collect::<String>()
+    }
+
+    /// `typeHash`, the keccak of [`TypedStruct::encode_type`].
+    pub fn type_hash(&self) -> [u8; 32] {
+        Keccak256::digest(self.encode_type()).into()
+    }
+
+    /// `typeHash || encodeData`, the message hashed by [`TypedStruct::hash_struct`].
+    pub fn encode_data(&self) -> Vec<u8> {
+        let mut encoded = self.type_hash().to_vec();
+        for member in &self.members {
+            encoded.extend(member.value.encode());
+        }
+        encoded
+    }
+
+    /// `hashStruct`.
+    pub fn hash_struct(&self) -> [u8; 32] {
+        Keccak256::digest(self.encode_data()).into()
+    }
+}
+
+/// The signing digest of `message` under the `EIP712Domain` struct `domain`.
+pub fn signing_digest(domain: &TypedStruct, message: &TypedStruct) -> [u8; 32] {
+    Keccak256::digest(signing_message(domain, message)).into()
+}
+
+fn signing_message(domain: &TypedStruct, message: &TypedStruct) -> Vec<u8> {
+    [&[0x19, 0x01][..], &domain.hash_struct(), &message.hash_struct()].concat()
+}
+
+/// The digest of input `child` is copied into the 32 bytes at `offset` of input `parent`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub child: usize,
+    pub parent: usize,
+    pub offset: usize,
+}
+
+/// The keccak inputs leading to a signing digest, and how they feed into each other.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HashTree {
+    pub inputs: Vec<Vec<u8>>,
+    pub links: Vec<Link>,
+    /// The input hashing to the domain separator.
+    pub domain_separator: usize,
+    /// The input hashing to the signing digest.
+    pub digest: usize,
+}
+
+impl HashTree {
+    pub fn new(domain: &TypedStruct, message: &TypedStruct) -> Self {
+        let mut tree = HashTree::default();
+        tree.domain_separator = tree.push_struct(domain);
+        let message_hash = tree.push_struct(message);
+        tree.digest = tree.push(signing_message(domain, message));
+        tree.links.push(Link { child: tree.domain_separator, parent: tree.digest, offset: 2 });
+        tree.links.push(Link { child: message_hash, parent: tree.digest, offset: 34 });
+        tree
+    }
+
+    fn push(&mut self, input: Vec<u8>) -> usize {
+        self.inputs.push(input);
+        self.inputs.len() - 1
+    }
+
+    fn push_struct(&mut self, value: &TypedStruct) -> usize {
+        let mut children = vec![(self.push(value.encode_type().into_bytes()), 0)];
+        for (idx, member) in value.members.iter().enumerate() {
+            let child = match &member.value {
+                TypedValue::Atomic(_) => continue,
+                TypedValue::Dynamic(bytes) => self.push(bytes.clone()),
+                TypedValue::Struct(inner) => self.push_struct(inner),
+            };
+            children.push((child, 32 * (idx + 1)));
+        }
+        let parent = self.push(value.encode_data());
+        self.links.extend(children.into_iter().map(|(child, offset)| Link { child, parent, offset }));
+        parent
+    }
+}
+
+/// The instance column of [`Eip712Circuit`]: the domain separator and the signing digest, each
+/// as its big-endian `(hi, lo)` halves.
+pub fn eip712_instances<F: PrimeField>(domain_separator: [u8; 32], digest: [u8; 32]) -> Vec<Vec<F>> {
+    let column = [domain_separator, digest]
+        .iter()
+        .flat_map(|hash| {
+            let hi = u128::from_be_bytes(hash[..16].try_into().unwrap());
+            let lo = u128::from_be_bytes(hash[16..].try_into().unwrap());
+            [F::from_u128(hi), F::from_u128(lo)]
+        })
+        .collect();
+    vec![column]
+}
+
+#[derive(Clone, Debug)]
+pub struct Eip712Config<F> {
+    keccak: KeccakCircuitConfig<F>,
+    bridge: KeccakBridgeConfig,
+    instance: Column<Instance>,
+}
+
+/// Circuit proving the signing digest of a typed data message.
+#[derive(Clone, Debug, Default)]
+pub struct Eip712Circuit<F: Field> {
+    config: KeccakConfigParams,
+    tree: HashTree,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Eip712Circuit<F> {
+    /// Creates the circuit proving the signing digest of `message` under `domain`.
+    pub fn new(
+        config: KeccakConfigParams,
+        domain: &TypedStruct,
+        message: &TypedStruct,
+    ) -> Result<Self, Keccak256Error> {
+        config.validate()?;
+        Ok(Eip712Circuit { config, tree: HashTree::new(domain, message), _marker: PhantomData })
+    }
+
+    /// The hashing tree proven by the circuit.
+    pub fn tree(&self) -> &HashTree {
+        &self.tree
+    }
+}
+
+impl<F: Field> Circuit<F> for Eip712Circuit<F> {
+    type Config = Eip712Config<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    /// Keeps the shape of the tree, which determines the layout, with zeroed inputs.
+    fn without_witnesses(&self) -> Self {
+        let inputs = self.tree.inputs.iter().map(|input| vec![0u8; input.len()]).collect();
+        Self {
+            config: self.config,
+            tree: HashTree { inputs, ..self.tree.clone() },
+            _marker: PhantomData,
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        // MockProver complains if you only have columns in SecondPhase, so let's just make an empty column in FirstPhase
+        meta.advice_column();
+
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+        let keccak = KeccakCircuitConfig::new(meta, params);
+        let bridge = KeccakBridgeConfig::configure(meta);
+        Eip712Config { keccak, bridge, instance }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+        let params = config.keccak.parameters;
+        config.keccak.load_aux_tables(&mut layouter)?;
+        config.bridge.load_byte_table(&mut layouter)?;
+
+        let (witness, _) = multi_keccak::<F, _>(&self.tree.inputs, None, params);
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut cache = vec![];
+        layouter.assign_region(
+            || "eip712 keccaks",
+            |mut region| {
+                if first_pass {
+                    first_pass = false;
+                    return Ok(());
+                }
+                cache.push(config.keccak.assign(&mut region, &witness));
+                Ok(())
+            },
+        )?;
+        let cells = input_cells(&cache[0], &self.tree.inputs, params.rows_per_round);
+
+        for link in &self.tree.links {
+            let child = &cells[link.child];
+            let digest = config.bridge.assign_digest(
+                layouter.namespace(|| "eip712 child digest"),
+                &child.hash_hi,
+                &child.hash_lo,
+            )?;
+            // The 32 bytes generally straddle 5 words of the parent
+            let first_word = link.offset / NUM_BYTES_PER_WORD;
+            let last_word = (link.offset + 31) / NUM_BYTES_PER_WORD;
+            let parent_bytes = config.bridge.assign_word_bytes(
+                layouter.namespace(|| "eip712 parent words"),
+                &cells[link.parent].words[first_word..=last_word],
+            )?;
+            let skip = link.offset - first_word * NUM_BYTES_PER_WORD;
+            layouter.assign_region(
+                || "eip712 link",
+                |mut region| {
+                    for (child_byte, parent_byte) in digest.bytes.iter().zip(&parent_bytes[skip..]) {
+                        constrain_equal(&mut region, child_byte.cell(), parent_byte.cell())?;
+                    }
+                    Ok(())
+                },
+            )?;
+        }
+
+        let public = [self.tree.domain_separator, self.tree.digest]
+            .iter()
+            .flat_map(|idx| [cells[*idx].hash_hi.cell(), cells[*idx].hash_lo.cell()])
+            .collect::<Vec<_>>();
+        for (row, cell) in public.into_iter().enumerate() {
+            constrain_instance(&mut layouter, cell, config.instance, row)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use hex::FromHex;
+
+    use super::*;
+
+    fn address(hex: &str) -> TypedValue {
+        TypedValue::address(<[u8; 20]>::from_hex(hex).unwrap())
+    }
+
+    fn person(name: &str, wallet: &str) -> TypedStruct {
+        TypedStruct::new("Person")
+            .member("name", "string", TypedValue::Dynamic(name.as_bytes().to_vec()))
+            .member("wallet", "address", address(wallet))
+    }
+
+    /// The example of the EIP-712 specification.
+    fn example() -> (TypedStruct, TypedStruct) {
+        let domain = TypedStruct::new("EIP712Domain")
+            .member("name", "string", TypedValue::Dynamic(b"Ether Mail".to_vec()))
+            .member("version", "string", TypedValue::Dynamic(b"1".to_vec()))
+            .member("chainId", "uint256", TypedValue::uint(1))
+            .member("verifyingContract", "address", address("CcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"));
+        let mail = TypedStruct::new("Mail")
+            .struct_member("from", person("Cow", "CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"))
+            .struct_member("to", person("Bob", "bBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"))
+            .member("contents", "string", TypedValue::Dynamic(b"Hello, Bob!".to_vec()));
+        (domain, mail)
+    }
+
+    #[test]
+    fn test_signing_digest() {
+        let (domain, mail) = example();
+        assert_eq!(
+            mail.encode_type(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            domain.hash_struct(),
+            <[u8; 32]>::from_hex("f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f").unwrap()
+        );
+        assert_eq!(
+            signing_digest(&domain, &mail),
+            <[u8; 32]>::from_hex("be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_eip712_circuit() {
+        let (domain, mail) = example();
+        let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..crate::DEFAULT_CONFIG };
+        let circuit = Eip712Circuit::<Fr>::new(config, &domain, &mail).unwrap();
+        let instances = eip712_instances(domain.hash_struct(), signing_digest(&domain, &mail));
+        MockProver::run(config.k, &circuit, instances).unwrap().assert_satisfied();
+
+        let other = TypedStruct { name: "Mail".to_string(), members: mail.members[..2].to_vec() }
+            .member("contents", "string", TypedValue::Dynamic(b"Hello, Eve!".to_vec()));
+        let forged = eip712_instances(domain.hash_struct(), signing_digest(&domain, &other));
+        assert!(MockProver::run(config.k, &circuit, forged).unwrap().verify().is_err());
+    }
+}
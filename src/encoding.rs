@@ -0,0 +1,187 @@
+//! The canonical text encoding of digests, instances and proofs, shared by the binaries and
+//! every serialized format: lowercase `0x` prefixed hex, with field elements as their 32
+//! big-endian bytes. Decoding accepts a missing prefix and uppercase digits, but rejects field
+//! elements that aren't reduced, so every value has a single encoding.
+//!
+//! The `hex_*` modules are for `#[serde(with = "...")]` on fields of these types.
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use halo2_proofs::halo2curves::ff::PrimeField;
+
+use crate::Keccak256Error;
+
+/// `0x` then the lowercase hex of `bytes`.
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 + 2 * bytes.len());
+    hex.push_str("0x");
+    for b in bytes {
+        hex.push_str(&format!("{b:02x}"));
+    }
+    hex
+}
+
+/// The bytes of `hex`, with or without its `0x` prefix.
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, Keccak256Error> {
+    let digits = hex.strip_prefix("0x").unwrap_or(hex);
+    if digits.len() % 2 != 0 {
+        return Err(Keccak256Error(format!("odd length hex `{}`", hex)));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| Keccak256Error(format!("invalid hex `{}`", hex)))
+        })
+        .collect()
+}
+
+/// A digest from its hex, which must be 32 bytes.
+pub fn digest_from_hex(hex: &str) -> Result<[u8; 32], Keccak256Error> {
+    from_hex(hex)?
+        .try_into()
+        .map_err(|bytes: Vec<u8>| Keccak256Error(format!("a digest is 32 bytes, got {}", bytes.len())))
+}
+
+/// The hex of the 32 big-endian bytes of `x`.
+pub fn fr_to_hex(x: &Fr) -> String {
+    let mut be_bytes = x.to_repr();
+    be_bytes.as_mut().reverse();
+    to_hex(be_bytes.as_ref())
+}
+
+/// A field element from the hex of its 32 big-endian bytes, which must be below the modulus.
+pub fn fr_from_hex(hex: &str) -> Result<Fr, Keccak256Error> {
+    let mut repr = <Fr as PrimeField>::Repr::default();
+    let bytes = from_hex(hex)?;
+    if bytes.len() != repr.as_ref().len() {
+        return Err(Keccak256Error(format!("a field element is 32 bytes, got {}", bytes.len())));
+    }
+    repr.as_mut().copy_from_slice(&bytes);
+    repr.as_mut().reverse();
+    Option::from(Fr::from_repr(repr))
+        .ok_or_else(|| Keccak256Error(format!("`{}` is not a reduced field element", hex)))
+}
+
+/// `#[serde(with)]` for byte strings, such as proofs and digests.
+pub mod hex_bytes {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer, T: AsRef<[u8]>>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_hex(bytes.as_ref()))
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: TryFrom<Vec<u8>>,
+    {
+        let bytes = super::from_hex(&String::deserialize(deserializer)?).map_err(D::Error::custom)?;
+        let len = bytes.len();
+        T::try_from(bytes).map_err(|_| D::Error::custom(format!("unexpected length {}", len)))
+    }
+}
+
+/// `#[serde(with)]` for lists of digests.
+pub mod hex_digests {
+    use serde::de::Error;
+    use serde::ser::SerializeSeq;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(digests: &[[u8; 32]], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(digests.len()))?;
+        for digest in digests {
+            seq.serialize_element(&super::to_hex(digest))?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<[u8; 32]>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|hex| super::digest_from_hex(hex).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// `#[serde(with)]` for instance columns.
+pub mod hex_instances {
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(instances: &[Vec<Fr>], serializer: S) -> Result<S::Ok, S::Error> {
+        instances
+            .iter()
+            .map(|column| column.iter().map(super::fr_to_hex).collect())
+            .collect::<Vec<Vec<String>>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Vec<Fr>>, D::Error> {
+        Vec::<Vec<String>>::deserialize(deserializer)?
+            .iter()
+            .map(|column| {
+                column
+                    .iter()
+                    .map(|hex| super::fr_from_hex(hex).map_err(D::Error::custom))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Encoded {
+        #[serde(with = "hex_bytes")]
+        proof: Vec<u8>,
+        #[serde(with = "hex_digests")]
+        digests: Vec<[u8; 32]>,
+        #[serde(with = "hex_instances")]
+        instances: Vec<Vec<Fr>>,
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(to_hex(&[]), "0x");
+        assert_eq!(to_hex(&[0, 0xab, 7]), "0x00ab07");
+        assert_eq!(from_hex("0x00AB07").unwrap(), [0, 0xab, 7]);
+        assert_eq!(from_hex("00ab07").unwrap(), [0, 0xab, 7]);
+        assert!(from_hex("0xabc").is_err());
+        assert!(from_hex("0xzz").is_err());
+        assert!(from_hex("0x+1").is_err());
+        assert!(digest_from_hex("0x00").is_err());
+    }
+
+    #[test]
+    fn test_field_elements() {
+        let x = Fr::from_u128(0x0102);
+        assert_eq!(fr_to_hex(&x), format!("0x{}0102", "0".repeat(60)));
+        assert_eq!(fr_from_hex(&fr_to_hex(&x)).unwrap(), x);
+        assert_eq!(fr_from_hex(&fr_to_hex(&-x)).unwrap(), -x);
+        // The modulus itself is the non-canonical encoding of zero
+        let modulus = "0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001";
+        assert!(fr_from_hex(modulus).is_err());
+        assert!(fr_from_hex("0x01").is_err());
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let encoded = Encoded {
+            proof: vec![1, 2, 3],
+            digests: vec![[0x11; 32]],
+            instances: vec![vec![Fr::from(5), -Fr::from(1)], vec![]],
+        };
+        let json = serde_json::to_string(&encoded).unwrap();
+        assert!(json.contains(r#""proof":"0x010203""#));
+        assert_eq!(serde_json::from_str::<Encoded>(&json).unwrap(), encoded);
+    }
+}
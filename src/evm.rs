@@ -0,0 +1,154 @@
+//! EVM verifier of the keccak circuit, generated with snark-verifier.
+//!
+//! The generated verifier reads proofs written with snark-verifier's EVM transcript, which
+//! hashes with keccak256 like [`TranscriptKind::Keccak256`](crate::TranscriptKind) but frames
+//! the transcript differently, so proofs for it come from [`prove_evm`] rather than the
+//! [`KeccakProver`](crate::KeccakProver). Its calldata is [`evm_calldata`], the instance cells
+//! as big-endian words followed by the proof.
+//!
+//! A verifier is specific to the length of every instance column, as its code unrolls them:
+//! [`num_instance`] of the instances of a proof gives the lengths to generate it for. With the
+//! fixed instance layout, which [`prove_evm`] requires, the lengths only depend on the config,
+//! so a single verifier takes the proofs of any messages within capacity.
+
+use std::rc::Rc;
+
+use halo2_proofs::halo2curves::bn256::{Bn256, Fq, Fr, G1Affine};
+use halo2_proofs::plonk::{create_proof, verify_proof, ProvingKey, VerifyingKey};
+use halo2_proofs::poly::commitment::{Params, ParamsProver};
+use halo2_proofs::poly::kzg::commitment::{KZGCommitmentScheme, ParamsKZG};
+use halo2_proofs::poly::kzg::multiopen::{ProverSHPLONK, VerifierSHPLONK};
+use halo2_proofs::poly::kzg::strategy::SingleStrategy;
+use halo2_proofs::transcript::{TranscriptReadBuffer, TranscriptWriterBuffer};
+use rand::thread_rng;
+use snark_verifier::loader::evm::{compile_yul, EvmLoader};
+use snark_verifier::pcs::kzg::{Bdfg21, KzgAs};
+use snark_verifier::system::halo2::transcript::evm::EvmTranscript;
+use snark_verifier::system::halo2::{compile, Config};
+use snark_verifier::verifier::{self, SnarkVerifier};
+
+pub use crate::solidity::evm_calldata;
+use crate::{KeccakCircuit, KeccakCircuitError, KeccakConfigParams};
+
+/// The SHPLONK verifier, matching [`KzgShplonk`](crate::proof_system::KzgShplonk).
+type PlonkVerifier = verifier::plonk::PlonkVerifier<KzgAs<Bn256, Bdfg21>>;
+
+/// The lengths of the instance columns, which a generated verifier is specific to.
+pub fn num_instance(instances: &[Vec<Fr>]) -> Vec<usize> {
+    instances.iter().map(Vec::len).collect()
+}
+
+/// The Yul code of the verifier of `vk`, for instance columns of `num_instance` cells.
+pub fn evm_verifier_yul(srs: &ParamsKZG<Bn256>, vk: &VerifyingKey<G1Affine>, num_instance: Vec<usize>) -> String {
+    let protocol = compile(srs, vk, Config::kzg().with_num_instance(num_instance.clone()));
+    let deciding_key = (srs.get_g()[0], srs.g2(), srs.s_g2()).into();
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let protocol = protocol.loaded(&loader);
+    let mut transcript = EvmTranscript::<_, Rc<EvmLoader>, _, _>::new(&loader);
+    let instances = transcript.load_instances(num_instance);
+    let proof = PlonkVerifier::read_proof(&deciding_key, &protocol, &instances, &mut transcript)
+        .expect("reading a proof with the EVM loader only emits code");
+    PlonkVerifier::verify(&deciding_key, &protocol, &instances, &proof)
+        .expect("verifying with the EVM loader only emits code");
+    loader.yul_code()
+}
+
+/// The deployment bytecode of [`evm_verifier_yul`], compiled with the `solc` on the path.
+pub fn evm_verifier_bytecode(
+    srs: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+) -> Vec<u8> {
+    compile_yul(&evm_verifier_yul(srs, vk, num_instance))
+}
+
+/// Proves the keccak hashes of `inputs` for the EVM verifier, returning the instance columns
+/// and the proof. As for the [`KeccakProver`](crate::KeccakProver), `pk` is generated without
+/// inputs, so `config` has to pass
+/// [`check_fixed_instance`](KeccakConfigParams::check_fixed_instance).
+pub fn prove_evm(
+    inputs: Vec<Vec<u8>>,
+    srs: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    config: KeccakConfigParams,
+) -> Result<(Vec<Vec<Fr>>, Vec<u8>), KeccakCircuitError> {
+    config.check_fixed_instance()?;
+    let circuit = KeccakCircuit::<Fr>::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k))
+        .inputs(inputs)
+        .build()?;
+    let instances = circuit.instances();
+    let instance_refs = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+    let mut transcript = EvmTranscript::<G1Affine, _, _, _>::init(vec![]);
+    create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
+        srs,
+        pk,
+        &[circuit],
+        &[&instance_refs],
+        thread_rng(),
+        &mut transcript,
+    )?;
+    Ok((instances, transcript.finalize()))
+}
+
+/// Verifies a proof from [`prove_evm`] natively, as the EVM verifier would.
+pub fn verify_evm(
+    proof: &[u8],
+    instances: &[Vec<Fr>],
+    srs: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+) -> Result<(), KeccakCircuitError> {
+    let instance_refs = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let mut transcript = EvmTranscript::<G1Affine, _, _, _>::init(proof);
+    verify_proof::<KZGCommitmentScheme<Bn256>, VerifierSHPLONK<'_, Bn256>, _, _, _>(
+        srs.verifier_params(),
+        vk,
+        SingleStrategy::new(srs),
+        &[&instance_refs],
+        &mut transcript,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::plonk::{keygen_pk, keygen_vk};
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{InstanceMode, DEFAULT_CONFIG};
+
+    #[test]
+    fn test_evm_verifier() {
+        let config = KeccakConfigParams {
+            k: 12,
+            rows_per_round: 5,
+            instance_mode: InstanceMode::Output,
+            ..DEFAULT_CONFIG
+        };
+        let srs = ParamsKZG::<Bn256>::setup(config.k, OsRng);
+        let circuit = KeccakCircuit::builder().config(config).capacity_rows(2usize.pow(config.k)).build().unwrap();
+        let vk = keygen_vk(&srs, &circuit).unwrap();
+        let pk = keygen_pk(&srs, vk.clone(), &circuit).unwrap();
+
+        let (instances, proof) = prove_evm(vec![b"abc".to_vec(), vec![]], &srs, &pk, config).unwrap();
+        verify_evm(&proof, &instances, &srs, &vk).unwrap();
+        let mut tampered = instances.clone();
+        tampered.last_mut().unwrap()[0] += Fr::from(1);
+        assert!(verify_evm(&proof, &tampered, &srs, &vk).is_err());
+
+        let yul = evm_verifier_yul(&srs, &vk, num_instance(&instances));
+        assert!(yul.contains("object"));
+        let calldata = evm_calldata(&instances, &proof);
+        assert_eq!(calldata.len(), 32 * instances.concat().len() + proof.len());
+
+        // The same lengths, and verifier, for other messages
+        let (other, _) = prove_evm(vec![vec![7u8; 300]], &srs, &pk, config).unwrap();
+        assert_eq!(num_instance(&other), num_instance(&instances));
+        let by_input = KeccakConfigParams { fixed_instance: false, ..config };
+        assert!(prove_evm(vec![b"abc".to_vec()], &srs, &pk, by_input).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Adapter exposing the keccak circuit through ezkl-style external circuit hooks: the circuit
+//! is described by a serialized settings blob, and proving/verifying are plain entry points
+//! over bytes, so an ML-proof pipeline can attach keccak commitments of model inputs/outputs.
+//!
+//! The commitment is the digest the circuit exposes in its `output` instance column, with the
+//! fixed instance layout, so settings have to expose the digests and keep the default layout.
+
+use std::path::Path;
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::{generate_halo2_proof_for_inputs, verify_halo2_proof};
+use crate::serialisation::InputsSerialisationWrapper;
+use crate::{
+    digest_from_instance, io, DigestSize, InstanceMode, Keccak256Error, KeccakCircuit, KeccakConfigParams,
+    DEFAULT_CONFIG,
+};
+
+/// Serialized settings of the keccak external circuit.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct EzklKeccakSettings {
+    /// Parameters the proving and verifying keys were generated with.
+    pub config: KeccakConfigParams,
+}
+
+impl Default for EzklKeccakSettings {
+    fn default() -> Self {
+        EzklKeccakSettings { config: KeccakConfigParams { instance_mode: InstanceMode::Output, ..DEFAULT_CONFIG } }
+    }
+}
+
+impl EzklKeccakSettings {
+    /// Serializes the settings to JSON.
+    pub fn to_json(&self) -> Result<String, Keccak256Error> {
+        serde_json::to_string(self)
+            .map_err(|e| Keccak256Error(format!("Failed to serialize settings: {}", e)))
+    }
+
+    /// Deserializes the settings from JSON.
+    pub fn from_json(json: &str) -> Result<Self, Keccak256Error> {
+        serde_json::from_str(json)
+            .map_err(|e| Keccak256Error(format!("Failed to deserialize settings: {}", e)))
+    }
+
+    /// Checks that the circuit exposes the 256 bit digest, laid out so that keys generated
+    /// without inputs bind it.
+    pub fn check(&self) -> Result<(), Keccak256Error> {
+        if !self.config.instance_mode.exposes_output() || self.config.digest_size != DigestSize::Bits256 {
+            return Err(Keccak256Error(format!(
+                "The commitment is the 256 bit digest of the output instance, got {:?} with {:?}",
+                self.config.instance_mode, self.config.digest_size
+            )));
+        }
+        self.config.check_fixed_instance()
+    }
+}
+
+/// Output of [`prove_commitment`].
+#[derive(Clone, Debug)]
+pub struct EzklKeccakProof {
+    /// The proof bytes.
+    pub proof: Vec<u8>,
+    /// The bincode serialized public inputs.
+    pub instances: Vec<u8>,
+    /// The keccak commitment of the committed data, as exposed by the proof.
+    pub digest: [u8; 32],
+}
+
+/// The commitment in the `output` instance column: the digest of the slot finishing the
+/// committed data, the slots before it being zero.
+fn commitment_from_output(output: &[Fr]) -> Option<[u8; 32]> {
+    (0..).map_while(|slot| digest_from_instance(output, slot)).find(|digest| *digest != [0; 32])
+}
+
+/// Encodes field elements (e.g. model inputs/outputs) as the byte string that gets committed to:
+/// the 32 byte little-endian representation of every element, concatenated.
+pub fn felts_to_bytes(felts: &[Fr]) -> Vec<u8> {
+    felts.iter().flat_map(|f| f.to_bytes()).collect()
+}
+
+/// Proves the keccak commitment of `data`.
+pub fn prove_commitment(
+    settings_json: &str,
+    srs_path: &Path,
+    pk_path: &Path,
+    data: &[u8],
+) -> Result<EzklKeccakProof, Keccak256Error> {
+    let settings = EzklKeccakSettings::from_json(settings_json)?;
+    settings.check()?;
+    let srs = io::read_srs_path(srs_path);
+    let pk = io::read_pk::<KeccakCircuit<Fr>>(pk_path, settings.config);
+
+    let (instances, proof) = generate_halo2_proof_for_inputs(vec![data.to_vec()], &srs, &pk, settings.config)
+        .map_err(|e| Keccak256Error(format!("Failed to generate the proof: {}", e)))?;
+    let digest = instances
+        .last()
+        .and_then(|output| commitment_from_output(output))
+        .ok_or_else(|| Keccak256Error("The proof exposes no digest".to_string()))?;
+    // A single instance vector, the columns concatenated
+    let instances = bincode::serialize(&InputsSerialisationWrapper(instances.concat()))
+        .map_err(|e| Keccak256Error(format!("Serialisation of Inputs failed: {}", e)))?;
+
+    Ok(EzklKeccakProof { proof, instances, digest })
+}
+
+/// Verifies a proof produced by [`prove_commitment`], and that the digest it exposes is the
+/// claimed commitment.
+pub fn verify_commitment(
+    settings_json: &str,
+    srs_path: &Path,
+    vk_path: &Path,
+    proof: &EzklKeccakProof,
+) -> Result<bool, Keccak256Error> {
+    let settings = EzklKeccakSettings::from_json(settings_json)?;
+    settings.check()?;
+    let srs = io::read_srs_path(srs_path);
+    let vk = io::read_vk::<KeccakCircuit<Fr>>(vk_path, settings.config);
+
+    let instances = bincode::deserialize::<InputsSerialisationWrapper>(&proof.instances)
+        .map_err(|e| Keccak256Error(e.to_string()))?
+        .0;
+
+    let verified = verify_halo2_proof(proof.proof.clone(), &instances, &srs, &vk, settings.config)
+        .map_err(|e| Keccak256Error(format!("Failed to verify the proof: {}", e)))?;
+    // The output column comes last, as long as in the circuit the keys were generated from
+    let output_len = KeccakCircuit::<Fr>::builder()
+        .config(settings.config)
+        .capacity_rows(2usize.pow(settings.config.k))
+        .build()?
+        .instances()
+        .last()
+        .map_or(0, Vec::len);
+    let output = &instances[instances.len().saturating_sub(output_len)..];
+    Ok(verified && commitment_from_output(output) == Some(proof.digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use sha3::{Digest, Keccak256};
+
+    use super::*;
+
+    #[test]
+    fn test_settings_roundtrip() {
+        let settings = EzklKeccakSettings::default();
+        let json = settings.to_json().unwrap();
+        let decoded = EzklKeccakSettings::from_json(&json).unwrap();
+        assert_eq!(decoded.config.k, settings.config.k);
+        assert_eq!(decoded.config.rows_per_round, settings.config.rows_per_round);
+    }
+
+    #[test]
+    fn test_settings_expose_the_digest() {
+        let settings = EzklKeccakSettings::default();
+        assert!(settings.check().is_ok());
+        let input = KeccakConfigParams { instance_mode: InstanceMode::Input, ..settings.config };
+        assert!(EzklKeccakSettings { config: input }.check().is_err());
+        let by_input = KeccakConfigParams { fixed_instance: false, ..settings.config };
+        assert!(EzklKeccakSettings { config: by_input }.check().is_err());
+    }
+
+    #[test]
+    fn test_commitment_from_output() {
+        let config = EzklKeccakSettings::default().config;
+        let data = vec![7u8; 300];
+        let circuit = KeccakCircuit::<Fr>::builder()
+            .config(config)
+            .capacity_rows(1 << 12)
+            .inputs(vec![data.clone()])
+            .build()
+            .unwrap();
+        let expected: [u8; 32] = Keccak256::digest(&data).into();
+        assert_eq!(commitment_from_output(circuit.instances().last().unwrap()), Some(expected));
+        assert_eq!(commitment_from_output(&[]), None);
+    }
+
+    #[test]
+    fn test_felts_to_bytes() {
+        let bytes = felts_to_bytes(&[Fr::from(1u64), Fr::from(2u64)]);
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(bytes[0], 1);
+        assert_eq!(bytes[32], 2);
+    }
+}
@@ -0,0 +1,211 @@
+//! One-call proving and verification of keccak256 hashes, for a first end-to-end flow:
+//!
+//! ```ignore
+//! let (digests, bundle) = prove_keccak256(&[b"hello".to_vec()])?;
+//! assert!(verify_keccak256(&bundle)?);
+//! ```
+//!
+//! The circuit is the smallest `k` at `rows_per_round = 5` whose capacity fits the inputs,
+//! exposing both inputs and digests with the fixed instance layout. SRS files come from an [`SrsStore`] in `$KECCAK_SRS_DIR`,
+//! or `halo2-keccak-srs` in the temporary directory; a missing one is generated locally, which
+//! is only fit for development, so production setups put a ceremony SRS there instead. Keys are
+//! generated on first use of a `k` and kept for the lifetime of the process.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use halo2_proofs::halo2curves::bn256::{Bn256, Fr, G1Affine};
+use halo2_proofs::plonk::ProvingKey;
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::generate_halo2_proof_for_inputs;
+use crate::config::ENV_PREFIX;
+use crate::encoding::{hex_bytes, hex_instances};
+use crate::progress::{observe, LogProgress, Stage};
+use crate::proof_system::{KzgShplonk, ProofSystem};
+use crate::srs::{SrsError, SrsStore};
+use crate::vanilla::keccak_packed_multi::{get_keccak_capacity, get_num_keccak_f};
+use crate::{digest_from_instance, message_digest_from_instance, InstanceMode, Keccak256Error, KeccakCircuit, KeccakConfigParams};
+
+/// Smallest `k` the facade picks.
+const MIN_K: u32 = 12;
+/// Largest `k` the facade picks, past which the inputs are rejected.
+const MAX_K: u32 = 22;
+/// Rows per round of the facade's circuits.
+const ROWS_PER_ROUND: usize = 5;
+
+lazy_static! {
+    static ref KEYS: Mutex<HashMap<u32, Arc<Keys>>> = Mutex::new(HashMap::new());
+}
+
+struct Keys {
+    srs: Arc<ParamsKZG<Bn256>>,
+    pk: ProvingKey<G1Affine>,
+}
+
+/// A proof from [`prove_keccak256`] with everything [`verify_keccak256`] needs, serialized
+/// with the [`encoding`](crate::encoding) of instances and proofs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProofBundle {
+    /// The parameters of the circuit the proof is for.
+    pub config: KeccakConfigParams,
+    /// The `input` and `output` instance columns.
+    #[serde(with = "hex_instances")]
+    pub instances: Vec<Vec<Fr>>,
+    /// The lengths of the inputs, which place their digests in the output column.
+    pub input_lengths: Vec<usize>,
+    #[serde(with = "hex_bytes")]
+    pub proof: Vec<u8>,
+}
+
+impl ProofBundle {
+    /// The digests the proof is for, in input order, each read at the keccak_f slot its input
+    /// finishes on.
+    pub fn digests(&self) -> Vec<[u8; 32]> {
+        let output = self.instances.get(1).map_or(&[][..], Vec::as_slice);
+        (0..self.input_lengths.len())
+            .map_while(|i| {
+                message_digest_from_instance(output, &self.input_lengths, i, &self.config)?.try_into().ok()
+            })
+            .collect()
+    }
+}
+
+/// Proves the keccak256 hashes of `inputs`, returning the digests and the proof.
+pub fn prove_keccak256(inputs: &[Vec<u8>]) -> Result<(Vec<[u8; 32]>, ProofBundle), Keccak256Error> {
+    let config = config_for(inputs)?;
+    let keys = keys(config)?;
+    let (instances, proof) = generate_halo2_proof_for_inputs(inputs.to_vec(), &*keys.srs, &keys.pk, config)
+        .map_err(|e| Keccak256Error(e.to_string()))?;
+    let input_lengths = inputs.iter().map(Vec::len).collect();
+    let bundle = ProofBundle { config, instances, input_lengths, proof };
+    Ok((bundle.digests(), bundle))
+}
+
+/// Verifies `bundle`, returning whether the proof is valid for its instances, and its input
+/// lengths end on the slots the proof finishes inputs on.
+pub fn verify_keccak256(bundle: &ProofBundle) -> Result<bool, Keccak256Error> {
+    if bundle.config.fingerprint() != config_for_k(bundle.config.k).fingerprint() {
+        return Err(Keccak256Error("The proof bundle wasn't produced by prove_keccak256".to_string()));
+    }
+    // Only the slots finishing an input have a nonzero digest, so lengths ending elsewhere
+    // would read the digests of other slots
+    let output = bundle.instances.get(1).map_or(&[][..], Vec::as_slice);
+    let final_slots = bundle.config.final_slots(&bundle.input_lengths);
+    for slot in 0..final_slots.last().map_or(0, |slot| slot + 1) {
+        match digest_from_instance(output, slot) {
+            Some(digest) if (digest == [0; 32]) != final_slots.binary_search(&slot).is_ok() => {}
+            _ => return Ok(false),
+        }
+    }
+    let keys = keys(bundle.config)?;
+    let instances = bundle.instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    Ok(KzgShplonk::<Bn256>::verify(&*keys.srs, keys.pk.get_vk(), &bundle.proof, &instances).is_ok())
+}
+
+fn config_for_k(k: u32) -> KeccakConfigParams {
+    KeccakConfigParams {
+        k,
+        rows_per_round: ROWS_PER_ROUND,
+        instance_mode: InstanceMode::InputOutput,
+        fixed_instance: true,
+        ..Default::default()
+    }
+}
+
+/// The config of the smallest `k` fitting `inputs`.
+fn config_for(inputs: &[Vec<u8>]) -> Result<KeccakConfigParams, Keccak256Error> {
+    let num_keccak_f = inputs.iter().map(|input| get_num_keccak_f(input.len())).sum::<usize>();
+    (MIN_K..=MAX_K)
+        .map(config_for_k)
+        .find(|config| get_keccak_capacity(config.usable_rows(), config.rows_per_round) >= num_keccak_f)
+        .ok_or_else(|| {
+            Keccak256Error(format!(
+                "{num_keccak_f} keccak_f exceed the capacity at k = {MAX_K}, configure a KeccakCircuit instead"
+            ))
+        })
+}
+
+/// The cached SRS and proving key of `config`, loaded or generated on first use. Generated
+/// without inputs, the keys only bind the inputs with the fixed instance layout.
+fn keys(config: KeccakConfigParams) -> Result<Arc<Keys>, Keccak256Error> {
+    config.check_fixed_instance()?;
+    // Held while generating, so concurrent first uses of a `k` don't generate it twice
+    let mut cache = KEYS.lock().unwrap();
+    if let Some(keys) = cache.get(&config.k) {
+        return Ok(Arc::clone(keys));
+    }
+    let srs = srs(config.k).map_err(|e| Keccak256Error(e.to_string()))?;
+    let circuit = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k))
+        .build()?;
+    let pk = KzgShplonk::<Bn256>::keygen_with_progress(&*srs, &circuit, &LogProgress)
+        .map_err(|e| Keccak256Error(format!("Failed to generate the keys: {:?}", e)))?;
+    let keys = Arc::new(Keys { srs, pk });
+    cache.insert(config.k, Arc::clone(&keys));
+    Ok(keys)
+}
+
+fn srs(k: u32) -> Result<Arc<ParamsKZG<Bn256>>, SrsError> {
+    let dir = std::env::var_os(format!("{ENV_PREFIX}SRS_DIR"))
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("halo2-keccak-srs"));
+    let store = SrsStore::new(dir);
+    match store.get(k) {
+        Err(SrsError::Missing { .. }) => {
+            store.insert(&observe(&LogProgress, Stage::Setup, || KzgShplonk::<Bn256>::setup(k)))?;
+            store.get(k)
+        }
+        srs => srs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha3::{Digest, Keccak256};
+
+    use super::*;
+
+    #[test]
+    fn test_prove_and_verify() {
+        let inputs = vec![b"abc".to_vec(), vec![], (0u8..200).collect()];
+        let (digests, bundle) = prove_keccak256(&inputs).unwrap();
+        assert_eq!(bundle.config.k, MIN_K);
+        assert_eq!(
+            digests,
+            inputs.iter().map(|input| Keccak256::digest(input).into()).collect::<Vec<[u8; 32]>>()
+        );
+        assert!(verify_keccak256(&bundle).unwrap());
+
+        let mut tampered = bundle.clone();
+        tampered.instances[1][0] += Fr::from(1);
+        assert!(!verify_keccak256(&tampered).unwrap());
+
+        // Lengths moving the digests to other slots
+        let mut tampered = bundle.clone();
+        tampered.input_lengths = vec![3, 200];
+        assert!(!verify_keccak256(&tampered).unwrap());
+        let mut tampered = bundle.clone();
+        tampered.input_lengths.swap(0, 2);
+        assert!(!verify_keccak256(&tampered).unwrap());
+
+        let mut tampered = bundle.clone();
+        tampered.config.instance_mode = InstanceMode::Input;
+        assert!(verify_keccak256(&tampered).is_err());
+        let mut tampered = bundle;
+        tampered.config.fixed_instance = false;
+        assert!(verify_keccak256(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_config_for() {
+        assert_eq!(config_for(&[vec![0u8; 100]]).unwrap().k, MIN_K);
+        let capacity = get_keccak_capacity(config_for_k(MIN_K).usable_rows(), ROWS_PER_ROUND);
+        let inputs = vec![vec![]; capacity + 1];
+        assert_eq!(config_for(&inputs).unwrap().k, MIN_K + 1);
+    }
+}
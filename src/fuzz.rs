@@ -0,0 +1,83 @@
+//! Entry points of the cargo-fuzz targets in `fuzz/`, exercising the paths a proving service
+//! exposes to untrusted input. Not a stable API.
+
+use std::collections::HashMap;
+
+use halo2_proofs::halo2curves::bn256::{Bn256, Fr, G1Affine};
+use halo2_proofs::halo2curves::ff::PrimeField;
+use halo2_proofs::plonk::VerifyingKey;
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use lazy_static::lazy_static;
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::{generate_halo2_proof, verify_halo2_proof};
+use crate::proof_system::{KzgShplonk, ProofSystem};
+use crate::schedule::{AbsorbScheduleIter, AbsorbStep};
+use crate::vanilla::witness::multi_keccak;
+use crate::{KeccakCircuit, KeccakConfigParams, SanityLevel, DEFAULT_CONFIG};
+
+/// Small enough for keygen to be quick.
+const FUZZ_CONFIG: KeccakConfigParams =
+    KeccakConfigParams { k: 12, rows_per_round: 5, sanity: SanityLevel::Off, ..DEFAULT_CONFIG };
+
+/// A valid proof to mutate, generated once per fuzzing process.
+struct ProofFixture {
+    srs: ParamsKZG<Bn256>,
+    vk: VerifyingKey<G1Affine>,
+    instances: Vec<Fr>,
+    proof: Vec<u8>,
+}
+
+impl ProofFixture {
+    fn new() -> Self {
+        let srs = KzgShplonk::<Bn256>::setup(FUZZ_CONFIG.k);
+        let circuit = KeccakCircuit::builder()
+            .config(FUZZ_CONFIG)
+            .capacity_rows(2usize.pow(FUZZ_CONFIG.k))
+            .build()
+            .unwrap();
+        let pk = KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap();
+        let vk = pk.get_vk().clone();
+
+        let input = b"fuzz".iter().map(|b| Fr::from(*b as u64)).collect();
+        let (instances, proof) =
+            generate_halo2_proof(HashMap::from([("in".to_string(), input)]), &srs, &pk, Some(FUZZ_CONFIG))
+                .unwrap();
+        ProofFixture { srs, vk, instances, proof }
+    }
+}
+
+lazy_static! {
+    static ref FIXTURE: ProofFixture = ProofFixture::new();
+}
+
+/// Generates the witness of `inputs` and checks every digest against the native implementation.
+pub fn check_witness(inputs: &[Vec<u8>]) {
+    let (rows, _) = multi_keccak::<Fr, _>(inputs, None, FUZZ_CONFIG);
+    for step in AbsorbScheduleIter::for_inputs(inputs, FUZZ_CONFIG.rows_per_round, FUZZ_CONFIG.digest_size) {
+        if let AbsorbStep::Final { input, row } = step {
+            assert!(rows[row].is_final);
+            let digest = Keccak256::digest(&inputs[input]);
+            let hi = Fr::from_u128(u128::from_be_bytes(digest[..16].try_into().unwrap()));
+            let lo = Fr::from_u128(u128::from_be_bytes(digest[16..].try_into().unwrap()));
+            rows[row].hash.hi().assert_if_known(|v| *v == hi);
+            rows[row].hash.lo().assert_if_known(|v| *v == lo);
+        }
+    }
+}
+
+/// XORs `mutation` into a valid proof and verifies it. Verification must neither panic nor
+/// accept a proof that differs from the original.
+pub fn verify_mutated_proof(mutation: &[(usize, u8)]) {
+    let fixture = &*FIXTURE;
+    let mut proof = fixture.proof.clone();
+    for (position, byte) in mutation {
+        let len = proof.len();
+        proof[position % len] ^= byte;
+    }
+    let mutated = proof != fixture.proof;
+    let verified =
+        verify_halo2_proof(proof, &fixture.instances, &fixture.srs, &fixture.vk, FUZZ_CONFIG)
+            .unwrap_or(false);
+    assert!(!(mutated && verified), "a mutated proof verified");
+}
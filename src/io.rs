@@ -1,7 +1,7 @@
 use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use halo2_proofs::halo2curves::bn256::{Bn256, Fr, G1Affine};
 use halo2_proofs::plonk::{Circuit, ProvingKey, VerifyingKey};
@@ -9,6 +9,8 @@ use halo2_proofs::poly::commitment::Params;
 use halo2_proofs::poly::kzg::commitment::ParamsKZG;
 use halo2_proofs::SerdeFormat::RawBytes;
 
+use crate::KeccakConfigParams;
+
 fn with_writer<E>(path: &Path, f: impl FnOnce(&mut BufWriter<File>) -> Result<(), E>)
 where
     E: fmt::Debug,
@@ -33,10 +35,30 @@ pub fn write_srs(srs: &ParamsKZG<Bn256>, path: &Path) {
     with_writer(path, |writer| srs.write(writer));
 }
 
-/// Write proving key and verification key to file.
-pub fn write_keys(pk: &ProvingKey<G1Affine>, pk_path: &Path, vk_path: &Path) {
+/// Write proving key and verification key to file, and the fingerprint of the `config` they were
+/// generated for next to the proving key.
+pub fn write_keys(pk: &ProvingKey<G1Affine>, pk_path: &Path, vk_path: &Path, config: KeccakConfigParams) {
     with_writer(pk_path, |writer| pk.write(writer, RawBytes));
     with_writer(vk_path, |writer| pk.get_vk().write(writer, RawBytes));
+    write_fingerprint(pk_path, config);
+}
+
+/// Path of the config fingerprint stored next to the proving key at `pk_path`.
+pub fn fingerprint_path(pk_path: &Path) -> PathBuf {
+    let mut path = pk_path.as_os_str().to_owned();
+    path.push(".fingerprint");
+    PathBuf::from(path)
+}
+
+/// Write the fingerprint of `config` next to the proving key at `pk_path`.
+pub fn write_fingerprint(pk_path: &Path, config: KeccakConfigParams) {
+    with_writer(&fingerprint_path(pk_path), |writer| writer.write_all(&config.fingerprint()));
+}
+
+/// Read the config fingerprint stored next to the proving key at `pk_path`, `None` if there is
+/// none.
+pub fn read_fingerprint(pk_path: &Path) -> Option<[u8; 32]> {
+    std::fs::read(fingerprint_path(pk_path)).ok()?.try_into().ok()
 }
 
 /// Read SRS from file.
@@ -0,0 +1,146 @@
+//! Keyed hashing `tag = keccak(key || message)` with a private key, the lightweight MAC used by
+//! many Ethereum contracts.
+//!
+//! The message is exposed through the `input` column as the [`PublicBytes::Suffix`] of the
+//! input and the tag through the `output` column. The key has a fixed length, a multiple of
+//! `NUM_BYTES_PER_WORD`, and its words stay private.
+//!
+//! The circuit has the [`disclosure_config`] of the base config, which masks the key words of
+//! the fixed instance layout, so keys generated without inputs serve every message and key
+//! length. The exposed `bytes_left` of every keccak_f pins the key to its length.
+
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::PublicBytes;
+use crate::disclosure::{disclosure_config, masked_instances};
+use crate::util::eth_types::Field;
+use crate::{Keccak256Error, KeccakCircuit, KeccakCircuitBuilder, KeccakConfigParams};
+
+/// The private key and the public message of a keyed hash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyedHash {
+    pub key: Vec<u8>,
+    pub message: Vec<u8>,
+}
+
+impl KeyedHash {
+    pub fn new(key: Vec<u8>, message: Vec<u8>) -> Self {
+        KeyedHash { key, message }
+    }
+
+    /// `key || message`, the message hashed by the circuit.
+    pub fn preimage(&self) -> Vec<u8> {
+        [self.key.as_slice(), self.message.as_slice()].concat()
+    }
+
+    /// The big-endian tag `keccak(key || message)`.
+    pub fn tag(&self) -> [u8; 32] {
+        Keccak256::digest(self.preimage()).into()
+    }
+
+    /// The bytes of the preimage exposed by the circuit, the message.
+    pub fn public_bytes(&self) -> PublicBytes {
+        PublicBytes::Suffix(self.key.len())
+    }
+
+    /// Builder of the circuit proving the tag of the public message under a private key, with
+    /// the [`disclosure_config`] of `config`. Needs `capacity_rows`, whose keys bind messages of
+    /// any length, as do those of [`KeccakProver`](crate::KeccakProver) with
+    /// [`Self::public_bytes`].
+    pub fn builder<F: Field>(&self, config: KeccakConfigParams) -> KeccakCircuitBuilder<F> {
+        KeccakCircuit::builder()
+            .config(disclosure_config(config))
+            .inputs(vec![self.preimage()])
+            .public_bytes(self.public_bytes())
+    }
+}
+
+/// The instance columns of the circuit from [`KeyedHash::builder`] with `config` and
+/// `capacity_rows`, computed from the key length, the message and the tag only.
+pub fn keyed_instances<F: Field>(
+    config: KeccakConfigParams,
+    capacity_rows: usize,
+    key_len: usize,
+    message: &[u8],
+    tag: [u8; 32],
+) -> Result<Vec<Vec<F>>, Keccak256Error> {
+    // Only the public part of the input matters, so any key of the right length will do
+    let input = [vec![0u8; key_len], message.to_vec()].concat();
+    masked_instances(
+        disclosure_config(config),
+        capacity_rows,
+        PublicBytes::Suffix(key_len),
+        vec![input],
+        &[tag.to_vec()],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::{Bn256, Fr};
+    use halo2_proofs::plonk::{keygen_pk, keygen_vk};
+    use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::circuit::verify_halo2_proof;
+    use crate::KeccakProver;
+
+    const CONFIG: KeccakConfigParams = KeccakConfigParams { k: 12, rows_per_round: 5, ..crate::DEFAULT_CONFIG };
+    const CAPACITY_ROWS: usize = (1 << CONFIG.k) - 109;
+
+    fn run(keyed: &KeyedHash, instances: Vec<Vec<Fr>>) -> bool {
+        let circuit = keyed.builder(CONFIG).capacity_rows(CAPACITY_ROWS).build().unwrap();
+        MockProver::run(CONFIG.k, &circuit, instances).unwrap().verify().is_ok()
+    }
+
+    #[test]
+    fn test_keyed_hash() {
+        let keyed = KeyedHash::new(vec![42u8; 32], b"transfer 100 to 0xabc".to_vec());
+        let tag = keyed.tag();
+        let instances = keyed_instances::<Fr>(CONFIG, CAPACITY_ROWS, 32, &keyed.message, tag).unwrap();
+        assert_eq!(instances[0][0], Fr::from(keyed.preimage().len() as u64));
+        assert!(instances[0][2..6].iter().all(|word| *word == Fr::from(0)));
+        assert!(run(&keyed, instances));
+
+        // The key length is bound: shifting the split between key and message is rejected
+        let shifted = keyed_instances::<Fr>(CONFIG, CAPACITY_ROWS, 24, &keyed.preimage()[24..], tag).unwrap();
+        assert!(!run(&keyed, shifted));
+
+        let forged = keyed_instances::<Fr>(CONFIG, CAPACITY_ROWS, 32, b"transfer 999 to 0xabc", tag).unwrap();
+        assert!(!run(&keyed, forged));
+    }
+
+    #[test]
+    fn test_keyed_proof() {
+        let config = disclosure_config(CONFIG);
+        let srs = ParamsKZG::<Bn256>::setup(config.k, OsRng);
+        let keygen = KeccakCircuit::<Fr>::builder().config(config).capacity_rows(1 << config.k).build().unwrap();
+        let vk = keygen_vk(&srs, &keygen).unwrap();
+        let pk = keygen_pk(&srs, vk.clone(), &keygen).unwrap();
+        let verify = |proof: &[u8], key_len, message: &[u8], tag| {
+            let instances = keyed_instances::<Fr>(CONFIG, 1 << CONFIG.k, key_len, message, tag).unwrap();
+            verify_halo2_proof(proof.to_vec(), &instances.concat(), &srs, &vk, config).unwrap()
+        };
+
+        // One key serves messages and keys of every length
+        for keyed in [
+            KeyedHash::new(vec![42u8; 32], b"transfer 100 to 0xabc".to_vec()),
+            KeyedHash::new(vec![7u8; 16], vec![1u8; 300]),
+        ] {
+            let prover = KeccakProver::new(srs.clone(), pk.clone(), config).with_public_bytes(keyed.public_bytes());
+            let (_, proof) = prover.prove(vec![keyed.preimage()]).unwrap();
+            assert!(verify(&proof, keyed.key.len(), &keyed.message, keyed.tag()));
+            assert!(!verify(&proof, keyed.key.len(), b"transfer 999 to 0xabc", keyed.tag()));
+            assert!(!verify(&proof, keyed.key.len() - 8, &keyed.preimage()[keyed.key.len() - 8..], keyed.tag()));
+        }
+    }
+
+    #[test]
+    fn test_unaligned_key() {
+        let keyed = KeyedHash::new(vec![1u8; 20], b"message".to_vec());
+        assert!(keyed.builder::<Fr>(CONFIG).capacity_rows(CAPACITY_ROWS).build().is_err());
+        assert!(keyed_instances::<Fr>(CONFIG, CAPACITY_ROWS, 20, &keyed.message, keyed.tag()).is_err());
+    }
+}
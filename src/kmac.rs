@@ -0,0 +1,254 @@
+//! cSHAKE256 and KMAC256 of NIST SP 800-185, with 256 bit outputs, on top of the keccak circuit
+//! with [`Padding::CShake`].
+//!
+//! cSHAKE256 prepends `bytepad(encode_string(N) || encode_string(S), 136)` to the message, whole
+//! blocks of their own, and pads with the `00` domain separation bits, so the circuit proves it
+//! as the hash of the prefixed message. KMAC256 is the cSHAKE256 with `N = "KMAC"` of
+//! `bytepad(encode_string(K), 136) || X || right_encode(L)`. The key blocks stay private through
+//! [`PublicBytes::Except`], masked out of the fixed instance layout of the [`kmac_config`], while
+//! the customization block and the message are exposed through the `input` column and the tag
+//! through the `output` column. The length of the preimage is public, which binds the number of
+//! key blocks but not the length of the key inside them, and keys generated without inputs
+//! serve every key and message.
+
+use crate::circuit::PublicBytes;
+use crate::config::Padding;
+use crate::disclosure::{disclosure_config, masked_instances};
+use crate::util::eth_types::Field;
+use crate::vanilla::param::RATE;
+use crate::{Keccak256Error, KeccakCircuit, KeccakCircuitBuilder, KeccakConfigParams};
+
+/// The output length `L` of KMAC256, in bits, that of the digest.
+const OUTPUT_BITS: u64 = 256;
+
+/// The big-endian bytes of `x`, at least one.
+fn be_bytes(x: u64) -> Vec<u8> {
+    let skip = (x.leading_zeros() / 8).min(7) as usize;
+    x.to_be_bytes()[skip..].to_vec()
+}
+
+/// `left_encode(x)`: the number of bytes of `x`, then its big-endian bytes.
+pub fn left_encode(x: u64) -> Vec<u8> {
+    let bytes = be_bytes(x);
+    [vec![bytes.len() as u8], bytes].concat()
+}
+
+/// `right_encode(x)`: the big-endian bytes of `x`, then their number.
+pub fn right_encode(x: u64) -> Vec<u8> {
+    let bytes = be_bytes(x);
+    let len = bytes.len() as u8;
+    [bytes, vec![len]].concat()
+}
+
+/// `encode_string(s)`: the length of `s` in bits, then `s`.
+pub fn encode_string(s: &[u8]) -> Vec<u8> {
+    [left_encode(8 * s.len() as u64), s.to_vec()].concat()
+}
+
+/// `bytepad(x, w)`: `left_encode(w) || x`, zero padded to a multiple of `w` bytes.
+pub fn bytepad(x: &[u8], w: usize) -> Vec<u8> {
+    let mut padded = [left_encode(w as u64), x.to_vec()].concat();
+    padded.resize((padded.len() + w - 1) / w * w, 0);
+    padded
+}
+
+/// A cSHAKE256 function, its function name `N` and customization string `S`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CShake {
+    pub function_name: Vec<u8>,
+    pub customization: Vec<u8>,
+}
+
+impl CShake {
+    /// Fails if both are empty, as cSHAKE256 is then SHAKE256, which pads differently.
+    pub fn new(function_name: Vec<u8>, customization: Vec<u8>) -> Result<Self, Keccak256Error> {
+        if function_name.is_empty() && customization.is_empty() {
+            return Err(Keccak256Error(
+                "cSHAKE256 without a function name nor a customization is SHAKE256".to_string(),
+            ));
+        }
+        Ok(CShake { function_name, customization })
+    }
+
+    /// The blocks prepended to every message.
+    pub fn prefix(&self) -> Vec<u8> {
+        bytepad(&[encode_string(&self.function_name), encode_string(&self.customization)].concat(), RATE)
+    }
+
+    /// The message hashed by the circuit.
+    pub fn preimage(&self, message: &[u8]) -> Vec<u8> {
+        [self.prefix(), message.to_vec()].concat()
+    }
+
+    /// The big-endian 256 bit output for `message`.
+    pub fn digest(&self, message: &[u8]) -> [u8; 32] {
+        Padding::CShake.digest(&self.preimage(message))
+    }
+
+    /// Builder of the circuit hashing `messages` with `config`, its padding set to cSHAKE. An
+    /// instance mode exposing the inputs exposes the prefix as well, binding `N` and `S`.
+    pub fn builder<F: Field>(&self, messages: &[Vec<u8>], config: KeccakConfigParams) -> KeccakCircuitBuilder<F> {
+        KeccakCircuit::builder()
+            .config(KeccakConfigParams { padding: Padding::CShake, ..config })
+            .inputs(messages.iter().map(|message| self.preimage(message)).collect())
+    }
+}
+
+/// A KMAC256 key and its customization string `S`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Kmac {
+    pub key: Vec<u8>,
+    pub customization: Vec<u8>,
+}
+
+impl Kmac {
+    pub fn new(key: Vec<u8>, customization: Vec<u8>) -> Self {
+        Kmac { key, customization }
+    }
+
+    fn cshake(&self) -> CShake {
+        CShake { function_name: b"KMAC".to_vec(), customization: self.customization.clone() }
+    }
+
+    /// `bytepad(encode_string(K), 136)`, the private blocks of the preimage.
+    pub fn key_blocks(&self) -> Vec<u8> {
+        bytepad(&encode_string(&self.key), RATE)
+    }
+
+    /// The message hashed by the circuit: the customization block, the key blocks, then
+    /// `message` and the output length.
+    pub fn preimage(&self, message: &[u8]) -> Vec<u8> {
+        self.cshake().preimage(&[self.key_blocks(), message.to_vec(), right_encode(OUTPUT_BITS)].concat())
+    }
+
+    /// The big-endian tag of `message`.
+    pub fn tag(&self, message: &[u8]) -> [u8; 32] {
+        Padding::CShake.digest(&self.preimage(message))
+    }
+
+    /// The bytes of the preimage exposed by the circuit, all but the key blocks.
+    pub fn public_bytes(&self) -> PublicBytes {
+        let start = self.cshake().prefix().len();
+        PublicBytes::Except { start, end: start + self.key_blocks().len() }
+    }
+
+    /// Builder of the circuit proving the tag of the public `message` under the private key,
+    /// with the [`kmac_config`] of `config`. Needs `capacity_rows`, whose keys serve every key
+    /// and message, as do those of [`KeccakProver`](crate::KeccakProver) with
+    /// [`Self::public_bytes`].
+    pub fn builder<F: Field>(&self, message: &[u8], config: KeccakConfigParams) -> KeccakCircuitBuilder<F> {
+        KeccakCircuit::builder()
+            .config(kmac_config(config))
+            .inputs(vec![self.preimage(message)])
+            .public_bytes(self.public_bytes())
+    }
+}
+
+/// The [`disclosure_config`] of `config`, its padding set to cSHAKE.
+pub fn kmac_config(config: KeccakConfigParams) -> KeccakConfigParams {
+    KeccakConfigParams { padding: Padding::CShake, ..disclosure_config(config) }
+}
+
+/// The instance columns of the circuit from [`Kmac::builder`] with `config` and
+/// `capacity_rows`, computed from the key length, the customization, the message and the tag
+/// only.
+pub fn kmac_instances<F: Field>(
+    config: KeccakConfigParams,
+    capacity_rows: usize,
+    key_len: usize,
+    customization: &[u8],
+    message: &[u8],
+    tag: [u8; 32],
+) -> Result<Vec<Vec<F>>, Keccak256Error> {
+    // Only the public part of the preimage matters, so any key of the right length will do
+    let kmac = Kmac::new(vec![0u8; key_len], customization.to_vec());
+    masked_instances(
+        kmac_config(config),
+        capacity_rows,
+        kmac.public_bytes(),
+        vec![kmac.preimage(message)],
+        &[tag.to_vec()],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use sha3::digest::{ExtendableOutput, Update, XofReader};
+    use sha3::{CShake256, CShake256Core};
+
+    use super::*;
+    use crate::circuit::pack_digests_to_instance;
+    use crate::{InstanceMode, DEFAULT_CONFIG};
+
+    const CONFIG: KeccakConfigParams = KeccakConfigParams { k: 12, rows_per_round: 5, ..DEFAULT_CONFIG };
+    const CAPACITY_ROWS: usize = (1 << CONFIG.k) - 109;
+
+    fn reference(function_name: &[u8], customization: &[u8], message: &[u8]) -> [u8; 32] {
+        let mut hasher = CShake256::from_core(CShake256Core::new_with_function_name(function_name, customization));
+        hasher.update(message);
+        let mut out = [0u8; 32];
+        hasher.finalize_xof().read(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(left_encode(0), [1, 0]);
+        assert_eq!(left_encode(136), [1, 136]);
+        assert_eq!(left_encode(256), [2, 1, 0]);
+        assert_eq!(right_encode(256), [1, 0, 2]);
+        assert_eq!(encode_string(b"KMAC"), [1, 32, b'K', b'M', b'A', b'C']);
+        assert_eq!(bytepad(&[], 136).len(), 136);
+        assert_eq!(bytepad(&[7u8; 134], 136).len(), 136);
+        assert_eq!(bytepad(&[7u8; 135], 136).len(), 272);
+    }
+
+    #[test]
+    fn test_cshake_matches_reference() {
+        assert!(CShake::new(vec![], vec![]).is_err());
+        for (function_name, customization) in [(&b""[..], &b"Email Signature"[..]), (&b"KMAC"[..], &[9u8; 200][..])] {
+            let cshake = CShake::new(function_name.to_vec(), customization.to_vec()).unwrap();
+            for message in [vec![], vec![1, 2, 3], vec![5u8; 300]] {
+                assert_eq!(cshake.digest(&message), reference(function_name, customization, &message));
+            }
+        }
+    }
+
+    #[test]
+    fn test_cshake_circuit() {
+        let cshake = CShake::new(vec![], b"Email Signature".to_vec()).unwrap();
+        let messages = vec![vec![], (0u8..200).collect()];
+        let config = KeccakConfigParams { instance_mode: InstanceMode::InputOutput, ..CONFIG };
+        let circuit = cshake.builder::<Fr>(&messages, config).build().unwrap();
+        let instances = circuit.instances();
+        let digests = messages.iter().map(|message| cshake.digest(message)).collect::<Vec<_>>();
+        assert_eq!(instances[1], pack_digests_to_instance::<Fr, _>(&digests));
+        MockProver::run(CONFIG.k, &circuit, instances).unwrap().assert_satisfied();
+    }
+
+    #[test]
+    fn test_kmac() {
+        let kmac = Kmac::new((0x40u8..0x60).collect(), b"My Tagged Application".to_vec());
+        let message = b"transfer 100 to 0xabc".to_vec();
+        let tag = kmac.tag(&message);
+        let padded = [kmac.key_blocks(), message.clone(), right_encode(256)].concat();
+        assert_eq!(tag, reference(b"KMAC", &kmac.customization, &padded));
+
+        let circuit = kmac.builder::<Fr>(&message, CONFIG).capacity_rows(CAPACITY_ROWS).build().unwrap();
+        let instances = |key_len, customization: &[u8], message: &[u8]| {
+            kmac_instances::<Fr>(CONFIG, CAPACITY_ROWS, key_len, customization, message, tag).unwrap()
+        };
+        let run = |instances| MockProver::run(CONFIG.k, &circuit, instances).unwrap().verify().is_ok();
+        let expected = instances(kmac.key.len(), &kmac.customization, &message);
+        // The key blocks are masked out, so the instance doesn't depend on the key
+        assert_eq!(circuit.instances(), expected);
+        assert!(run(expected));
+        assert!(!run(instances(kmac.key.len(), &kmac.customization, b"transfer 999 to 0xabc")));
+        // The customization block is public
+        assert!(!run(instances(kmac.key.len(), b"Other Application", &message)));
+        // The number of key blocks is bound
+        assert!(!run(instances(200, &kmac.customization, &message)));
+    }
+}
@@ -5,13 +5,42 @@
 //! The circuits can be configured to use different numbers of columns, by specifying the number of rows per internal
 //! round of the keccak_f permutation.
 
+#[cfg(all(feature = "halo2-pse", feature = "halo2-axiom"))]
+compile_error!("Features `halo2-pse` and `halo2-axiom` are mutually exclusive");
+#[cfg(not(any(feature = "halo2-pse", feature = "halo2-axiom")))]
+compile_error!("One of the features `halo2-pse` or `halo2-axiom` must be enabled");
+
+#[cfg(feature = "halo2-axiom")]
+extern crate halo2_axiom as halo2_proofs;
+
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::path::Path;
 use halo2_proofs::halo2curves::bn256::Fr;
+use halo2_proofs::plonk;
 use thiserror::Error;
-pub use circuit::{KeccakCircuit};
-pub use vanilla::KeccakConfigParams;
+pub use circuit::{
+    accumulator_indices, digest_bytes_from_instance, digest_from_instance, input_cells, message_digest_from_instance, verify_digest, with_accumulator,
+    DefaultEngine, InputCells, KeccakCircuit, KeccakCircuitBuilder, PublicBytes,
+    ACCUMULATOR_INSTANCE_LEN, ACCUMULATOR_LIMBS, ACCUMULATOR_LIMB_BITS, DIGEST_INSTANCE_STRIDE,
+};
+/// The circuit configuration and row types, for crates embedding the keccak circuit in their
+/// own. These root paths are the stable API: they only change in a breaking release, while the
+/// module layout behind them may change at any commit.
+pub use vanilla::{keccak_packed_multi::KeccakRow, KeccakAssignedRow, KeccakCircuitConfig, KeccakConfigParams};
+/// Constants of the keccak permutation and of the instance layout, with the same stability as
+/// the types above.
+pub use vanilla::param;
+pub use vanilla::inspect::inspect_witness;
+pub use vanilla::witness::{multi_keccak_with_states, RoundStates};
+pub use config::{
+    DigestSize, InputBinding, InstanceMode, LookupLayout, Padding, ProverCost, SanityLevel, TranscriptKind,
+};
+pub use prover::{
+    prove_keccak, verify_keccak, KeccakProver, ProofTimings, ProverError, ProverLimits, VerifierError,
+};
+pub use schedule::HashSchedule;
+pub use facade::{prove_keccak256, verify_keccak256, ProofBundle};
 use crate::circuit::{generate_halo2_proof, verify_halo2_proof};
 use crate::serialisation::{deserialize_circuit_inputs, InputsSerialisationWrapper};
 
@@ -20,7 +49,37 @@ mod vanilla;
 mod util;
 
 mod circuit;
+mod facade;
+mod schedule;
+pub mod config;
 pub mod io;
+pub mod prover;
+pub mod proof_system;
+pub mod bridge;
+pub mod commit_reveal;
+pub mod disclosure;
+pub mod eip712;
+pub mod encoding;
+pub mod keyed;
+pub mod kmac;
+pub mod membership;
+pub mod planner;
+pub mod progress;
+pub mod queue;
+pub mod salted;
+pub mod solidity;
+pub mod srs;
+#[cfg(feature = "coprocessor")]
+pub mod promise;
+#[cfg(feature = "ezkl")]
+pub mod ezkl;
+#[cfg(feature = "evm")]
+pub mod evm;
+#[cfg(feature = "recursion")]
+pub mod recursion;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzz;
 
 #[cfg(test)]
 mod tests;
@@ -29,6 +88,19 @@ mod serialisation;
 pub const DEFAULT_CONFIG : KeccakConfigParams = KeccakConfigParams {
     k: 14,
     rows_per_round: 28,
+    instance_mode: InstanceMode::Input,
+    transcript: TranscriptKind::Blake2b,
+    sanity: SanityLevel::Full,
+    max_degree: 0,
+    lookup_layout: LookupLayout::Split,
+    input_binding: InputBinding::Word,
+    input_columns: 1,
+    round_constants: None,
+    padding: Padding::Keccak,
+    digest_size: DigestSize::Bits256,
+    max_input_len: None,
+    fixed_instance: true,
+    selective_disclosure: false,
 };
 
 #[derive(Debug, Error)]
@@ -40,6 +112,54 @@ impl Display for Keccak256Error {
     }
 }
 
+/// Errors of proving and verifying with the circuit.
+#[derive(Debug, Error)]
+pub enum KeccakCircuitError {
+    /// The circuit parameters or inputs are rejected, see the message.
+    #[error(transparent)]
+    InvalidCircuit(#[from] Keccak256Error),
+    /// A named value is missing from the proof inputs.
+    #[error("`{0}` value not found in proof input")]
+    MissingInput(&'static str),
+    /// An input element isn't a byte.
+    #[error("input element {index} is not a byte")]
+    NotAByte { index: usize },
+    /// The instance has fewer cells than the instance layout of the config.
+    #[error("{got} instance cells, fewer than the {expected} cells of the layout")]
+    InstanceTooShort { expected: usize, got: usize },
+    /// The instance has more cells than the instance layout of the config.
+    #[error("{got} instance cells, more than the {expected} cells of the layout")]
+    InstanceTooLong { expected: usize, got: usize },
+    /// The deadline of the proof passed before it started.
+    #[error("the deadline passed before the proof started")]
+    DeadlinePassed,
+    /// The witness disagrees with the native computation, a bug of the witness generation.
+    #[error("witness mismatch: {0}")]
+    WitnessMismatch(String),
+    /// halo2 failed to generate the proof, e.g. with `Synthesis` once a deadline passes or a
+    /// witness mismatches.
+    #[error("halo2 failed: {0}")]
+    Halo2(#[from] plonk::Error),
+}
+
+impl KeccakCircuitError {
+    /// A numeric code of the variant, for callers that can't match on it, e.g. across an FFI
+    /// boundary. Codes are stable: a new variant takes a new code and codes are never reused.
+    /// They are in `100..200`, [`ProverError`] and [`VerifierError`] codes follow.
+    pub fn code(&self) -> u16 {
+        match self {
+            KeccakCircuitError::InvalidCircuit(_) => 100,
+            KeccakCircuitError::MissingInput(_) => 101,
+            KeccakCircuitError::NotAByte { .. } => 102,
+            KeccakCircuitError::InstanceTooShort { .. } => 103,
+            KeccakCircuitError::DeadlinePassed => 104,
+            KeccakCircuitError::WitnessMismatch(_) => 105,
+            KeccakCircuitError::Halo2(_) => 106,
+            KeccakCircuitError::InstanceTooLong { .. } => 107,
+        }
+    }
+}
+
 pub fn prove(
     srs_key_path: &str,
     proving_key_path: &str,
@@ -93,8 +213,7 @@ pub fn verify(
         circuit_config,
     );
 
-    let is_valid =
-        verify_halo2_proof(proof, &deserialized_inputs, &srs, &verifying_key).unwrap();
+    let is_valid = verify_halo2_proof(proof, &deserialized_inputs, &srs, &verifying_key, circuit_config)?;
 
     Ok(is_valid)
 }
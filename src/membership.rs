@@ -0,0 +1,195 @@
+//! A `(key, hash_hi, hash_lo)` membership table of digests proven by the keccak circuit, for
+//! host circuits referencing the same message in many statements.
+//!
+//! Each unique message is hashed once. Its digest is copied into the table together with a
+//! `key` naming the message, any field element the host binds to the input, e.g. a Poseidon or
+//! RLC commitment of the word cells it copied out through the bridge. Every statement then
+//! looks the pair up, which costs a single row instead of another keccak. [`dedup_inputs`]
+//! builds the unique inputs to hash and the table entry of every occurrence.
+
+use std::collections::HashMap;
+
+use halo2_proofs::circuit::{Layouter, Value};
+use halo2_proofs::plonk::{Advice, Column, ConstraintSystem, Error, Selector};
+use halo2_proofs::poly::Rotation;
+
+use crate::bridge::SharedCell;
+use crate::util::assign_value::{assigned_value, constrain_equal, raw_assign_advice};
+use crate::util::eth_types::Field;
+use crate::util::Halo2AssignedCell;
+
+/// The unique messages of `inputs` in order of first occurrence, and for every input the
+/// index of its message, i.e. of its table entry. Proving the unique messages in this order,
+/// the digest of `inputs[i]` is the one of `unique[entries[i]]`.
+pub fn dedup_inputs<B: AsRef<[u8]>>(inputs: &[B]) -> (Vec<Vec<u8>>, Vec<usize>) {
+    let mut unique = vec![];
+    let mut index = HashMap::new();
+    let entries = inputs
+        .iter()
+        .map(|input| {
+            *index.entry(input.as_ref()).or_insert_with(|| {
+                unique.push(input.as_ref().to_vec());
+                unique.len() - 1
+            })
+        })
+        .collect();
+    (unique, entries)
+}
+
+/// Configuration of the membership table. Table entries and lookups share the three columns,
+/// told apart by their selectors.
+#[derive(Clone, Debug)]
+pub struct KeccakMembershipConfig {
+    /// The `key`, `hash_hi` and `hash_lo` columns, equality enabled.
+    pub columns: [Column<Advice>; 3],
+    q_table: Selector,
+    q_lookup: Selector,
+}
+
+impl KeccakMembershipConfig {
+    /// Configures the membership table.
+    pub fn configure<F: Field>(meta: &mut ConstraintSystem<F>) -> Self {
+        let columns = [(); 3].map(|_| meta.advice_column());
+        for column in columns {
+            meta.enable_equality(column);
+        }
+        let q_table = meta.complex_selector();
+        let q_lookup = meta.complex_selector();
+
+        // The selectors are looked up as well: a disabled lookup row is `(0, 0, 0, 0)`, which the
+        // disabled table rows match, while an enabled one is `(1, ..)`, only found in the entries.
+        meta.lookup_any("keccak membership", |meta| {
+            let q_table = meta.query_selector(q_table);
+            let q_lookup = meta.query_selector(q_lookup);
+            std::iter::once((q_lookup.clone(), q_table.clone()))
+                .chain(columns.iter().map(|column| {
+                    let value = meta.query_advice(*column, Rotation::cur());
+                    (q_lookup.clone() * value.clone(), q_table.clone() * value)
+                }))
+                .collect()
+        });
+
+        KeccakMembershipConfig { columns, q_table, q_lookup }
+    }
+
+    /// Copies the table entries, each the `key` of a message followed by the `hash_hi`/`hash_lo`
+    /// cells of its final row, into the table.
+    pub fn assign_table<F: Field>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        entries: &[[Halo2AssignedCell<'_, F>; 3]],
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "membership table",
+            |mut region| {
+                for (offset, entry) in entries.iter().enumerate() {
+                    self.q_table.enable(&mut region, offset)?;
+                    for (column, cell) in self.columns.iter().zip(entry) {
+                        let assigned = raw_assign_advice(&mut region, *column, offset, assigned_value(cell));
+                        constrain_equal(&mut region, cell.cell(), assigned.cell())?;
+                    }
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Looks up `(key, hash_hi, hash_lo)` in the table, returning the cells for the host to
+    /// copy into its statement.
+    pub fn lookup<'v, F: Field>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        entry: [Value<F>; 3],
+    ) -> Result<[SharedCell<'v, F>; 3], Error> {
+        layouter.assign_region(
+            || "membership lookup",
+            |mut region| {
+                self.q_lookup.enable(&mut region, 0)?;
+                Ok(array_init::array_init(|i| raw_assign_advice(&mut region, self.columns[i], 0, entry[i])))
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::circuit::SimpleFloorPlanner;
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use halo2_proofs::plonk::Circuit;
+
+    use super::*;
+
+    /// Table entries assigned in a column standing for the keccak circuit's, then looked up.
+    #[derive(Default)]
+    struct MembershipTestCircuit {
+        entries: Vec<[u64; 3]>,
+        lookups: Vec<[u64; 3]>,
+    }
+
+    impl Circuit<Fr> for MembershipTestCircuit {
+        type Config = (Column<Advice>, KeccakMembershipConfig);
+        type FloorPlanner = SimpleFloorPlanner;
+        type Params = ();
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let digests = meta.advice_column();
+            meta.enable_equality(digests);
+            (digests, KeccakMembershipConfig::configure(meta))
+        }
+
+        fn synthesize(
+            &self,
+            (digests, membership): Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            let entries = layouter.assign_region(
+                || "digests",
+                |mut region| {
+                    Ok(self
+                        .entries
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, entry)| {
+                            array_init::array_init(|i| {
+                                raw_assign_advice(&mut region, digests, 3 * idx + i, Value::known(Fr::from(entry[i])))
+                            })
+                        })
+                        .collect::<Vec<_>>())
+                },
+            )?;
+            membership.assign_table(layouter.namespace(|| "table"), &entries)?;
+            for lookup in &self.lookups {
+                membership.lookup(layouter.namespace(|| "lookup"), lookup.map(|v| Value::known(Fr::from(v))))?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_membership_lookup() {
+        let entries = vec![[1, 10, 11], [2, 20, 21]];
+        let run = |lookups: Vec<[u64; 3]>| {
+            let circuit = MembershipTestCircuit { entries: entries.clone(), lookups };
+            MockProver::run(6, &circuit, vec![]).unwrap().verify()
+        };
+
+        assert!(run(vec![[2, 20, 21], [1, 10, 11], [2, 20, 21]]).is_ok());
+        // The digest of another message, or of no message at all
+        assert!(run(vec![[1, 20, 21]]).is_err());
+        assert!(run(vec![[3, 30, 31]]).is_err());
+        // Nor the all-zero entry of the rows outside the table
+        assert!(run(vec![[0, 0, 0]]).is_err());
+    }
+
+    #[test]
+    fn test_dedup_inputs() {
+        let (unique, entries) = dedup_inputs(&[b"a".as_slice(), b"b", b"a", b"", b"b"]);
+        assert_eq!(unique, vec![b"a".to_vec(), b"b".to_vec(), vec![]]);
+        assert_eq!(entries, vec![0, 1, 0, 2, 1]);
+    }
+}
@@ -0,0 +1,161 @@
+//! Partitioning of a large hashing workload into proofs.
+//!
+//! Every proof pays for its whole circuit, however many of its keccak_f slots are used, so a
+//! workload costs as many proofs as it takes batches. [`plan_batches`] packs the messages with
+//! first-fit decreasing on their keccak_f count, which uses at most 11/9 of the optimal number
+//! of batches plus one.
+//!
+//! The plan only depends on the message sizes: the batches come in first-fit order, and each
+//! lists its messages in request order, which is the order to pass them to the circuit in.
+//! [`digests_in_request_order`] maps the digests of the proofs back to the workload.
+
+use std::time::Duration;
+
+use crate::vanilla::keccak_packed_multi::get_keccak_capacity;
+use crate::{Keccak256Error, KeccakConfigParams};
+
+/// Rough proving cost per byte of [`KeccakConfigParams::estimate_prover_memory`], on a single
+/// core. The prover's FFTs and MSMs stream through that data a bounded number of times, which
+/// makes it a fair proxy for the work; calibrate against a measured proof before relying on it.
+const PROVING_NANOS_PER_BYTE: u64 = 20;
+
+/// A set of messages proven together.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Batch {
+    /// Indices of the messages in the workload, in increasing order.
+    pub inputs: Vec<usize>,
+    /// Number of keccak_f the messages take.
+    pub num_keccak_f: usize,
+    /// Share of the circuit's keccak_f capacity the messages take.
+    pub utilization: f64,
+    /// Estimated single core proving time, the same for every batch of a config.
+    pub estimated_proving_time: Duration,
+}
+
+/// Partitions messages of `message_sizes` bytes into batches fitting the keccak_f capacity of
+/// `config`, each to be proven with `capacity_rows = 2^k`. Fails if a message alone exceeds it.
+pub fn plan_batches(message_sizes: &[usize], config: KeccakConfigParams) -> Result<Vec<Batch>, Keccak256Error> {
+    let capacity = get_keccak_capacity(config.usable_rows(), config.rows_per_round);
+    let mut order = (0..message_sizes.len()).collect::<Vec<_>>();
+    // Largest first, ties in request order so the plan is deterministic
+    order.sort_by_key(|idx| std::cmp::Reverse(config.num_keccak_f(message_sizes[*idx])));
+
+    let mut bins: Vec<(Vec<usize>, usize)> = vec![];
+    for idx in order {
+        let num_keccak_f = config.num_keccak_f(message_sizes[idx]);
+        if num_keccak_f > capacity {
+            return Err(Keccak256Error(format!(
+                "Message {idx} of {} bytes needs {num_keccak_f} keccak_f, more than the capacity of {capacity} at k = {}, rows_per_round = {}",
+                message_sizes[idx], config.k, config.rows_per_round
+            )));
+        }
+        match bins.iter_mut().find(|(_, used)| used + num_keccak_f <= capacity) {
+            Some((inputs, used)) => {
+                inputs.push(idx);
+                *used += num_keccak_f;
+            }
+            None => bins.push((vec![idx], num_keccak_f)),
+        }
+    }
+
+    let estimated_proving_time = Duration::from_nanos(config.estimate_prover_memory() * PROVING_NANOS_PER_BYTE);
+    Ok(bins
+        .into_iter()
+        .map(|(mut inputs, num_keccak_f)| {
+            inputs.sort_unstable();
+            Batch {
+                inputs,
+                num_keccak_f,
+                utilization: num_keccak_f as f64 / capacity as f64,
+                estimated_proving_time,
+            }
+        })
+        .collect())
+}
+
+/// The digests of the whole workload in request order, from the digests of every batch in the
+/// order of its [`Batch::inputs`], e.g. read with [`digest_from_instance`](crate::digest_from_instance).
+pub fn digests_in_request_order(
+    batches: &[Batch],
+    batch_digests: &[Vec<[u8; 32]>],
+) -> Result<Vec<[u8; 32]>, Keccak256Error> {
+    if batches.len() != batch_digests.len() {
+        return Err(Keccak256Error(format!(
+            "{} batches but digests for {}",
+            batches.len(),
+            batch_digests.len()
+        )));
+    }
+    let num_inputs = batches.iter().map(|batch| batch.inputs.len()).sum();
+    let mut digests = vec![None; num_inputs];
+    for (idx, (batch, batch_digests)) in batches.iter().zip(batch_digests).enumerate() {
+        if batch.inputs.len() != batch_digests.len() {
+            return Err(Keccak256Error(format!(
+                "Batch {idx} has {} inputs but {} digests",
+                batch.inputs.len(),
+                batch_digests.len()
+            )));
+        }
+        for (input, digest) in batch.inputs.iter().zip(batch_digests) {
+            match digests.get_mut(*input) {
+                Some(slot @ None) => *slot = Some(*digest),
+                _ => return Err(Keccak256Error(format!("Batch {idx} holds input {input} out of the plan"))),
+            }
+        }
+    }
+    Ok(digests.into_iter().map(Option::unwrap).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vanilla::param::RATE;
+
+    #[test]
+    fn test_plan_batches() {
+        let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+        let capacity = get_keccak_capacity(config.usable_rows(), config.rows_per_round);
+        // One message taking all but one keccak_f, the first small one filling its batch
+        let sizes = [10, (capacity - 2) * RATE, 0, 20, 30];
+        let batches = plan_batches(&sizes, config).unwrap();
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].inputs, vec![0, 1]);
+        assert_eq!(batches[0].num_keccak_f, capacity);
+        assert_eq!(batches[0].utilization, 1.0);
+        assert_eq!(batches[1].inputs, vec![2, 3, 4]);
+        let mut all = batches.iter().flat_map(|batch| batch.inputs.clone()).collect::<Vec<_>>();
+        all.sort_unstable();
+        assert_eq!(all, (0..sizes.len()).collect::<Vec<_>>());
+        assert!(batches[0].estimated_proving_time > Duration::ZERO);
+
+        assert_eq!(plan_batches(&sizes, config).unwrap(), batches);
+
+        assert!(plan_batches(&[capacity * RATE], config).is_err());
+        assert!(plan_batches(&[], config).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_digests_in_request_order() {
+        use sha3::{Digest, Keccak256};
+
+        let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+        let capacity = get_keccak_capacity(config.usable_rows(), config.rows_per_round);
+        let inputs = [vec![1u8; 10], vec![2u8; (capacity - 2) * RATE], vec![], vec![3u8; 300]];
+        let batches = plan_batches(&inputs.iter().map(Vec::len).collect::<Vec<_>>(), config).unwrap();
+        let batch_digests = batches
+            .iter()
+            .map(|batch| batch.inputs.iter().map(|idx| Keccak256::digest(&inputs[*idx]).into()).collect())
+            .collect::<Vec<_>>();
+
+        let digests = digests_in_request_order(&batches, &batch_digests).unwrap();
+        let expected = inputs.iter().map(|input| Keccak256::digest(input).into()).collect::<Vec<[u8; 32]>>();
+        assert_eq!(digests, expected);
+
+        // Batches proven out of plan order map back to the wrong messages
+        let mut swapped = batch_digests.clone();
+        swapped.swap(0, 1);
+        assert_ne!(digests_in_request_order(&batches, &swapped).unwrap(), expected);
+        assert!(digests_in_request_order(&batches, &batch_digests[1..]).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Progress of the long running stages, setup, key generation and proving, for CLIs and
+//! services to show what they are busy with, e.g. during the minutes a keygen at `k >= 18`
+//! takes.
+//!
+//! halo2 reports nothing from within its calls, so a stage is one of them:
+//! [`Stage::VerifyingKey`] synthesizes the fixed columns, builds the permutation argument and
+//! commits to both, [`Stage::ProvingKey`] synthesizes again and evaluates the fixed, selector
+//! and permutation polynomials over the extended domain.
+
+use std::time::{Duration, Instant};
+
+/// A stage of the setup, key generation or proving.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Generation of the SRS, only done locally for development.
+    Setup,
+    /// Generation of the verifying key.
+    VerifyingKey,
+    /// Generation of the proving key, from the verifying key.
+    ProvingKey,
+    /// Witness generation and proving.
+    Proof,
+}
+
+/// An event reported to a [`ProgressObserver`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProgressEvent {
+    Started(Stage),
+    Finished { stage: Stage, elapsed: Duration },
+}
+
+/// Receives the [`ProgressEvent`]s of key generation and proving, on the calling thread.
+pub trait ProgressObserver: Send + Sync {
+    fn on_event(&self, event: ProgressEvent);
+}
+
+impl<F: Fn(ProgressEvent) + Send + Sync> ProgressObserver for F {
+    fn on_event(&self, event: ProgressEvent) {
+        self(event)
+    }
+}
+
+/// Ignores every event.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {
+    fn on_event(&self, _: ProgressEvent) {}
+}
+
+/// Logs every event at info level.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LogProgress;
+
+impl ProgressObserver for LogProgress {
+    fn on_event(&self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::Started(stage) => log::info!("{stage:?} started"),
+            ProgressEvent::Finished { stage, elapsed } => log::info!("{stage:?} finished in {elapsed:?}"),
+        }
+    }
+}
+
+/// Runs `f` as `stage`, reporting its start and end to `observer`, whether it fails or not.
+pub(crate) fn observe<T>(observer: &dyn ProgressObserver, stage: Stage, f: impl FnOnce() -> T) -> T {
+    observer.on_event(ProgressEvent::Started(stage));
+    let start = Instant::now();
+    let result = f();
+    observer.on_event(ProgressEvent::Finished { stage, elapsed: start.elapsed() });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use halo2_proofs::halo2curves::bn256::Bn256;
+
+    use super::*;
+    use crate::proof_system::{KzgShplonk, ProofSystem};
+    use crate::{KeccakCircuit, KeccakConfigParams, KeccakProver, DEFAULT_CONFIG};
+
+    const CONFIG: KeccakConfigParams = KeccakConfigParams { k: 12, rows_per_round: 5, ..DEFAULT_CONFIG };
+
+    #[test]
+    fn test_keygen_and_proving_progress() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let recorder = {
+            let events = Arc::clone(&events);
+            move |event: ProgressEvent| events.lock().unwrap().push(event)
+        };
+        let stages = |events: &Mutex<Vec<ProgressEvent>>| {
+            events
+                .lock()
+                .unwrap()
+                .drain(..)
+                .map(|event| match event {
+                    ProgressEvent::Started(stage) => (stage, false),
+                    ProgressEvent::Finished { stage, .. } => (stage, true),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let srs = KzgShplonk::<Bn256>::setup(CONFIG.k);
+        let circuit = KeccakCircuit::builder()
+            .config(CONFIG)
+            .capacity_rows(2usize.pow(CONFIG.k))
+            .build()
+            .unwrap();
+        let pk = KzgShplonk::<Bn256>::keygen_with_progress(&srs, &circuit, &recorder).unwrap();
+        assert_eq!(
+            stages(&events),
+            vec![
+                (Stage::VerifyingKey, false),
+                (Stage::VerifyingKey, true),
+                (Stage::ProvingKey, false),
+                (Stage::ProvingKey, true)
+            ]
+        );
+
+        let prover = KeccakProver::new(srs, pk, CONFIG).with_observer(Arc::new(recorder));
+        prover.prove(vec![b"progress".to_vec()]).unwrap();
+        assert_eq!(stages(&events), vec![(Stage::Proof, false), (Stage::Proof, true)]);
+    }
+}
@@ -0,0 +1,744 @@
+//! Keccak "promise call" interface, following the query formats used by Axiom's coprocessor
+//! stack. A host circuit records keccak calls as promises; this circuit later proves them.
+//!
+//! Two query formats are supported:
+//! - fixed-length queries, where the whole byte string is hashed;
+//! - variable-length queries, where the bytes are padded to a fixed `max_len` and only the
+//!   first `len` bytes are hashed.
+//!
+//! The results of a batch are bound together by a commitment, so a host circuit only has to
+//! carry a single value to reference every promise it made. [`KeccakPromiseCircuit`] proves
+//! the commitment: it hashes the queries and the commitment preimage in one keccak region,
+//! with the digests and lengths copy constrained into the preimage, and exposes the
+//! commitment alone. Its layout only depends on the `(tag, max_len)` of every query, so a key
+//! serves every batch of the same shape.
+//!
+//! Computed natively by [`KeccakPromiseBatch::fulfill`], the commitment is otherwise an
+//! untrusted hint, until the results are checked against the digests a [`crate::KeccakCircuit`]
+//! proof exposes with [`KeccakPromiseBatch::matches_output`] and the commitment recomputed
+//! from them.
+
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner, Value};
+use halo2_proofs::halo2curves::bn256::Fr;
+use halo2_proofs::halo2curves::ff::PrimeField;
+use halo2_proofs::plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Fixed, Instance, Selector};
+use halo2_proofs::poly::Rotation;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use crate::bridge::KeccakBridgeConfig;
+use crate::util::assign_value::{
+    assigned_value, constrain_equal, constrain_instance, raw_assign_advice, raw_assign_fixed,
+};
+use crate::util::eth_types::Field;
+use crate::util::expression::Expr;
+use crate::util::{Halo2AssignedCell, SKIP_FIRST_PASS};
+use crate::vanilla::keccak_packed_multi::get_keccak_capacity;
+use crate::vanilla::param::{NUM_BYTES_PER_WORD, NUM_ROUNDS, NUM_WORDS_TO_ABSORB};
+use crate::vanilla::witness::multi_keccak;
+use crate::vanilla::KeccakCircuitConfig;
+use crate::{message_digest_from_instance, DigestSize, Keccak256Error, KeccakConfigParams, Padding};
+
+/// Number of bytes of every result in the commitment preimage, see [`commit_results`].
+const RECORD_BYTES: usize = 1 + 8 + 8 + 16 + 16;
+
+/// A single keccak promise call.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeccakQuery {
+    /// Hash of all of `bytes`.
+    FixLen { bytes: Vec<u8> },
+    /// Hash of `bytes[..len]`, where `bytes` is padded to `max_len`.
+    VarLen { bytes: Vec<u8>, len: usize, max_len: usize },
+}
+
+impl KeccakQuery {
+    /// Creates a variable-length query, padding `bytes` with zeros up to `max_len`.
+    pub fn var_len(bytes: &[u8], max_len: usize) -> Result<Self, Keccak256Error> {
+        if bytes.len() > max_len {
+            return Err(Keccak256Error(format!(
+                "Variable length query of {} bytes exceeds max_len {}",
+                bytes.len(),
+                max_len
+            )));
+        }
+        let mut padded = bytes.to_vec();
+        padded.resize(max_len, 0);
+        Ok(KeccakQuery::VarLen { bytes: padded, len: bytes.len(), max_len })
+    }
+
+    /// The bytes that are actually hashed by this query.
+    pub fn logical_input(&self) -> Result<&[u8], Keccak256Error> {
+        match self {
+            KeccakQuery::FixLen { bytes } => Ok(bytes),
+            KeccakQuery::VarLen { bytes, len, max_len } => {
+                if bytes.len() != *max_len || len > max_len {
+                    return Err(Keccak256Error(format!(
+                        "Malformed variable length query: {} bytes, len {}, max_len {}",
+                        bytes.len(),
+                        len,
+                        max_len
+                    )));
+                }
+                Ok(&bytes[..*len])
+            }
+        }
+    }
+
+    /// The `(tag, max_len)` of the query, see [`commit_results`], which fix its layout in
+    /// [`KeccakPromiseCircuit`] whatever its length.
+    fn shape(&self) -> (u8, usize) {
+        match self {
+            KeccakQuery::FixLen { bytes } => (0, bytes.len()),
+            KeccakQuery::VarLen { max_len, .. } => (1, *max_len),
+        }
+    }
+
+    /// The query with the same shape and zeroed bytes.
+    fn without_witnesses(&self) -> Self {
+        match self {
+            KeccakQuery::FixLen { bytes } => KeccakQuery::FixLen { bytes: vec![0; bytes.len()] },
+            KeccakQuery::VarLen { max_len, .. } => {
+                KeccakQuery::VarLen { bytes: vec![0; *max_len], len: *max_len, max_len: *max_len }
+            }
+        }
+    }
+}
+
+/// The fulfilled result of a [`KeccakQuery`], with the digest split into big-endian halves as
+/// they appear in the circuit's `hash_hi`/`hash_lo` cells.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeccakPromiseResult {
+    pub query: KeccakQuery,
+    pub hash_hi: u128,
+    pub hash_lo: u128,
+}
+
+/// A batch of fulfilled promises together with their commitment, a native hint over the
+/// results rather than a value of the circuit.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeccakPromiseBatch {
+    pub results: Vec<KeccakPromiseResult>,
+    pub commitment: [u8; 32],
+}
+
+impl KeccakPromiseBatch {
+    /// Fulfills all `queries` and commits to the results.
+    pub fn fulfill(queries: Vec<KeccakQuery>) -> Result<Self, Keccak256Error> {
+        let results = queries
+            .into_iter()
+            .map(|query| {
+                let out = Keccak256::digest(query.logical_input()?);
+                Ok(KeccakPromiseResult {
+                    hash_hi: u128::from_be_bytes(out[..16].try_into().unwrap()),
+                    hash_lo: u128::from_be_bytes(out[16..].try_into().unwrap()),
+                    query,
+                })
+            })
+            .collect::<Result<Vec<_>, Keccak256Error>>()?;
+        let commitment = commit_results(&results);
+        Ok(KeccakPromiseBatch { results, commitment })
+    }
+
+    /// The inputs to pass to [`crate::KeccakCircuit`], in promise order.
+    pub fn circuit_inputs(&self) -> Result<Vec<Vec<u8>>, Keccak256Error> {
+        self.results
+            .iter()
+            .map(|result| result.query.logical_input().map(|bytes| bytes.to_vec()))
+            .collect()
+    }
+
+    /// Checks that every result matches its query and that the commitment is consistent.
+    pub fn verify(&self) -> Result<bool, Keccak256Error> {
+        for result in &self.results {
+            let out = Keccak256::digest(result.query.logical_input()?);
+            if result.hash_hi != u128::from_be_bytes(out[..16].try_into().unwrap())
+                || result.hash_lo != u128::from_be_bytes(out[16..].try_into().unwrap())
+            {
+                return Ok(false);
+            }
+        }
+        Ok(self.commitment == commit_results(&self.results))
+    }
+
+    /// Whether the results are the digests exposed in the `output` instance column of a proof
+    /// of [`Self::circuit_inputs`] with the fixed instance layout of `config`, each at the
+    /// keccak_f slot its input finishes on. Fails unless `config` proves Keccak-256.
+    pub fn matches_output(&self, output: &[Fr], config: &KeccakConfigParams) -> Result<bool, Keccak256Error> {
+        check_keccak256(config)?;
+        let lengths = self
+            .results
+            .iter()
+            .map(|result| result.query.logical_input().map(<[u8]>::len))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, result) in self.results.iter().enumerate() {
+            let expected = [result.hash_hi.to_be_bytes(), result.hash_lo.to_be_bytes()].concat();
+            if message_digest_from_instance(output, &lengths, i, config) != Some(expected) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The instance column of a [`KeccakPromiseCircuit`] proving the batch: the commitment as
+    /// its big-endian `(hi, lo)` halves.
+    pub fn commitment_instances<F: PrimeField>(&self) -> Vec<Vec<F>> {
+        let hi = u128::from_be_bytes(self.commitment[..16].try_into().unwrap());
+        let lo = u128::from_be_bytes(self.commitment[16..].try_into().unwrap());
+        vec![vec![F::from_u128(hi), F::from_u128(lo)]]
+    }
+}
+
+fn check_keccak256(config: &KeccakConfigParams) -> Result<(), Keccak256Error> {
+    if config.padding != Padding::Keccak || config.digest_size != DigestSize::Bits256 {
+        return Err(Keccak256Error(format!(
+            "Promises are Keccak-256 calls, got {:?} padding with {:?} digests",
+            config.padding, config.digest_size
+        )));
+    }
+    Ok(())
+}
+
+/// Commits to a list of promise results.
+///
+/// Each result contributes `tag || max_len || len || hash_hi || hash_lo` (lengths as big-endian
+/// `u64`), where `tag` is `0` for fixed-length and `1` for variable-length queries; the
+/// commitment is the keccak of the concatenation.
+pub fn commit_results(results: &[KeccakPromiseResult]) -> [u8; 32] {
+    Keccak256::digest(commitment_preimage(results)).into()
+}
+
+/// The concatenated records [`commit_results`] hashes.
+fn commitment_preimage(results: &[KeccakPromiseResult]) -> Vec<u8> {
+    let mut preimage = Vec::with_capacity(results.len() * RECORD_BYTES);
+    for result in results {
+        let (tag, len, max_len) = match &result.query {
+            KeccakQuery::FixLen { bytes } => (0u8, bytes.len(), bytes.len()),
+            KeccakQuery::VarLen { len, max_len, .. } => (1u8, *len, *max_len),
+        };
+        preimage.push(tag);
+        preimage.extend((max_len as u64).to_be_bytes());
+        preimage.extend((len as u64).to_be_bytes());
+        preimage.extend(result.hash_hi.to_be_bytes());
+        preimage.extend(result.hash_lo.to_be_bytes());
+    }
+    preimage
+}
+
+#[derive(Clone, Debug)]
+pub struct KeccakPromiseConfig<F> {
+    keccak: KeccakCircuitConfig<F>,
+    bridge: KeccakBridgeConfig,
+    /// `is_final` of the previous slot, the product `p`, `m = is_final * p`, `bytes_left` and
+    /// the length accumulator, equality enabled. The first two are reused by the constant and
+    /// bound rows.
+    columns: [Column<Advice>; 5],
+    constant: Column<Fixed>,
+    q_const: Selector,
+    q_bound: Selector,
+    q_len_first: Selector,
+    q_len: Selector,
+    instance: Column<Instance>,
+}
+
+impl<F: Field> KeccakPromiseConfig<F> {
+    /// Constrains every cell to its constant.
+    fn constrain_constants(
+        &self,
+        mut layouter: impl Layouter<F>,
+        constants: &[(&Halo2AssignedCell<'_, F>, F)],
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "promise constants",
+            |mut region| {
+                for (offset, (cell, value)) in constants.iter().enumerate() {
+                    self.q_const.enable(&mut region, offset)?;
+                    raw_assign_fixed(&mut region, self.constant, offset, *value);
+                    let copied = raw_assign_advice(&mut region, self.columns[0], offset, assigned_value(cell));
+                    constrain_equal(&mut region, cell.cell(), copied.cell())?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// The length of the message finishing on the last of the slots of a query, given the
+    /// `is_final` cell of the slot before each of them, `None` for the first, whose previous
+    /// slot is final, and the `bytes_left` cell starting each of them.
+    ///
+    /// Walking back from the last slot, `p` stays 1 until the slot the message starts on, where
+    /// the previous slot is final, and is 0 before. Only the `bytes_left` of that slot, the
+    /// length, is accumulated, whatever messages fill the slots before.
+    fn assign_length<'v>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        slots: &[(Option<&Halo2AssignedCell<'v, F>>, &Halo2AssignedCell<'v, F>)],
+    ) -> Result<Halo2AssignedCell<'v, F>, Error> {
+        let [f_col, p_col, m_col, bytes_left_col, acc_col] = self.columns;
+        layouter.assign_region(
+            || "promise length",
+            |mut region| {
+                let mut prev: Option<(Value<F>, Value<F>, Value<F>)> = None;
+                let mut acc_cell = None;
+                for (offset, (is_final, bytes_left)) in slots.iter().rev().enumerate() {
+                    let f = is_final.map_or(Value::known(F::ONE), assigned_value);
+                    let bytes_left_value = assigned_value(bytes_left);
+                    let (p, acc_prev) = match prev {
+                        Some((f_prev, p_prev, acc_prev)) => (p_prev * (Value::known(F::ONE) - f_prev), acc_prev),
+                        None => (Value::known(F::ONE), Value::known(F::ZERO)),
+                    };
+                    let m = f * p;
+                    let acc = acc_prev + m * bytes_left_value;
+                    match prev {
+                        Some(_) => self.q_len.enable(&mut region, offset)?,
+                        None => self.q_len_first.enable(&mut region, offset)?,
+                    }
+
+                    let f_cell = raw_assign_advice(&mut region, f_col, offset, f);
+                    match is_final {
+                        Some(cell) => constrain_equal(&mut region, cell.cell(), f_cell.cell())?,
+                        None => {
+                            self.q_const.enable(&mut region, offset)?;
+                            raw_assign_fixed(&mut region, self.constant, offset, F::ONE);
+                        }
+                    }
+                    raw_assign_advice(&mut region, p_col, offset, p);
+                    raw_assign_advice(&mut region, m_col, offset, m);
+                    let copied = raw_assign_advice(&mut region, bytes_left_col, offset, bytes_left_value);
+                    constrain_equal(&mut region, bytes_left.cell(), copied.cell())?;
+                    acc_cell = Some(raw_assign_advice(&mut region, acc_col, offset, acc));
+                    prev = Some((f, p, acc));
+                }
+                Ok(acc_cell.expect("a query spans at least one slot"))
+            },
+        )
+    }
+
+    /// Assigns `max_len - len` and constrains `len` plus it to be `max_len`.
+    fn assign_bound<'v>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        len: &Halo2AssignedCell<'v, F>,
+        max_len: usize,
+    ) -> Result<Halo2AssignedCell<'v, F>, Error> {
+        layouter.assign_region(
+            || "promise bound",
+            |mut region| {
+                self.q_bound.enable(&mut region, 0)?;
+                let max_len = F::from(max_len as u64);
+                raw_assign_fixed(&mut region, self.constant, 0, max_len);
+                let diff = assigned_value(len).map(|len| max_len - len);
+                let diff = raw_assign_advice(&mut region, self.columns[0], 0, diff);
+                let copied = raw_assign_advice(&mut region, self.columns[1], 0, assigned_value(len));
+                constrain_equal(&mut region, len.cell(), copied.cell())?;
+                Ok(diff)
+            },
+        )
+    }
+}
+
+/// Circuit proving the commitment of a [`KeccakPromiseBatch`], exposed as
+/// [`KeccakPromiseBatch::commitment_instances`].
+///
+/// Query `i` gets the `num_keccak_f(max_len)` keccak_f slots its longest input needs. Shorter
+/// inputs are preceded by empty messages, so every input finishes on the last slot of its
+/// query, where its digest is read. Its length is the `bytes_left` of the slot it starts on,
+/// found from the `is_final` cells, and is bounded by `max_len` through a range check of
+/// `max_len - len`. The records of the commitment preimage are hashed last, with the tag and
+/// `max_len` bytes fixed and the length and digest bytes copy constrained.
+#[derive(Clone, Debug)]
+pub struct KeccakPromiseCircuit<F: Field> {
+    config: KeccakConfigParams,
+    batch: KeccakPromiseBatch,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> KeccakPromiseCircuit<F> {
+    /// Creates the circuit proving the commitment of `batch`, which has to be Keccak-256 and
+    /// fit the keccak_f capacity of `config`.
+    pub fn new(config: KeccakConfigParams, batch: KeccakPromiseBatch) -> Result<Self, Keccak256Error> {
+        config.validate()?;
+        check_keccak256(&config)?;
+        for result in &batch.results {
+            result.query.logical_input()?;
+        }
+        let circuit = KeccakPromiseCircuit { config, batch, _marker: PhantomData };
+        let capacity = get_keccak_capacity(config.usable_rows(), config.rows_per_round);
+        if circuit.num_slots() > capacity {
+            return Err(Keccak256Error(format!(
+                "The promises take {} keccak_f, more than the {capacity} of the config",
+                circuit.num_slots()
+            )));
+        }
+        let longest = circuit.queries().map(|query| query.shape().1).fold(circuit.preimage_len(), usize::max);
+        if let Some(max_input_len) = config.max_input_len {
+            if longest > max_input_len {
+                return Err(Keccak256Error(format!(
+                    "The promises hash messages of up to {longest} bytes, more than the max_input_len {max_input_len}"
+                )));
+            }
+        }
+        Ok(circuit)
+    }
+
+    fn queries(&self) -> impl Iterator<Item = &KeccakQuery> {
+        self.batch.results.iter().map(|result| &result.query)
+    }
+
+    fn preimage_len(&self) -> usize {
+        self.batch.results.len() * RECORD_BYTES
+    }
+
+    /// The first keccak_f slot of every query, then of the commitment preimage.
+    fn first_slots(&self) -> Vec<usize> {
+        let mut slot = 0;
+        let mut slots = vec![];
+        for query in self.queries() {
+            slots.push(slot);
+            slot += self.config.num_keccak_f(query.shape().1);
+        }
+        slots.push(slot);
+        slots
+    }
+
+    fn num_slots(&self) -> usize {
+        self.first_slots().last().unwrap() + self.config.num_keccak_f(self.preimage_len())
+    }
+
+    /// The messages hashed: every input preceded by the empty messages filling its query, then
+    /// the commitment preimage.
+    fn messages(&self) -> Vec<Vec<u8>> {
+        let mut messages = vec![];
+        for query in self.queries() {
+            let input = query.logical_input().expect("checked by new").to_vec();
+            let fillers = self.config.num_keccak_f(query.shape().1) - self.config.num_keccak_f(input.len());
+            messages.extend(std::iter::repeat(vec![]).take(fillers));
+            messages.push(input);
+        }
+        messages.push(commitment_preimage(&self.batch.results));
+        messages
+    }
+}
+
+impl<F: Field> Circuit<F> for KeccakPromiseCircuit<F> {
+    type Config = KeccakPromiseConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    /// Keeps the shape of every query, which determines the layout, with zeroed inputs.
+    fn without_witnesses(&self) -> Self {
+        let results = self
+            .batch
+            .results
+            .iter()
+            .map(|result| KeccakPromiseResult { query: result.query.without_witnesses(), hash_hi: 0, hash_lo: 0 })
+            .collect();
+        Self {
+            config: self.config,
+            batch: KeccakPromiseBatch { results, commitment: [0; 32] },
+            _marker: PhantomData,
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        // MockProver complains if you only have columns in SecondPhase, so let's just make an empty column in FirstPhase
+        meta.advice_column();
+
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+        let keccak = KeccakCircuitConfig::new(meta, params);
+        let bridge = KeccakBridgeConfig::configure(meta);
+        let columns = [(); 5].map(|_| meta.advice_column());
+        for column in columns {
+            meta.enable_equality(column);
+        }
+        let constant = meta.fixed_column();
+        let q_const = meta.selector();
+        let q_bound = meta.selector();
+        let q_len_first = meta.selector();
+        let q_len = meta.selector();
+
+        meta.create_gate("promise constant", |meta| {
+            let q = meta.query_selector(q_const);
+            vec![q * (meta.query_advice(columns[0], Rotation::cur()) - meta.query_fixed(constant, Rotation::cur()))]
+        });
+
+        // `max_len - len` followed by `len`
+        meta.create_gate("promise bound", |meta| {
+            let q = meta.query_selector(q_bound);
+            let diff = meta.query_advice(columns[0], Rotation::cur());
+            let len = meta.query_advice(columns[1], Rotation::cur());
+            vec![q * (diff + len - meta.query_fixed(constant, Rotation::cur()))]
+        });
+
+        meta.create_gate("promise length", |meta| {
+            let q_first = meta.query_selector(q_len_first);
+            let q = meta.query_selector(q_len);
+            let [f, p, m, bytes_left, acc] = columns.map(|column| meta.query_advice(column, Rotation::cur()));
+            let [f_prev, p_prev, _, _, acc_prev] = columns.map(|column| meta.query_advice(column, Rotation::prev()));
+            vec![
+                q_first.clone() * (p.clone() - 1.expr()),
+                q_first.clone() * (acc.clone() - m.clone() * bytes_left.clone()),
+                (q_first + q.clone()) * (m.clone() - f * p.clone()),
+                q.clone() * (p - p_prev * (1.expr() - f_prev)),
+                q * (acc - acc_prev - m * bytes_left),
+            ]
+        });
+
+        KeccakPromiseConfig {
+            keccak,
+            bridge,
+            columns,
+            constant,
+            q_const,
+            q_bound,
+            q_len_first,
+            q_len,
+            instance,
+        }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+        let params = config.keccak.parameters;
+        let rows_per_round = params.rows_per_round;
+        config.keccak.load_aux_tables(&mut layouter)?;
+        config.bridge.load_byte_table(&mut layouter)?;
+
+        let (witness, _) = multi_keccak::<F, _>(&self.messages(), None, params);
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut cache = vec![];
+        layouter.assign_region(
+            || "promise keccaks",
+            |mut region| {
+                if first_pass {
+                    first_pass = false;
+                    return Ok(());
+                }
+                cache.push(config.keccak.assign(&mut region, &witness));
+                Ok(())
+            },
+        )?;
+        let rows = &cache[0];
+        // first round is dummy
+        let start = |slot: usize| rows_per_round * (1 + slot * (NUM_ROUNDS + 1));
+        let digest_row = |slot: usize| &rows[start(slot) + NUM_ROUNDS * rows_per_round];
+
+        let first_slots = self.first_slots();
+        let preimage_slot = *first_slots.last().unwrap();
+        let preimage_words = (preimage_slot..self.num_slots())
+            .flat_map(|slot| (0..NUM_WORDS_TO_ABSORB).map(move |word| start(slot) + word * rows_per_round))
+            .map(|row| rows[row].word_value.clone())
+            .take((self.preimage_len() + NUM_BYTES_PER_WORD - 1) / NUM_BYTES_PER_WORD)
+            .collect::<Vec<_>>();
+        let preimage =
+            config.bridge.assign_word_bytes(layouter.namespace(|| "promise preimage"), &preimage_words)?;
+
+        let mut equalities = vec![];
+        let mut constants = vec![];
+        for (idx, query) in self.queries().enumerate() {
+            let (tag, max_len) = query.shape();
+            let slots = first_slots[idx]..first_slots[idx + 1];
+            let last = slots.end - 1;
+            let slot_cells = slots
+                .clone()
+                .map(|slot| {
+                    let is_final = (slot != slots.start).then(|| &digest_row(slot - 1).is_final);
+                    (is_final, &rows[start(slot)].bytes_left)
+                })
+                .collect::<Vec<_>>();
+            let len = config.assign_length(layouter.namespace(|| "promise length"), &slot_cells)?;
+            let diff = config.assign_bound(layouter.namespace(|| "promise bound"), &len, max_len)?;
+            let len_bytes = config.bridge.assign_word_bytes(layouter.namespace(|| "promise length bytes"), &[len])?;
+            config.bridge.assign_word_bytes(layouter.namespace(|| "promise bound bytes"), &[diff.clone()])?;
+            let digest = config.bridge.assign_digest(
+                layouter.namespace(|| "promise digest"),
+                &digest_row(last).hash_hi,
+                &digest_row(last).hash_lo,
+            )?;
+
+            let record = &preimage[idx * RECORD_BYTES..(idx + 1) * RECORD_BYTES];
+            constants.push((record[0].clone(), F::from(tag as u64)));
+            for (cell, byte) in record[1..9].iter().zip((max_len as u64).to_be_bytes()) {
+                constants.push((cell.clone(), F::from(byte as u64)));
+            }
+            equalities.extend(record[9..17].iter().zip(len_bytes.iter().rev()).map(|(a, b)| (a.cell(), b.cell())));
+            equalities.extend(record[17..].iter().zip(&digest.bytes).map(|(a, b)| (a.cell(), b.cell())));
+            constants.push((digest_row(last).is_final.clone(), F::ONE));
+            if tag == 0 {
+                constants.push((diff, F::ZERO));
+            }
+        }
+        // The preimage starts right after the last query, which is final, and has its fixed length
+        let commitment = digest_row(self.num_slots() - 1);
+        constants.push((rows[start(preimage_slot)].bytes_left.clone(), F::from(self.preimage_len() as u64)));
+        constants.push((commitment.is_final.clone(), F::ONE));
+        config.constrain_constants(
+            layouter.namespace(|| "promise constants"),
+            &constants.iter().map(|(cell, value)| (cell, *value)).collect::<Vec<_>>(),
+        )?;
+        layouter.assign_region(
+            || "promise records",
+            |mut region| {
+                for (left, right) in &equalities {
+                    constrain_equal(&mut region, *left, *right)?;
+                }
+                Ok(())
+            },
+        )?;
+
+        for (row, cell) in [&commitment.hash_hi, &commitment.hash_lo].into_iter().enumerate() {
+            constrain_instance(&mut layouter, cell.cell(), config.instance, row)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::dev::MockProver;
+
+    use super::*;
+    use crate::{InstanceMode, KeccakCircuit, DEFAULT_CONFIG};
+
+    #[test]
+    fn test_fulfill_and_verify() {
+        let queries = vec![
+            KeccakQuery::FixLen { bytes: vec![1u8, 2, 3] },
+            KeccakQuery::var_len(&[4u8, 5], 10).unwrap(),
+        ];
+        let batch = KeccakPromiseBatch::fulfill(queries).unwrap();
+        assert!(batch.verify().unwrap());
+        assert_eq!(batch.circuit_inputs().unwrap(), vec![vec![1u8, 2, 3], vec![4u8, 5]]);
+
+        let mut tampered = batch.clone();
+        tampered.results[1].hash_lo ^= 1;
+        assert!(!tampered.verify().unwrap());
+    }
+
+    #[test]
+    fn test_matches_output() {
+        let config = KeccakConfigParams { instance_mode: InstanceMode::Output, ..DEFAULT_CONFIG };
+        let queries = vec![
+            KeccakQuery::FixLen { bytes: vec![7u8; 200] },
+            KeccakQuery::var_len(&[4u8, 5], 10).unwrap(),
+        ];
+        let batch = KeccakPromiseBatch::fulfill(queries).unwrap();
+        let circuit = KeccakCircuit::<Fr>::builder()
+            .config(config)
+            .capacity_rows(1 << 12)
+            .inputs(batch.circuit_inputs().unwrap())
+            .build()
+            .unwrap();
+        let output = circuit.instances().pop().unwrap();
+        assert!(batch.matches_output(&output, &config).unwrap());
+
+        let mut tampered = batch;
+        tampered.results[0].hash_hi ^= 1;
+        tampered.commitment = commit_results(&tampered.results);
+        assert!(!tampered.matches_output(&output, &config).unwrap());
+
+        let sha3 = KeccakConfigParams { padding: crate::Padding::Sha3, ..config };
+        assert!(tampered.matches_output(&output, &sha3).is_err());
+    }
+
+    fn promise_config() -> KeccakConfigParams {
+        KeccakConfigParams { k: 12, rows_per_round: 5, ..DEFAULT_CONFIG }
+    }
+
+    fn batch(var_len: usize) -> KeccakPromiseBatch {
+        KeccakPromiseBatch::fulfill(vec![
+            KeccakQuery::FixLen { bytes: vec![7u8; 200] },
+            KeccakQuery::var_len(&vec![5u8; var_len], 300).unwrap(),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_promise_circuit() {
+        let config = promise_config();
+        let batch = batch(2);
+        let circuit = KeccakPromiseCircuit::<Fr>::new(config, batch.clone()).unwrap();
+        let prover = MockProver::run(config.k, &circuit, batch.commitment_instances()).unwrap();
+        prover.assert_satisfied();
+
+        // Another commitment for the same proof
+        let other = self::batch(3);
+        assert!(MockProver::run(config.k, &circuit, other.commitment_instances()).unwrap().verify().is_err());
+
+        // A committed digest that isn't the one of its query
+        let mut tampered = batch;
+        tampered.results[1].hash_lo ^= 1;
+        tampered.commitment = commit_results(&tampered.results);
+        let circuit = KeccakPromiseCircuit::<Fr>::new(config, tampered.clone()).unwrap();
+        assert!(MockProver::run(config.k, &circuit, tampered.commitment_instances()).unwrap().verify().is_err());
+
+        let sha3 = KeccakConfigParams { padding: crate::Padding::Sha3, ..config };
+        assert!(KeccakPromiseCircuit::<Fr>::new(sha3, self::batch(2)).is_err());
+    }
+
+    #[test]
+    fn test_promise_circuit_keys() {
+        use halo2_proofs::halo2curves::bn256::{Bn256, G1Affine};
+        use halo2_proofs::plonk::{create_proof, keygen_pk, keygen_vk, verify_proof};
+        use halo2_proofs::poly::commitment::ParamsProver;
+        use halo2_proofs::poly::kzg::commitment::{KZGCommitmentScheme, ParamsKZG};
+        use halo2_proofs::poly::kzg::multiopen::{ProverSHPLONK, VerifierSHPLONK};
+        use halo2_proofs::poly::kzg::strategy::SingleStrategy;
+        use halo2_proofs::transcript::{
+            Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
+        };
+        use rand_core::OsRng;
+
+        // Keys of a batch whose variable length input takes a single keccak_f, proving one
+        // where it takes two: max_len alone fixes the layout
+        let config = promise_config();
+        let params = ParamsKZG::<Bn256>::setup(config.k, OsRng);
+        let keygen_circuit = KeccakPromiseCircuit::<Fr>::new(config, batch(2)).unwrap().without_witnesses();
+        let vk = keygen_vk(&params, &keygen_circuit).unwrap();
+        let pk = keygen_pk(&params, vk, &keygen_circuit).unwrap();
+
+        let batch = batch(250);
+        let instances = batch.commitment_instances::<Fr>();
+        let instance_refs = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let circuit = KeccakPromiseCircuit::<Fr>::new(config, batch).unwrap();
+        let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+        create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
+            &params,
+            &pk,
+            &[circuit],
+            &[instance_refs.as_slice()],
+            OsRng,
+            &mut transcript,
+        )
+        .unwrap();
+        let proof = transcript.finalize();
+
+        let verify = |instance_refs: &[&[Fr]]| {
+            let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(&proof[..]);
+            verify_proof::<KZGCommitmentScheme<Bn256>, VerifierSHPLONK<'_, Bn256>, _, _, _>(
+                params.verifier_params(),
+                pk.get_vk(),
+                SingleStrategy::new(&params),
+                &[instance_refs],
+                &mut transcript,
+            )
+        };
+        assert!(verify(&instance_refs).is_ok());
+        let forged = self::batch(251).commitment_instances::<Fr>();
+        assert!(verify(&forged.iter().map(Vec::as_slice).collect::<Vec<_>>()).is_err());
+    }
+
+    #[test]
+    fn test_var_len_exceeding_max_len() {
+        assert!(KeccakQuery::var_len(&[0u8; 11], 10).is_err());
+    }
+}
@@ -0,0 +1,345 @@
+//! Proof systems the keccak circuit can be proven with, so applications can switch between
+//! them by type parameter.
+
+use std::fmt::Debug;
+use std::io::Write;
+use std::marker::PhantomData;
+
+use halo2_proofs::halo2curves::bn256::{Bn256, G1Affine};
+use halo2_proofs::halo2curves::ff::{FromUniformBytes, WithSmallOrderMulGroup};
+use halo2_proofs::halo2curves::pairing::MultiMillerLoop;
+use halo2_proofs::halo2curves::serde::SerdeObject;
+use halo2_proofs::halo2curves::CurveAffine;
+use halo2_proofs::plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, Error, ProvingKey, VerifyingKey};
+use halo2_proofs::poly::commitment::ParamsProver;
+use halo2_proofs::poly::ipa::commitment::{IPACommitmentScheme, ParamsIPA};
+use halo2_proofs::poly::ipa::multiopen::{ProverIPA, VerifierIPA};
+use halo2_proofs::poly::kzg::commitment::{KZGCommitmentScheme, ParamsKZG};
+use halo2_proofs::poly::kzg::multiopen::{ProverGWC, ProverSHPLONK, VerifierGWC, VerifierSHPLONK};
+use halo2_proofs::poly::{ipa, kzg};
+use halo2_proofs::transcript::{
+    Blake2bRead, Blake2bWrite, Challenge255, Keccak256Read, Keccak256Write, TranscriptReadBuffer,
+    TranscriptWriterBuffer,
+};
+use rand::thread_rng;
+
+use crate::progress::{observe, NoProgress, ProgressObserver, Stage};
+use crate::util::eth_types::Field;
+use crate::KeccakCircuit;
+
+/// Blake2b transcript writer, the default.
+pub type Blake2bTranscriptWrite<C> = Blake2bWrite<Vec<u8>, C, Challenge255<C>>;
+/// Blake2b transcript reader, the default.
+pub type Blake2bTranscriptRead<'a, C> = Blake2bRead<&'a [u8], C, Challenge255<C>>;
+/// Keccak256 transcript writer, cheap to verify in the EVM.
+pub type KeccakTranscriptWrite<C> = Keccak256Write<Vec<u8>, C, Challenge255<C>>;
+/// Keccak256 transcript reader, cheap to verify in the EVM.
+pub type KeccakTranscriptRead<'a, C> = Keccak256Read<&'a [u8], C, Challenge255<C>>;
+
+// `halo2_proofs` has no Poseidon transcript. snark-verifier's, which the `recursion` feature
+// proves with, challenges with its own `ChallengeScalar` rather than `Challenge255`, so its
+// proofs come from `recursion::prove_poseidon` rather than the `*_with_transcript` methods.
+
+/// Setup, key generation, proving and verification of [`KeccakCircuit`] with one commitment
+/// scheme. [`ProofSystem::prove`] and [`ProofSystem::verify`] use a Blake2b transcript, any
+/// other transcript can be passed as a type parameter to the `*_with_transcript` variants.
+pub trait ProofSystem {
+    /// Scalar field of the circuit.
+    type Scalar: Field + FromUniformBytes<64>;
+    /// Curve the commitments are on.
+    type Curve: CurveAffine<ScalarExt = Self::Scalar>;
+    /// Public parameters of the commitment scheme.
+    type Params;
+
+    /// Generates the public parameters for circuits of 2<sup>k</sup> rows. Only suitable for
+    /// testing with KZG, whose setup needs to be trusted.
+    fn setup(k: u32) -> Self::Params;
+
+    /// Generates the proving key of `circuit`, which may be without witnesses.
+    fn keygen(
+        params: &Self::Params,
+        circuit: &KeccakCircuit<Self::Scalar>,
+    ) -> Result<ProvingKey<Self::Curve>, Error> {
+        Self::keygen_with_progress(params, circuit, &NoProgress)
+    }
+
+    /// [`ProofSystem::keygen`], reporting the verifying and proving key stages to `observer`.
+    fn keygen_with_progress(
+        params: &Self::Params,
+        circuit: &KeccakCircuit<Self::Scalar>,
+        observer: &dyn ProgressObserver,
+    ) -> Result<ProvingKey<Self::Curve>, Error>;
+
+    /// Proves `circuit` against the `instances` columns, streaming the proof to `writer` with
+    /// the transcript `T` as it is produced, and returns the writer.
+    fn prove_to_writer_with_transcript<W, T>(
+        params: &Self::Params,
+        pk: &ProvingKey<Self::Curve>,
+        circuit: KeccakCircuit<Self::Scalar>,
+        instances: &[&[Self::Scalar]],
+        writer: W,
+    ) -> Result<W, Error>
+    where
+        W: Write,
+        T: TranscriptWriterBuffer<W, Self::Curve, Challenge255<Self::Curve>>;
+
+    /// Proves `circuit` against the `instances` columns, writing the proof with the transcript `T`.
+    fn prove_with_transcript<T>(
+        params: &Self::Params,
+        pk: &ProvingKey<Self::Curve>,
+        circuit: KeccakCircuit<Self::Scalar>,
+        instances: &[&[Self::Scalar]],
+    ) -> Result<Vec<u8>, Error>
+    where
+        T: TranscriptWriterBuffer<Vec<u8>, Self::Curve, Challenge255<Self::Curve>>,
+    {
+        Self::prove_to_writer_with_transcript::<_, T>(params, pk, circuit, instances, vec![])
+    }
+
+    /// Verifies `proof` against the `instances` columns, reading the proof with the transcript `T`.
+    fn verify_with_transcript<'a, T>(
+        params: &Self::Params,
+        vk: &VerifyingKey<Self::Curve>,
+        proof: &'a [u8],
+        instances: &[&[Self::Scalar]],
+    ) -> Result<(), Error>
+    where
+        T: TranscriptReadBuffer<&'a [u8], Self::Curve, Challenge255<Self::Curve>>;
+
+    /// Proves `circuit` against the `instances` columns with a Blake2b transcript.
+    fn prove(
+        params: &Self::Params,
+        pk: &ProvingKey<Self::Curve>,
+        circuit: KeccakCircuit<Self::Scalar>,
+        instances: &[&[Self::Scalar]],
+    ) -> Result<Vec<u8>, Error> {
+        Self::prove_with_transcript::<Blake2bTranscriptWrite<Self::Curve>>(params, pk, circuit, instances)
+    }
+
+    /// Proves `circuit` against the `instances` columns with a Blake2b transcript, streaming the
+    /// proof to `writer`.
+    fn prove_to_writer<W: Write>(
+        params: &Self::Params,
+        pk: &ProvingKey<Self::Curve>,
+        circuit: KeccakCircuit<Self::Scalar>,
+        instances: &[&[Self::Scalar]],
+        writer: W,
+    ) -> Result<W, Error> {
+        Self::prove_to_writer_with_transcript::<_, Blake2bWrite<W, Self::Curve, Challenge255<Self::Curve>>>(
+            params, pk, circuit, instances, writer,
+        )
+    }
+
+    /// Verifies `proof` against the `instances` columns with a Blake2b transcript.
+    fn verify(
+        params: &Self::Params,
+        vk: &VerifyingKey<Self::Curve>,
+        proof: &[u8],
+        instances: &[&[Self::Scalar]],
+    ) -> Result<(), Error> {
+        Self::verify_with_transcript::<Blake2bTranscriptRead<'_, Self::Curve>>(params, vk, proof, instances)
+    }
+}
+
+/// KZG commitments with the SHPLONK multiopen argument.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct KzgShplonk<E = Bn256>(PhantomData<E>);
+
+/// KZG commitments with the GWC multiopen argument.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct KzgGwc<E = Bn256>(PhantomData<E>);
+
+/// IPA commitments, which need no trusted setup. Defaults to the BN254 G1 curve, so the
+/// circuit stays over the same field as with KZG.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Ipa<C = G1Affine>(PhantomData<C>);
+
+macro_rules! impl_kzg_proof_system {
+    ($ty:ident, $prover:ident, $verifier:ident) => {
+        impl<E> ProofSystem for $ty<E>
+        where
+            E: MultiMillerLoop + Debug,
+            E::Scalar: Field + Ord + FromUniformBytes<64> + WithSmallOrderMulGroup<3>,
+            E::G1Affine: SerdeObject,
+            E::G2Affine: SerdeObject,
+        {
+            type Scalar = E::Scalar;
+            type Curve = E::G1Affine;
+            type Params = ParamsKZG<E>;
+
+            fn setup(k: u32) -> Self::Params {
+                ParamsKZG::<E>::setup(k, thread_rng())
+            }
+
+            fn keygen_with_progress(
+                params: &Self::Params,
+                circuit: &KeccakCircuit<Self::Scalar>,
+                observer: &dyn ProgressObserver,
+            ) -> Result<ProvingKey<Self::Curve>, Error> {
+                let vk = observe(observer, Stage::VerifyingKey, || keygen_vk(params, circuit))?;
+                observe(observer, Stage::ProvingKey, || keygen_pk(params, vk, circuit))
+            }
+
+            fn prove_to_writer_with_transcript<W, T>(
+                params: &Self::Params,
+                pk: &ProvingKey<Self::Curve>,
+                circuit: KeccakCircuit<Self::Scalar>,
+                instances: &[&[Self::Scalar]],
+                writer: W,
+            ) -> Result<W, Error>
+            where
+                W: Write,
+                T: TranscriptWriterBuffer<W, Self::Curve, Challenge255<Self::Curve>>,
+            {
+                let mut transcript = T::init(writer);
+                create_proof::<KZGCommitmentScheme<E>, $prover<'_, E>, Challenge255<E::G1Affine>, _, T, _>(
+                    params,
+                    pk,
+                    &[circuit],
+                    &[instances],
+                    thread_rng(),
+                    &mut transcript,
+                )?;
+                Ok(transcript.finalize())
+            }
+
+            fn verify_with_transcript<'a, T>(
+                params: &Self::Params,
+                vk: &VerifyingKey<Self::Curve>,
+                proof: &'a [u8],
+                instances: &[&[Self::Scalar]],
+            ) -> Result<(), Error>
+            where
+                T: TranscriptReadBuffer<&'a [u8], Self::Curve, Challenge255<Self::Curve>>,
+            {
+                let mut transcript = T::init(proof);
+                verify_proof::<KZGCommitmentScheme<E>, $verifier<'_, E>, Challenge255<E::G1Affine>, T, _>(
+                    params.verifier_params(),
+                    vk,
+                    kzg::strategy::SingleStrategy::new(params),
+                    &[instances],
+                    &mut transcript,
+                )
+            }
+        }
+    };
+}
+
+impl_kzg_proof_system!(KzgShplonk, ProverSHPLONK, VerifierSHPLONK);
+impl_kzg_proof_system!(KzgGwc, ProverGWC, VerifierGWC);
+
+impl<C> ProofSystem for Ipa<C>
+where
+    C: CurveAffine + SerdeObject,
+    C::Scalar: Field + Ord + FromUniformBytes<64> + WithSmallOrderMulGroup<3>,
+{
+    type Scalar = C::Scalar;
+    type Curve = C;
+    type Params = ParamsIPA<C>;
+
+    fn setup(k: u32) -> Self::Params {
+        ParamsIPA::<C>::new(k)
+    }
+
+    fn keygen_with_progress(
+        params: &Self::Params,
+        circuit: &KeccakCircuit<Self::Scalar>,
+        observer: &dyn ProgressObserver,
+    ) -> Result<ProvingKey<Self::Curve>, Error> {
+        let vk = observe(observer, Stage::VerifyingKey, || keygen_vk(params, circuit))?;
+        observe(observer, Stage::ProvingKey, || keygen_pk(params, vk, circuit))
+    }
+
+    fn prove_to_writer_with_transcript<W, T>(
+        params: &Self::Params,
+        pk: &ProvingKey<Self::Curve>,
+        circuit: KeccakCircuit<Self::Scalar>,
+        instances: &[&[Self::Scalar]],
+        writer: W,
+    ) -> Result<W, Error>
+    where
+        W: Write,
+        T: TranscriptWriterBuffer<W, Self::Curve, Challenge255<Self::Curve>>,
+    {
+        let mut transcript = T::init(writer);
+        create_proof::<IPACommitmentScheme<C>, ProverIPA<'_, C>, Challenge255<C>, _, T, _>(
+            params,
+            pk,
+            &[circuit],
+            &[instances],
+            thread_rng(),
+            &mut transcript,
+        )?;
+        Ok(transcript.finalize())
+    }
+
+    fn verify_with_transcript<'a, T>(
+        params: &Self::Params,
+        vk: &VerifyingKey<Self::Curve>,
+        proof: &'a [u8],
+        instances: &[&[Self::Scalar]],
+    ) -> Result<(), Error>
+    where
+        T: TranscriptReadBuffer<&'a [u8], Self::Curve, Challenge255<Self::Curve>>,
+    {
+        let mut transcript = T::init(proof);
+        verify_proof::<IPACommitmentScheme<C>, VerifierIPA<'_, C>, Challenge255<C>, T, _>(
+            params.verifier_params(),
+            vk,
+            ipa::strategy::SingleStrategy::new(params),
+            &[instances],
+            &mut transcript,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{KeccakConfigParams, SanityLevel};
+
+    fn prove_and_verify<P: ProofSystem>() {
+        let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+        let inputs = vec![(0u8..100).collect::<Vec<_>>(), vec![1u8; 8]];
+        let builder = KeccakCircuit::<P::Scalar>::builder()
+            .config(config)
+            .capacity_rows(1 << config.k)
+            .sanity(SanityLevel::Off);
+
+        let params = P::setup(config.k);
+        let pk = P::keygen(&params, &builder.clone().build().unwrap()).unwrap();
+        let circuit = builder.inputs(inputs).build().unwrap();
+        let instance = circuit.instances().remove(0);
+        let proof = P::prove(&params, &pk, circuit, &[&instance[..]]).unwrap();
+        assert!(P::verify(&params, pk.get_vk(), &proof, &[&instance[..]]).is_ok());
+    }
+
+    #[test]
+    fn test_keccak_transcript() {
+        type P = KzgShplonk;
+        let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+        let inputs = vec![(0u8..100).collect::<Vec<_>>()];
+        let builder = KeccakCircuit::builder()
+            .config(config)
+            .capacity_rows(1 << config.k)
+            .sanity(SanityLevel::Off);
+
+        let params = P::setup(config.k);
+        let pk = P::keygen(&params, &builder.clone().build().unwrap()).unwrap();
+        let circuit = builder.inputs(inputs).build().unwrap();
+        let instance = circuit.instances().remove(0);
+        let proof = P::prove_with_transcript::<KeccakTranscriptWrite<_>>(&params, &pk, circuit, &[&instance[..]])
+        .unwrap();
+        assert!(P::verify_with_transcript::<KeccakTranscriptRead<_>>(&params, pk.get_vk(), &proof, &[&instance[..]]).is_ok());
+        assert!(P::verify(&params, pk.get_vk(), &proof, &[&instance[..]]).is_err());
+    }
+
+    #[test_case(prove_and_verify::<KzgShplonk> ; "kzg shplonk")]
+    #[test_case(prove_and_verify::<KzgGwc> ; "kzg gwc")]
+    #[test_case(prove_and_verify::<Ipa> ; "ipa")]
+    fn test_proof_systems(run: fn()) {
+        run();
+    }
+}
@@ -0,0 +1,581 @@
+//! Long-lived prover handle, holding the SRS and proving key so they are loaded once and
+//! shared by every proof.
+
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use halo2_proofs::halo2curves::bn256::{Bn256, Fr, G1Affine};
+use halo2_proofs::plonk::{self, ProvingKey, VerifyingKey};
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use thiserror::Error;
+
+use crate::circuit::{generate_halo2_proof_for_inputs, write_halo2_proof_for_inputs, Deadline};
+use crate::progress::{observe, NoProgress, ProgressObserver, Stage};
+use crate::proof_system::{Blake2bTranscriptRead, KeccakTranscriptRead, KzgShplonk, ProofSystem};
+use crate::{
+    io, Keccak256Error, KeccakCircuit, KeccakCircuitError, KeccakConfigParams, PublicBytes, TranscriptKind,
+};
+
+/// Errors returned by [`KeccakProver`].
+#[derive(Debug, Error)]
+pub enum ProverError {
+    #[error("input {index} is {len} bytes, exceeding the limit of {max} bytes")]
+    InputTooLong { index: usize, len: usize, max: usize },
+    #[error("inputs total {total} bytes, exceeding the limit of {max} bytes")]
+    TotalTooLarge { total: usize, max: usize },
+    #[error("{count} messages exceed the limit of {max} messages")]
+    TooManyMessages { count: usize, max: usize },
+    #[error("failed to generate the proof: {0}")]
+    Proof(KeccakCircuitError),
+    #[error("proving key {} {reason}, regenerate the keys for this config with `gen-keys`", path.display())]
+    StaleKey { path: PathBuf, reason: &'static str },
+    #[error("failed to lock the prover memory: {0}")]
+    MemoryLock(std::io::Error),
+    #[error("the proof was aborted past its budget of {budget:?}, {timings:?}")]
+    TimedOut { budget: Duration, timings: ProofTimings },
+}
+
+impl ProverError {
+    /// A stable numeric code of the variant in `200..300`, see [`KeccakCircuitError::code`]. A
+    /// failed proof takes the code of its cause.
+    pub fn code(&self) -> u16 {
+        match self {
+            ProverError::InputTooLong { .. } => 200,
+            ProverError::TotalTooLarge { .. } => 201,
+            ProverError::TooManyMessages { .. } => 202,
+            ProverError::Proof(e) => e.code(),
+            ProverError::StaleKey { .. } => 203,
+            ProverError::MemoryLock(_) => 204,
+            ProverError::TimedOut { .. } => 205,
+        }
+    }
+}
+
+/// Where the time of a proof went, up to where it stopped.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProofTimings {
+    /// From the start of the proof until its witness was generated and assigned, the instance
+    /// included, `None` if it wasn't.
+    pub witness: Option<Duration>,
+    /// From the assigned witness until the proof was done or aborted: the commitments, the
+    /// quotient and the opening. `None` if the witness wasn't assigned.
+    pub commitments: Option<Duration>,
+    /// The whole proof.
+    pub total: Duration,
+}
+
+/// Errors returned by [`verify_keccak`].
+#[derive(Debug, Error)]
+pub enum VerifierError {
+    #[error("{got} instance columns, but the circuit has {expected}")]
+    InstanceColumns { expected: usize, got: usize },
+    #[error("the proof is invalid: {0}")]
+    InvalidProof(plonk::Error),
+    #[error("the config can't be verified with keys generated without inputs: {0}")]
+    InvalidConfig(Keccak256Error),
+}
+
+impl VerifierError {
+    /// A stable numeric code of the variant in `300..400`, see [`KeccakCircuitError::code`].
+    pub fn code(&self) -> u16 {
+        match self {
+            VerifierError::InstanceColumns { .. } => 300,
+            VerifierError::InvalidProof(_) => 301,
+            VerifierError::InvalidConfig(_) => 302,
+        }
+    }
+}
+
+/// Proves the keccak hashes of `inputs` with the keys generated for `config`, returning the
+/// instance columns and the proof. [`KeccakProver`] keeps the keys for repeated proofs. The
+/// config has to pass [`KeccakConfigParams::check_fixed_instance`], and the keys are the ones
+/// of the circuit with `capacity_rows = 2^k` and no inputs.
+pub fn prove_keccak(
+    inputs: Vec<Vec<u8>>,
+    srs: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    config: KeccakConfigParams,
+) -> Result<(Vec<Vec<Fr>>, Vec<u8>), ProverError> {
+    generate_halo2_proof_for_inputs(inputs, srs, pk, config).map_err(ProverError::Proof)
+}
+
+/// Verifies a proof from [`prove_keccak`] against its instance columns, with the transcript of
+/// `config`.
+pub fn verify_keccak(
+    proof: &[u8],
+    instances: &[Vec<Fr>],
+    srs: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    config: KeccakConfigParams,
+) -> Result<(), VerifierError> {
+    config.check_fixed_instance().map_err(VerifierError::InvalidConfig)?;
+    let expected = config.num_input_columns() + usize::from(config.instance_mode.exposes_output());
+    if instances.len() != expected {
+        return Err(VerifierError::InstanceColumns { expected, got: instances.len() });
+    }
+    let instances = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    match config.transcript {
+        TranscriptKind::Blake2b => KzgShplonk::<Bn256>::verify_with_transcript::<
+            Blake2bTranscriptRead<G1Affine>,
+        >(srs, vk, proof, &instances),
+        TranscriptKind::Keccak256 => KzgShplonk::<Bn256>::verify_with_transcript::<
+            KeccakTranscriptRead<G1Affine>,
+        >(srs, vk, proof, &instances),
+    }
+    .map_err(VerifierError::InvalidProof)
+}
+
+/// Request size limits, checked before any witness is generated. `None` means unlimited.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProverLimits {
+    /// Maximum length in bytes of a single message.
+    pub max_input_len: Option<usize>,
+    /// Maximum number of bytes over all messages.
+    pub max_total_bytes: Option<usize>,
+    /// Maximum number of messages.
+    pub max_messages: Option<usize>,
+}
+
+impl ProverLimits {
+    /// Checks `inputs` against the limits.
+    pub fn check<B: AsRef<[u8]>>(&self, inputs: &[B]) -> Result<(), ProverError> {
+        if let Some(max) = self.max_messages {
+            if inputs.len() > max {
+                return Err(ProverError::TooManyMessages { count: inputs.len(), max });
+            }
+        }
+        if let Some(max) = self.max_input_len {
+            if let Some((index, input)) =
+                inputs.iter().enumerate().find(|(_, input)| input.as_ref().len() > max)
+            {
+                return Err(ProverError::InputTooLong { index, len: input.as_ref().len(), max });
+            }
+        }
+        if let Some(max) = self.max_total_bytes {
+            // Saturating, so that the check itself can't overflow on adversarial inputs
+            let total = inputs.iter().fold(0usize, |acc, input| acc.saturating_add(input.as_ref().len()));
+            if total > max {
+                return Err(ProverError::TotalTooLarge { total, max });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Prover handle for the keccak circuit.
+pub struct KeccakProver {
+    srs: ParamsKZG<Bn256>,
+    pk: ProvingKey<G1Affine>,
+    config: KeccakConfigParams,
+    limits: ProverLimits,
+    public_bytes: PublicBytes,
+    time_budget: Option<Duration>,
+    observer: Arc<dyn ProgressObserver>,
+}
+
+impl fmt::Debug for KeccakProver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeccakProver")
+            .field("config", &self.config)
+            .field("limits", &self.limits)
+            .field("public_bytes", &self.public_bytes)
+            .field("time_budget", &self.time_budget)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KeccakProver {
+    /// Creates a prover from an SRS and a proving key generated for `config`, limiting the
+    /// message length to the config's `max_input_len`, which the circuit enforces anyway. Its
+    /// proofs lay out the instance like the circuit with `capacity_rows = 2^k`, so `pk` has to be
+    /// generated from that circuit, and proving fails unless `config` has a fixed layout.
+    pub fn new(srs: ParamsKZG<Bn256>, pk: ProvingKey<G1Affine>, config: KeccakConfigParams) -> Self {
+        let limits = ProverLimits { max_input_len: config.max_input_len, ..Default::default() };
+        KeccakProver {
+            srs,
+            pk,
+            config,
+            limits,
+            public_bytes: PublicBytes::All,
+            time_budget: None,
+            observer: Arc::new(NoProgress),
+        }
+    }
+
+    /// Creates a prover from SRS and proving key files. The proving key is refused unless the
+    /// fingerprint stored next to it by [`io::write_keys`] matches `config`, and so is a
+    /// config failing [`KeccakConfigParams::check_fixed_instance`].
+    pub fn from_files(
+        srs_path: &Path,
+        pk_path: &Path,
+        config: KeccakConfigParams,
+    ) -> Result<Self, ProverError> {
+        config.check_fixed_instance().map_err(|e| ProverError::Proof(e.into()))?;
+        check_fingerprint(pk_path, config)?;
+        let srs = io::read_srs_path(srs_path);
+        let pk = io::read_pk::<KeccakCircuit<Fr>>(pk_path, config);
+        Ok(Self::new(srs, pk, config))
+    }
+
+    /// Sets the request size limits.
+    pub fn with_limits(mut self, limits: ProverLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Exposes only the `public_bytes` of every message, all of them by default. Keys generated
+    /// without inputs bind the others only with
+    /// [`selective_disclosure`](KeccakConfigParams::selective_disclosure), which the config of
+    /// the prover needs for anything but [`PublicBytes::All`].
+    pub fn with_public_bytes(mut self, public_bytes: PublicBytes) -> Self {
+        self.public_bytes = public_bytes;
+        self
+    }
+
+    /// Aborts every proof not done within `budget` of its start with [`ProverError::TimedOut`],
+    /// so that a latency-sensitive service fails fast instead of holding a worker. halo2 can't
+    /// be interrupted within a phase, so the budget is checked between them: after the
+    /// instance, after the witness generation and after its assignment, before the
+    /// commitments. A proof whose commitments end past the deadline is still returned.
+    pub fn with_time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    /// Reports every proof as a [`Stage::Proof`] to `observer`, e.g. the one the keys were
+    /// generated with.
+    pub fn with_observer(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// The request size limits.
+    pub fn limits(&self) -> &ProverLimits {
+        &self.limits
+    }
+
+    /// The circuit parameters.
+    pub fn config(&self) -> &KeccakConfigParams {
+        &self.config
+    }
+
+    /// Pays the cold start costs before the first request, returning how long it took: pages in
+    /// the SRS and proving key, starts the rayon pool and grows the allocator by proving an
+    /// empty batch, whose proof is dropped. halo2 computes the FFT twiddles on every FFT, so
+    /// there is no table to precompute beyond these.
+    ///
+    /// With `lock_memory`, the pages of the process, the keys included, are then locked in RAM
+    /// so that they aren't swapped out between requests. This needs `CAP_IPC_LOCK` or a large
+    /// enough `RLIMIT_MEMLOCK`, and fails off Unix.
+    pub fn warm_up(&self, lock_memory: bool) -> Result<Duration, ProverError> {
+        let start = Instant::now();
+        prove_keccak(vec![], &self.srs, &self.pk, self.config)?;
+        if lock_memory {
+            lock_memory_pages()?;
+        }
+        Ok(start.elapsed())
+    }
+
+    /// Proves the keccak hashes of `inputs`, returning the instance columns and the proof.
+    pub fn prove(&self, inputs: Vec<Vec<u8>>) -> Result<(Vec<Vec<Fr>>, Vec<u8>), ProverError> {
+        self.prove_with(inputs, Vec::with_capacity(self.config.estimate_proof_size()))
+    }
+
+    /// Proves the keccak hashes of `inputs`, streaming the proof to `writer` as it is produced,
+    /// e.g. to a socket, instead of buffering it. Returns the instance columns and the writer.
+    pub fn prove_to_writer<W: Write>(
+        &self,
+        inputs: Vec<Vec<u8>>,
+        writer: W,
+    ) -> Result<(Vec<Vec<Fr>>, W), ProverError> {
+        self.prove_with(inputs, writer)
+    }
+
+    fn prove_with<W: Write>(&self, inputs: Vec<Vec<u8>>, writer: W) -> Result<(Vec<Vec<Fr>>, W), ProverError> {
+        self.limits.check(&inputs)?;
+        let start = Instant::now();
+        let deadline = self.time_budget.map(|budget| Deadline::new(start + budget));
+        let proof = observe(&*self.observer, Stage::Proof, || {
+            write_halo2_proof_for_inputs(
+                inputs,
+                &self.srs,
+                &self.pk,
+                self.config,
+                self.public_bytes,
+                writer,
+                deadline.clone(),
+            )
+        });
+        self.log_memory();
+        match (proof, self.time_budget.zip(deadline)) {
+            (Err(_), Some((budget, deadline))) if deadline.expired() => {
+                let witness_done = deadline.witness_done();
+                let timings = ProofTimings {
+                    witness: witness_done.map(|done| done - start),
+                    commitments: witness_done.map(|done| done.elapsed()),
+                    total: start.elapsed(),
+                };
+                Err(ProverError::TimedOut { budget, timings })
+            }
+            (proof, _) => proof.map_err(ProverError::Proof),
+        }
+    }
+
+    /// Logs the peak resident memory of the process against the estimate, when info logging is
+    /// enabled and the platform reports it.
+    fn log_memory(&self) {
+        if !log::log_enabled!(log::Level::Info) {
+            return;
+        }
+        if let Some(peak) = peak_rss_bytes() {
+            log::info!(
+                "peak RSS {} MiB, estimated prover memory {} MiB",
+                peak >> 20,
+                self.config.estimate_prover_memory() >> 20
+            );
+        }
+    }
+}
+
+fn check_fingerprint(pk_path: &Path, config: KeccakConfigParams) -> Result<(), ProverError> {
+    let stale = |reason| ProverError::StaleKey { path: pk_path.to_path_buf(), reason };
+    match io::read_fingerprint(pk_path) {
+        None => Err(stale("has no config fingerprint")),
+        Some(fingerprint) if fingerprint != config.fingerprint() => {
+            Err(stale("was generated for a different config"))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn lock_memory_pages() -> Result<(), ProverError> {
+    // SAFETY: mlockall only changes the paging of the process' existing mappings
+    if unsafe { libc::mlockall(libc::MCL_CURRENT) } != 0 {
+        return Err(ProverError::MemoryLock(std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn lock_memory_pages() -> Result<(), ProverError> {
+    Err(ProverError::MemoryLock(std::io::ErrorKind::Unsupported.into()))
+}
+
+/// The peak resident set size of the process in bytes, read from `/proc/self/status`. `None` on
+/// platforms without procfs.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib = line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim();
+    kib.parse::<u64>().ok().map(|kib| kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+    use crate::DEFAULT_CONFIG;
+
+    const CONFIG: KeccakConfigParams = KeccakConfigParams { k: 12, rows_per_round: 5, ..DEFAULT_CONFIG };
+
+    // Services share one handle between request threads
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_prover_is_send_sync() {
+        assert_send_sync::<KeccakProver>();
+    }
+
+    #[test]
+    fn test_check_fingerprint() {
+        let dir = std::env::temp_dir().join(format!("halo2-keccak-fingerprint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pk_path = dir.join("keccak256_pk");
+
+        assert!(matches!(check_fingerprint(&pk_path, CONFIG), Err(ProverError::StaleKey { .. })));
+        io::write_fingerprint(&pk_path, CONFIG);
+        assert!(check_fingerprint(&pk_path, CONFIG).is_ok());
+        let other = KeccakConfigParams { rows_per_round: 6, ..CONFIG };
+        assert!(matches!(check_fingerprint(&pk_path, other), Err(ProverError::StaleKey { .. })));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_peak_rss() {
+        assert!(peak_rss_bytes().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_warm_up() {
+        let srs = KzgShplonk::<Bn256>::setup(CONFIG.k);
+        let circuit = KeccakCircuit::builder()
+            .config(CONFIG)
+            .capacity_rows(2usize.pow(CONFIG.k))
+            .build()
+            .unwrap();
+        let pk = KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap();
+        let prover = KeccakProver::new(srs, pk, CONFIG);
+
+        assert!(prover.warm_up(false).unwrap() > Duration::ZERO);
+        assert!(prover.prove(vec![b"warm".to_vec()]).is_ok());
+    }
+
+    #[test]
+    fn test_prove_to_writer() {
+        let srs = KzgShplonk::<Bn256>::setup(CONFIG.k);
+        let circuit = KeccakCircuit::builder()
+            .config(CONFIG)
+            .capacity_rows(2usize.pow(CONFIG.k))
+            .build()
+            .unwrap();
+        let pk = KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap();
+        let vk = pk.get_vk().clone();
+        let prover = KeccakProver::new(srs.clone(), pk, CONFIG);
+
+        let (instances, proof) = prover.prove_to_writer(vec![b"streamed".to_vec()], vec![]).unwrap();
+        let instance_refs = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        assert!(KzgShplonk::<Bn256>::verify(&srs, &vk, &proof, &instance_refs).is_ok());
+        // The estimate the buffered path preallocates with is close to the actual size
+        let estimate = CONFIG.estimate_proof_size();
+        assert!(proof.len().abs_diff(estimate) <= estimate / 10, "{} vs {}", proof.len(), estimate);
+    }
+
+    #[test]
+    fn test_prove_and_verify_keccak() {
+        let config = KeccakConfigParams { transcript: TranscriptKind::Keccak256, ..CONFIG };
+        let srs = KzgShplonk::<Bn256>::setup(config.k);
+        let circuit = KeccakCircuit::builder()
+            .config(config)
+            .capacity_rows(2usize.pow(config.k))
+            .build()
+            .unwrap();
+        let pk = KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap();
+
+        let (mut instances, proof) = prove_keccak(vec![b"public api".to_vec()], &srs, &pk, config).unwrap();
+        assert!(verify_keccak(&proof, &instances, &srs, pk.get_vk(), config).is_ok());
+        // Read with the wrong transcript
+        let blake2b = KeccakConfigParams { transcript: TranscriptKind::Blake2b, ..config };
+        assert!(matches!(
+            verify_keccak(&proof, &instances, &srs, pk.get_vk(), blake2b),
+            Err(VerifierError::InvalidProof(_))
+        ));
+        instances[0][0] += Fr::from(1);
+        assert!(matches!(
+            verify_keccak(&proof, &instances, &srs, pk.get_vk(), config),
+            Err(VerifierError::InvalidProof(_))
+        ));
+        assert!(matches!(
+            verify_keccak(&proof, &[], &srs, pk.get_vk(), config),
+            Err(VerifierError::InstanceColumns { expected: 1, got: 0 })
+        ));
+        let by_input = KeccakConfigParams { fixed_instance: false, ..config };
+        assert!(matches!(
+            verify_keccak(&proof, &instances, &srs, pk.get_vk(), by_input),
+            Err(VerifierError::InvalidConfig(_))
+        ));
+        assert!(matches!(prove_keccak(vec![], &srs, &pk, by_input), Err(ProverError::Proof(_))));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_concurrent_proofs() {
+        const THREADS: usize = 4;
+        const PROOFS_PER_THREAD: usize = 2;
+
+        let srs = KzgShplonk::<Bn256>::setup(CONFIG.k);
+        let circuit = KeccakCircuit::builder()
+            .config(CONFIG)
+            .capacity_rows(2usize.pow(CONFIG.k))
+            .build()
+            .unwrap();
+        let pk = KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap();
+        let vk = pk.get_vk().clone();
+        let prover = Arc::new(KeccakProver::new(srs.clone(), pk, CONFIG));
+
+        let handles = (0..THREADS)
+            .map(|thread| {
+                let prover = Arc::clone(&prover);
+                thread::spawn(move || {
+                    (0..PROOFS_PER_THREAD)
+                        .map(|i| {
+                            // Distinct messages, so a proof leaking state from another thread
+                            // can't verify against its own instances
+                            let input = vec![(thread * PROOFS_PER_THREAD + i) as u8; 17 * (i + 1)];
+                            prover.prove(vec![input]).unwrap()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let proofs = handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect::<Vec<_>>();
+
+        assert_eq!(proofs.len(), THREADS * PROOFS_PER_THREAD);
+        for (instances, proof) in &proofs {
+            let instance_refs = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            assert!(KzgShplonk::<Bn256>::verify(&srs, &vk, proof, &instance_refs).is_ok());
+        }
+        // No thread kept a reference to the handle, so the SRS and key are freed with it
+        assert_eq!(Arc::strong_count(&prover), 1);
+    }
+
+    #[test]
+    fn test_time_budget() {
+        let srs = KzgShplonk::<Bn256>::setup(CONFIG.k);
+        let circuit = KeccakCircuit::builder()
+            .config(CONFIG)
+            .capacity_rows(2usize.pow(CONFIG.k))
+            .build()
+            .unwrap();
+        let pk = KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap();
+
+        let prover = KeccakProver::new(srs, pk, CONFIG).with_time_budget(Duration::ZERO);
+        match prover.prove(vec![b"late".to_vec()]) {
+            Err(ProverError::TimedOut { budget, timings }) => {
+                assert_eq!(budget, Duration::ZERO);
+                assert_eq!((timings.witness, timings.commitments), (None, None));
+            }
+            other => panic!("expected a timeout, got {:?}", other.map(|_| ())),
+        }
+        let prover = prover.with_time_budget(Duration::from_secs(3600));
+        assert!(prover.prove(vec![b"on time".to_vec()]).is_ok());
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = ProverLimits {
+            max_input_len: Some(4),
+            max_total_bytes: Some(6),
+            max_messages: Some(2),
+        };
+        assert!(limits.check(&[vec![0u8; 4], vec![0u8; 2]]).is_ok());
+        assert!(matches!(
+            limits.check(&[vec![0u8; 1], vec![0u8; 5]]),
+            Err(ProverError::InputTooLong { index: 1, len: 5, max: 4 })
+        ));
+        assert!(matches!(
+            limits.check(&[vec![0u8; 4], vec![0u8; 4]]),
+            Err(ProverError::TotalTooLarge { total: 8, max: 6 })
+        ));
+        assert!(matches!(
+            limits.check(&[vec![0u8; 0], vec![], vec![]]),
+            Err(ProverError::TooManyMessages { count: 3, max: 2 })
+        ));
+        assert!(ProverLimits::default().check(&[vec![0u8; 1 << 16]]).is_ok());
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let limits = ProverLimits { max_messages: Some(0), ..Default::default() };
+        assert_eq!(limits.check(&[b"abc"]).unwrap_err().code(), 202);
+        let proof = ProverError::Proof(KeccakCircuitError::NotAByte { index: 0 });
+        assert_eq!(proof.code(), 102);
+        assert_eq!(VerifierError::InvalidProof(plonk::Error::ConstraintSystemFailure).code(), 301);
+    }
+}
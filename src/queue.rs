@@ -0,0 +1,298 @@
+//! A bounded, prioritized job queue in front of a shared [`KeccakProver`], for one proving
+//! deployment serving several applications.
+//!
+//! Tenants submit jobs with a priority and poll their status. Higher priorities are proven
+//! first, jobs of the same priority in submission order. Every tenant can be rate limited to a
+//! number of submissions per window, so a burst from one application can't fill the queue for
+//! the others. Worker threads call [`ProverQueue::run_worker`] until [`ProverQueue::shutdown`].
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use thiserror::Error;
+
+use crate::prover::{KeccakProver, ProverError};
+
+/// Errors returned when submitting to a [`ProverQueue`].
+#[derive(Debug, Error)]
+pub enum QueueError {
+    #[error("the queue is full with {capacity} pending jobs")]
+    Full { capacity: usize },
+    #[error("tenant {tenant} exceeded its rate limit, retry in {retry_after:?}")]
+    RateLimited { tenant: String, retry_after: Duration },
+    #[error(transparent)]
+    Rejected(#[from] ProverError),
+}
+
+/// Identifier of a submitted job.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct JobId(u64);
+
+/// Status of a job, as polled with [`ProverQueue::status`].
+#[derive(Clone, Debug)]
+pub enum JobStatus {
+    /// Waiting, with `position` jobs to be proven before it.
+    Queued { position: usize },
+    /// Being proven.
+    Proving,
+    /// Proven, with the instance columns and the proof.
+    Done { instances: Vec<Vec<Fr>>, proof: Vec<u8> },
+    /// Proving failed.
+    Failed(String),
+}
+
+/// Maximum number of submissions of a tenant in any window of `per`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub max_jobs: usize,
+    pub per: Duration,
+}
+
+struct Pending {
+    priority: u8,
+    id: u64,
+    inputs: Vec<Vec<u8>>,
+}
+
+impl Pending {
+    /// Higher priorities first, then earlier submissions.
+    fn key(&self) -> (u8, std::cmp::Reverse<u64>) {
+        (self.priority, std::cmp::Reverse(self.id))
+    }
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    pending: BinaryHeap<Pending>,
+    statuses: HashMap<u64, JobStatus>,
+    submissions: HashMap<String, VecDeque<Instant>>,
+    shutdown: bool,
+}
+
+/// Job queue of a shared prover.
+pub struct ProverQueue {
+    prover: Arc<KeccakProver>,
+    capacity: usize,
+    rate_limits: HashMap<String, RateLimit>,
+    default_rate_limit: Option<RateLimit>,
+    state: Mutex<State>,
+    available: Condvar,
+}
+
+impl ProverQueue {
+    /// Creates a queue holding at most `capacity` pending jobs, without rate limits.
+    pub fn new(prover: Arc<KeccakProver>, capacity: usize) -> Self {
+        ProverQueue {
+            prover,
+            capacity,
+            rate_limits: HashMap::new(),
+            default_rate_limit: None,
+            state: Mutex::new(State::default()),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Sets the rate limit of `tenant`.
+    pub fn with_rate_limit(mut self, tenant: impl Into<String>, limit: RateLimit) -> Self {
+        self.rate_limits.insert(tenant.into(), limit);
+        self
+    }
+
+    /// Sets the rate limit of the tenants without their own.
+    pub fn with_default_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.default_rate_limit = Some(limit);
+        self
+    }
+
+    /// Queues the proof of `inputs` for `tenant`. The inputs are checked against the prover's
+    /// limits right away, so an oversized job is rejected instead of failing later.
+    pub fn submit(&self, tenant: &str, priority: u8, inputs: Vec<Vec<u8>>) -> Result<JobId, QueueError> {
+        self.prover.limits().check(&inputs)?;
+        let mut state = self.state.lock().unwrap();
+        if state.pending.len() >= self.capacity {
+            return Err(QueueError::Full { capacity: self.capacity });
+        }
+        if let Some(limit) = self.rate_limits.get(tenant).or(self.default_rate_limit.as_ref()) {
+            let now = Instant::now();
+            let submissions = state.submissions.entry(tenant.to_string()).or_default();
+            while submissions.front().map_or(false, |at| now.duration_since(*at) >= limit.per) {
+                submissions.pop_front();
+            }
+            if submissions.len() >= limit.max_jobs {
+                let retry_after = submissions
+                    .front()
+                    .map_or(limit.per, |oldest| limit.per - now.duration_since(*oldest));
+                return Err(QueueError::RateLimited { tenant: tenant.to_string(), retry_after });
+            }
+            submissions.push_back(now);
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        state.pending.push(Pending { priority, id, inputs });
+        self.available.notify_one();
+        Ok(JobId(id))
+    }
+
+    /// The status of job `id`, `None` if it is unknown or its result was taken.
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        let state = self.state.lock().unwrap();
+        if let Some(status) = state.statuses.get(&id.0) {
+            return Some(status.clone());
+        }
+        let job = state.pending.iter().find(|job| job.id == id.0)?;
+        let position = state.pending.iter().filter(|other| other.key() > job.key()).count();
+        Some(JobStatus::Queued { position })
+    }
+
+    /// Removes and returns the status of a finished job, freeing its proof.
+    pub fn take(&self, id: JobId) -> Option<JobStatus> {
+        let mut state = self.state.lock().unwrap();
+        match state.statuses.get(&id.0) {
+            Some(JobStatus::Done { .. } | JobStatus::Failed(_)) => state.statuses.remove(&id.0),
+            _ => None,
+        }
+    }
+
+    /// Proves the next job, if any, returning its id.
+    pub fn run_next(&self) -> Option<JobId> {
+        let job = {
+            let mut state = self.state.lock().unwrap();
+            let job = state.pending.pop()?;
+            state.statuses.insert(job.id, JobStatus::Proving);
+            job
+        };
+        Some(self.prove(job))
+    }
+
+    /// Proves jobs as they come, blocking until [`Self::shutdown`] once the queue is drained.
+    pub fn run_worker(&self) {
+        loop {
+            let job = {
+                let mut state = self.state.lock().unwrap();
+                loop {
+                    if let Some(job) = state.pending.pop() {
+                        state.statuses.insert(job.id, JobStatus::Proving);
+                        break job;
+                    }
+                    if state.shutdown {
+                        return;
+                    }
+                    state = self.available.wait(state).unwrap();
+                }
+            };
+            self.prove(job);
+        }
+    }
+
+    /// Stops the workers once the pending jobs are proven.
+    pub fn shutdown(&self) {
+        self.state.lock().unwrap().shutdown = true;
+        self.available.notify_all();
+    }
+
+    fn prove(&self, job: Pending) -> JobId {
+        let status = match self.prover.prove(job.inputs) {
+            Ok((instances, proof)) => JobStatus::Done { instances, proof },
+            Err(e) => JobStatus::Failed(e.to_string()),
+        };
+        self.state.lock().unwrap().statuses.insert(job.id, status);
+        JobId(job.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use halo2_proofs::halo2curves::bn256::Bn256;
+
+    use super::*;
+    use crate::proof_system::{KzgShplonk, ProofSystem};
+    use crate::prover::ProverLimits;
+    use crate::{KeccakCircuit, KeccakConfigParams, DEFAULT_CONFIG};
+
+    const CONFIG: KeccakConfigParams = KeccakConfigParams { k: 12, rows_per_round: 5, ..DEFAULT_CONFIG };
+
+    fn prover() -> Arc<KeccakProver> {
+        let srs = KzgShplonk::<Bn256>::setup(CONFIG.k);
+        let circuit = KeccakCircuit::builder()
+            .config(CONFIG)
+            .capacity_rows(2usize.pow(CONFIG.k))
+            .build()
+            .unwrap();
+        let pk = KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap();
+        let limits = ProverLimits { max_input_len: Some(100), ..Default::default() };
+        Arc::new(KeccakProver::new(srs, pk, CONFIG).with_limits(limits))
+    }
+
+    #[test]
+    fn test_priorities_and_limits() {
+        let limit = RateLimit { max_jobs: 2, per: Duration::from_secs(3600) };
+        let queue = ProverQueue::new(prover(), 3).with_rate_limit("burst", limit);
+
+        let low = queue.submit("a", 0, vec![b"low".to_vec()]).unwrap();
+        let high = queue.submit("b", 5, vec![b"high".to_vec()]).unwrap();
+        let second_low = queue.submit("burst", 0, vec![]).unwrap();
+        assert!(matches!(queue.status(high), Some(JobStatus::Queued { position: 0 })));
+        assert!(matches!(queue.status(low), Some(JobStatus::Queued { position: 1 })));
+        assert!(matches!(queue.status(second_low), Some(JobStatus::Queued { position: 2 })));
+        assert!(matches!(queue.submit("a", 9, vec![]), Err(QueueError::Full { capacity: 3 })));
+        assert!(matches!(queue.submit("a", 0, vec![vec![0; 101]]), Err(QueueError::Rejected(_))));
+
+        assert_eq!(queue.run_next(), Some(high));
+        assert!(matches!(queue.status(high), Some(JobStatus::Done { .. })));
+        assert!(matches!(queue.status(low), Some(JobStatus::Queued { position: 0 })));
+
+        assert_eq!(queue.run_next(), Some(low));
+        queue.submit("burst", 0, vec![]).unwrap();
+        assert!(matches!(queue.submit("burst", 0, vec![]), Err(QueueError::RateLimited { .. })));
+
+        assert!(matches!(queue.take(high), Some(JobStatus::Done { .. })));
+        assert!(queue.status(high).is_none());
+        assert!(queue.take(second_low).is_none());
+    }
+
+    #[test]
+    fn test_workers() {
+        let queue = Arc::new(ProverQueue::new(prover(), 8));
+        let ids = (0..3u8).map(|i| queue.submit("a", i, vec![vec![i; 10]]).unwrap()).collect::<Vec<_>>();
+        let workers = (0..2)
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || queue.run_worker())
+            })
+            .collect::<Vec<_>>();
+        queue.shutdown();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        for id in ids {
+            assert!(matches!(queue.status(id), Some(JobStatus::Done { .. })));
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! In-circuit verification of keccak proofs, with snark-verifier's halo2 loader.
+//!
+//! A keccak proof to be verified in another circuit is written with snark-verifier's Poseidon
+//! transcript by [`prove_poseidon`], whose challenges are cheap to recompute in-circuit, and
+//! wrapped with its protocol in a [`KeccakSnark`]. [`verify_keccak_snark`] is the gadget: on
+//! the [`Halo2Loader`] of a host circuit it assigns the instance of the proof, reads it from
+//! the transcript and runs the succinct verifier, returning the assigned instance cells, e.g.
+//! the digests to copy constrain to the host's own cells, and the KZG accumulator whose
+//! pairing check is deferred. The host exposes the accumulator as limbs for the next layer,
+//! or the final verifier, to decide.
+//!
+//! [`KeccakAggregationCircuit`] is such a host: it folds the accumulators of any number of
+//! keccak proofs into one, exposed as the first [`AGGREGATION_ACCUMULATOR_LEN`] cells of its
+//! instance column, followed by the instance cells of every proof in order.
+//!
+//! The in-circuit chips come from halo2wrong, so accumulators are encoded with [`LIMBS`]
+//! limbs of [`BITS`] bits rather than the
+//! [`ACCUMULATOR_LIMBS`](crate::ACCUMULATOR_LIMBS) layout the keccak circuit reserves for
+//! halo2-lib aggregators. Proofs are KZG with SHPLONK, as for
+//! [`KzgShplonk`](crate::proof_system::KzgShplonk).
+
+use std::rc::Rc;
+
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner, Value};
+use halo2_proofs::halo2curves::bn256::{Bn256, Fq, Fr, G1Affine};
+use halo2_proofs::plonk::{self, create_proof, verify_proof, Circuit, ConstraintSystem, ProvingKey, VerifyingKey};
+use halo2_proofs::poly::commitment::{Params, ParamsProver};
+use halo2_proofs::poly::kzg::commitment::{KZGCommitmentScheme, ParamsKZG};
+use halo2_proofs::poly::kzg::multiopen::{ProverSHPLONK, VerifierSHPLONK};
+use halo2_proofs::poly::kzg::strategy::SingleStrategy;
+use halo2_wrong_ecc::integer::rns::Rns;
+use halo2_wrong_ecc::maingate::{
+    MainGate, MainGateConfig, MainGateInstructions, RangeChip, RangeConfig, RangeInstructions, RegionCtx,
+};
+use halo2_wrong_ecc::EccConfig;
+use itertools::Itertools;
+use rand::thread_rng;
+use snark_verifier::loader::{self, native::NativeLoader};
+use snark_verifier::pcs::kzg::{
+    Bdfg21, KzgAccumulator, KzgAs, KzgDecidingKey, KzgSuccinctVerifyingKey, LimbsEncoding, LimbsEncodingInstructions,
+};
+use snark_verifier::pcs::{AccumulationDecider, AccumulationScheme, AccumulationSchemeProver};
+use snark_verifier::system::halo2::{compile, Config};
+use snark_verifier::util::arithmetic::fe_to_limbs;
+use snark_verifier::verifier::plonk::PlonkProtocol;
+use snark_verifier::verifier::{self, SnarkVerifier};
+
+use crate::{Keccak256Error, KeccakCircuit, KeccakCircuitError, KeccakConfigParams};
+
+/// Number of limbs of every accumulator coordinate in-circuit, halo2wrong's integer limbs.
+pub const LIMBS: usize = 4;
+
+/// Number of bits of every accumulator limb in-circuit.
+pub const BITS: usize = 68;
+
+/// Number of instance cells of the accumulator of a [`KeccakAggregationCircuit`]: the limbs
+/// of the x and y coordinates of its two G1 points.
+pub const AGGREGATION_ACCUMULATOR_LEN: usize = 4 * LIMBS;
+
+// The Poseidon parameters of the transcript, snark-verifier's for BN254
+const T: usize = 5;
+const RATE: usize = 4;
+const R_F: usize = 8;
+const R_P: usize = 60;
+
+type As = KzgAs<Bn256, Bdfg21>;
+type PlonkSuccinctVerifier = verifier::plonk::PlonkSuccinctVerifier<As, LimbsEncoding<LIMBS, BITS>>;
+type Svk = KzgSuccinctVerifyingKey<G1Affine>;
+
+/// The ECC chip the gadget verifies with.
+pub type BaseFieldEccChip = halo2_wrong_ecc::BaseFieldEccChip<G1Affine, LIMBS, BITS>;
+/// The loader of a host circuit running [`verify_keccak_snark`].
+pub type Halo2Loader<'a> = loader::halo2::Halo2Loader<'a, G1Affine, BaseFieldEccChip>;
+/// A scalar assigned by [`Halo2Loader`].
+pub type LoadedScalar<'a> = loader::halo2::Scalar<'a, G1Affine, BaseFieldEccChip>;
+/// The Poseidon transcript of proofs verified in-circuit, natively with `L = NativeLoader`.
+pub type PoseidonTranscript<L, S> =
+    snark_verifier::system::halo2::transcript::halo2::PoseidonTranscript<G1Affine, L, S, T, RATE, R_F, R_P>;
+
+/// Proves the keccak hashes of `inputs` with the Poseidon transcript, returning the instance
+/// columns and the proof. As for the [`KeccakProver`](crate::KeccakProver), `pk` is generated
+/// without inputs, so `config` has to pass
+/// [`check_fixed_instance`](KeccakConfigParams::check_fixed_instance).
+pub fn prove_poseidon(
+    inputs: Vec<Vec<u8>>,
+    srs: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    config: KeccakConfigParams,
+) -> Result<(Vec<Vec<Fr>>, Vec<u8>), KeccakCircuitError> {
+    config.check_fixed_instance()?;
+    let circuit = KeccakCircuit::<Fr>::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k))
+        .inputs(inputs)
+        .build()?;
+    let instances = circuit.instances();
+    let instance_refs = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+    let mut transcript = PoseidonTranscript::<NativeLoader, _>::new(vec![]);
+    create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
+        srs,
+        pk,
+        &[circuit],
+        &[&instance_refs],
+        thread_rng(),
+        &mut transcript,
+    )?;
+    Ok((instances, transcript.finalize()))
+}
+
+/// Verifies a proof from [`prove_poseidon`] natively.
+pub fn verify_poseidon(
+    proof: &[u8],
+    instances: &[Vec<Fr>],
+    srs: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+) -> Result<(), KeccakCircuitError> {
+    let instance_refs = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let mut transcript = PoseidonTranscript::<NativeLoader, _>::new(proof);
+    verify_proof::<KZGCommitmentScheme<Bn256>, VerifierSHPLONK<'_, Bn256>, _, _, _>(
+        srs.verifier_params(),
+        vk,
+        SingleStrategy::new(srs),
+        &[&instance_refs],
+        &mut transcript,
+    )?;
+    Ok(())
+}
+
+/// A keccak proof from [`prove_poseidon`] with its instance and the protocol of its verifying
+/// key, what [`verify_keccak_snark`] verifies.
+#[derive(Clone, Debug)]
+pub struct KeccakSnark {
+    protocol: PlonkProtocol<G1Affine>,
+    instances: Vec<Vec<Fr>>,
+    proof: Vec<u8>,
+}
+
+impl KeccakSnark {
+    /// Compiles the protocol of `vk` for the instance column lengths of `instances`.
+    pub fn new(srs: &ParamsKZG<Bn256>, vk: &VerifyingKey<G1Affine>, instances: Vec<Vec<Fr>>, proof: Vec<u8>) -> Self {
+        let num_instance = instances.iter().map(Vec::len).collect();
+        let protocol = compile(srs, vk, Config::kzg().with_num_instance(num_instance));
+        Self { protocol, instances, proof }
+    }
+
+    pub fn instances(&self) -> &[Vec<Fr>] {
+        &self.instances
+    }
+
+    /// Runs the succinct verifier natively, returning the accumulators left to decide.
+    fn accumulators(&self, svk: &Svk) -> Result<Vec<KzgAccumulator<G1Affine, NativeLoader>>, KeccakCircuitError> {
+        let mut transcript = PoseidonTranscript::<NativeLoader, _>::new(self.proof.as_slice());
+        PlonkSuccinctVerifier::read_proof(svk, &self.protocol, &self.instances, &mut transcript)
+            .and_then(|proof| PlonkSuccinctVerifier::verify(svk, &self.protocol, &self.instances, &proof))
+            .map_err(|e| Keccak256Error(format!("invalid keccak proof: {:?}", e)).into())
+    }
+}
+
+/// The witness of a [`KeccakSnark`] in a host circuit, unknown during keygen.
+#[derive(Clone, Debug)]
+pub struct KeccakSnarkWitness {
+    protocol: PlonkProtocol<G1Affine>,
+    instances: Vec<Vec<Value<Fr>>>,
+    proof: Value<Vec<u8>>,
+}
+
+impl From<KeccakSnark> for KeccakSnarkWitness {
+    fn from(snark: KeccakSnark) -> Self {
+        Self {
+            protocol: snark.protocol,
+            instances: snark
+                .instances
+                .into_iter()
+                .map(|instances| instances.into_iter().map(Value::known).collect())
+                .collect(),
+            proof: Value::known(snark.proof),
+        }
+    }
+}
+
+impl KeccakSnarkWitness {
+    /// The witness of a proof of the same protocol and instance lengths, with unknown values.
+    pub fn without_witnesses(&self) -> Self {
+        Self {
+            protocol: self.protocol.clone(),
+            instances: self.instances.iter().map(|instances| vec![Value::unknown(); instances.len()]).collect(),
+            proof: Value::unknown(),
+        }
+    }
+
+    fn proof(&self) -> Value<&[u8]> {
+        self.proof.as_ref().map(Vec::as_slice)
+    }
+}
+
+/// Verifies `snark` on the `loader` of a host circuit, up to the pairing check. Returns the
+/// assigned instance columns of the proof and the accumulator the host has to expose, or
+/// fold with [`KzgAs`], for the pairing check to be done by whoever verifies the host.
+pub fn verify_keccak_snark<'a>(
+    svk: &Svk,
+    loader: &Rc<Halo2Loader<'a>>,
+    snark: &KeccakSnarkWitness,
+) -> Result<(Vec<Vec<LoadedScalar<'a>>>, Vec<KzgAccumulator<G1Affine, Rc<Halo2Loader<'a>>>>), plonk::Error> {
+    let protocol = snark.protocol.loaded(loader);
+    let instances = snark
+        .instances
+        .iter()
+        .map(|instances| instances.iter().map(|instance| loader.assign_scalar(*instance)).collect_vec())
+        .collect_vec();
+    let mut transcript = PoseidonTranscript::<Rc<Halo2Loader>, _>::new(loader, snark.proof());
+    let accumulators = PlonkSuccinctVerifier::read_proof(svk, &protocol, &instances, &mut transcript)
+        .and_then(|proof| PlonkSuccinctVerifier::verify(svk, &protocol, &instances, &proof))
+        .map_err(|_| plonk::Error::Synthesis)?;
+    Ok((instances, accumulators))
+}
+
+#[derive(Clone)]
+pub struct KeccakAggregationConfig {
+    main_gate: MainGateConfig,
+    range: RangeConfig,
+}
+
+impl KeccakAggregationConfig {
+    pub fn configure(meta: &mut ConstraintSystem<Fr>) -> Self {
+        let main_gate = MainGate::<Fr>::configure(meta);
+        let range = RangeChip::<Fr>::configure(
+            meta,
+            &main_gate,
+            vec![BITS / LIMBS],
+            Rns::<Fq, Fr, LIMBS, BITS>::construct().overflow_lengths(),
+        );
+        Self { main_gate, range }
+    }
+
+    fn ecc_chip(&self) -> BaseFieldEccChip {
+        BaseFieldEccChip::new(EccConfig::new(self.range.clone(), self.main_gate.clone()))
+    }
+}
+
+/// Folds the accumulators of keccak proofs into one, exposed as
+/// [`AGGREGATION_ACCUMULATOR_LEN`] limbs followed by the instance cells of every proof.
+#[derive(Clone, Debug)]
+pub struct KeccakAggregationCircuit {
+    svk: Svk,
+    snarks: Vec<KeccakSnarkWitness>,
+    instances: Vec<Fr>,
+    as_proof: Value<Vec<u8>>,
+}
+
+impl KeccakAggregationCircuit {
+    /// Verifies `snarks` natively, pairing check included, and folds their accumulators. Fails
+    /// if any of them doesn't verify, as the circuit would then be unsatisfiable.
+    pub fn new(srs: &ParamsKZG<Bn256>, snarks: Vec<KeccakSnark>) -> Result<Self, KeccakCircuitError> {
+        let svk: Svk = srs.get_g()[0].into();
+        let accumulators = snarks
+            .iter()
+            .map(|snark| snark.accumulators(&svk))
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+
+        let mut transcript = PoseidonTranscript::<NativeLoader, _>::new(Vec::new());
+        let accumulator = As::create_proof(&Default::default(), &accumulators, &mut transcript, thread_rng())
+            .map_err(|e| Keccak256Error(format!("failed to fold the accumulators: {:?}", e)))?;
+        let as_proof = transcript.finalize();
+        let deciding_key: KzgDecidingKey<Bn256> = (srs.get_g()[0], srs.g2(), srs.s_g2()).into();
+        As::decide(&deciding_key, accumulator.clone())
+            .map_err(|e| Keccak256Error(format!("invalid keccak proof: {:?}", e)))?;
+
+        let KzgAccumulator { lhs, rhs } = accumulator;
+        let limbs = [lhs.x, lhs.y, rhs.x, rhs.y].map(fe_to_limbs::<_, _, LIMBS, BITS>).concat();
+        let instances = limbs.into_iter().chain(snarks.iter().flat_map(|snark| snark.instances.concat())).collect();
+        Ok(Self { svk, snarks: snarks.into_iter().map_into().collect(), instances, as_proof: Value::known(as_proof) })
+    }
+
+    /// The single instance column: the accumulator limbs, then the instance of every proof.
+    pub fn instances(&self) -> Vec<Vec<Fr>> {
+        vec![self.instances.clone()]
+    }
+
+    /// The `(column, row)` of every accumulator limb, for the aggregator of this circuit.
+    pub fn accumulator_indices() -> Vec<(usize, usize)> {
+        (0..AGGREGATION_ACCUMULATOR_LEN).map(|row| (0, row)).collect()
+    }
+
+    fn as_proof(&self) -> Value<&[u8]> {
+        self.as_proof.as_ref().map(Vec::as_slice)
+    }
+}
+
+impl Circuit<Fr> for KeccakAggregationCircuit {
+    type Config = KeccakAggregationConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = ();
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            svk: self.svk,
+            snarks: self.snarks.iter().map(KeccakSnarkWitness::without_witnesses).collect(),
+            instances: vec![],
+            as_proof: Value::unknown(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+        KeccakAggregationConfig::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), plonk::Error> {
+        RangeChip::<Fr>::new(config.range.clone()).load_table(&mut layouter)?;
+
+        let public_cells = layouter.assign_region(
+            || "keccak aggregation",
+            |region| {
+                let loader = Halo2Loader::new(config.ecc_chip(), RegionCtx::new(region, 0));
+                let mut accumulators = vec![];
+                let mut instance_cells = vec![];
+                for snark in self.snarks.iter() {
+                    let (instances, snark_accumulators) = verify_keccak_snark(&self.svk, &loader, snark)?;
+                    instance_cells.extend(instances.into_iter().flatten().map(|instance| instance.into_assigned()));
+                    accumulators.extend(snark_accumulators);
+                }
+
+                let mut transcript = PoseidonTranscript::<Rc<Halo2Loader>, _>::new(&loader, self.as_proof());
+                let KzgAccumulator { lhs, rhs } = As::read_proof(&Default::default(), &accumulators, &mut transcript)
+                    .and_then(|proof| As::verify(&Default::default(), &accumulators, &proof))
+                    .map_err(|_| plonk::Error::Synthesis)?;
+
+                let mut cells = vec![];
+                for point in [lhs, rhs] {
+                    cells.extend(loader.ecc_chip().assign_ec_point_to_limbs(&mut loader.ctx_mut(), point.assigned())?);
+                }
+                cells.extend(instance_cells);
+                Ok(cells)
+            },
+        )?;
+
+        let main_gate = MainGate::<Fr>::new(config.main_gate);
+        for (row, cell) in public_cells.into_iter().enumerate() {
+            main_gate.expose_public(layouter.namespace(|| "public cell"), cell, row)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::plonk::{keygen_pk, keygen_vk};
+    use rand::rngs::OsRng;
+    use sha3::{Digest, Keccak256};
+
+    use super::*;
+    use crate::{message_digest_from_instance, InstanceMode, DEFAULT_CONFIG};
+
+    const AGGREGATION_K: u32 = 22;
+
+    #[test]
+    fn test_keccak_aggregation() {
+        let config = KeccakConfigParams { instance_mode: InstanceMode::Output, ..DEFAULT_CONFIG };
+        let srs = ParamsKZG::<Bn256>::setup(config.k, OsRng);
+        let circuit = KeccakCircuit::builder().config(config).capacity_rows(2usize.pow(config.k)).build().unwrap();
+        let vk = keygen_vk(&srs, &circuit).unwrap();
+        let pk = keygen_pk(&srs, vk.clone(), &circuit).unwrap();
+
+        let inputs = vec![b"abc".to_vec(), vec![7u8; 300]];
+        let (instances, proof) = prove_poseidon(inputs.clone(), &srs, &pk, config).unwrap();
+        verify_poseidon(&proof, &instances, &srs, &vk).unwrap();
+
+        let snark = KeccakSnark::new(&srs, &vk, instances.clone(), proof.clone());
+        let aggregation = KeccakAggregationCircuit::new(&srs, vec![snark]).unwrap();
+        let public = aggregation.instances();
+        assert_eq!(public[0].len(), AGGREGATION_ACCUMULATOR_LEN + instances.concat().len());
+        // The digests are exposed after the accumulator as they were by the keccak proof, whose
+        // `input` column is empty
+        let output = &public[0][AGGREGATION_ACCUMULATOR_LEN..];
+        let lengths = inputs.iter().map(Vec::len).collect::<Vec<_>>();
+        let digest = message_digest_from_instance(output, &lengths, 1, &config).unwrap();
+        assert_eq!(digest, Keccak256::digest(&inputs[1]).to_vec());
+        MockProver::run(AGGREGATION_K, &aggregation, public.clone()).unwrap().assert_satisfied();
+
+        // The aggregation circuit binds the instance of the keccak proof
+        let mut tampered = public.clone();
+        tampered[0][AGGREGATION_ACCUMULATOR_LEN] += Fr::from(1);
+        assert!(MockProver::run(AGGREGATION_K, &aggregation, tampered).unwrap().verify().is_err());
+
+        // A proof that doesn't verify can't be aggregated
+        let mut forged = instances;
+        forged.last_mut().unwrap()[0] += Fr::from(1);
+        let snark = KeccakSnark::new(&srs, &vk, forged, proof);
+        assert!(KeccakAggregationCircuit::new(&srs, vec![snark]).is_err());
+    }
+}
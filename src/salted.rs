@@ -0,0 +1,193 @@
+//! Salted batch hashing `digest = keccak(salt || message)`, with a 32 byte salt per message for
+//! domain separated, replay resistant commitments.
+//!
+//! The salt fills the first four words of every message, so the salt slots sit at the same
+//! place of the absorb schedule whatever the messages. A [`SaltVisibility::Private`] salt stays
+//! private through [`PublicBytes::Suffix`], its words masked out of the instance, while the
+//! exposed `bytes_left` of every keccak_f pins it to exactly 32 bytes. A [`SaltVisibility::Public`] salt is exposed
+//! with the message. The digests are exposed through the `output` column either way.
+//!
+//! The circuit has the [`disclosure_config`] of the base config, which masks the private salts
+//! out of the fixed instance layout, so the layout, and keys generated without inputs, are the
+//! same for both visibilities and every batch.
+
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::PublicBytes;
+use crate::disclosure::{disclosure_config, masked_instances};
+use crate::util::eth_types::Field;
+use crate::{Keccak256Error, KeccakCircuit, KeccakCircuitBuilder, KeccakConfigParams};
+
+/// Length of the salt prepended to every message, a multiple of the word.
+pub const SALT_LEN: usize = 32;
+
+/// Whether the salts are exposed along with the messages.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SaltVisibility {
+    /// Only the messages and the digests are public.
+    #[default]
+    Private,
+    /// The salts are public as well.
+    Public,
+}
+
+impl SaltVisibility {
+    /// The bytes of every preimage exposed by the circuit.
+    pub fn public_bytes(self) -> PublicBytes {
+        match self {
+            SaltVisibility::Private => PublicBytes::Suffix(SALT_LEN),
+            SaltVisibility::Public => PublicBytes::All,
+        }
+    }
+}
+
+/// A message and its salt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Salted {
+    pub salt: [u8; SALT_LEN],
+    pub message: Vec<u8>,
+}
+
+impl Salted {
+    pub fn new(salt: [u8; SALT_LEN], message: Vec<u8>) -> Self {
+        Salted { salt, message }
+    }
+
+    /// `salt || message`, the message hashed by the circuit.
+    pub fn preimage(&self) -> Vec<u8> {
+        [&self.salt[..], &self.message].concat()
+    }
+
+    /// The big-endian digest `keccak(salt || message)`.
+    pub fn digest(&self) -> [u8; 32] {
+        Keccak256::digest(self.preimage()).into()
+    }
+}
+
+/// Builder of the circuit hashing every salted message of `batch` with the
+/// [`disclosure_config`] of `config`, exposing the messages, the salts if `visibility` is
+/// public, and the digests. Needs `capacity_rows`, whose keys serve every batch and both
+/// visibilities, as do those of [`KeccakProver`](crate::KeccakProver) with
+/// [`SaltVisibility::public_bytes`].
+pub fn salted_builder<F: Field>(
+    batch: &[Salted],
+    visibility: SaltVisibility,
+    config: KeccakConfigParams,
+) -> KeccakCircuitBuilder<F> {
+    KeccakCircuit::builder()
+        .config(disclosure_config(config))
+        .inputs(batch.iter().map(Salted::preimage).collect())
+        .public_bytes(visibility.public_bytes())
+}
+
+/// The instance columns of the circuit from [`salted_builder`] with `config` and
+/// `capacity_rows`, computed from the messages, their digests, and their salts if public,
+/// `None` for private salts.
+pub fn salted_instances<F: Field>(
+    config: KeccakConfigParams,
+    capacity_rows: usize,
+    salts: Option<&[[u8; SALT_LEN]]>,
+    messages: &[Vec<u8>],
+    digests: &[[u8; 32]],
+) -> Result<Vec<Vec<F>>, Keccak256Error> {
+    // Private salts don't show in the instance, so any will do
+    let zero_salts = vec![[0u8; SALT_LEN]; messages.len()];
+    let (salts, visibility) = match salts {
+        Some(salts) => (salts, SaltVisibility::Public),
+        None => (&zero_salts[..], SaltVisibility::Private),
+    };
+    let inputs = salts
+        .iter()
+        .zip(messages)
+        .map(|(salt, message)| Salted::new(*salt, message.clone()).preimage())
+        .collect::<Vec<_>>();
+    let digests = digests.iter().map(|digest| digest.to_vec()).collect::<Vec<_>>();
+    masked_instances(disclosure_config(config), capacity_rows, visibility.public_bytes(), inputs, &digests)
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::{Bn256, Fr};
+    use halo2_proofs::plonk::keygen_vk;
+    use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+    use halo2_proofs::SerdeFormat::RawBytes;
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::DEFAULT_CONFIG;
+
+    const CONFIG: KeccakConfigParams = KeccakConfigParams { k: 12, rows_per_round: 5, ..DEFAULT_CONFIG };
+    const CAPACITY_ROWS: usize = (1 << CONFIG.k) - 109;
+
+    fn instances(salts: Option<&[[u8; SALT_LEN]]>, messages: &[Vec<u8>], digests: &[[u8; 32]]) -> Vec<Vec<Fr>> {
+        salted_instances(CONFIG, CAPACITY_ROWS, salts, messages, digests).unwrap()
+    }
+
+    fn batch() -> Vec<Salted> {
+        vec![
+            Salted::new([1u8; SALT_LEN], b"vote: yes".to_vec()),
+            Salted::new([2u8; SALT_LEN], vec![]),
+            Salted::new([3u8; SALT_LEN], (0u8..150).collect()),
+        ]
+    }
+
+    fn run(batch: &[Salted], visibility: SaltVisibility, instances: Vec<Vec<Fr>>) -> bool {
+        let circuit = salted_builder(batch, visibility, CONFIG).capacity_rows(CAPACITY_ROWS).build().unwrap();
+        MockProver::run(CONFIG.k, &circuit, instances).unwrap().verify().is_ok()
+    }
+
+    #[test]
+    fn test_private_salts() {
+        let batch = batch();
+        let messages = batch.iter().map(|salted| salted.message.clone()).collect::<Vec<_>>();
+        let digests = batch.iter().map(Salted::digest).collect::<Vec<_>>();
+        assert!(run(&batch, SaltVisibility::Private, instances(None, &messages, &digests)));
+
+        let mut forged = messages.clone();
+        forged[0] = b"vote: no!".to_vec();
+        assert!(!run(&batch, SaltVisibility::Private, instances(None, &forged, &digests)));
+        // The same message under another salt has another digest
+        let resalted = Salted::new([9u8; SALT_LEN], messages[0].clone());
+        assert_ne!(resalted.digest(), digests[0]);
+    }
+
+    #[test]
+    fn test_public_salts() {
+        let batch = batch();
+        let salts = batch.iter().map(|salted| salted.salt).collect::<Vec<_>>();
+        let messages = batch.iter().map(|salted| salted.message.clone()).collect::<Vec<_>>();
+        let digests = batch.iter().map(Salted::digest).collect::<Vec<_>>();
+        assert!(run(&batch, SaltVisibility::Public, instances(Some(&salts), &messages, &digests)));
+
+        let mut replayed = salts.clone();
+        replayed[1] = salts[0];
+        assert!(!run(&batch, SaltVisibility::Public, instances(Some(&replayed), &messages, &digests)));
+    }
+
+    #[test]
+    fn test_layout_independent_of_salts() {
+        let batch = batch();
+        let srs = ParamsKZG::<Bn256>::setup(CONFIG.k, OsRng);
+        let vk = |batch: &[Salted], visibility| {
+            let circuit = salted_builder::<Fr>(batch, visibility, CONFIG).capacity_rows(CAPACITY_ROWS).build().unwrap();
+            let mut bytes = vec![];
+            keygen_vk(&srs, &circuit).unwrap().write(&mut bytes, RawBytes).unwrap();
+            bytes
+        };
+        let private = vk(&batch, SaltVisibility::Private);
+        assert_eq!(private, vk(&batch, SaltVisibility::Public));
+        assert_eq!(private, vk(&batch[..1], SaltVisibility::Private));
+        assert_eq!(private, vk(&[], SaltVisibility::Public));
+
+        // Private salts are zeros in the instance, of the same length as public ones
+        let messages = batch.iter().map(|salted| salted.message.clone()).collect::<Vec<_>>();
+        let digests = batch.iter().map(Salted::digest).collect::<Vec<_>>();
+        let salts = batch.iter().map(|salted| salted.salt).collect::<Vec<_>>();
+        let hidden = instances(None, &messages, &digests);
+        let shown = instances(Some(&salts), &messages, &digests);
+        assert_eq!(hidden[0].len(), shown[0].len());
+        assert!(hidden[0][2..6].iter().all(|word| *word == Fr::from(0)));
+        assert_eq!(shown[0][2], Fr::from(u64::from_le_bytes([1u8; 8])));
+    }
+}
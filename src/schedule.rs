@@ -0,0 +1,204 @@
+//! The absorb schedule of a keccak region: where the words and the digest of every input land.
+//!
+//! Inputs are hashed one after the other after a dummy first round, each taking
+//! `num_keccak_f(len)` keccak_f of `NUM_ROUNDS + 1` rounds. The first row of each of the
+//! first `words_to_absorb` rounds of a keccak_f absorbs a word, and the first row of the
+//! last round of the last keccak_f of an input holds its digest. Everything that walks the
+//! region goes through [`AbsorbScheduleIter`], so the offset arithmetic lives in one place.
+
+use crate::config::DigestSize;
+use crate::vanilla::param::{NUM_BYTES_PER_WORD, NUM_ROUNDS};
+
+/// A step of the absorb schedule, rows being relative to the start of the region.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum AbsorbStep {
+    /// The row absorbing the word at `byte_offset` of input `input`.
+    Word { input: usize, row: usize, byte_offset: usize },
+    /// The row holding `is_final` and the digest of input `input`.
+    Final { input: usize, row: usize },
+}
+
+/// Iterates over the words and then the final row of every input, in order.
+#[derive(Clone, Debug)]
+pub(crate) struct AbsorbScheduleIter {
+    lens: Vec<usize>,
+    rows_per_round: usize,
+    digest_size: DigestSize,
+    input: usize,
+    word: usize,
+    start_row: usize,
+}
+
+impl AbsorbScheduleIter {
+    /// The schedule of a region hashing inputs of the given lengths into digests of
+    /// `digest_size`.
+    pub(crate) fn new(
+        lens: impl IntoIterator<Item = usize>,
+        rows_per_round: usize,
+        digest_size: DigestSize,
+    ) -> Self {
+        AbsorbScheduleIter {
+            lens: lens.into_iter().collect(),
+            rows_per_round,
+            digest_size,
+            input: 0,
+            word: 0,
+            // first round is dummy
+            start_row: rows_per_round,
+        }
+    }
+
+    /// The schedule of a region hashing `inputs`.
+    pub(crate) fn for_inputs<B: AsRef<[u8]>>(
+        inputs: &[B],
+        rows_per_round: usize,
+        digest_size: DigestSize,
+    ) -> Self {
+        Self::new(inputs.iter().map(|input| input.as_ref().len()), rows_per_round, digest_size)
+    }
+
+    fn keccak_f_rows(&self) -> usize {
+        (NUM_ROUNDS + 1) * self.rows_per_round
+    }
+}
+
+impl Iterator for AbsorbScheduleIter {
+    type Item = AbsorbStep;
+
+    fn next(&mut self) -> Option<AbsorbStep> {
+        let len = *self.lens.get(self.input)?;
+        let input = self.input;
+        if self.word * NUM_BYTES_PER_WORD < len {
+            let word = self.word;
+            self.word += 1;
+            let words_to_absorb = self.digest_size.words_to_absorb();
+            let row = self.start_row
+                + (word / words_to_absorb) * self.keccak_f_rows()
+                + (word % words_to_absorb) * self.rows_per_round;
+            return Some(AbsorbStep::Word { input, row, byte_offset: word * NUM_BYTES_PER_WORD });
+        }
+        let num_keccak_f = len / self.digest_size.rate() + 1;
+        let row = self.start_row
+            + (num_keccak_f - 1) * self.keccak_f_rows()
+            + NUM_ROUNDS * self.rows_per_round;
+        self.input += 1;
+        self.word = 0;
+        self.start_row += num_keccak_f * self.keccak_f_rows();
+        Some(AbsorbStep::Final { input, row })
+    }
+}
+
+/// Where a single hash lands in the trace of a region, rows being relative to the start of
+/// the region, as returned by [`KeccakCircuit::schedule`](crate::KeccakCircuit::schedule).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HashSchedule {
+    /// Index of the input among the hashed inputs.
+    pub input_idx: usize,
+    /// Number of keccak_f permutations of the hash.
+    pub num_keccak_f: usize,
+    /// First row of the first round of its first keccak_f, absorbing its first word.
+    pub first_round_row: usize,
+    /// Last row of the last round of its last keccak_f.
+    pub last_round_row: usize,
+    /// Row holding `is_final` and the digest, the first row of its last round.
+    pub digest_row: usize,
+}
+
+/// The [`HashSchedule`] of every input of a region hashing inputs of the given lengths.
+pub(crate) fn hash_schedules(
+    lens: impl IntoIterator<Item = usize>,
+    rows_per_round: usize,
+    digest_size: DigestSize,
+) -> Vec<HashSchedule> {
+    // first round is dummy
+    let mut start_row = rows_per_round;
+    lens.into_iter()
+        .enumerate()
+        .map(|(input_idx, len)| {
+            let num_keccak_f = len / digest_size.rate() + 1;
+            let end_row = start_row + num_keccak_f * (NUM_ROUNDS + 1) * rows_per_round;
+            let schedule = HashSchedule {
+                input_idx,
+                num_keccak_f,
+                first_round_row: start_row,
+                last_round_row: end_row - 1,
+                digest_row: end_row - rows_per_round,
+            };
+            start_row = end_row;
+            schedule
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::vanilla::param::RATE;
+    use crate::vanilla::witness::multi_keccak;
+    use crate::KeccakConfigParams;
+
+    #[test]
+    fn test_schedule_rows() {
+        let steps = AbsorbScheduleIter::new([0, 136, 137], 5, DigestSize::Bits256).collect::<Vec<_>>();
+        assert_eq!(steps.len(), 1 + 18 + 19);
+        assert_eq!(steps[0], AbsorbStep::Final { input: 0, row: 125 });
+        assert_eq!(steps[1], AbsorbStep::Word { input: 1, row: 130, byte_offset: 0 });
+        assert_eq!(steps[17], AbsorbStep::Word { input: 1, row: 210, byte_offset: 128 });
+        // 136 bytes need a second keccak_f for the padding
+        assert_eq!(steps[18], AbsorbStep::Final { input: 1, row: 375 });
+        assert_eq!(steps[36], AbsorbStep::Word { input: 2, row: 505, byte_offset: 136 });
+        assert_eq!(steps[37], AbsorbStep::Final { input: 2, row: 625 });
+    }
+
+    #[test]
+    fn test_hash_schedules_match_steps() {
+        let lens = [0, 136, 137, 300];
+        let schedules = hash_schedules(lens, 5, DigestSize::Bits256);
+        assert_eq!(schedules.len(), lens.len());
+        assert_eq!(schedules[1].first_round_row, schedules[0].last_round_row + 1);
+        for step in AbsorbScheduleIter::new(lens, 5, DigestSize::Bits256) {
+            match step {
+                AbsorbStep::Word { input, row, .. } => {
+                    let schedule = schedules[input];
+                    assert!((schedule.first_round_row..schedule.digest_row).contains(&row));
+                }
+                AbsorbStep::Final { input, row } => assert_eq!(schedules[input].digest_row, row),
+            }
+        }
+        assert_eq!(schedules[3].num_keccak_f, 3);
+        assert_eq!(schedules[3].last_round_row, 5 + 8 * 125 - 1);
+    }
+
+    #[test]
+    fn test_schedule_matches_witness() {
+        let mut rng = StdRng::seed_from_u64(956);
+        for (rows_per_round, digest_size) in
+            [(5, DigestSize::Bits256), (28, DigestSize::Bits256), (5, DigestSize::Bits512)]
+        {
+            let config = KeccakConfigParams { k: 14, rows_per_round, digest_size, ..Default::default() };
+            let inputs = (0..8)
+                .map(|_| vec![0xab; rng.gen_range(0..=3 * RATE)])
+                .collect::<Vec<_>>();
+            let (witness, _) = multi_keccak::<Fr, _>(&inputs, None, config);
+            let mut finals = vec![];
+            for step in AbsorbScheduleIter::for_inputs(&inputs, rows_per_round, digest_size) {
+                match step {
+                    AbsorbStep::Word { input, row, byte_offset } => {
+                        let len = inputs[input].len();
+                        assert_eq!(witness[row].bytes_left, Fr::from((len - byte_offset) as u64));
+                    }
+                    AbsorbStep::Final { row, .. } => finals.push(row),
+                }
+            }
+            let witness_finals = (0..witness.len())
+                .step_by(rows_per_round)
+                .filter(|row| witness[*row].is_final)
+                .collect::<Vec<_>>();
+            assert_eq!(finals, witness_finals);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Solidity library of the instance layout of a circuit config, for contracts consuming the EVM
+//! verifier.
+//!
+//! The verifier takes the instance columns concatenated: the `input` columns, then the `output`
+//! column if the instance mode exposes digests. The library pins the offsets within them, the
+//! keccak_f capacity that moves with `rows_per_round`, and the hash of the verifying key the
+//! contract is expected to be deployed with.
+//!
+//! [`foundry_test`] embeds a proof in a Foundry test, for end-to-end tests of a deployed verifier.
+
+use std::fmt::Write;
+
+use halo2_proofs::halo2curves::bn256::{Fr, G1Affine};
+use halo2_proofs::halo2curves::ff::PrimeField;
+use halo2_proofs::plonk::VerifyingKey;
+use halo2_proofs::SerdeFormat::RawBytes;
+use sha3::{Digest, Keccak256};
+
+use crate::disclosure::DISCLOSURE_HEADER_LEN;
+use crate::encoding::to_hex;
+use crate::vanilla::keccak_packed_multi::get_keccak_capacity;
+use crate::vanilla::param::NUM_BYTES_PER_WORD;
+use crate::{DigestSize, KeccakConfigParams, ACCUMULATOR_INSTANCE_LEN, DIGEST_INSTANCE_STRIDE};
+
+/// The keccak256 hash of the raw serialization of `vk`.
+pub fn vk_hash(vk: &VerifyingKey<G1Affine>) -> [u8; 32] {
+    let mut bytes = vec![];
+    vk.write(&mut bytes, RawBytes).expect("writing to a Vec can't fail");
+    Keccak256::digest(bytes).into()
+}
+
+/// The Solidity library `name` describing the instance layout of `config`, with the first
+/// input cells reserved for an accumulator if `accumulator` is set. The layout is the fixed one
+/// of the circuit with `capacity_rows = 2^k`, see
+/// [`KeccakConfigParams::check_fixed_instance`]: a slot of input cells per keccak_f, and the
+/// digest of every message on the slot of its last keccak_f.
+pub fn layout_library(
+    name: &str,
+    config: KeccakConfigParams,
+    accumulator: bool,
+    vk: &VerifyingKey<G1Affine>,
+) -> String {
+    let accumulator_len = if accumulator { ACCUMULATOR_INSTANCE_LEN } else { 0 };
+    let capacity = get_keccak_capacity(2usize.pow(config.k), config.rows_per_round);
+    let rate = config.digest_size.rate();
+    let bytes_per_cell = config.input_binding.bytes_per_input_cell();
+    let header_len = if config.selective_disclosure { DISCLOSURE_HEADER_LEN } else { 0 };
+    let slot_cells = header_len + (rate + bytes_per_cell - 1) / bytes_per_cell;
+
+    let mut sol = String::new();
+    writeln!(sol, "// SPDX-License-Identifier: MIT").unwrap();
+    writeln!(sol, "// Generated by halo2-keccak-256 {}, do not edit.", env!("CARGO_PKG_VERSION")).unwrap();
+    writeln!(sol, "pragma solidity ^0.8.0;").unwrap();
+    writeln!(sol).unwrap();
+    writeln!(
+        sol,
+        "/// Instance layout of the keccak circuit with k = {}, rows_per_round = {}.",
+        config.k, config.rows_per_round
+    )
+    .unwrap();
+    writeln!(sol, "library {name} {{").unwrap();
+    let constants = [
+        ("K", config.k.to_string()),
+        ("ROWS_PER_ROUND", config.rows_per_round.to_string()),
+        ("KECCAK_F_CAPACITY", capacity.to_string()),
+        ("RATE", rate.to_string()),
+        ("BYTES_PER_WORD", NUM_BYTES_PER_WORD.to_string()),
+        ("BYTES_PER_INPUT_CELL", bytes_per_cell.to_string()),
+        ("SLOT_INPUT_CELLS", if config.instance_mode.exposes_input() { slot_cells } else { 0 }.to_string()),
+        ("DISCLOSURE_HEADER_LEN", header_len.to_string()),
+        ("INPUT_COLUMNS", config.num_input_columns().to_string()),
+        ("ACCUMULATOR_LEN", accumulator_len.to_string()),
+        ("DIGEST_STRIDE", config.digest_size.num_digest_cells().to_string()),
+    ];
+    for (constant, value) in constants {
+        writeln!(sol, "    uint256 internal constant {constant} = {value};").unwrap();
+    }
+    writeln!(sol, "    bool internal constant EXPOSES_INPUT = {};", config.instance_mode.exposes_input()).unwrap();
+    writeln!(sol, "    bool internal constant EXPOSES_OUTPUT = {};", config.instance_mode.exposes_output())
+        .unwrap();
+    writeln!(sol, "    bytes32 internal constant VK_HASH = {};", to_hex(&vk_hash(vk))).unwrap();
+    writeln!(sol, "    bytes32 internal constant CONFIG_FINGERPRINT = {};", to_hex(&config.fingerprint()))
+        .unwrap();
+    writeln!(sol).unwrap();
+    writeln!(sol, "    /// Number of keccak_f slots a message of `len` bytes takes, padding included.").unwrap();
+    writeln!(sol, "    function keccakF(uint256 len) internal pure returns (uint256) {{").unwrap();
+    writeln!(sol, "        return len / RATE + 1;").unwrap();
+    writeln!(sol, "    }}").unwrap();
+    writeln!(sol).unwrap();
+    writeln!(sol, "    /// Offset of the input cells of keccak_f `slot` in the concatenated instances, zero").unwrap();
+    writeln!(sol, "    /// past the end of its message, `BYTES_PER_INPUT_CELL` little-endian bytes a cell. With").unwrap();
+    writeln!(sol, "    /// selective disclosure, the `bytes_left` of the slot and its disclosure mask come first,").unwrap();
+    writeln!(sol, "    /// `DISCLOSURE_HEADER_LEN` cells, and private words are zero.").unwrap();
+    writeln!(sol, "    function inputOffset(uint256 slot) internal pure returns (uint256) {{").unwrap();
+    writeln!(sol, "        require(EXPOSES_INPUT, \"inputs are not public\");").unwrap();
+    writeln!(sol, "        return ACCUMULATOR_LEN + SLOT_INPUT_CELLS * slot;").unwrap();
+    writeln!(sol, "    }}").unwrap();
+    writeln!(sol).unwrap();
+    writeln!(sol, "    /// Offset of the digest finished by keccak_f `slot` in the concatenated instances, 128").unwrap();
+    writeln!(sol, "    /// bits a cell. The digest of message `i` is on the slot of its last keccak_f, the sum").unwrap();
+    writeln!(sol, "    /// of `keccakF` over messages `0..=i` minus one; the other slots hold zero.").unwrap();
+    writeln!(sol, "    function digestOffset(uint256 slot) internal pure returns (uint256) {{").unwrap();
+    writeln!(sol, "        require(EXPOSES_OUTPUT, \"digests are not public\");").unwrap();
+    writeln!(sol, "        require(slot < KECCAK_F_CAPACITY, \"slot out of capacity\");").unwrap();
+    writeln!(
+        sol,
+        "        return ACCUMULATOR_LEN + SLOT_INPUT_CELLS * KECCAK_F_CAPACITY + DIGEST_STRIDE * slot;"
+    )
+    .unwrap();
+    writeln!(sol, "    }}").unwrap();
+    writeln!(sol, "}}").unwrap();
+    sol
+}
+
+/// The calldata of the EVM verifier: every instance cell as a big-endian 32 byte word, the
+/// columns concatenated, followed by the proof, which must use the keccak256 transcript.
+pub fn evm_calldata(instances: &[Vec<Fr>], proof: &[u8]) -> Vec<u8> {
+    instances
+        .iter()
+        .flatten()
+        .flat_map(|cell| cell.to_repr().as_ref().iter().rev().copied().collect::<Vec<_>>())
+        .chain(proof.iter().copied())
+        .collect()
+}
+
+/// A Foundry test contract `name` calling the verifier at a placeholder address with the proof
+/// of `inputs` under `config`. If the config exposes 256 bit digests, the test also checks that
+/// the calldata holds them, each on the slot of the last keccak_f of its message.
+pub fn foundry_test(
+    name: &str,
+    config: &KeccakConfigParams,
+    inputs: &[Vec<u8>],
+    instances: &[Vec<Fr>],
+    proof: &[u8],
+) -> String {
+    // Solidity hex literals take the digits without the prefix
+    let hex = |bytes: &[u8]| to_hex(bytes)[2..].to_string();
+
+    let mut sol = String::new();
+    writeln!(sol, "// SPDX-License-Identifier: MIT").unwrap();
+    writeln!(sol, "// Generated by halo2-keccak-256 {}, do not edit.", env!("CARGO_PKG_VERSION")).unwrap();
+    writeln!(sol, "pragma solidity ^0.8.0;").unwrap();
+    writeln!(sol).unwrap();
+    writeln!(sol, "import \"forge-std/Test.sol\";").unwrap();
+    writeln!(sol).unwrap();
+    writeln!(sol, "contract {name} is Test {{").unwrap();
+    writeln!(sol, "    /// The deployed keccak verifier, to be filled in.").unwrap();
+    writeln!(sol, "    address internal constant VERIFIER = address(0);").unwrap();
+    writeln!(sol).unwrap();
+    writeln!(sol, "    bytes internal constant CALLDATA = hex\"{}\";", hex(&evm_calldata(instances, proof))).unwrap();
+    writeln!(sol).unwrap();
+    writeln!(sol, "    function testVerify() public {{").unwrap();
+    writeln!(sol, "        (bool ok, ) = VERIFIER.call(CALLDATA);").unwrap();
+    writeln!(sol, "        assertTrue(ok);").unwrap();
+    writeln!(sol, "    }}").unwrap();
+    writeln!(sol).unwrap();
+    writeln!(sol, "    function testTamperedProof() public {{").unwrap();
+    writeln!(sol, "        bytes memory data = CALLDATA;").unwrap();
+    writeln!(sol, "        data[data.length - 1] ^= 0x01;").unwrap();
+    writeln!(sol, "        (bool ok, ) = VERIFIER.call(data);").unwrap();
+    writeln!(sol, "        assertFalse(ok);").unwrap();
+    writeln!(sol, "    }}").unwrap();
+    if config.instance_mode.exposes_output() && config.digest_size == DigestSize::Bits256 {
+        let digest_offset = instances[..instances.len() - 1].iter().map(Vec::len).sum::<usize>();
+        writeln!(sol).unwrap();
+        writeln!(sol, "    function digest(uint256 slot) internal pure returns (bytes32) {{").unwrap();
+        writeln!(sol, "        bytes memory data = CALLDATA;").unwrap();
+        writeln!(sol, "        uint256 offset = 32 * ({digest_offset} + {DIGEST_INSTANCE_STRIDE} * slot);").unwrap();
+        writeln!(sol, "        uint256 hi;").unwrap();
+        writeln!(sol, "        uint256 lo;").unwrap();
+        writeln!(sol, "        assembly {{").unwrap();
+        writeln!(sol, "            hi := mload(add(add(data, 32), offset))").unwrap();
+        writeln!(sol, "            lo := mload(add(add(data, 64), offset))").unwrap();
+        writeln!(sol, "        }}").unwrap();
+        writeln!(sol, "        return bytes32((hi << 128) | lo);").unwrap();
+        writeln!(sol, "    }}").unwrap();
+        writeln!(sol).unwrap();
+        writeln!(sol, "    function testDigests() public {{").unwrap();
+        let mut slot = 0;
+        for input in inputs {
+            slot += config.num_keccak_f(input.len());
+            writeln!(sol, "        assertEq(digest({}), {});", slot - 1, to_hex(&config.digest(input))).unwrap();
+        }
+        writeln!(sol, "    }}").unwrap();
+    }
+    writeln!(sol, "}}").unwrap();
+    sol
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::halo2curves::bn256::Bn256;
+
+    use super::*;
+    use crate::proof_system::{KzgShplonk, ProofSystem};
+    use crate::{InstanceMode, KeccakCircuit};
+
+    #[test]
+    fn test_layout_library() {
+        let config = KeccakConfigParams {
+            k: 12,
+            rows_per_round: 5,
+            instance_mode: InstanceMode::InputOutput,
+            ..Default::default()
+        };
+        let circuit = KeccakCircuit::builder()
+            .config(config)
+            .capacity_rows(2usize.pow(config.k))
+            .build()
+            .unwrap();
+        let srs = KzgShplonk::<Bn256>::setup(config.k);
+        let vk = KzgShplonk::<Bn256>::keygen(&srs, &circuit).unwrap().get_vk().clone();
+
+        let sol = layout_library("KeccakLayout", config, true, &vk);
+        assert!(sol.contains("library KeccakLayout {"));
+        assert!(sol.contains("uint256 internal constant ROWS_PER_ROUND = 5;"));
+        assert!(sol.contains("uint256 internal constant ACCUMULATOR_LEN = 12;"));
+        assert!(sol.contains("uint256 internal constant BYTES_PER_INPUT_CELL = 8;"));
+        assert!(sol.contains("bool internal constant EXPOSES_OUTPUT = true;"));
+        let capacity = get_keccak_capacity(2usize.pow(config.k), 5);
+        assert!(sol.contains(&format!("uint256 internal constant KECCAK_F_CAPACITY = {};", capacity)));
+        assert!(sol.contains("uint256 internal constant SLOT_INPUT_CELLS = 17;"));
+        // The offsets of the library are the ones of the circuit keys are generated for
+        let instances = circuit.instances();
+        assert_eq!(instances[0].len(), 17 * capacity);
+        assert_eq!(instances[1].len(), 2 * capacity);
+    }
+
+    #[test]
+    fn test_foundry_test() {
+        let config = KeccakConfigParams { instance_mode: InstanceMode::InputOutput, ..Default::default() };
+        let inputs = vec![b"abc".to_vec(), vec![0u8; 200]];
+        let instances = vec![vec![Fr::from(0x636261)], vec![Fr::from(1), Fr::from(2)]];
+        let proof = vec![0xab; 4];
+
+        let calldata = evm_calldata(&instances, &proof);
+        assert_eq!(calldata.len(), 3 * 32 + 4);
+        assert_eq!(&calldata[29..32], &[0x63, 0x62, 0x61]);
+        assert_eq!(&calldata[96..], &proof[..]);
+
+        let sol = foundry_test("KeccakVerifierTest", &config, &inputs, &instances, &proof);
+        assert!(sol.contains("contract KeccakVerifierTest is Test {"));
+        assert!(sol.contains("uint256 offset = 32 * (1 + 2 * slot);"));
+        // keccak256("abc"), on the first slot
+        assert!(sol.contains(
+            "assertEq(digest(0), 0x4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45);"
+        ));
+        // The second message takes two keccak_f, its digest is on the last one
+        let digest = to_hex(&Keccak256::digest(&inputs[1]));
+        assert!(sol.contains(&format!("assertEq(digest(2), {});", digest)));
+    }
+}
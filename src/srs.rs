@@ -0,0 +1,204 @@
+//! A local directory of KZG SRS files keyed by `k`, checked against known checksums on load.
+//!
+//! Files are named `srs_k{k}` and hold the raw [`ParamsKZG`] serialization. A request for `k`
+//! is served from the file of the smallest `k' >= k` in the directory, downsized when `k' > k`,
+//! so a single large SRS covers every smaller circuit. With the `srs-download` feature a missing
+//! file is fetched from the configured URL first. Loaded parameters are cached and shared.
+
+use std::collections::HashMap;
+use std::fs;
+#[cfg(feature = "srs-download")]
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use halo2_proofs::halo2curves::bn256::Bn256;
+use halo2_proofs::poly::commitment::Params;
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+use crate::encoding::to_hex;
+
+/// Largest `k` looked for in the store directory.
+const MAX_K: u32 = 28;
+
+/// Errors returned by [`SrsStore`].
+#[derive(Debug, Error)]
+pub enum SrsError {
+    #[error("no SRS file for k >= {k} in {}", dir.display())]
+    Missing { k: u32, dir: PathBuf },
+    #[error("SRS file {} has checksum {}, expected {}", path.display(), to_hex(actual), to_hex(expected))]
+    ChecksumMismatch { path: PathBuf, expected: [u8; 32], actual: [u8; 32] },
+    #[error("failed to access SRS file {}: {reason}", path.display())]
+    Io { path: PathBuf, reason: String },
+    #[error("failed to download the SRS for k = {k}: {reason}")]
+    Download { k: u32, reason: String },
+}
+
+/// The keccak256 checksum of an SRS file, as registered with [`SrsStore::with_checksum`].
+pub fn srs_checksum(bytes: &[u8]) -> [u8; 32] {
+    Keccak256::digest(bytes).into()
+}
+
+/// Local store of SRS files, see the [module documentation](self).
+#[derive(Debug)]
+pub struct SrsStore {
+    dir: PathBuf,
+    checksums: HashMap<u32, [u8; 32]>,
+    #[cfg(feature = "srs-download")]
+    url: Option<String>,
+    cache: Mutex<HashMap<u32, Arc<ParamsKZG<Bn256>>>>,
+}
+
+impl SrsStore {
+    /// A store backed by `dir`, which is created on the first download if missing.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        SrsStore {
+            dir: dir.into(),
+            checksums: HashMap::new(),
+            #[cfg(feature = "srs-download")]
+            url: None,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Registers the known checksum of the file for `k`. Files without a checksum are loaded
+    /// unchecked.
+    pub fn with_checksum(mut self, k: u32, checksum: [u8; 32]) -> Self {
+        self.checksums.insert(k, checksum);
+        self
+    }
+
+    /// Downloads missing files from `{url}/srs_k{k}`.
+    #[cfg(feature = "srs-download")]
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// The path of the file for `k`.
+    pub fn path(&self, k: u32) -> PathBuf {
+        self.dir.join(format!("srs_k{k}"))
+    }
+
+    /// Writes `srs` to the store as the file for its `k`.
+    pub fn insert(&self, srs: &ParamsKZG<Bn256>) -> Result<(), SrsError> {
+        let path = self.path(srs.k());
+        let mut bytes = vec![];
+        srs.write(&mut bytes)
+            .and_then(|_| fs::create_dir_all(&self.dir))
+            .and_then(|_| fs::write(&path, bytes))
+            .map_err(|e| SrsError::Io { path, reason: e.to_string() })
+    }
+
+    /// The parameters for circuits of 2<sup>k</sup> rows.
+    pub fn get(&self, k: u32) -> Result<Arc<ParamsKZG<Bn256>>, SrsError> {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(srs) = cache.get(&k) {
+            return Ok(Arc::clone(srs));
+        }
+        let source_k = match (k..=MAX_K).find(|k| self.path(*k).exists()) {
+            Some(source_k) => source_k,
+            None => self.download(k)?,
+        };
+        let mut srs = self.load(source_k)?;
+        if source_k > k {
+            srs.downsize(k);
+        }
+        let srs = Arc::new(srs);
+        cache.insert(k, Arc::clone(&srs));
+        Ok(srs)
+    }
+
+    fn load(&self, k: u32) -> Result<ParamsKZG<Bn256>, SrsError> {
+        let path = self.path(k);
+        let bytes = fs::read(&path).map_err(|e| SrsError::Io { path: path.clone(), reason: e.to_string() })?;
+        if let Some(expected) = self.checksums.get(&k) {
+            let actual = srs_checksum(&bytes);
+            if actual != *expected {
+                return Err(SrsError::ChecksumMismatch { path, expected: *expected, actual });
+            }
+        }
+        ParamsKZG::read(&mut bytes.as_slice()).map_err(|e| SrsError::Io { path, reason: e.to_string() })
+    }
+
+    #[cfg(feature = "srs-download")]
+    fn download(&self, k: u32) -> Result<u32, SrsError> {
+        let url = self.url.as_ref().ok_or_else(|| self.missing(k))?;
+        let error = |reason: String| SrsError::Download { k, reason };
+        let response = ureq::get(&format!("{}/srs_k{k}", url.trim_end_matches('/')))
+            .call()
+            .map_err(|e| error(e.to_string()))?;
+        let mut bytes = vec![];
+        response
+            .into_reader()
+            .read_to_end(&mut bytes)
+            .map_err(|e| error(e.to_string()))?;
+        // Written to a temporary file first, so an interrupted download never looks complete
+        let tmp = self.dir.join(format!("srs_k{k}.part"));
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&tmp, &bytes))
+            .and_then(|_| fs::rename(&tmp, self.path(k)))
+            .map_err(|e| error(e.to_string()))?;
+        Ok(k)
+    }
+
+    #[cfg(not(feature = "srs-download"))]
+    fn download(&self, k: u32) -> Result<u32, SrsError> {
+        Err(self.missing(k))
+    }
+
+    fn missing(&self, k: u32) -> SrsError {
+        SrsError::Missing { k, dir: self.dir.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    /// A fresh directory, removed if left over from a previous run.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("halo2-keccak-srs-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn srs(k: u32) -> ParamsKZG<Bn256> {
+        ParamsKZG::<Bn256>::setup(k, StdRng::seed_from_u64(963))
+    }
+
+    #[test]
+    fn test_downsize_and_cache() {
+        let dir = test_dir("downsize");
+        let store = SrsStore::new(&dir);
+        store.insert(&srs(6)).unwrap();
+
+        let small = store.get(4).unwrap();
+        assert_eq!(small.k(), 4);
+        assert!(Arc::ptr_eq(&small, &store.get(4).unwrap()));
+        assert_eq!(store.get(6).unwrap().k(), 6);
+        assert!(matches!(store.get(7), Err(SrsError::Missing { k: 7, .. })));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_checksum() {
+        let dir = test_dir("checksum");
+        let srs = srs(4);
+        let mut bytes = vec![];
+        srs.write(&mut bytes).unwrap();
+        SrsStore::new(&dir).insert(&srs).unwrap();
+
+        let store = SrsStore::new(&dir).with_checksum(4, srs_checksum(&bytes));
+        assert_eq!(store.get(4).unwrap().k(), 4);
+
+        let store = SrsStore::new(&dir).with_checksum(4, [0u8; 32]);
+        assert!(matches!(store.get(4), Err(SrsError::ChecksumMismatch { .. })));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
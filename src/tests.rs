@@ -1,9 +1,13 @@
 use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
     dev::MockProver,
     halo2curves::bn256::Fr,
     halo2curves::bn256::{Bn256, G1Affine},
-    halo2curves::ff::FromUniformBytes,
-    plonk::{create_proof, keygen_pk, keygen_vk, verify_proof},
+    halo2curves::ff::{FromUniformBytes, PrimeField},
+    plonk::{
+        create_proof, keygen_pk, keygen_vk, verify_proof, Advice, Circuit, Column, ConstraintSystem,
+        Error, Fixed, Instance,
+    },
     poly::{
         commitment::ParamsProver,
         kzg::{
@@ -11,18 +15,28 @@ use halo2_proofs::{
             multiopen::{ProverSHPLONK, VerifierSHPLONK},
             strategy::SingleStrategy,
         },
+        Rotation,
     },
     transcript::{
         Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
     },
 };
 use hex::FromHex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rand_core::OsRng;
-use sha3::{Digest, Keccak256};
+use sha3::{Digest, Keccak256, Keccak512, Sha3_256, Sha3_512};
 use test_case::test_case;
 
-use crate::circuit::KeccakCircuit;
+use crate::circuit::{
+    accumulator_indices, digest_from_instance, instance_columns, pack_digests_to_instance, pack_input_to_instance,
+    pack_output_to_instance, with_accumulator, KeccakCircuit, PublicBytes, PublicCell, ACCUMULATOR_INSTANCE_LEN,
+};
+use crate::config::{DigestSize, InputBinding, InstanceMode, LookupLayout, Padding, SanityLevel};
+use crate::util::assign_value::{constrain_instance, raw_assign_advice, raw_assign_fixed};
 use crate::util::eth_types::Field;
+use crate::util::word::Word;
+use crate::util::SKIP_FIRST_PASS;
 use crate::vanilla::*;
 
 fn verify_mock<F: Field + Ord + FromUniformBytes<64>>(
@@ -31,12 +45,70 @@ fn verify_mock<F: Field + Ord + FromUniformBytes<64>>(
     _success: bool,
 ) {
     let k = config.k;
-    let circuit = KeccakCircuit::new(config, Some(2usize.pow(k) - 109), inputs, true, false);
+    let circuit = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(k) - 109)
+        .inputs(inputs)
+        .instance_mode(InstanceMode::None)
+        .sanity(SanityLevel::Full)
+        .build()
+        .unwrap();
 
     let prover = MockProver::<F>::run(k, &circuit, vec![vec![]]).unwrap();
     prover.assert_satisfied();
 }
 
+/// The part sizes fix the witness layout, so they must be the same on 32 and 64 bit targets.
+/// Precomputed with arbitrary precision integers.
+#[test]
+fn lookup_part_sizes() {
+    let expected = [
+        (10, [6, 4, 4, 3]),
+        (12, [7, 5, 5, 4]),
+        (14, [8, 6, 6, 5]),
+        (20, [12, 9, 8, 7]),
+        (28, [17, 13, 12, 10]),
+        (32, [20, 15, 13, 12]),
+    ];
+    for (k, sizes) in expected {
+        for (range, size) in [3, 4, 5, 6].into_iter().zip(sizes) {
+            assert_eq!(table::get_num_bits_per_lookup(range, k), size, "range {range}, k {k}");
+        }
+    }
+}
+
+/// Packing the largest parts must not overflow, whatever the width of usize.
+#[test]
+fn pack_largest_parts() {
+    let bits = [1u8; 64];
+    let part = crate::util::PartInfo { bits: (0..20).collect() };
+    // (8^20 - 1) / 7
+    assert_eq!(crate::util::pack_part(&bits, &part), 164703072086692425);
+    // Rotation parts of the rho/pi lookups reach 11 bits from k = 28
+    assert!(KeccakConfigParams { k: 28, rows_per_round: 28, ..Default::default() }.degree() > 0);
+}
+
+/// The table entries, and with them the keys, are the same as when packing every combination
+/// of parts.
+#[test]
+fn table_entries_order() {
+    use itertools::Itertools;
+
+    for (part_size, range) in [(1, 2), (3, 3), (4, 6), (5, 5)] {
+        let expected = (0..part_size)
+            .map(|_| 0..range)
+            .multi_cartesian_product()
+            .map(|perm| {
+                perm.iter().rev().fold((0u64, 0u64), |(input, output), part| {
+                    let base = param::BIT_SIZE as u64;
+                    (input * base + part, output * base + (part & 1))
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(table::table_entries(part_size, range, |part| part & 1), expected);
+    }
+}
+
 #[test_case(14, 28; "k: 14, rows_per_round: 28")]
 #[test_case(12, 5; "k: 12, rows_per_round: 5")]
 fn packed_multi_keccak_simple(k: u32, rows_per_round: usize) {
@@ -50,7 +122,7 @@ fn packed_multi_keccak_simple(k: u32, rows_per_round: usize) {
             (0u8..136).collect::<Vec<_>>(),
             (0u8..200).collect::<Vec<_>>(),
         ];
-        verify_mock::<Fr>(KeccakConfigParams { k, rows_per_round }, inputs, true);
+        verify_mock::<Fr>(KeccakConfigParams { k, rows_per_round, ..Default::default() }, inputs, true);
     }
     {
         // First input is not empty.
@@ -61,10 +133,522 @@ fn packed_multi_keccak_simple(k: u32, rows_per_round: usize) {
             (0u8..135).collect::<Vec<_>>(),
             (0u8..136).collect::<Vec<_>>(),
         ];
-        verify_mock::<Fr>(KeccakConfigParams { k, rows_per_round }, inputs, true);
+        verify_mock::<Fr>(KeccakConfigParams { k, rows_per_round, ..Default::default() }, inputs, true);
+    }
+}
+
+#[test_case(14, 28; "k: 14, rows_per_round: 28")]
+#[test_case(12, 5; "k: 12, rows_per_round: 5")]
+fn packed_multi_keccak_tagged_lookups(k: u32, rows_per_round: usize) {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = KeccakConfigParams {
+        k,
+        rows_per_round,
+        lookup_layout: LookupLayout::Tagged,
+        ..Default::default()
+    };
+    let inputs = vec![
+        vec![],
+        (0u8..1).collect::<Vec<_>>(),
+        (0u8..135).collect::<Vec<_>>(),
+        (0u8..136).collect::<Vec<_>>(),
+        (0u8..200).collect::<Vec<_>>(),
+    ];
+    verify_mock::<Fr>(config, inputs, true);
+}
+
+#[test]
+fn packed_multi_keccak_split_regions() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = KeccakConfigParams { k: 14, rows_per_round: 28, ..Default::default() };
+    // 4096 rows fit 5 keccak_f per region, so 12 inputs need 3 regions.
+    let inputs = (1u8..=12).map(|len| (0..len * 10).collect::<Vec<_>>()).collect::<Vec<_>>();
+    let instance = pack_input_to_instance::<Fr>(&inputs);
+
+    let builder = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(1 << 12)
+        .fixed_instance(false)
+        .split_regions(true)
+        .sanity(SanityLevel::Full);
+    assert!(builder.clone().split_regions(false).inputs(inputs.clone()).build().is_err());
+    let circuit = builder.clone().inputs(inputs.clone()).build().unwrap();
+
+    let prover = MockProver::<Fr>::run(config.k, &circuit, vec![instance]).unwrap();
+    prover.assert_satisfied();
+
+    let too_many = vec![vec![0u8; 100]; 30];
+    assert!(builder.inputs(too_many).build().is_err());
+}
+
+#[test]
+fn packed_multi_keccak_output_instance() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::InputOutput,
+        ..Default::default()
+    };
+    let inputs = vec![(0u8..1).collect::<Vec<_>>(), (0u8..100).collect::<Vec<_>>()];
+    let instances = instance_columns::<Fr>(&inputs, &config, PublicBytes::All);
+    assert_eq!(instances[1].len(), 2 * inputs.len());
+
+    let circuit = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k) - 109)
+        .fixed_instance(false)
+        .inputs(inputs)
+        .build()
+        .unwrap();
+    let prover = MockProver::<Fr>::run(config.k, &circuit, instances.clone()).unwrap();
+    prover.assert_satisfied();
+
+    let mut wrong_digest = instances;
+    wrong_digest[1][0] += Fr::from(1);
+    let prover = MockProver::<Fr>::run(config.k, &circuit, wrong_digest).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn packed_multi_keccak_output_only_instance() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::Output,
+        ..Default::default()
+    };
+    let inputs = vec![vec![], (0u8..200).collect::<Vec<_>>()];
+    let instances = instance_columns::<Fr>(&inputs, &config, PublicBytes::All);
+    assert!(instances[0].is_empty());
+
+    let circuit = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k) - 109)
+        .fixed_instance(false)
+        .inputs(inputs)
+        .build()
+        .unwrap();
+    let prover = MockProver::<Fr>::run(config.k, &circuit, instances).unwrap();
+    prover.assert_satisfied();
+}
+
+/// The exposed digests aren't recomputed natively, so only the copy constraints fixed at keygen
+/// tie them to the messages, and keygen without inputs needs the fixed layout.
+#[test]
+fn output_instance_keygen_without_inputs() {
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::Output,
+        ..Default::default()
+    };
+    let builder = KeccakCircuit::<Fr>::builder().config(config).capacity_rows(2usize.pow(config.k) - 109);
+    assert!(builder.clone().build().is_ok());
+    assert!(builder.clone().fixed_instance(false).build().is_err());
+    assert!(builder.fixed_instance(false).inputs(vec![vec![]]).build().is_ok());
+    assert!(config.check_fixed_instance().is_ok());
+    assert!(KeccakConfigParams { fixed_instance: false, ..config }.check_fixed_instance().is_err());
+}
+
+#[test_case(InputBinding::Word, 47; "word binding")]
+#[test_case(InputBinding::Packed, 18; "packed binding")]
+#[test_case(InputBinding::Bytes31, 15; "bytes31 binding")]
+fn packed_multi_keccak_input_instance(input_binding: InputBinding, num_cells: usize) {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = KeccakConfigParams { k: 12, rows_per_round: 5, input_binding, ..Default::default() };
+    // Multi-block inputs, and inputs after the first one, must be bound too
+    let inputs = vec![vec![1u8; 30], (0u8..200).collect(), vec![], vec![9u8; 137]];
+    let instances = instance_columns::<Fr>(&inputs, &config, PublicBytes::All);
+    assert_eq!(instances[0].len(), num_cells);
+
+    let circuit = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k) - 109)
+        .fixed_instance(false)
+        .inputs(inputs)
+        .build()
+        .unwrap();
+    let prover = MockProver::<Fr>::run(config.k, &circuit, instances.clone()).unwrap();
+    prover.assert_satisfied();
+
+    let mut wrong_word = instances;
+    *wrong_word[0].last_mut().unwrap() += Fr::from(1);
+    let prover = MockProver::<Fr>::run(config.k, &circuit, wrong_word).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test_case(InputBinding::Word, 35; "word binding")]
+#[test_case(InputBinding::Packed, 12; "packed binding")]
+#[test_case(InputBinding::Bytes31, 10; "bytes31 binding")]
+fn packed_multi_keccak_fixed_instance(input_binding: InputBinding, row_of_byte_150: usize) {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::InputOutput,
+        input_binding,
+        ..Default::default()
+    };
+    let builder = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k) - 109)
+        .fixed_instance(true);
+    assert!(builder.clone().public_bytes(PublicBytes::Prefix(8)).build().is_err());
+
+    let circuit = builder
+        .clone()
+        .inputs(vec![vec![1u8; 30], (0u8..200).collect(), vec![], vec![9u8; 137]])
+        .build()
+        .unwrap();
+    let instances = circuit.instances();
+    // The layout doesn't depend on the inputs
+    let empty = builder.build().unwrap().instances();
+    assert_eq!(instances[0].len(), empty[0].len());
+    assert_eq!(instances[1].len(), empty[1].len());
+    // The second keccak_f of the second input is the third slot
+    assert_eq!(circuit.instance_row_for(1, 150), Some(row_of_byte_150));
+    assert_eq!(circuit.instance_row_for(1, 200), None);
+
+    let prover = MockProver::<Fr>::run(config.k, &circuit, instances.clone()).unwrap();
+    prover.assert_satisfied();
+
+    // The cells of the padding slots are bound too
+    let mut wrong_padding = instances.clone();
+    *wrong_padding[0].last_mut().unwrap() += Fr::from(1);
+    let prover = MockProver::<Fr>::run(config.k, &circuit, wrong_padding).unwrap();
+    assert!(prover.verify().is_err());
+
+    let mut wrong_digest = instances;
+    *wrong_digest[1].last_mut().unwrap() += Fr::from(1);
+    let prover = MockProver::<Fr>::run(config.k, &circuit, wrong_digest).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test_case(false; "by input")]
+#[test_case(true; "fixed instance")]
+fn packed_multi_keccak_input_columns(fixed_instance: bool) {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::InputOutput,
+        input_columns: 3,
+        ..Default::default()
+    };
+    let inputs = vec![vec![1u8; 30], (0u8..200).collect(), vec![], vec![9u8; 137]];
+    let builder = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k) - 109)
+        .fixed_instance(fixed_instance);
+    assert!(builder.clone().accumulator(true).build().is_err());
+    let circuit = builder.inputs(inputs.clone()).build().unwrap();
+
+    let instances = circuit.instances();
+    assert_eq!(instances.len(), 4);
+    let single = KeccakCircuit::builder()
+        .config(KeccakConfigParams { input_columns: 1, ..config })
+        .capacity_rows(2usize.pow(config.k) - 109)
+        .fixed_instance(fixed_instance)
+        .inputs(inputs)
+        .build()
+        .unwrap()
+        .instances();
+    // Round robin over the columns, the digests untouched
+    for (row, value) in single[0].iter().enumerate() {
+        assert_eq!(instances[row % 3][row / 3], *value);
+    }
+    assert_eq!(instances[3], single[1]);
+
+    let prover = MockProver::<Fr>::run(config.k, &circuit, instances.clone()).unwrap();
+    prover.assert_satisfied();
+
+    for column in 0..3 {
+        let mut wrong_word = instances.clone();
+        wrong_word[column][1] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(config.k, &circuit, wrong_word).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
+
+#[test]
+fn packed_multi_keccak_round_constants() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let mut round_constants = [0u64; NUM_ROUNDS];
+    round_constants.copy_from_slice(&ROUND_CST[..NUM_ROUNDS]);
+    round_constants[3] ^= 1 << 17;
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        round_constants: Some(round_constants),
+        ..Default::default()
+    };
+    let inputs = vec![b"abc".to_vec(), (0u8..200).collect()];
+
+    let circuit = KeccakCircuit::builder().config(config).inputs(inputs.clone()).build().unwrap();
+    let prover = MockProver::<Fr>::run(config.k, &circuit, circuit.instances()).unwrap();
+    prover.assert_satisfied();
+
+    let (_, digests) = witness::multi_keccak::<Fr, _>(&inputs, None, config);
+    let standard = KeccakConfigParams { round_constants: None, ..config };
+    let (_, standard_digests) = witness::multi_keccak::<Fr, _>(&inputs, None, standard);
+    assert_ne!(digests, standard_digests);
+    assert_ne!(config.fingerprint(), standard.fingerprint());
+
+    let exposing = KeccakConfigParams { instance_mode: InstanceMode::InputOutput, ..config };
+    assert!(KeccakCircuit::<Fr>::builder().config(exposing).build().is_err());
+}
+
+#[test]
+fn packed_multi_sha3() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::InputOutput,
+        padding: Padding::Sha3,
+        ..Default::default()
+    };
+    // Lengths where the domain byte shares the last byte of the block with the final bit
+    let inputs = vec![vec![], b"abc".to_vec(), vec![5u8; param::RATE - 1], vec![9u8; param::RATE]];
+
+    let circuit = KeccakCircuit::builder().config(config).inputs(inputs.clone()).build().unwrap();
+    let instances = circuit.instances();
+    for (idx, input) in inputs.iter().enumerate() {
+        let expected: [u8; 32] = Sha3_256::digest(input).into();
+        assert_eq!(digest_from_instance(&instances[1], idx), Some(expected));
+    }
+    let prover = MockProver::<Fr>::run(config.k, &circuit, instances.clone()).unwrap();
+    prover.assert_satisfied();
+
+    // The keccak digests don't verify against the SHA3 circuit
+    let keccak = KeccakConfigParams { padding: Padding::Keccak, ..config };
+    let keccak_instances = KeccakCircuit::builder().config(keccak).inputs(inputs).build().unwrap().instances();
+    assert_ne!(keccak_instances[1], instances[1]);
+    let prover = MockProver::<Fr>::run(config.k, &circuit, keccak_instances).unwrap();
+    assert!(prover.verify().is_err());
+    assert_ne!(config.fingerprint(), keccak.fingerprint());
+}
+
+#[test_case(0; "empty only")]
+#[test_case(136; "one block")]
+#[test_case(300; "two byte bound")]
+fn packed_multi_keccak_max_input_len(max_input_len: usize) {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        max_input_len: Some(max_input_len),
+        ..Default::default()
+    };
+    let inputs = vec![vec![], vec![7u8; max_input_len]];
+    let circuit = KeccakCircuit::builder().config(config).inputs(inputs).build().unwrap();
+    let prover = MockProver::<Fr>::run(config.k, &circuit, circuit.instances()).unwrap();
+    prover.assert_satisfied();
+
+    let too_long = vec![vec![], vec![7u8; max_input_len + 1]];
+    assert!(KeccakCircuit::<Fr>::builder().config(config).inputs(too_long.clone()).build().is_err());
+    // Without the builder check, the proof of a longer message fails
+    let circuit = KeccakCircuit::builder()
+        .config(config)
+        .sanity(SanityLevel::Off)
+        .inputs(too_long)
+        .build()
+        .unwrap();
+    let prover = MockProver::<Fr>::run(config.k, &circuit, circuit.instances()).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test_case(Padding::Keccak; "keccak-512")]
+#[test_case(Padding::Sha3; "sha3-512")]
+fn packed_multi_keccak_512(padding: Padding) {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::InputOutput,
+        padding,
+        digest_size: DigestSize::Bits512,
+        ..Default::default()
+    };
+    let rate = param::NUM_WORDS_TO_ABSORB_512 * param::NUM_BYTES_PER_WORD;
+    // Around the block boundary of the smaller rate
+    let inputs = vec![vec![], vec![5u8; rate - 1], vec![9u8; rate], (0u8..200).collect()];
+
+    let circuit = KeccakCircuit::builder().config(config).inputs(inputs.clone()).build().unwrap();
+    let instances = circuit.instances();
+    assert_eq!(instances[1].len(), 4 * inputs.len());
+    for (input, limbs) in inputs.iter().zip(instances[1].chunks(4)) {
+        let expected = match padding {
+            Padding::Keccak => Keccak512::digest(input).to_vec(),
+            Padding::Sha3 => Sha3_512::digest(input).to_vec(),
+        };
+        let expected = expected.chunks(16).map(|limb| Fr::from_u128(u128::from_be_bytes(limb.try_into().unwrap())));
+        assert!(limbs.iter().copied().eq(expected));
+    }
+    let prover = MockProver::<Fr>::run(config.k, &circuit, instances.clone()).unwrap();
+    prover.assert_satisfied();
+
+    // The second half of the digest is constrained too
+    let mut wrong_digest = instances;
+    wrong_digest[1][3] += Fr::from(1);
+    let prover = MockProver::<Fr>::run(config.k, &circuit, wrong_digest).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+/// The state after the last round of every keccak_f is the reference permutation of the state
+/// after the previous one with the next block absorbed.
+#[test]
+fn round_states_match_reference_permutation() {
+    let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+    let inputs = vec![vec![], b"abc".to_vec(), (0u8..=255).collect()];
+    let (_, digests, states) = witness::multi_keccak_with_states::<Fr, _>(&inputs, Some(10), config);
+    let (_, expected_digests) = witness::multi_keccak::<Fr, _>(&inputs, Some(10), config);
+    assert_eq!(digests, expected_digests);
+    assert_eq!(states.len(), inputs.iter().map(|input| config.num_keccak_f(input.len())).sum::<usize>());
+
+    let mut states = states.into_iter();
+    for input in &inputs {
+        let mut padded = input.clone();
+        padded.push(0x01);
+        padded.resize((padded.len() + param::RATE - 1) / param::RATE * param::RATE, 0);
+        *padded.last_mut().unwrap() |= 0x80;
+        let mut state = [0u64; 25];
+        for block in padded.chunks(param::RATE) {
+            for (lane, word) in state.iter_mut().zip(block.chunks(8)) {
+                *lane ^= u64::from_le_bytes(word.try_into().unwrap());
+            }
+            keccak::f1600(&mut state);
+            let round_states = states.next().unwrap();
+            assert_eq!(round_states.len(), param::NUM_ROUNDS);
+            assert_eq!(round_states[param::NUM_ROUNDS - 1], state);
+        }
+        let digest = state[..4].iter().flat_map(|lane| lane.to_le_bytes()).collect::<Vec<_>>();
+        assert_eq!(digest, Keccak256::digest(input).to_vec());
+    }
+}
+
+/// Compares, for random input lengths, the cells the circuit constrains to the `input` column
+/// against the instance built on the verifier side, cell by cell.
+#[test]
+fn public_input_cells_match_instance_columns() {
+    let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+    let mut rng = StdRng::seed_from_u64(955);
+    for _ in 0..20 {
+        let inputs = (0..rng.gen_range(1..6))
+            .map(|_| {
+                let len = rng.gen_range(0..=3 * param::RATE);
+                (0..len).map(|_| rng.gen()).collect::<Vec<u8>>()
+            })
+            .collect::<Vec<_>>();
+        let packed = KeccakConfigParams { input_binding: InputBinding::Packed, ..config };
+        let bytes31 = KeccakConfigParams { input_binding: InputBinding::Bytes31, ..config };
+        let cases = [
+            (config, PublicBytes::All),
+            (config, PublicBytes::Prefix(8 * rng.gen_range(0..20))),
+            (config, PublicBytes::Suffix(8 * rng.gen_range(0..20))),
+            (config, PublicBytes::Except { start: 8 * rng.gen_range(0..10), end: 8 * rng.gen_range(10..20) }),
+            (packed, PublicBytes::All),
+            (bytes31, PublicBytes::All),
+        ];
+        for (config, public_bytes) in cases {
+            let (witness, _) = witness::multi_keccak::<Fr, _>(&inputs, None, config);
+            let circuit = KeccakCircuit::<Fr>::builder()
+                .config(config)
+                .inputs(inputs.clone())
+                .public_bytes(public_bytes)
+                .build()
+                .unwrap();
+            let constrained = circuit
+                .public_input_cells(&inputs)
+                .into_iter()
+                .map(|cell| match cell {
+                    PublicCell::WordValue(row) => witness[row].word_value,
+                    PublicCell::BytesLeft(row) => witness[row].bytes_left,
+                    PublicCell::InputChunk(row) => witness[row].input_acc,
+                })
+                .collect::<Vec<_>>();
+            let expected = instance_columns::<Fr>(&inputs, &config, public_bytes);
+            let lens = inputs.iter().map(Vec::len).collect::<Vec<_>>();
+            assert_eq!(constrained, expected[0], "lengths {:?}, {:?}", lens, public_bytes);
+        }
     }
 }
 
+/// Every byte is found in the instance cell `instance_row_for` points at, or isn't public.
+#[test]
+fn instance_row_for_locates_bytes() {
+    let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+    let packed = KeccakConfigParams { input_binding: InputBinding::Packed, ..config };
+    let bytes31 = KeccakConfigParams { input_binding: InputBinding::Bytes31, ..config };
+    let inputs = vec![(0u8..200).collect::<Vec<_>>(), vec![], (0u8..=255).rev().collect()];
+    let cases = [
+        (config, PublicBytes::All),
+        (config, PublicBytes::Suffix(16)),
+        (config, PublicBytes::Except { start: 16, end: 40 }),
+        (packed, PublicBytes::All),
+        (bytes31, PublicBytes::All),
+    ];
+    for (config, public_bytes) in cases {
+        let circuit = KeccakCircuit::<Fr>::builder()
+            .config(config)
+            .inputs(inputs.clone())
+            .public_bytes(public_bytes)
+            .accumulator(true)
+            .build()
+            .unwrap();
+        let instance = instance_columns::<Fr>(&inputs, &config, public_bytes);
+        for (idx, input) in inputs.iter().enumerate() {
+            for (byte_offset, byte) in input.iter().enumerate() {
+                let row = match circuit.instance_row_for(idx, byte_offset) {
+                    Some(row) => row,
+                    None => {
+                        assert!(!public_bytes.range(input.len()).contains(&byte_offset));
+                        continue;
+                    }
+                };
+                let shift = byte_offset % param::RATE % config.input_binding.bytes_per_input_cell();
+                let cell = instance[0][row - ACCUMULATOR_INSTANCE_LEN].to_bytes_le();
+                assert_eq!(cell[shift], *byte);
+            }
+            assert_eq!(circuit.instance_row_for(idx, input.len()), None);
+        }
+    }
+}
+
+#[test]
+fn packed_multi_keccak_accumulator() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+    let inputs = vec![(0u8..50).collect::<Vec<_>>(), vec![7u8; 20]];
+    let accumulator = (0..ACCUMULATOR_INSTANCE_LEN as u64).map(|i| Fr::from(1000 + i)).collect::<Vec<_>>();
+    let instances = with_accumulator(
+        instance_columns::<Fr>(&inputs, &config, PublicBytes::All),
+        &accumulator,
+    )
+    .unwrap();
+    assert_eq!(accumulator_indices().len(), ACCUMULATOR_INSTANCE_LEN);
+    assert_eq!(instances[0][..ACCUMULATOR_INSTANCE_LEN], accumulator[..]);
+
+    let circuit = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k) - 109)
+        .fixed_instance(false)
+        .inputs(inputs)
+        .accumulator(true)
+        .build()
+        .unwrap();
+    let prover = MockProver::<Fr>::run(config.k, &circuit, instances.clone()).unwrap();
+    prover.assert_satisfied();
+
+    // The input words follow the accumulator
+    let mut shifted = instances;
+    shifted[0].drain(..ACCUMULATOR_INSTANCE_LEN);
+    let prover = MockProver::<Fr>::run(config.k, &circuit, shifted).unwrap();
+    assert!(prover.verify().is_err());
+}
+
 #[ignore]
 #[test_case(14, 25 ; "k: 14, rows_per_round: 25")]
 #[test_case(18, 9 ; "k: 18, rows_per_round: 9")]
@@ -80,13 +664,14 @@ fn packed_multi_keccak_prover(k: u32, rows_per_round: usize) {
         (0u8..135).collect::<Vec<_>>(),
         (0u8..136).collect::<Vec<_>>(),
     ];
-    let circuit = KeccakCircuit::new(
-        KeccakConfigParams { k, rows_per_round },
-        Some(2usize.pow(k)),
-        inputs,
-        false,
-        false,
-    );
+    let circuit = KeccakCircuit::builder()
+        .config(KeccakConfigParams { k, rows_per_round, ..Default::default() })
+        .capacity_rows(2usize.pow(k))
+        .inputs(inputs)
+        .instance_mode(InstanceMode::None)
+        .sanity(SanityLevel::Off)
+        .build()
+        .unwrap();
 
     let vk = keygen_vk(&params, &circuit).unwrap();
     let pk = keygen_pk(&params, vk, &circuit).unwrap();
@@ -129,23 +714,23 @@ fn packed_multi_keccak_prover(k: u32, rows_per_round: usize) {
 // Keccak Known Answer Test (KAT) vectors from https://keccak.team/obsolete/KeccakKAT-3.zip.
 // Only selecting a small subset for now (add more later)
 // KAT includes inputs at the bit level; we only include the ones that are bytes
+// input, output, Len in bits
+const KAT_VECTORS: [(&str, &str); 7] = [
+    ("", "C5D2460186F7233C927E7DB2DCC703C0E500B653CA82273B7BFAD8045D85A470"), // ShortMsgKAT_256 Len = 0
+    ("CC", "EEAD6DBFC7340A56CAEDC044696A168870549A6A7F6F56961E84A54BD9970B8A"), // ShortMsgKAT_256 Len = 8
+    ("B55C10EAE0EC684C16D13463F29291BF26C82E2FA0422A99C71DB4AF14DD9C7F33EDA52FD73D017CC0F2DBE734D831F0D820D06D5F89DACC485739144F8CFD4799223B1AFF9031A105CB6A029BA71E6E5867D85A554991C38DF3C9EF8C1E1E9A7630BE61CAABCA69280C399C1FB7A12D12AEFC", "0347901965D3635005E75A1095695CCA050BC9ED2D440C0372A31B348514A889"), // ShortMsgKAT_256 Len = 920
+    ("2EDC282FFB90B97118DD03AAA03B145F363905E3CBD2D50ECD692B37BF000185C651D3E9726C690D3773EC1E48510E42B17742B0B0377E7DE6B8F55E00A8A4DB4740CEE6DB0830529DD19617501DC1E9359AA3BCF147E0A76B3AB70C4984C13E339E6806BB35E683AF8527093670859F3D8A0FC7D493BCBA6BB12B5F65E71E705CA5D6C948D66ED3D730B26DB395B3447737C26FAD089AA0AD0E306CB28BF0ACF106F89AF3745F0EC72D534968CCA543CD2CA50C94B1456743254E358C1317C07A07BF2B0ECA438A709367FAFC89A57239028FC5FECFD53B8EF958EF10EE0608B7F5CB9923AD97058EC067700CC746C127A61EE3", "DD1D2A92B3F3F3902F064365838E1F5F3468730C343E2974E7A9ECFCD84AA6DB"), // ShortMsgKAT_256 Len = 1952,
+    ("724627916C50338643E6996F07877EAFD96BDF01DA7E991D4155B9BE1295EA7D21C9391F4C4A41C75F77E5D27389253393725F1427F57914B273AB862B9E31DABCE506E558720520D33352D119F699E784F9E548FF91BC35CA147042128709820D69A8287EA3257857615EB0321270E94B84F446942765CE882B191FAEE7E1C87E0F0BD4E0CD8A927703524B559B769CA4ECE1F6DBF313FDCF67C572EC4185C1A88E86EC11B6454B371980020F19633B6B95BD280E4FBCB0161E1A82470320CEC6ECFA25AC73D09F1536F286D3F9DACAFB2CD1D0CE72D64D197F5C7520B3CCB2FD74EB72664BA93853EF41EABF52F015DD591500D018DD162815CC993595B195", "EA0E416C0F7B4F11E3F00479FDDF954F2539E5E557753BD546F69EE375A5DE29"), // LongMsgKAT_256 Len = 2048
+    ("6E1CADFB2A14C5FFB1DD69919C0124ED1B9A414B2BEA1E5E422D53B022BDD13A9C88E162972EBB9852330006B13C5B2F2AFBE754AB7BACF12479D4558D19DDBB1A6289387B3AC084981DF335330D1570850B97203DBA5F20CF7FF21775367A8401B6EBE5B822ED16C39383232003ABC412B0CE0DD7C7DA064E4BB73E8C58F222A1512D5FE6D947316E02F8AA87E7AA7A3AA1C299D92E6414AE3B927DB8FF708AC86A09B24E1884743BC34067BB0412453B4A6A6509504B550F53D518E4BCC3D9C1EFDB33DA2EACCB84C9F1CAEC81057A8508F423B25DB5500E5FC86AB3B5EB10D6D0BF033A716DDE55B09FD53451BBEA644217AE1EF91FAD2B5DCC6515249C96EE7EABFD12F1EF65256BD1CFF2087DABF2F69AD1FFB9CF3BC8CA437C7F18B6095BC08D65DF99CC7F657C418D8EB109FDC91A13DC20A438941726EF24F9738B6552751A320C4EA9C8D7E8E8592A3B69D30A419C55FB6CB0850989C029AAAE66305E2C14530B39EAA86EA3BA2A7DECF4B2848B01FAA8AA91F2440B7CC4334F63061CE78AA1589BEFA38B194711697AE3AADCB15C9FBF06743315E2F97F1A8B52236ACB444069550C2345F4ED12E5B8E881CDD472E803E5DCE63AE485C2713F81BC307F25AC74D39BAF7E3BC5E7617465C2B9C309CB0AC0A570A7E46C6116B2242E1C54F456F6589E20B1C0925BF1CD5F9344E01F63B5BA9D4671ABBF920C7ED32937A074C33836F0E019DFB6B35D865312C6058DFDAFF844C8D58B75071523E79DFBAB2EA37479DF12C474584F4FF40F00F92C6BADA025CE4DF8FAF0AFB2CE75C07773907CA288167D6B011599C3DE0FFF16C1161D31DF1C1DDE217CB574ED5A33751759F8ED2B1E6979C5088B940926B9155C9D250B479948C20ACB5578DC02C97593F646CC5C558A6A0F3D8D273258887CCFF259197CB1A7380622E371FD2EB5376225EC04F9ED1D1F2F08FA2376DB5B790E73086F581064ED1C5F47E989E955D77716B50FB64B853388FBA01DAC2CEAE99642341F2DA64C56BEFC4789C051E5EB79B063F2F084DB4491C3C5AA7B4BCF7DD7A1D7CED1554FA67DCA1F9515746A237547A4A1D22ACF649FA1ED3B9BB52BDE0C6996620F8CFDB293F8BACAD02BCE428363D0BB3D391469461D212769048219220A7ED39D1F9157DFEA3B4394CA8F5F612D9AC162BF0B961BFBC157E5F863CE659EB235CF98E8444BC8C7880BDDCD0B3B389AAA89D5E05F84D0649EEBACAB4F1C75352E89F0E9D91E4ACA264493A50D2F4AED66BD13650D1F18E7199E931C78AEB763E903807499F1CD99AF81276B615BE8EC709B039584B2B57445B014F6162577F3548329FD288B0800F936FC5EA1A412E3142E609FC8E39988CA53DF4D8FB5B5FB5F42C0A01648946AC6864CFB0E92856345B08E5DF0D235261E44CFE776456B40AEF0AC1A0DFA2FE639486666C05EA196B0C1A9D346435E03965E6139B1CE10129F8A53745F80100A94AE04D996C13AC14CF2713E39DFBB19A936CF3861318BD749B1FB82F40D73D714E406CBEB3D920EA037B7DE566455CCA51980F0F53A762D5BF8A4DBB55AAC0EDDB4B1F2AED2AA3D01449D34A57FDE4329E7FF3F6BECE4456207A4225218EE9F174C2DE0FF51CEAF2A07CF84F03D1DF316331E3E725C5421356C40ED25D5ABF9D24C4570FED618CA41000455DBD759E32E2BF0B6C5E61297C20F752C3042394CE840C70943C451DD5598EB0E4953CE26E833E5AF64FC1007C04456D19F87E45636F456B7DC9D31E757622E2739573342DE75497AE181AAE7A5425756C8E2A7EEF918E5C6A968AEFE92E8B261BBFE936B19F9E69A3C90094096DAE896450E1505ED5828EE2A7F0EA3A28E6EC47C0AF711823E7689166EA07ECA00FFC493131D65F93A4E1D03E0354AFC2115CFB8D23DAE8C6F96891031B23226B8BC82F1A73DAA5BB740FC8CC36C0975BEFA0C7895A9BBC261EDB7FD384103968F7A18353D5FE56274E4515768E4353046C785267DE01E816A2873F97AAD3AB4D7234EBFD9832716F43BE8245CF0B4408BA0F0F764CE9D24947AB6ABDD9879F24FCFF10078F5894B0D64F6A8D3EA3DD92A0C38609D3C14FDC0A44064D501926BE84BF8034F1D7A8C5F382E6989BFFA2109D4FBC56D1F091E8B6FABFF04D21BB19656929D19DECB8E8291E6AE5537A169874E0FE9890DFF11FFD159AD23D749FB9E8B676E2C31313C16D1EFA06F4D7BC191280A4EE63049FCEF23042B20303AECDD412A526D7A53F760A089FBDF13F361586F0DCA76BB928EDB41931D11F679619F948A6A9E8DBA919327769006303C6EF841438A7255C806242E2E7FF4621BB0F8AFA0B4A248EAD1A1E946F3E826FBFBBF8013CE5CC814E20FEF21FA5DB19EC7FF0B06C592247B27E500EB4705E6C37D41D09E83CB0A618008CA1AAAE8A215171D817659063C2FA385CFA3C1078D5C2B28CE7312876A276773821BE145785DFF24BBB24D590678158A61EA49F2BE56FDAC8CE7F94B05D62F15ADD351E5930FD4F31B3E7401D5C0FF7FC845B165FB6ABAFD4788A8B0615FEC91092B34B710A68DA518631622BA2AAE5D19010D307E565A161E64A4319A6B261FB2F6A90533997B1AEC32EF89CF1F232696E213DAFE4DBEB1CF1D5BBD12E5FF2EBB2809184E37CD9A0E58A4E0AF099493E6D8CC98B05A2F040A7E39515038F6EE21FC25F8D459A327B83EC1A28A234237ACD52465506942646AC248EC96EBBA6E1B092475F7ADAE4D35E009FD338613C7D4C12E381847310A10E6F02C02392FC32084FBE939689BC6518BE27AF7842DEEA8043828E3DFFE3BBAC4794CA0CC78699722709F2E4B0EAE7287DEB06A27B462423EC3F0DF227ACF589043292685F2C0E73203E8588B62554FF19D6260C7FE48DF301509D33BE0D8B31D3F658C921EF7F55449FF3887D91BFB894116DF57206098E8C5835B", "3C79A3BD824542C20AF71F21D6C28DF2213A041F77DD79A328A0078123954E7B"), // LongMsgKAT_256 Len = 16664
+    ("7ADC0B6693E61C269F278E6944A5A2D8300981E40022F839AC644387BFAC9086650085C2CDC585FEA47B9D2E52D65A2B29A7DC370401EF5D60DD0D21F9E2B90FAE919319B14B8C5565B0423CEFB827D5F1203302A9D01523498A4DB10374", "4CC2AFF141987F4C2E683FA2DE30042BACDCD06087D7A7B014996E9CFEAA58CE"), // ShortMsgKAT_256 Len = 752
+];
+
 #[test]
 fn test_vanilla_keccak_kat_vectors() {
     let _ = env_logger::builder().is_test(true).try_init();
 
-    // input, output, Len in bits
-    let test_vectors = vec![
-            ("", "C5D2460186F7233C927E7DB2DCC703C0E500B653CA82273B7BFAD8045D85A470"), // ShortMsgKAT_256 Len = 0
-            ("CC", "EEAD6DBFC7340A56CAEDC044696A168870549A6A7F6F56961E84A54BD9970B8A"), // ShortMsgKAT_256 Len = 8
-            ("B55C10EAE0EC684C16D13463F29291BF26C82E2FA0422A99C71DB4AF14DD9C7F33EDA52FD73D017CC0F2DBE734D831F0D820D06D5F89DACC485739144F8CFD4799223B1AFF9031A105CB6A029BA71E6E5867D85A554991C38DF3C9EF8C1E1E9A7630BE61CAABCA69280C399C1FB7A12D12AEFC", "0347901965D3635005E75A1095695CCA050BC9ED2D440C0372A31B348514A889"), // ShortMsgKAT_256 Len = 920
-            ("2EDC282FFB90B97118DD03AAA03B145F363905E3CBD2D50ECD692B37BF000185C651D3E9726C690D3773EC1E48510E42B17742B0B0377E7DE6B8F55E00A8A4DB4740CEE6DB0830529DD19617501DC1E9359AA3BCF147E0A76B3AB70C4984C13E339E6806BB35E683AF8527093670859F3D8A0FC7D493BCBA6BB12B5F65E71E705CA5D6C948D66ED3D730B26DB395B3447737C26FAD089AA0AD0E306CB28BF0ACF106F89AF3745F0EC72D534968CCA543CD2CA50C94B1456743254E358C1317C07A07BF2B0ECA438A709367FAFC89A57239028FC5FECFD53B8EF958EF10EE0608B7F5CB9923AD97058EC067700CC746C127A61EE3", "DD1D2A92B3F3F3902F064365838E1F5F3468730C343E2974E7A9ECFCD84AA6DB"), // ShortMsgKAT_256 Len = 1952,
-            ("724627916C50338643E6996F07877EAFD96BDF01DA7E991D4155B9BE1295EA7D21C9391F4C4A41C75F77E5D27389253393725F1427F57914B273AB862B9E31DABCE506E558720520D33352D119F699E784F9E548FF91BC35CA147042128709820D69A8287EA3257857615EB0321270E94B84F446942765CE882B191FAEE7E1C87E0F0BD4E0CD8A927703524B559B769CA4ECE1F6DBF313FDCF67C572EC4185C1A88E86EC11B6454B371980020F19633B6B95BD280E4FBCB0161E1A82470320CEC6ECFA25AC73D09F1536F286D3F9DACAFB2CD1D0CE72D64D197F5C7520B3CCB2FD74EB72664BA93853EF41EABF52F015DD591500D018DD162815CC993595B195", "EA0E416C0F7B4F11E3F00479FDDF954F2539E5E557753BD546F69EE375A5DE29"), // LongMsgKAT_256 Len = 2048
-            ("6E1CADFB2A14C5FFB1DD69919C0124ED1B9A414B2BEA1E5E422D53B022BDD13A9C88E162972EBB9852330006B13C5B2F2AFBE754AB7BACF12479D4558D19DDBB1A6289387B3AC084981DF335330D1570850B97203DBA5F20CF7FF21775367A8401B6EBE5B822ED16C39383232003ABC412B0CE0DD7C7DA064E4BB73E8C58F222A1512D5FE6D947316E02F8AA87E7AA7A3AA1C299D92E6414AE3B927DB8FF708AC86A09B24E1884743BC34067BB0412453B4A6A6509504B550F53D518E4BCC3D9C1EFDB33DA2EACCB84C9F1CAEC81057A8508F423B25DB5500E5FC86AB3B5EB10D6D0BF033A716DDE55B09FD53451BBEA644217AE1EF91FAD2B5DCC6515249C96EE7EABFD12F1EF65256BD1CFF2087DABF2F69AD1FFB9CF3BC8CA437C7F18B6095BC08D65DF99CC7F657C418D8EB109FDC91A13DC20A438941726EF24F9738B6552751A320C4EA9C8D7E8E8592A3B69D30A419C55FB6CB0850989C029AAAE66305E2C14530B39EAA86EA3BA2A7DECF4B2848B01FAA8AA91F2440B7CC4334F63061CE78AA1589BEFA38B194711697AE3AADCB15C9FBF06743315E2F97F1A8B52236ACB444069550C2345F4ED12E5B8E881CDD472E803E5DCE63AE485C2713F81BC307F25AC74D39BAF7E3BC5E7617465C2B9C309CB0AC0A570A7E46C6116B2242E1C54F456F6589E20B1C0925BF1CD5F9344E01F63B5BA9D4671ABBF920C7ED32937A074C33836F0E019DFB6B35D865312C6058DFDAFF844C8D58B75071523E79DFBAB2EA37479DF12C474584F4FF40F00F92C6BADA025CE4DF8FAF0AFB2CE75C07773907CA288167D6B011599C3DE0FFF16C1161D31DF1C1DDE217CB574ED5A33751759F8ED2B1E6979C5088B940926B9155C9D250B479948C20ACB5578DC02C97593F646CC5C558A6A0F3D8D273258887CCFF259197CB1A7380622E371FD2EB5376225EC04F9ED1D1F2F08FA2376DB5B790E73086F581064ED1C5F47E989E955D77716B50FB64B853388FBA01DAC2CEAE99642341F2DA64C56BEFC4789C051E5EB79B063F2F084DB4491C3C5AA7B4BCF7DD7A1D7CED1554FA67DCA1F9515746A237547A4A1D22ACF649FA1ED3B9BB52BDE0C6996620F8CFDB293F8BACAD02BCE428363D0BB3D391469461D212769048219220A7ED39D1F9157DFEA3B4394CA8F5F612D9AC162BF0B961BFBC157E5F863CE659EB235CF98E8444BC8C7880BDDCD0B3B389AAA89D5E05F84D0649EEBACAB4F1C75352E89F0E9D91E4ACA264493A50D2F4AED66BD13650D1F18E7199E931C78AEB763E903807499F1CD99AF81276B615BE8EC709B039584B2B57445B014F6162577F3548329FD288B0800F936FC5EA1A412E3142E609FC8E39988CA53DF4D8FB5B5FB5F42C0A01648946AC6864CFB0E92856345B08E5DF0D235261E44CFE776456B40AEF0AC1A0DFA2FE639486666C05EA196B0C1A9D346435E03965E6139B1CE10129F8A53745F80100A94AE04D996C13AC14CF2713E39DFBB19A936CF3861318BD749B1FB82F40D73D714E406CBEB3D920EA037B7DE566455CCA51980F0F53A762D5BF8A4DBB55AAC0EDDB4B1F2AED2AA3D01449D34A57FDE4329E7FF3F6BECE4456207A4225218EE9F174C2DE0FF51CEAF2A07CF84F03D1DF316331E3E725C5421356C40ED25D5ABF9D24C4570FED618CA41000455DBD759E32E2BF0B6C5E61297C20F752C3042394CE840C70943C451DD5598EB0E4953CE26E833E5AF64FC1007C04456D19F87E45636F456B7DC9D31E757622E2739573342DE75497AE181AAE7A5425756C8E2A7EEF918E5C6A968AEFE92E8B261BBFE936B19F9E69A3C90094096DAE896450E1505ED5828EE2A7F0EA3A28E6EC47C0AF711823E7689166EA07ECA00FFC493131D65F93A4E1D03E0354AFC2115CFB8D23DAE8C6F96891031B23226B8BC82F1A73DAA5BB740FC8CC36C0975BEFA0C7895A9BBC261EDB7FD384103968F7A18353D5FE56274E4515768E4353046C785267DE01E816A2873F97AAD3AB4D7234EBFD9832716F43BE8245CF0B4408BA0F0F764CE9D24947AB6ABDD9879F24FCFF10078F5894B0D64F6A8D3EA3DD92A0C38609D3C14FDC0A44064D501926BE84BF8034F1D7A8C5F382E6989BFFA2109D4FBC56D1F091E8B6FABFF04D21BB19656929D19DECB8E8291E6AE5537A169874E0FE9890DFF11FFD159AD23D749FB9E8B676E2C31313C16D1EFA06F4D7BC191280A4EE63049FCEF23042B20303AECDD412A526D7A53F760A089FBDF13F361586F0DCA76BB928EDB41931D11F679619F948A6A9E8DBA919327769006303C6EF841438A7255C806242E2E7FF4621BB0F8AFA0B4A248EAD1A1E946F3E826FBFBBF8013CE5CC814E20FEF21FA5DB19EC7FF0B06C592247B27E500EB4705E6C37D41D09E83CB0A618008CA1AAAE8A215171D817659063C2FA385CFA3C1078D5C2B28CE7312876A276773821BE145785DFF24BBB24D590678158A61EA49F2BE56FDAC8CE7F94B05D62F15ADD351E5930FD4F31B3E7401D5C0FF7FC845B165FB6ABAFD4788A8B0615FEC91092B34B710A68DA518631622BA2AAE5D19010D307E565A161E64A4319A6B261FB2F6A90533997B1AEC32EF89CF1F232696E213DAFE4DBEB1CF1D5BBD12E5FF2EBB2809184E37CD9A0E58A4E0AF099493E6D8CC98B05A2F040A7E39515038F6EE21FC25F8D459A327B83EC1A28A234237ACD52465506942646AC248EC96EBBA6E1B092475F7ADAE4D35E009FD338613C7D4C12E381847310A10E6F02C02392FC32084FBE939689BC6518BE27AF7842DEEA8043828E3DFFE3BBAC4794CA0CC78699722709F2E4B0EAE7287DEB06A27B462423EC3F0DF227ACF589043292685F2C0E73203E8588B62554FF19D6260C7FE48DF301509D33BE0D8B31D3F658C921EF7F55449FF3887D91BFB894116DF57206098E8C5835B", "3C79A3BD824542C20AF71F21D6C28DF2213A041F77DD79A328A0078123954E7B"), // LongMsgKAT_256 Len = 16664
-            ("7ADC0B6693E61C269F278E6944A5A2D8300981E40022F839AC644387BFAC9086650085C2CDC585FEA47B9D2E52D65A2B29A7DC370401EF5D60DD0D21F9E2B90FAE919319B14B8C5565B0423CEFB827D5F1203302A9D01523498A4DB10374", "4CC2AFF141987F4C2E683FA2DE30042BACDCD06087D7A7B014996E9CFEAA58CE"), // ShortMsgKAT_256 Len = 752
-        ];
-
     let mut inputs = vec![];
-    for (input, output) in test_vectors {
+    for (input, output) in KAT_VECTORS {
         let input = Vec::from_hex(input).unwrap();
         let output = Vec::from_hex(output).unwrap();
         // test against native sha3 implementation because that's what we will test circuit against
@@ -157,8 +742,472 @@ fn test_vanilla_keccak_kat_vectors() {
         KeccakConfigParams {
             k: 12,
             rows_per_round: 5,
+            ..Default::default()
         },
         inputs,
         true,
     );
-}
\ No newline at end of file
+}
+
+/// Proves `inputs` in batches that fit a k = 12 circuit, with the digests exposed and checked
+/// against the native implementation through the instance.
+fn verify_digests_mock(inputs: Vec<Vec<u8>>) {
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::Output,
+        ..Default::default()
+    };
+    let capacity_rows = 2usize.pow(config.k) - 109;
+    let capacity = keccak_packed_multi::get_keccak_capacity(capacity_rows, config.rows_per_round);
+
+    let mut batches: Vec<Vec<Vec<u8>>> = vec![vec![]];
+    let mut used = 0;
+    for input in inputs {
+        let num_keccak_f = keccak_packed_multi::get_num_keccak_f(input.len());
+        assert!(num_keccak_f <= capacity, "input of {} bytes doesn't fit", input.len());
+        if used + num_keccak_f > capacity {
+            batches.push(vec![]);
+            used = 0;
+        }
+        used += num_keccak_f;
+        batches.last_mut().unwrap().push(input);
+    }
+
+    for batch in batches {
+        let instances = instance_columns::<Fr>(&batch, &config, PublicBytes::All);
+        let circuit = KeccakCircuit::builder()
+            .config(config)
+            .capacity_rows(capacity_rows)
+            .fixed_instance(false)
+            .inputs(batch)
+            .sanity(SanityLevel::Full)
+            .build()
+            .unwrap();
+        let prover = MockProver::<Fr>::run(config.k, &circuit, instances).unwrap();
+        prover.assert_satisfied();
+    }
+}
+
+// Soundness regression suite, gated behind `--ignored` as it takes several minutes.
+// The ExtremelyLongMsgKAT vector hashes 1 GiB, i.e. millions of keccak_f, which no MockProver
+// run can hold, so only the LongMsgKAT vectors are included.
+#[ignore]
+#[test]
+fn soundness_long_message_vectors() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let inputs = KAT_VECTORS
+        .iter()
+        .map(|(input, _)| Vec::from_hex(input).unwrap())
+        .filter(|input| input.len() >= 256)
+        .collect::<Vec<_>>();
+    assert!(!inputs.is_empty());
+    verify_digests_mock(inputs);
+}
+
+#[ignore]
+#[test]
+fn soundness_every_length_up_to_three_blocks() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    // Covers the padding edge cases at 135, 136, 137, 271 and 272 bytes
+    let inputs = (0..=3 * param::RATE)
+        .map(|len| (0..len).map(|i| (i * 7 + len) as u8).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    verify_digests_mock(inputs);
+}
+
+/// A host circuit hashing `input` without exposing it, its words looked up in the host's
+/// `(bytes_left, word_value)` table instead.
+#[derive(Clone, Default)]
+struct WordTableCircuit {
+    config: KeccakConfigParams,
+    input: Vec<u8>,
+    table: Vec<(u64, u64)>,
+}
+
+impl Circuit<Fr> for WordTableCircuit {
+    type Config = (KeccakCircuitConfig<Fr>, Column<Fixed>, [Column<Advice>; 2]);
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self { config: self.config, ..Default::default() }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<Fr>, params: Self::Params) -> Self::Config {
+        let keccak = KeccakCircuitConfig::new(meta, params);
+        let q_table = meta.fixed_column();
+        let columns = [meta.advice_column(), meta.advice_column()];
+        keccak.lookup_words(meta, "word table", |meta| {
+            let q_table = meta.query_fixed(q_table, Rotation::cur());
+            columns.map(|column| q_table.clone() * meta.query_advice(column, Rotation::cur()))
+        });
+        (keccak, q_table, columns)
+    }
+
+    fn configure(_: &mut ConstraintSystem<Fr>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        (keccak, q_table, columns): Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        keccak.load_aux_tables(&mut layouter)?;
+        let (witness, _) = witness::multi_keccak::<Fr, _>(&[&self.input], None, self.config);
+        let mut first_pass = SKIP_FIRST_PASS;
+        layouter.assign_region(
+            || "keccak",
+            |mut region| {
+                if first_pass {
+                    first_pass = false;
+                    return Ok(());
+                }
+                keccak.assign(&mut region, &witness);
+                Ok(())
+            },
+        )?;
+        layouter.assign_region(
+            || "word table",
+            |mut region| {
+                for (offset, (bytes_left, word_value)) in self.table.iter().enumerate() {
+                    raw_assign_fixed(&mut region, q_table, offset, Fr::from(1));
+                    for (column, value) in columns.iter().zip([bytes_left, word_value]) {
+                        raw_assign_advice(&mut region, *column, offset, Value::known(Fr::from(*value)));
+                    }
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+#[test]
+fn packed_multi_keccak_word_table_lookup() {
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::None,
+        ..Default::default()
+    };
+    let input = (0u8..200).collect::<Vec<_>>();
+    let table = input
+        .chunks(param::NUM_BYTES_PER_WORD)
+        .enumerate()
+        .map(|(idx, word)| {
+            let mut le_bytes = [0u8; 8];
+            le_bytes[..word.len()].copy_from_slice(word);
+            ((input.len() - idx * param::NUM_BYTES_PER_WORD) as u64, u64::from_le_bytes(le_bytes))
+        })
+        .collect::<Vec<_>>();
+
+    let circuit = WordTableCircuit { config, input: input.clone(), table: table.clone() };
+    MockProver::run(config.k, &circuit, vec![]).unwrap().assert_satisfied();
+
+    // A word differing from the hashed one isn't found in the table
+    let mut tampered = table;
+    tampered[3].1 ^= 1;
+    let circuit = WordTableCircuit { config, input, table: tampered };
+    assert!(MockProver::run(config.k, &circuit, vec![]).unwrap().verify().is_err());
+}
+
+/// Hashes `inputs` and binds the length cell of every input to the instance, as a host
+/// circuit would.
+#[derive(Clone, Default)]
+struct InputLengthCircuit {
+    config: KeccakConfigParams,
+    inputs: Vec<Vec<u8>>,
+}
+
+impl Circuit<Fr> for InputLengthCircuit {
+    type Config = (KeccakCircuitConfig<Fr>, Column<Instance>);
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self { config: self.config, ..Default::default() }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<Fr>, params: Self::Params) -> Self::Config {
+        let lengths = meta.instance_column();
+        meta.enable_equality(lengths);
+        (KeccakCircuitConfig::new(meta, params), lengths)
+    }
+
+    fn configure(_: &mut ConstraintSystem<Fr>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        (keccak, lengths): Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        keccak.load_aux_tables(&mut layouter)?;
+        let (witness, _) = witness::multi_keccak::<Fr, _>(&self.inputs, None, self.config);
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut cells = vec![];
+        layouter.assign_region(
+            || "keccak",
+            |mut region| {
+                if first_pass {
+                    first_pass = false;
+                    return Ok(());
+                }
+                let assigned_rows = keccak.assign(&mut region, &witness);
+                cells = crate::input_cells(&assigned_rows, &self.inputs, self.config.rows_per_round);
+                Ok(())
+            },
+        )?;
+        for (idx, cells) in cells.iter().enumerate() {
+            constrain_instance(&mut layouter, cells.length.cell(), lengths, idx)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn input_length_cells() {
+    let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+    let inputs = vec![vec![1u8; 30], vec![], vec![2u8; 200]];
+    let circuit = InputLengthCircuit { config, inputs: inputs.clone() };
+    let lengths = inputs.iter().map(|input| Fr::from(input.len() as u64)).collect::<Vec<_>>();
+    MockProver::run(config.k, &circuit, vec![lengths.clone()]).unwrap().assert_satisfied();
+
+    let mut wrong = lengths;
+    wrong[2] += Fr::from(1);
+    assert!(MockProver::run(config.k, &circuit, vec![wrong]).unwrap().verify().is_err());
+}
+
+/// Hashes `inputs` padded to `capacity` keccak_f, with the witness rows altered by `tamper`
+/// before they are assigned, to check what the gates alone reject.
+#[derive(Clone, Default)]
+struct TamperedWitnessCircuit {
+    config: KeccakConfigParams,
+    inputs: Vec<Vec<u8>>,
+    capacity: usize,
+    tamper: Option<fn(&mut [keccak_packed_multi::KeccakRow<Fr>])>,
+    /// Padding rule of the messages, keccak's if `None`.
+    padding: Option<padding::DomainPadding>,
+}
+
+impl Circuit<Fr> for TamperedWitnessCircuit {
+    type Config = KeccakCircuitConfig<Fr>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self { config: self.config, ..Default::default() }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<Fr>, params: Self::Params) -> Self::Config {
+        KeccakCircuitConfig::new(meta, params)
+    }
+
+    fn configure(_: &mut ConstraintSystem<Fr>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(&self, keccak: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+        keccak.load_aux_tables(&mut layouter)?;
+        let padding = self.padding.unwrap_or(self.config.padding.rule());
+        let (mut witness, _) = witness::multi_keccak_with_padding::<Fr, _>(
+            &self.inputs,
+            Some(self.capacity),
+            self.config,
+            &padding,
+        );
+        if let Some(tamper) = self.tamper {
+            tamper(&mut witness);
+        }
+        let mut first_pass = SKIP_FIRST_PASS;
+        layouter.assign_region(
+            || "keccak",
+            |mut region| {
+                if first_pass {
+                    first_pass = false;
+                    return Ok(());
+                }
+                keccak.assign(&mut region, &witness);
+                Ok(())
+            },
+        )
+    }
+}
+
+/// With rows_per_round = 5, a 30 bytes input fills the words on rows 5 to 5 + 3 * 5 of its
+/// keccak_f, and the padding keccak_f slot 2 absorbs its first word on row 5 * (1 + 2 * 25).
+const PAST_INPUT_ROW: usize = 5 + 5 * 5;
+const PADDING_SLOT_ROW: usize = 5 * (1 + 2 * 25);
+
+#[test_case(None; "honest witness")]
+#[test_case(Some(|w| w[PAST_INPUT_ROW].word_value = Fr::from(1)); "word past the input")]
+#[test_case(Some(|w| w[PAST_INPUT_ROW].bytes_left = Fr::from(8)); "bytes_left past the input")]
+#[test_case(Some(|w| w[PADDING_SLOT_ROW].word_value = Fr::from(1)); "word of a padding slot")]
+#[test_case(Some(|w| w[PADDING_SLOT_ROW].bytes_left = Fr::from(8)); "bytes_left of a padding slot")]
+fn padding_words_are_zero(tamper: Option<fn(&mut [keccak_packed_multi::KeccakRow<Fr>])>) {
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::None,
+        ..Default::default()
+    };
+    let circuit = TamperedWitnessCircuit { config, inputs: vec![vec![7u8; 30]], capacity: 3, tamper, padding: None };
+    let prover = MockProver::run(config.k, &circuit, vec![]).unwrap();
+    assert_eq!(prover.verify().is_ok(), tamper.is_none());
+}
+
+#[test_case(Padding::Keccak, padding::KECCAK_PADDING, true; "keccak")]
+#[test_case(Padding::Keccak, padding::SHA3_PADDING, false; "sha3 in keccak")]
+#[test_case(Padding::Keccak, padding::DomainPadding(0x1f), false; "shake in keccak")]
+#[test_case(Padding::Sha3, padding::SHA3_PADDING, true; "sha3")]
+#[test_case(Padding::Sha3, padding::KECCAK_PADDING, false; "keccak in sha3")]
+fn padding_rule_is_constrained(config_padding: Padding, rule: padding::DomainPadding, accepted: bool) {
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::None,
+        padding: config_padding,
+        ..Default::default()
+    };
+    // The last block spans both a partial and a fully padded word
+    let circuit = TamperedWitnessCircuit {
+        config,
+        inputs: vec![vec![7u8; 30], vec![]],
+        capacity: 3,
+        tamper: None,
+        padding: Some(rule),
+    };
+    let prover = MockProver::run(config.k, &circuit, vec![]).unwrap();
+    assert_eq!(prover.verify().is_ok(), accepted);
+}
+
+/// The rows of the words of a 30 bytes input with rows_per_round = 5, and of its digest.
+const FIRST_WORD_ROW: usize = 5;
+const SECOND_WORD_ROW: usize = 5 + 5;
+const FINAL_ROW: usize = 5 + 24 * 5;
+
+#[test_case(|w| w[FIRST_WORD_ROW].word_value += Fr::from(1); "word value")]
+#[test_case(|w| w[SECOND_WORD_ROW].bytes_left += Fr::from(1); "bytes_left not decreasing by the word")]
+#[test_case(|w| w[FIRST_WORD_ROW].bytes_left = Fr::from(31); "bytes_left above the input length")]
+#[test_case(|w| w[FINAL_ROW].bytes_left = Fr::from(1); "bytes_left left on the final row")]
+fn input_consistency_is_constrained(tamper: fn(&mut [keccak_packed_multi::KeccakRow<Fr>])) {
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::None,
+        ..Default::default()
+    };
+    let circuit =
+        TamperedWitnessCircuit { config, inputs: vec![vec![7u8; 30]], capacity: 3, tamper: Some(tamper), padding: None };
+    let prover = MockProver::run(config.k, &circuit, vec![]).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn digest_is_constrained() {
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::None,
+        ..Default::default()
+    };
+    let tamper: fn(&mut [keccak_packed_multi::KeccakRow<Fr>]) =
+        |w| w[FINAL_ROW].hash = Word::new([w[FINAL_ROW].hash.lo(), Value::known(Fr::from(1))]);
+    let circuit =
+        TamperedWitnessCircuit { config, inputs: vec![vec![7u8; 30]], capacity: 3, tamper: Some(tamper), padding: None };
+    let prover = MockProver::run(config.k, &circuit, vec![]).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+/// Without the native recomputation, the digests of an output-exposing circuit are only
+/// checked through the instance, so an assigned digest differing from keccak must fail there.
+#[test]
+fn exposed_digests_need_no_native_check() {
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::Output,
+        ..Default::default()
+    };
+    let inputs = vec![vec![], (0u8..200).collect::<Vec<_>>()];
+    let mut instances = instance_columns::<Fr>(&inputs, &config, PublicBytes::All);
+    for sanity in [SanityLevel::Full, SanityLevel::Off] {
+        let circuit = KeccakCircuit::builder()
+            .config(config)
+            .capacity_rows(2usize.pow(config.k) - 109)
+            .fixed_instance(false)
+            .inputs(inputs.clone())
+            .sanity(sanity)
+            .build()
+            .unwrap();
+        MockProver::run(config.k, &circuit, instances.clone()).unwrap().assert_satisfied();
+    }
+    // The digest of another message
+    instances[1][..2].copy_from_slice(&pack_output_to_instance::<Fr>(&[vec![0]], &config));
+    let circuit = KeccakCircuit::builder()
+        .config(config)
+        .capacity_rows(2usize.pow(config.k) - 109)
+        .fixed_instance(false)
+        .inputs(inputs)
+        .build()
+        .unwrap();
+    assert!(MockProver::run(config.k, &circuit, instances).unwrap().verify().is_err());
+}
+
+#[test_case(InputBinding::Word; "word binding")]
+#[test_case(InputBinding::Packed; "packed binding")]
+fn word_value_is_below_2_64(input_binding: InputBinding) {
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::None,
+        input_binding,
+        ..Default::default()
+    };
+    // The same word with a bit above its 64 bits, which the field holds without wrapping
+    let tamper: fn(&mut [keccak_packed_multi::KeccakRow<Fr>]) = |w| {
+        let high_bit = Fr::from_u128(1 << param::NUM_BITS_PER_WORD);
+        w[FIRST_WORD_ROW].word_value += high_bit;
+        w[FIRST_WORD_ROW].input_acc += high_bit;
+    };
+    let circuit =
+        TamperedWitnessCircuit { config, inputs: vec![vec![7u8; 30]], capacity: 3, tamper: Some(tamper), padding: None };
+    let prover = MockProver::run(config.k, &circuit, vec![]).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+/// With rows_per_round = 5, the rows of the second element of the first keccak_f, and of the
+/// low bytes of word 3 ending the first one.
+const SECOND_ELEMENT_ROW: usize = 5 + 4 * 5;
+const FIRST_ELEMENT_END_ROW: usize = 5 + 3 * 5;
+
+#[test_case(None; "honest witness")]
+#[test_case(Some(|w| w[FIRST_WORD_ROW].input_acc += Fr::from(1)); "first element")]
+#[test_case(Some(|w| w[SECOND_ELEMENT_ROW].input_acc += Fr::from(1)); "second element")]
+#[test_case(Some(|w| w[FIRST_ELEMENT_END_ROW].input_acc += Fr::from(1)); "low bytes ending an element")]
+fn bytes31_elements_are_constrained(tamper: Option<fn(&mut [keccak_packed_multi::KeccakRow<Fr>])>) {
+    let config = KeccakConfigParams {
+        k: 12,
+        rows_per_round: 5,
+        instance_mode: InstanceMode::None,
+        input_binding: InputBinding::Bytes31,
+        ..Default::default()
+    };
+    let circuit = TamperedWitnessCircuit { config, inputs: vec![vec![7u8; 100]], capacity: 3, tamper, padding: None };
+    let prover = MockProver::run(config.k, &circuit, vec![]).unwrap();
+    assert_eq!(prover.verify().is_ok(), tamper.is_none());
+}
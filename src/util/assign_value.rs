@@ -1,10 +1,14 @@
+//! Region assignment helpers. These are the only places where the PSE and axiom forks of
+//! `halo2_proofs` disagree on the API, so all circuit code goes through them.
+
 use halo2_proofs::arithmetic::Field;
-use halo2_proofs::circuit::{Cell, Region, Value};
-use halo2_proofs::plonk::{Advice, Assigned, Column, Fixed};
+use halo2_proofs::circuit::{Cell, Layouter, Region, Value};
+use halo2_proofs::plonk::{Advice, Assigned, Column, Error, Fixed, Instance};
 
 use crate::util::Halo2AssignedCell;
 
 /// Assign advice to physical region.
+#[cfg(feature = "halo2-pse")]
 #[inline(always)]
 pub fn raw_assign_advice<'v, F: Field>(
     region: &mut Region<F>,
@@ -23,6 +27,19 @@ pub fn raw_assign_advice<'v, F: Field>(
         .unwrap()
 }
 
+/// Assign advice to physical region.
+#[cfg(feature = "halo2-axiom")]
+#[inline(always)]
+pub fn raw_assign_advice<'v, F: Field>(
+    region: &mut Region<F>,
+    column: Column<Advice>,
+    offset: usize,
+    value: Value<impl Into<Assigned<F>>>,
+) -> Halo2AssignedCell<'v, F> {
+    region.assign_advice(column, offset, value)
+}
+
+#[cfg(feature = "halo2-pse")]
 #[inline(always)]
 pub fn raw_assign_fixed<F: Field>(
     region: &mut Region<F>,
@@ -39,4 +56,50 @@ pub fn raw_assign_fixed<F: Field>(
         )
         .unwrap()
         .cell()
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "halo2-axiom")]
+#[inline(always)]
+pub fn raw_assign_fixed<F: Field>(
+    region: &mut Region<F>,
+    column: Column<Fixed>,
+    offset: usize,
+    value: F,
+) -> Cell {
+    region.assign_fixed(column, offset, value)
+}
+
+/// Constrain two cells to be equal.
+#[inline(always)]
+pub fn constrain_equal<F: Field>(region: &mut Region<F>, left: Cell, right: Cell) -> Result<(), Error> {
+    #[cfg(feature = "halo2-pse")]
+    return region.constrain_equal(left, right);
+    #[cfg(feature = "halo2-axiom")]
+    {
+        region.constrain_equal(left, right);
+        Ok(())
+    }
+}
+
+/// Constrain a cell to equal a row of an instance column.
+#[inline(always)]
+pub fn constrain_instance<F: Field, L: Layouter<F>>(
+    layouter: &mut L,
+    cell: Cell,
+    column: Column<Instance>,
+    row: usize,
+) -> Result<(), Error> {
+    #[cfg(feature = "halo2-pse")]
+    return layouter.constrain_instance(cell, column, row);
+    #[cfg(feature = "halo2-axiom")]
+    {
+        layouter.constrain_instance(cell, column, row);
+        Ok(())
+    }
+}
+
+/// Returns the evaluated value of an assigned cell, if known.
+#[inline(always)]
+pub fn assigned_value<F: Field>(cell: &Halo2AssignedCell<'_, F>) -> Value<F> {
+    cell.value().map(|v| v.evaluate())
+}
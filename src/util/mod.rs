@@ -12,7 +12,10 @@ pub mod prime_field;
 pub mod assign_value;
 pub(crate) mod word;
 
+#[cfg(feature = "halo2-pse")]
 pub type Halo2AssignedCell<'v, F> = AssignedCell<Assigned<F>, F>;
+#[cfg(feature = "halo2-axiom")]
+pub type Halo2AssignedCell<'v, F> = AssignedCell<&'v Assigned<F>, F>;
 
 
 pub const SKIP_FIRST_PASS: bool = true;
@@ -100,6 +103,46 @@ pub fn unpack<F: Field>(packed: F) -> [u8; NUM_BITS_PER_WORD] {
     bits
 }
 
+/// Formats a sparse keccak word for debugging: its `BIT_SIZE` base digits, one per bit of the
+/// original word, most significant first and grouped per byte, e.g.
+/// `00000000_..._01100011`. Hex formatting prints the word itself when every digit is a bit,
+/// and falls back to the digits otherwise.
+#[derive(Copy, Clone)]
+pub struct SparseWord<F>(pub F);
+
+impl<F: Field> std::fmt::Display for SparseWord<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = unpack(self.0);
+        for (idx, byte) in digits.chunks(NUM_BITS_PER_BYTE).rev().enumerate() {
+            if idx > 0 {
+                write!(f, "_")?;
+            }
+            for digit in byte.iter().rev() {
+                write!(f, "{digit}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<F: Field> std::fmt::LowerHex for SparseWord<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = unpack(self.0);
+        if digits.iter().all(|digit| *digit <= 1) {
+            let word = digits.iter().rev().fold(0u64, |acc, bit| acc << 1 | *bit as u64);
+            write!(f, "{word:016x}")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+impl<F: Field> std::fmt::Debug for SparseWord<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SparseWord({self})")
+    }
+}
+
 /// Returns the size (in bits) of each part size when splitting up a keccak word
 /// in parts of `part_size`
 pub fn target_part_sizes(part_size: usize) -> Vec<usize> {
@@ -58,6 +58,12 @@ impl<F: PrimeField> Cell<F> {
         }
     }
 
+    /// Queries the cell `offset` rows away from within a gate, unlike [`Self::at_offset`],
+    /// which has to be called outside of one.
+    pub(crate) fn query_at_offset(&self, meta: &mut VirtualCells<'_, F>, offset: i32) -> Expression<F> {
+        meta.query_advice(self.column.unwrap(), Rotation(self.rotation + offset))
+    }
+
     pub(crate) fn assign(&self, region: &mut KeccakRegion<F>, offset: i32, value: F) {
         region.assign(self.column_idx, (offset + self.rotation) as usize, value);
     }
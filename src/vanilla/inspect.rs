@@ -0,0 +1,103 @@
+//! Human readable rendering of the witness trace, for checking changes to the padding or
+//! absorb witness by eye.
+
+use std::fmt::Write;
+
+use halo2_proofs::halo2curves::bn256::Fr;
+
+use super::cell_manager::CellManager;
+use super::keccak_packed_multi::KeccakRow;
+use super::param::{KECCAK_WIDTH, NUM_ROUNDS};
+use super::witness::multi_keccak;
+use super::KeccakConfigParams;
+use crate::util::eth_types::Field;
+use crate::util::SparseWord;
+
+/// Renders the trace of hashing `input` as a table per round: the flags, `bytes_left` and
+/// `word_value` of the round's first row, then the 25 state words entering the round in hex,
+/// `s[i][0..5]` on line `i`.
+pub fn inspect_witness(input: &[u8], config: KeccakConfigParams) -> String {
+    let (rows, _) = multi_keccak::<Fr, _>(&[input], None, config);
+    let rows_per_round = config.rows_per_round;
+
+    // The state words are the first cells the witness queries in every round
+    let mut cell_manager = CellManager::<Fr>::new(rows_per_round);
+    let state_cells = (0..KECCAK_WIDTH).map(|_| cell_manager.query_cell_value()).collect::<Vec<_>>();
+
+    let mut out = String::new();
+    for keccak_f in 0..config.num_keccak_f(input.len()) {
+        for round in 0..NUM_ROUNDS + 1 {
+            let start = rows_per_round * (1 + keccak_f * (NUM_ROUNDS + 1) + round);
+            let row = &rows[start];
+            writeln!(out, "keccak_f {keccak_f} round {round} (row {start}){}", flags(row)).unwrap();
+            writeln!(
+                out,
+                "  bytes_left {}  word_value 0x{:016x}",
+                to_u64(row.bytes_left),
+                to_u64(row.word_value)
+            )
+            .unwrap();
+            for lane in state_cells.chunks(5) {
+                let words = lane
+                    .iter()
+                    .map(|cell| {
+                        let value = rows[start + cell.rotation as usize].cell_values[cell.column_idx];
+                        format!("{:x}", SparseWord(value))
+                    })
+                    .collect::<Vec<_>>();
+                writeln!(out, "  {}", words.join(" ")).unwrap();
+            }
+        }
+    }
+    out
+}
+
+fn flags(row: &KeccakRow<Fr>) -> String {
+    [
+        ("q_input", row.q_input),
+        ("q_input_last", row.q_input_last),
+        ("q_absorb", row.q_absorb),
+        ("is_final", row.is_final),
+    ]
+    .iter()
+    .filter(|(_, set)| *set)
+    .map(|(name, _)| format!(" {name}"))
+    .collect()
+}
+
+/// The low 64 bits of `value`.
+fn to_u64(value: Fr) -> u64 {
+    u64::from_le_bytes(value.to_bytes_le()[..8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inspect_witness() {
+        let config = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+        let trace = inspect_witness(b"abc", config);
+        assert_eq!(trace.matches("keccak_f 0 round").count(), NUM_ROUNDS + 1);
+        assert!(trace.contains("keccak_f 0 round 0 (row 5) q_input\n  bytes_left 3  word_value 0x0000000000636261"));
+        assert!(trace.contains("keccak_f 0 round 16 (row 85) q_input q_input_last\n"));
+        assert!(trace.contains("keccak_f 0 round 24 (row 125) q_absorb is_final\n"));
+        // The state entering the absorb round holds the digest, keccak("abc") starting with the
+        // little-endian word 4e03657aea45a94f
+        let absorb = trace.split("round 24").nth(1).unwrap();
+        assert!(absorb.lines().nth(2).unwrap().trim_start().starts_with("4fa945ea7a65034e"), "{}", absorb);
+    }
+
+    #[test]
+    fn test_sparse_word() {
+        use crate::util::pack;
+        use crate::vanilla::util::pack_u64;
+
+        let word = SparseWord(pack_u64::<Fr>(0x8063));
+        assert_eq!(word.to_string(), format!("{}_10000000_01100011", ["00000000"; 6].join("_")));
+        assert_eq!(format!("{word:x}"), "0000000000008063");
+        // Unnormalized digits, e.g. of a theta sum, print as digits in hex too
+        let sum = SparseWord(pack::<Fr>(&[3, 0, 5]));
+        assert!(format!("{sum:x}").ends_with("_00000503"));
+    }
+}
@@ -4,6 +4,7 @@ use halo2_proofs::{
     plonk::{Advice, Column, ConstraintSystem, Expression},
 };
 
+use crate::config::DigestSize;
 use crate::util::{constraint_builder::BaseConstraintBuilder, eth_types::Field, expression::Expr, Halo2AssignedCell, word::Word};
 
 use super::{cell_manager::*, param::*, table::*};
@@ -29,15 +30,44 @@ pub(crate) fn get_num_bits_per_base_chi_lookup(k: u32) -> usize {
 /// `num_rows` should be number of usable rows without blinding factors
 pub fn get_keccak_capacity(num_rows: usize, rows_per_round: usize) -> usize {
     // - 1 because we have a dummy round at the very beginning of multi_keccak
-    // - NUM_WORDS_TO_ABSORB because `absorb_data_next` and `absorb_result_next` query `NUM_WORDS_TO_ABSORB * num_rows_per_round` beyond any row where `q_absorb == 1`
+    // - NUM_WORDS_TO_ABSORB because `absorb_data_next` and `absorb_result_next` query up to `NUM_WORDS_TO_ABSORB * num_rows_per_round` beyond any row where `q_absorb == 1`
     (num_rows / rows_per_round - 1 - NUM_WORDS_TO_ABSORB) / (NUM_ROUNDS + 1)
 }
 
+/// The number of keccak_f's hashing `byte_length` bytes with 256 bit digests, see
+/// [`KeccakConfigParams::num_keccak_f`](crate::KeccakConfigParams::num_keccak_f) for any rate.
 pub fn get_num_keccak_f(byte_length: usize) -> usize {
     // ceil( (byte_length + 1) / RATE )
     byte_length / RATE + 1
 }
 
+/// The word of a keccak_f whose row holds the instance cell of the bytes starting at `start`
+/// of its block: the word itself, or the next one if `start` is inside a word, whose row then
+/// holds the bytes ending the previous cell.
+pub(crate) fn input_cell_word(start: usize) -> usize {
+    (start + NUM_BYTES_PER_WORD - 1) / NUM_BYTES_PER_WORD
+}
+
+/// The `input_acc` value of every input row of a keccak_f absorbing `block`, zero padded to
+/// `RATE` bytes, with `InputBinding::Bytes31`: each element of `NUM_BYTES_PER_INPUT_ELEMENT`
+/// bytes on the row of [`input_cell_word`], and the bytes of an element ending inside a word on
+/// the row of that word, all little-endian.
+pub(crate) fn input_element_column<F: PrimeField>(block: &[u8]) -> Vec<F> {
+    let le_value = |bytes: &[u8]| {
+        bytes.iter().rev().fold(F::ZERO, |acc, byte| acc * F::from(256) + F::from(*byte as u64))
+    };
+    let mut column = vec![F::ZERO; NUM_WORDS_TO_ABSORB];
+    for start in (0..RATE).step_by(NUM_BYTES_PER_INPUT_ELEMENT) {
+        let end = RATE.min(start + NUM_BYTES_PER_INPUT_ELEMENT);
+        column[input_cell_word(start)] = le_value(&block[start..end]);
+        if end % NUM_BYTES_PER_WORD != 0 {
+            let word = end / NUM_BYTES_PER_WORD;
+            column[word] = le_value(&block[word * NUM_BYTES_PER_WORD..end]);
+        }
+    }
+    column
+}
+
 /// AbsorbData
 #[derive(Clone, Default, Debug, PartialEq)]
 pub(crate) struct AbsorbData<F: PrimeField> {
@@ -61,13 +91,18 @@ pub struct KeccakRow<F: PrimeField> {
     pub(crate) q_round_last: bool,
     pub(crate) q_input: bool,
     pub(crate) q_input_last: bool,
+    pub(crate) q_input_chunk_last: bool,
     pub(crate) round_cst: F,
     pub(crate) is_final: bool,
     pub(crate) cell_values: Vec<F>,
     pub(crate) hash: Word<Value<F>>,
+    // The last 32 bytes of a 512 bit digest
+    pub(crate) hash_ext: Word<Value<F>>,
     pub(crate) bytes_left: F,
     // A keccak word(NUM_BYTES_PER_WORD bytes)
     pub(crate) word_value: F,
+    // The words from this one to the end of its input chunk
+    pub(crate) input_acc: F,
 }
 
 impl<F: PrimeField> KeccakRow<F> {
@@ -80,12 +115,15 @@ impl<F: PrimeField> KeccakRow<F> {
                 q_round_last: false,
                 q_input: false,
                 q_input_last: false,
+                q_input_chunk_last: false,
                 round_cst: F::ZERO,
                 is_final: false,
                 cell_values: Vec::new(),
                 hash: Word::default().into_value(),
+                hash_ext: Word::default().into_value(),
                 bytes_left: F::ZERO,
                 word_value: F::ZERO,
+                input_acc: F::ZERO,
             })
             .collect()
     }
@@ -134,8 +172,10 @@ impl<F: PrimeField> KeccakRegion<F> {
 pub struct KeccakTable {
     /// True when the row is enabled
     pub is_enabled: Column<Advice>,
-    /// Keccak hash of input
+    /// Keccak hash of input, its first 32 bytes with 512 bit digests
     pub output: Word<Column<Advice>>,
+    /// The last 32 bytes of 512 bit digests
+    pub output_ext: Option<Word<Column<Advice>>>,
     /// Raw keccak words(NUM_BYTES_PER_WORD bytes) of inputs
     pub word_value: Column<Advice>,
     /// Number of bytes left of a input
@@ -144,7 +184,7 @@ pub struct KeccakTable {
 
 impl KeccakTable {
     /// Construct a new KeccakTable
-    pub fn construct<F: Field>(meta: &mut ConstraintSystem<F>) -> Self {
+    pub fn construct<F: Field>(meta: &mut ConstraintSystem<F>, digest_size: DigestSize) -> Self {
         let is_enabled = meta.advice_column();
         let word_value = meta.advice_column();
         let bytes_left = meta.advice_column();
@@ -155,7 +195,13 @@ impl KeccakTable {
         meta.enable_equality(bytes_left);
         meta.enable_equality(hash_lo);
         meta.enable_equality(hash_hi);
-        Self { is_enabled, output: Word::new([hash_lo, hash_hi]), word_value, bytes_left }
+        let output_ext = (digest_size == DigestSize::Bits512).then(|| {
+            let [hash_lo, hash_hi] = [(); 2].map(|_| meta.advice_column());
+            meta.enable_equality(hash_lo);
+            meta.enable_equality(hash_hi);
+            Word::new([hash_lo, hash_hi])
+        });
+        Self { is_enabled, output: Word::new([hash_lo, hash_hi]), output_ext, word_value, bytes_left }
     }
 }
 
@@ -186,7 +232,7 @@ pub(crate) mod decode {
 pub(crate) mod split {
     use halo2_proofs::plonk::{ConstraintSystem, Expression};
 
-    use crate::util::{pack, pack_part, unpack, WordParts};
+    use crate::util::{pack, pack_part, unpack, SparseWord, WordParts};
 
     use super::{
         BaseConstraintBuilder, CellManager, decode, Expr, Field, KeccakRegion, Part, PartValue,
@@ -250,7 +296,7 @@ pub(crate) mod split {
                 value: F::from(value),
             });
         }
-        debug_assert_eq!(decode::value(parts.clone()), input);
+        debug_assert_eq!(decode::value(parts.clone()), input, "split of {} doesn't decode", SparseWord(input));
         parts
     }
 }
@@ -315,7 +361,7 @@ pub(crate) mod split_uniform {
                 // Make sure the parts combined equal the value in the uniform output
                 let expr = part_a.expr()
                     + part_b.expr()
-                        * F::from((BIT_SIZE as u32).pow(word_part.bits.len() as u32) as u64);
+                        * F::from((BIT_SIZE as u64).pow(word_part.bits.len() as u32));
                 cb.require_equal("rot part", expr, output_cells[counter].expr());
 
                 // Input needs the two parts because it needs to be able to undo the rotation
@@ -423,17 +469,17 @@ pub(crate) mod split_uniform {
             }
         }
         let input_parts = rotate_rev(input_parts, rot, target_part_size);
-        debug_assert_eq!(decode::value(input_parts), input);
+        debug_assert_eq!(decode::value(input_parts), input, "split of {} doesn't decode", SparseWord(input));
         output_parts
     }
 }
 
 // Transform values using a lookup table
 pub(crate) mod transform {
-    use halo2_proofs::plonk::{ConstraintSystem, TableColumn};
+    use halo2_proofs::plonk::ConstraintSystem;
     use itertools::Itertools;
 
-    use super::{CellManager, Field, KeccakRegion, Part, PartValue, PrimeField, transform_to};
+    use super::{CellManager, Field, KeccakRegion, LookupTable, Part, PartValue, PrimeField, transform_to};
 
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn expr<F: PrimeField>(
@@ -442,7 +488,7 @@ pub(crate) mod transform {
         cell_manager: &mut CellManager<F>,
         lookup_counter: &mut usize,
         input: Vec<Part<F>>,
-        transform_table: [TableColumn; 2],
+        transform_table: LookupTable,
         uniform_lookup: bool,
     ) -> Vec<Part<F>> {
         let cells = input
@@ -490,11 +536,11 @@ pub(crate) mod transform {
 
 // Transforms values to cells
 pub(crate) mod transform_to {
-    use halo2_proofs::plonk::{ConstraintSystem, TableColumn};
+    use halo2_proofs::plonk::ConstraintSystem;
 
     use crate::vanilla::{
         Cell,
-        Expr, Field, KeccakRegion, Part, PartValue, PrimeField, util::{pack, to_bytes, unpack},
+        Expr, Field, KeccakRegion, LookupTable, Part, PartValue, PrimeField, util::{pack, to_bytes, unpack},
     };
 
     #[allow(clippy::too_many_arguments)]
@@ -504,7 +550,7 @@ pub(crate) mod transform_to {
         cells: &[Cell<F>],
         lookup_counter: &mut usize,
         input: Vec<Part<F>>,
-        transform_table: [TableColumn; 2],
+        transform_table: LookupTable,
         uniform_lookup: bool,
     ) -> Vec<Part<F>> {
         let mut output = Vec::with_capacity(input.len());
@@ -512,10 +558,7 @@ pub(crate) mod transform_to {
             let output_part = cells[idx].clone();
             if !uniform_lookup || input_part.cell.rotation == 0 {
                 meta.lookup(name, |_| {
-                    vec![
-                        (input_part.expr.clone(), transform_table[0]),
-                        (output_part.expr(), transform_table[1]),
-                    ]
+                    transform_table.lookup(input_part.expr.clone(), Some(output_part.expr()))
                 });
                 *lookup_counter += 1;
             }
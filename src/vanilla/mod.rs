@@ -2,7 +2,7 @@ use self::{cell_manager::*, keccak_packed_multi::*, param::*, table::*, util::*}
 use halo2_proofs::{
         circuit::{Layouter, Region, Value},
         halo2curves::ff::PrimeField,
-        plonk::{Column, ConstraintSystem, Error, Expression, Fixed, TableColumn, VirtualCells},
+        plonk::{Advice, Column, ConstraintSystem, Error, Expression, Fixed, VirtualCells},
         poly::Rotation,
     };
 
@@ -18,9 +18,12 @@ use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use std::marker::PhantomData;
 use serde::{Deserialize, Serialize};
 use crate::util::assign_value::{raw_assign_advice, raw_assign_fixed};
+use crate::config::{DigestSize, InputBinding, InstanceMode, LookupLayout, Padding, SanityLevel, TranscriptKind};
 
 pub mod cell_manager;
+pub mod inspect;
 pub mod keccak_packed_multi;
+pub(crate) mod padding;
 pub mod param;
 pub mod table;
 pub mod util;
@@ -28,12 +31,46 @@ pub mod util;
 pub mod witness;
 
 /// Configuration parameters to define [`KeccakCircuitConfig`]
-#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+///
+/// Missing fields deserialize to those of [`DEFAULT_CONFIG`](crate::DEFAULT_CONFIG), which is
+/// also its [`Default`].
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct KeccakConfigParams {
     /// The circuit degree, i.e., circuit has 2<sup>k</sup> rows
     pub k: u32,
     /// The number of rows to use for each round in the keccak_f permutation
     pub rows_per_round: usize,
+    /// Which values are exposed through the instance column
+    pub instance_mode: InstanceMode,
+    /// The transcript used for proving and verifying
+    pub transcript: TranscriptKind,
+    /// The prover side witness checks
+    pub sanity: SanityLevel,
+    /// Upper bound on the circuit degree required by the backend, `0` for no bound
+    pub max_degree: usize,
+    /// How the fixed lookup tables are laid out
+    pub lookup_layout: LookupLayout,
+    /// How the absorbed words are bound to the `input` instance column
+    pub input_binding: InputBinding,
+    /// Number of instance columns the `input` cells are spread over, `0` or `1` for one
+    pub input_columns: usize,
+    /// The iota round constants, `None` for the standard [`ROUND_CST`](param::ROUND_CST)
+    pub round_constants: Option<[u64; NUM_ROUNDS]>,
+    /// The padding of the messages, i.e. whether the circuit proves Keccak-256 or SHA3-256
+    pub padding: Padding,
+    /// The size of the digests, which sets the number of words absorbed per keccak_f
+    pub digest_size: DigestSize,
+    /// The length in bytes no message may exceed, enforced in the circuit, `None` for no bound
+    pub max_input_len: Option<usize>,
+    /// Whether circuits with `capacity_rows` lay out the instance by keccak_f slot, see
+    /// [`KeccakCircuitBuilder::fixed_instance`](crate::KeccakCircuitBuilder::fixed_instance),
+    /// so that keys generated without inputs bind the instance of every proof
+    pub fixed_instance: bool,
+    /// Whether the fixed instance layout exposes every word through a disclosure mask, so that
+    /// [`PublicBytes`](crate::PublicBytes) other than `All` keep bytes private with keys
+    /// generated without inputs, see [`disclosure`](crate::disclosure)
+    pub selective_disclosure: bool,
 }
 
 /// KeccakConfig
@@ -50,21 +87,27 @@ pub struct KeccakCircuitConfig<F> {
     // Bool. True on 1st row of last rounds.
     q_round_last: Column<Fixed>,
     // Bool. True on 1st row of rounds which might contain inputs.
-    // Note: first NUM_WORDS_TO_ABSORB rounds of each chunk might contain inputs.
+    // Note: first `words_to_absorb` rounds of each chunk might contain inputs.
     // It "might" contain inputs because it's possible that a round only have paddings.
     q_input: Column<Fixed>,
     // Bool. True on 1st row of all last input round.
     q_input_last: Column<Fixed>,
+    // With packed input binding, the selector of the last word of each input chunk.
+    q_input_chunk_last: Option<Column<Fixed>>,
+    // With packed or bytes31 input binding, the values bound to the `input` instance column.
+    input_acc: Option<Column<Advice>>,
+    // With a maximum input length, the little-endian bytes of `max_input_len - bytes_left`.
+    input_len_bytes: Vec<Column<Advice>>,
 
     pub keccak_table: KeccakTable,
 
     cell_manager: CellManager<F>,
     round_cst: Column<Fixed>,
-    normalize_3: [TableColumn; 2],
-    normalize_4: [TableColumn; 2],
-    normalize_6: [TableColumn; 2],
-    chi_base_table: [TableColumn; 2],
-    pack_table: [TableColumn; 2],
+    normalize_3: LookupTable,
+    normalize_4: LookupTable,
+    normalize_6: LookupTable,
+    chi_base_table: LookupTable,
+    pack_table: LookupTable,
 
     // config parameters for convenience
     pub parameters: KeccakConfigParams,
@@ -75,8 +118,10 @@ pub struct KeccakCircuitConfig<F> {
 impl<F: Field> KeccakCircuitConfig<F> {
     /// Return a new KeccakCircuitConfig
     pub fn new(meta: &mut ConstraintSystem<F>, parameters: KeccakConfigParams) -> Self {
-        let k = parameters.k;
+        let k = parameters.lookup_k();
         let num_rows_per_round = parameters.rows_per_round;
+        let words_to_absorb = parameters.digest_size.words_to_absorb();
+        let words_to_squeeze = parameters.digest_size.words_to_squeeze();
 
         let q_enable = meta.fixed_column();
         let q_first = meta.fixed_column();
@@ -86,16 +131,14 @@ impl<F: Field> KeccakCircuitConfig<F> {
         let q_input = meta.fixed_column();
         let q_input_last = meta.fixed_column();
         let round_cst = meta.fixed_column();
-        let keccak_table = KeccakTable::construct(meta);
+        let keccak_table = KeccakTable::construct(meta, parameters.digest_size);
 
         let is_final = keccak_table.is_enabled;
-        let hash_word = keccak_table.output;
+        // The digest, 32 bytes per word
+        let hash_words_out = std::iter::once(keccak_table.output).chain(keccak_table.output_ext).collect_vec();
 
-        let normalize_3 = array_init::array_init(|_| meta.lookup_table_column());
-        let normalize_4 = array_init::array_init(|_| meta.lookup_table_column());
-        let normalize_6 = array_init::array_init(|_| meta.lookup_table_column());
-        let chi_base_table = array_init::array_init(|_| meta.lookup_table_column());
-        let pack_table = array_init::array_init(|_| meta.lookup_table_column());
+        let [normalize_3, normalize_4, normalize_6, chi_base_table, pack_table] =
+            LookupTable::configure(meta, parameters.lookup_layout);
 
         let mut cell_manager = CellManager::new(num_rows_per_round);
         let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
@@ -126,10 +169,10 @@ impl<F: Field> KeccakCircuitConfig<F> {
         let absorb_from = cell_manager.query_cell(meta);
         let absorb_data = cell_manager.query_cell(meta);
         let absorb_result = cell_manager.query_cell(meta);
-        let mut absorb_from_next = vec![0u64.expr(); NUM_WORDS_TO_ABSORB];
-        let mut absorb_data_next = vec![0u64.expr(); NUM_WORDS_TO_ABSORB];
-        let mut absorb_result_next = vec![0u64.expr(); NUM_WORDS_TO_ABSORB];
-        for i in 0..NUM_WORDS_TO_ABSORB {
+        let mut absorb_from_next = vec![0u64.expr(); words_to_absorb];
+        let mut absorb_data_next = vec![0u64.expr(); words_to_absorb];
+        let mut absorb_result_next = vec![0u64.expr(); words_to_absorb];
+        for i in 0..words_to_absorb {
             let rot = ((i + 1) * num_rows_per_round) as i32;
             absorb_from_next[i] = absorb_from.at_offset(meta, rot).expr();
             absorb_data_next[i] = absorb_data.at_offset(meta, rot).expr();
@@ -185,7 +228,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
             &mut cell_manager,
             &mut lookup_counter,
             packed_parts,
-            pack_table.into_iter().rev().collect::<Vec<_>>().try_into().unwrap(),
+            pack_table.reversed(),
             true,
         );
         debug_assert_eq!(input_bytes.len(), NUM_BYTES_PER_WORD);
@@ -338,7 +381,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
         // in a single lookup but doesn't save that much.
         for c in pi_region_start..pi_region_end {
             meta.lookup("pi part range check", |_| {
-                vec![(cell_manager.columns()[c].expr.clone(), normalize_4[0])]
+                normalize_4.lookup(cell_manager.columns()[c].expr.clone(), None)
             });
             lookup_counter += 1;
         }
@@ -368,7 +411,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
                     - input[(i + 2) % 5].clone();
                 let output = output[i].clone();
                 meta.lookup("chi base", |_| {
-                    vec![(input.clone(), chi_base_table[0]), (output.clone(), chi_base_table[1])]
+                    chi_base_table.lookup(input.clone(), Some(output.clone()))
                 });
                 lookup_counter += 1;
             }
@@ -426,7 +469,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
 
         // Squeeze data
         let squeeze_from = cell_manager.query_cell(meta);
-        let mut squeeze_from_prev = vec![0u64.expr(); NUM_WORDS_TO_SQUEEZE];
+        let mut squeeze_from_prev = vec![0u64.expr(); words_to_squeeze];
         for (idx, squeeze_from_prev) in squeeze_from_prev.iter_mut().enumerate() {
             let rot = (-(idx as i32) - 1) * num_rows_per_round as i32;
             *squeeze_from_prev = squeeze_from.at_offset(meta, rot).expr();
@@ -448,7 +491,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
             &mut cell_manager,
             &mut lookup_counter,
             squeeze_from_parts,
-            pack_table.into_iter().rev().collect::<Vec<_>>().try_into().unwrap(),
+            pack_table.reversed(),
             true,
         );
         info!("- Post squeeze:");
@@ -463,7 +506,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
         meta.create_gate("absorb", |meta| {
             let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
             let continue_hash = not::expr(start_new_hash(meta, Rotation::cur()));
-            let absorb_positions = get_absorb_positions();
+            let absorb_positions = get_absorb_positions(words_to_absorb);
             let mut a_slice = 0;
             for j in 0..5 {
                 for i in 0..5 {
@@ -499,7 +542,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
 
         // Collect the bytes that are spread out over previous rows
         let mut hash_bytes = Vec::new();
-        for i in 0..NUM_WORDS_TO_SQUEEZE {
+        for i in 0..words_to_squeeze {
             for byte in squeeze_bytes.iter() {
                 let rot = (-(i as i32) - 1) * num_rows_per_round as i32;
                 hash_bytes.push(byte.cell.at_offset(meta, rot).expr());
@@ -510,9 +553,9 @@ impl<F: Field> KeccakCircuitConfig<F> {
         meta.create_gate("squeeze", |meta| {
             let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
             let start_new_hash = start_new_hash(meta, Rotation::cur());
-            // The words to squeeze
+            // The words to squeeze, in lane order
             let hash_words: Vec<_> =
-                pre_s.into_iter().take(4).map(|a| a[0].clone()).take(4).collect();
+                (0..words_to_squeeze).map(|idx| pre_s[idx % 5][idx / 5].clone()).collect();
             // Verify if we converted the correct words to bytes on previous rows
             for (idx, word) in hash_words.iter().enumerate() {
                 cb.condition(start_new_hash.clone(), |cb| {
@@ -524,14 +567,17 @@ impl<F: Field> KeccakCircuitConfig<F> {
                 });
             }
 
-            let hash_bytes_le = hash_bytes.into_iter().rev().collect::<Vec<_>>();
-            cb.condition(start_new_hash, |cb| {
-                cb.require_equal_word(
-                    "output check",
-                    word::Word32::new(hash_bytes_le.try_into().expect("32 limbs")).to_word(),
-                    hash_word.map(|col| meta.query_advice(col, Rotation::cur())),
-                );
-            });
+            // Every 32 bytes of the digest, big-endian, against their (lo, hi) word
+            for (bytes, hash_word) in hash_bytes.chunks(32).zip(hash_words_out.iter()) {
+                let hash_bytes_le = bytes.iter().rev().cloned().collect::<Vec<_>>();
+                cb.condition(start_new_hash.clone(), |cb| {
+                    cb.require_equal_word(
+                        "output check",
+                        word::Word32::new(hash_bytes_le.try_into().expect("32 limbs")).to_word(),
+                        hash_word.map(|col| meta.query_advice(col, Rotation::cur())),
+                    );
+                });
+            }
             cb.gate(meta.query_fixed(q_round_last, Rotation::cur()))
         });
 
@@ -586,6 +632,14 @@ impl<F: Field> KeccakCircuitConfig<F> {
         43        0          0           1         0        0
         */
 
+        // Together with the padding and bytes_left gates, this zeroes the words past the end of
+        // every input: their bytes are all padding, so word_value is 0 and bytes_left keeps its
+        // value, which is_final forces to 0 on the last round, see `padding_words_are_zero`.
+        // The keccak_f padding a region to its capacity are checked like any other hash: they
+        // are only bound to the empty input through a fixed instance.
+        // Every input byte is looked up in the pack table, so is below 256, and word_value, the
+        // one cell of the word copied to the instance, is below 2^64: a prover can't carry
+        // extra high bits in it, see `word_value_is_below_2_64`.
         meta.create_gate("word_value", |meta| {
             let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
             let masked_input_bytes = input_bytes
@@ -649,10 +703,126 @@ impl<F: Field> KeccakCircuitConfig<F> {
             cb.gate(q(q_enable, meta))
         });
 
+        // With a maximum input length, `max_input_len - bytes_left` is decomposed into bytes on
+        // every input round. bytes_left only decreases within a hash, so this bounds its first
+        // value, the length of the message. A longer message makes the difference wrap around
+        // the field, far above what the bytes can hold.
+        let input_len_bytes = (0..parameters.num_input_len_bytes()).map(|_| meta.advice_column()).collect_vec();
+        if let Some(max_input_len) = parameters.max_input_len {
+            for column in input_len_bytes.iter() {
+                meta.lookup("max input len byte range check", |meta| {
+                    pack_table.lookup(meta.query_advice(*column, Rotation::cur()), None)
+                });
+            }
+            meta.create_gate("max input len", |meta| {
+                let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
+                let bytes = input_len_bytes
+                    .iter()
+                    .map(|column| meta.query_advice(*column, Rotation::cur()))
+                    .collect_vec();
+                cb.require_equal(
+                    "max_input_len - bytes_left fits in the length bytes",
+                    (max_input_len as u64).expr() - meta.query_advice(keccak_table.bytes_left, Rotation::cur()),
+                    from_bytes::expr(&bytes),
+                );
+                cb.gate(q(q_input, meta))
+            });
+        }
+
+        // With packed input binding, the words of a keccak_f are accumulated backwards in chunks
+        // of NUM_WORDS_PER_INPUT_CHUNK, so that the row of the first word of a chunk holds the
+        // whole chunk, little-endian, and only that cell is constrained to the instance.
+        let input_acc = (parameters.input_binding != InputBinding::Word).then(|| {
+            let input_acc = meta.advice_column();
+            meta.enable_equality(input_acc);
+            input_acc
+        });
+        let packed_acc = input_acc.filter(|_| parameters.input_binding == InputBinding::Packed);
+        let q_input_chunk_last = packed_acc.map(|input_acc| {
+            let q_input_chunk_last = meta.fixed_column();
+            meta.create_gate("input chunk", |meta| {
+                let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
+                let acc_next = meta.query_advice(input_acc, Rotation(num_rows_per_round as i32));
+                cb.require_equal(
+                    "input_acc[cur] == word_value[cur] + 2^64 * input_acc[cur + num_rows_per_round] unless the chunk ends",
+                    meta.query_advice(input_acc, Rotation::cur()),
+                    meta.query_advice(keccak_table.word_value, Rotation::cur())
+                        + not::expr(q(q_input_chunk_last, meta))
+                            * acc_next
+                            * F::from_u128(1 << NUM_BITS_PER_WORD),
+                );
+                cb.gate(q(q_input, meta))
+            });
+            q_input_chunk_last
+        });
+
+        // With bytes31 input binding, the bytes of a keccak_f are split in elements of
+        // NUM_BYTES_PER_INPUT_ELEMENT, each held by the row of `input_cell_word`. The row of a
+        // word with an element ending inside it holds the low bytes of the word ending the
+        // element, so that
+        //   256^(start % 8) * element == word_value - low bytes of the first word
+        //                              + 2^64 * word_value of the next words
+        //                              + 2^(64 * i) * low bytes of the i-th word if it ends inside.
+        // The byte cells are range checked, so this pins down every element. All of it is
+        // checked from the last input row of the keccak_f, looking back at the others.
+        let bytes31_acc = input_acc.filter(|_| parameters.input_binding == InputBinding::Bytes31);
+        if let Some(input_acc) = bytes31_acc {
+            meta.create_gate("input elements", |meta| {
+                let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
+                let rot = |word: usize| {
+                    (word as i32 - (NUM_WORDS_TO_ABSORB - 1) as i32) * num_rows_per_round as i32
+                };
+                let word_value = |meta: &mut VirtualCells<'_, F>, word| {
+                    meta.query_advice(keccak_table.word_value, Rotation(rot(word)))
+                };
+                let acc = |meta: &mut VirtualCells<'_, F>, word| {
+                    meta.query_advice(input_acc, Rotation(rot(word)))
+                };
+                let pow_256 = |exp: usize| (0..exp).fold(F::from(1), |acc, _| acc * F::from(256));
+                for start in (0..RATE).step_by(NUM_BYTES_PER_INPUT_ELEMENT) {
+                    let end = RATE.min(start + NUM_BYTES_PER_INPUT_ELEMENT);
+                    let first_word = start / NUM_BYTES_PER_WORD;
+                    let mut bytes = 0.expr();
+                    for word in first_word..(end + NUM_BYTES_PER_WORD - 1) / NUM_BYTES_PER_WORD {
+                        let shift = pow_256((word - first_word) * NUM_BYTES_PER_WORD);
+                        let value = if (word + 1) * NUM_BYTES_PER_WORD > end {
+                            acc(meta, word)
+                        } else if word * NUM_BYTES_PER_WORD < start {
+                            word_value(meta, word) - acc(meta, word)
+                        } else {
+                            word_value(meta, word)
+                        };
+                        bytes = bytes + value * shift;
+                    }
+                    cb.require_equal(
+                        "input element matches the absorbed bytes",
+                        acc(meta, input_cell_word(start)) * pow_256(start % NUM_BYTES_PER_WORD),
+                        bytes,
+                    );
+                    if end % NUM_BYTES_PER_WORD != 0 {
+                        let word = end / NUM_BYTES_PER_WORD;
+                        let low_bytes = input_bytes
+                            .iter()
+                            .zip(is_paddings.iter())
+                            .take(end % NUM_BYTES_PER_WORD)
+                            .enumerate()
+                            .map(|(idx, (byte, is_padding))| {
+                                byte.cell.query_at_offset(meta, rot(word))
+                                    * not::expr(is_padding.query_at_offset(meta, rot(word)))
+                                    * pow_256(idx)
+                            })
+                            .fold(0.expr(), |sum, byte| sum + byte);
+                        cb.require_equal("input element low bytes", acc(meta, word), low_bytes);
+                    }
+                }
+                cb.gate(q(q_input_last, meta))
+            });
+        }
+
         // Enforce logic for when this block is the last block for a hash
         let last_is_padding_in_block = is_paddings.last().unwrap().at_offset(
             meta,
-            -(((NUM_ROUNDS + 1 - NUM_WORDS_TO_ABSORB) * num_rows_per_round) as i32),
+            -(((NUM_ROUNDS + 1 - words_to_absorb) * num_rows_per_round) as i32),
         );
         meta.create_gate("is final", |meta| {
             let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
@@ -688,6 +858,8 @@ impl<F: Field> KeccakCircuitConfig<F> {
         // currently easier to do it like this.
         let prev_is_padding =
             is_paddings.last().unwrap().at_offset(meta, -(num_rows_per_round as i32));
+        // The first padding byte, holding the domain separation bits and the first bit of pad10*1
+        let domain_byte = parameters.padding.rule().0;
         meta.create_gate("padding", |meta| {
             let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
             let q_input = meta.query_fixed(q_input, Rotation::cur());
@@ -727,32 +899,34 @@ impl<F: Field> KeccakCircuitConfig<F> {
                     cb.condition(
                         and::expr([q_input.expr() - q_input_last.expr(), is_paddings[idx].expr()]),
                         |cb| {
-                            // Input bytes need to be zero, or one if this is the first padding byte
+                            // Input bytes need to be zero, or the domain byte if this is the first
+                            // padding byte
                             cb.require_equal(
                                 "padding start/intermediate byte last byte",
                                 input_bytes[idx].expr.clone(),
-                                is_first_padding.expr(),
+                                is_first_padding.expr() * domain_byte.expr(),
                             );
                         },
                     );
                     // Padding start/end byte, only on the last padding row
                     cb.condition(and::expr([q_input_last.expr(), is_paddings[idx].expr()]), |cb| {
                         // The input byte needs to be 128, unless it's also the first padding
-                        // byte then it's 129
+                        // byte then it's 128 plus the domain byte, e.g. 129 for keccak
                         cb.require_equal(
                             "padding start/end byte",
                             input_bytes[idx].expr.clone(),
-                            is_first_padding.expr() + 128.expr(),
+                            is_first_padding.expr() * domain_byte.expr() + 128.expr(),
                         );
                     });
                 } else {
                     // Padding start/intermediate byte
                     cb.condition(and::expr([q_input.expr(), is_paddings[idx].expr()]), |cb| {
-                        // Input bytes need to be zero, or one if this is the first padding byte
+                        // Input bytes need to be zero, or the domain byte if this is the first
+                        // padding byte
                         cb.require_equal(
                             "padding start/intermediate byte",
                             input_bytes[idx].expr.clone(),
-                            is_first_padding.expr(),
+                            is_first_padding.expr() * domain_byte.expr(),
                         );
                     });
                 }
@@ -787,6 +961,9 @@ impl<F: Field> KeccakCircuitConfig<F> {
             q_round_last,
             q_input,
             q_input_last,
+            q_input_chunk_last,
+            input_acc,
+            input_len_bytes,
             keccak_table,
             cell_manager,
             round_cst,
@@ -799,6 +976,34 @@ impl<F: Field> KeccakCircuitConfig<F> {
             _marker: PhantomData,
         }
     }
+
+    /// Looks up every absorbed word, as `(bytes_left, word_value)` on the first row of its round,
+    /// in a table of the host circuit given by the two expressions `table` returns, e.g. the
+    /// columns of a bytecode table. The host proves that it hashed exactly the bytes of its
+    /// table without exposing them through the `input` instance column, so it would usually
+    /// pair this with an instance mode that doesn't expose inputs.
+    ///
+    /// Rounds without an input word look up `(0, 0)`, which the host gets on its disabled rows
+    /// by multiplying the table columns by its selector. A word only matches a table row with
+    /// the same number of bytes left to the end of the message, so a table holding several
+    /// messages has to tell them apart itself, e.g. by also binding their digests.
+    pub fn lookup_words(
+        &self,
+        meta: &mut ConstraintSystem<F>,
+        name: &'static str,
+        table: impl FnOnce(&mut VirtualCells<'_, F>) -> [Expression<F>; 2],
+    ) {
+        let q_input = self.q_input;
+        let KeccakTable { bytes_left, word_value, .. } = self.keccak_table;
+        meta.lookup_any(name, |meta| {
+            let q_input = meta.query_fixed(q_input, Rotation::cur());
+            let [table_bytes_left, table_word_value] = table(meta);
+            vec![
+                (q_input.clone() * meta.query_advice(bytes_left, Rotation::cur()), table_bytes_left),
+                (q_input * meta.query_advice(word_value, Rotation::cur()), table_word_value),
+            ]
+        });
+    }
 }
 
 #[derive(Clone)]
@@ -806,11 +1011,29 @@ pub struct KeccakAssignedRow<'v, F: Field> {
     pub is_final: KeccakAssignedValue<'v, F>,
     pub hash_lo: KeccakAssignedValue<'v, F>,
     pub hash_hi: KeccakAssignedValue<'v, F>,
+    /// The `(hi, lo)` cells of the last 32 bytes of 512 bit digests, whose first 32 bytes are
+    /// in `hash_hi` and `hash_lo`.
+    pub hash_ext: Option<(KeccakAssignedValue<'v, F>, KeccakAssignedValue<'v, F>)>,
     pub bytes_left: KeccakAssignedValue<'v, F>,
     pub word_value: KeccakAssignedValue<'v, F>,
+    /// The accumulated input chunk with packed input binding, the input element or the low
+    /// bytes ending one with bytes31 input binding.
+    pub input_acc: Option<KeccakAssignedValue<'v, F>>,
     pub _marker: PhantomData<&'v ()>,
 }
 
+impl<'v, F: Field> KeccakAssignedRow<'v, F> {
+    /// The 128 bit cells of the digest, most significant first, as laid out in the `output`
+    /// instance column.
+    pub fn digest_cells(&self) -> Vec<&KeccakAssignedValue<'v, F>> {
+        let mut cells = vec![&self.hash_hi, &self.hash_lo];
+        if let Some((hi, lo)) = &self.hash_ext {
+            cells.extend([hi, lo]);
+        }
+        cells
+    }
+}
+
 impl<F: Field> KeccakCircuitConfig<F> {
     /// Returns vector of `is_final`, `length`, `hash.lo`, `hash.hi` for assigned rows
     pub fn assign<'v>(
@@ -825,6 +1048,13 @@ impl<F: Field> KeccakCircuitConfig<F> {
             .collect()
     }
 
+    /// Extends the region to `num_rows` rows by assigning a disabled selector on its last row.
+    /// The rows between the witness and the end stay zero, so the gates of the last keccak_f,
+    /// which query up to `NUM_WORDS_TO_ABSORB` rounds ahead, never see the next region.
+    pub fn reserve_rows(&self, region: &mut Region<F>, num_rows: usize) {
+        raw_assign_fixed(region, self.q_enable, num_rows - 1, F::ZERO);
+    }
+
     /// Output is `is_final`, `length`, `hash.lo`, `hash.hi` at that row
     pub fn set_row<'v>(
         &self,
@@ -855,6 +1085,29 @@ impl<F: Field> KeccakCircuitConfig<F> {
         ]
         .map(|(_name, column, value)| raw_assign_advice(region, column, offset, value));
 
+        let hash_ext = self.keccak_table.output_ext.map(|output| {
+            (
+                raw_assign_advice(region, output.hi(), offset, row.hash_ext.hi()),
+                raw_assign_advice(region, output.lo(), offset, row.hash_ext.lo()),
+            )
+        });
+        if let Some(q_input_chunk_last) = self.q_input_chunk_last {
+            raw_assign_fixed(region, q_input_chunk_last, offset, F::from(row.q_input_chunk_last));
+        }
+        let input_acc = self
+            .input_acc
+            .map(|input_acc| raw_assign_advice(region, input_acc, offset, Value::known(row.input_acc)));
+
+        // The bytes of `max_input_len - bytes_left`, wrapping around the field if the message is
+        // too long so that the gate fails
+        let remaining = match self.parameters.max_input_len.filter(|_| row.q_input) {
+            Some(max_input_len) => (F::from(max_input_len as u64) - row.bytes_left).to_repr(),
+            None => [0; 32],
+        };
+        for (column, byte) in self.input_len_bytes.iter().zip(remaining) {
+            raw_assign_advice(region, *column, offset, Value::known(F::from(byte as u64)));
+        }
+
         // Cell values
         row.cell_values.iter().zip(self.cell_manager.columns()).for_each(|(bit, column)| {
             raw_assign_advice(region, column.advice, offset, Value::known(*bit));
@@ -867,23 +1120,29 @@ impl<F: Field> KeccakCircuitConfig<F> {
             is_final,
             hash_lo,
             hash_hi,
+            hash_ext,
             bytes_left,
             word_value,
+            input_acc,
             _marker: PhantomData,
         }
     }
 
-    pub fn load_aux_tables(&self, layouter: &mut impl Layouter<F>, k: u32) -> Result<(), Error> {
-        load_normalize_table(layouter, "normalize_6", &self.normalize_6, 6u64, k)?;
-        load_normalize_table(layouter, "normalize_4", &self.normalize_4, 4u64, k)?;
-        load_normalize_table(layouter, "normalize_3", &self.normalize_3, 3u64, k)?;
-        load_lookup_table(
+    pub fn load_aux_tables(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
+        let k = self.parameters.lookup_k();
+        load_tables(
             layouter,
-            "chi base",
-            &self.chi_base_table,
-            get_num_bits_per_base_chi_lookup(k),
-            &CHI_BASE_LOOKUP_TABLE,
-        )?;
-        load_pack_table(layouter, &self.pack_table)
+            &[
+                ("normalize_6", self.normalize_6, normalize_table_entries(6, k)),
+                ("normalize_4", self.normalize_4, normalize_table_entries(4, k)),
+                ("normalize_3", self.normalize_3, normalize_table_entries(3, k)),
+                (
+                    "chi base",
+                    self.chi_base_table,
+                    lookup_table_entries(get_num_bits_per_base_chi_lookup(k), &CHI_BASE_LOOKUP_TABLE),
+                ),
+                ("pack", self.pack_table, pack_table_entries()),
+            ],
+        )
     }
 }
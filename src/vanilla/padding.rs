@@ -0,0 +1,101 @@
+//! Padding rules of the sponge, applied to the message bits before absorbing.
+//!
+//! The circuit constrains the padding of the config's [`Padding`](crate::config::Padding), so
+//! another rule yields a witness the gates reject until matching padding constraints exist. The
+//! rules share the absorb row generation of
+//! [`multi_keccak_with_padding`](super::witness::multi_keccak_with_padding), e.g. for cSHAKE or
+//! K12 variants, or for tests checking that wrong padding is rejected.
+
+/// A padding rule of the sponge.
+pub(crate) trait PaddingRule: Sync {
+    /// Pads the message bits, least significant bit of every byte first, to a multiple of
+    /// `rate_in_bits`, e.g. `RATE_IN_BITS`.
+    fn pad(&self, bits: &mut Vec<u8>, rate_in_bits: usize);
+}
+
+/// The multi-rate padding `pad10*1` starting with the bits of a domain byte, least significant
+/// first up to its highest set bit, which is the first bit of `pad10*1`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct DomainPadding(pub u8);
+
+/// Keccak's padding, without domain separation bits.
+pub(crate) const KECCAK_PADDING: DomainPadding = DomainPadding(0x01);
+/// SHA3's padding, after the `01` domain separation bits.
+pub(crate) const SHA3_PADDING: DomainPadding = DomainPadding(0x06);
+/// cSHAKE's padding, after the `00` domain separation bits.
+pub(crate) const CSHAKE_PADDING: DomainPadding = DomainPadding(0x04);
+
+impl DomainPadding {
+    /// The first `output_len` bytes squeezed by a sponge of `rate` bytes absorbing `input` with
+    /// this padding, natively, for the hashes without a type in `sha3`. At most one block is
+    /// squeezed.
+    pub(crate) fn digest(&self, input: &[u8], rate: usize, output_len: usize) -> Vec<u8> {
+        assert!(output_len <= rate, "only one block is squeezed");
+        let mut padded = input.to_vec();
+        padded.push(self.0);
+        padded.resize((padded.len() + rate - 1) / rate * rate, 0);
+        *padded.last_mut().unwrap() |= 0x80;
+
+        let mut state = [0u64; 25];
+        for block in padded.chunks(rate) {
+            for (lane, word) in state.iter_mut().zip(block.chunks(8)) {
+                *lane ^= u64::from_le_bytes(word.try_into().unwrap());
+            }
+            keccak::f1600(&mut state);
+        }
+        state.iter().flat_map(|lane| lane.to_le_bytes()).take(output_len).collect()
+    }
+}
+
+impl PaddingRule for DomainPadding {
+    fn pad(&self, bits: &mut Vec<u8>, rate_in_bits: usize) {
+        assert_ne!(self.0, 0, "the domain byte must contain the first padding bit");
+        let num_domain_bits = 8 - self.0.leading_zeros() as usize;
+        bits.extend((0..num_domain_bits).map(|i| (self.0 >> i) & 1));
+        while (bits.len() + 1) % rate_in_bits != 0 {
+            bits.push(0);
+        }
+        bits.push(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha3::{Digest, Keccak256, Sha3_256, Sha3_512};
+
+    use super::*;
+    use crate::vanilla::param::{NUM_BITS_PER_BYTE, RATE_IN_BITS};
+    use crate::vanilla::util::into_bits;
+
+    #[test]
+    fn test_domain_padding() {
+        let mut bits = into_bits(b"abc");
+        KECCAK_PADDING.pad(&mut bits, RATE_IN_BITS);
+        assert_eq!(bits.len(), RATE_IN_BITS);
+        assert_eq!(bits[24..26], [1, 0]);
+        assert_eq!(bits[RATE_IN_BITS - 1], 1);
+
+        let mut bits = into_bits(&[0u8; 135]);
+        SHA3_PADDING.pad(&mut bits, RATE_IN_BITS);
+        // The domain bits and the final bit share the last byte, 0x86
+        assert_eq!(bits.len(), RATE_IN_BITS);
+        assert_eq!(bits[135 * 8..], [0, 1, 1, 0, 0, 0, 0, 1]);
+
+        // SHA3-512 absorbs 72 bytes per keccak_f
+        let rate_in_bits = 72 * NUM_BITS_PER_BYTE;
+        let mut bits = into_bits(&[0u8; 72]);
+        SHA3_PADDING.pad(&mut bits, rate_in_bits);
+        assert_eq!(bits.len(), 2 * rate_in_bits);
+        assert_eq!(bits[72 * 8..72 * 8 + 3], [0, 1, 1]);
+        assert_eq!(bits[2 * rate_in_bits - 1], 1);
+    }
+
+    #[test]
+    fn test_native_digest() {
+        for input in [vec![], b"abc".to_vec(), vec![0u8; 135], vec![7u8; 136], vec![1u8; 300]] {
+            assert_eq!(KECCAK_PADDING.digest(&input, 136, 32), Keccak256::digest(&input).to_vec());
+            assert_eq!(SHA3_PADDING.digest(&input, 136, 32), Sha3_256::digest(&input).to_vec());
+            assert_eq!(SHA3_PADDING.digest(&input, 72, 64), Sha3_512::digest(&input).to_vec());
+        }
+    }
+}
@@ -1,3 +1,4 @@
+//! Constants of the keccak permutation and of the circuit layout.
 #![allow(dead_code)]
 pub(crate) const MAX_DEGREE: usize = 3;
 pub(crate) const ABSORB_LOOKUP_RANGE: usize = 3;
@@ -11,10 +12,19 @@ pub const NUM_BITS_PER_WORD: usize = NUM_BYTES_PER_WORD * NUM_BITS_PER_BYTE;
 pub const KECCAK_WIDTH: usize = 5 * 5;
 pub const KECCAK_WIDTH_IN_BITS: usize = KECCAK_WIDTH * NUM_BITS_PER_WORD;
 pub const NUM_ROUNDS: usize = 24;
+/// Number of words absorbed per keccak_f with 256 bit digests, the default.
 pub const NUM_WORDS_TO_ABSORB: usize = 17;
+/// Number of words absorbed per keccak_f with 512 bit digests, whose capacity is twice as large.
+pub const NUM_WORDS_TO_ABSORB_512: usize = 9;
+/// Number of words accumulated in a single instance cell with `InputBinding::Packed`.
+pub const NUM_WORDS_PER_INPUT_CHUNK: usize = 3;
+/// Number of message bytes in a single instance cell with `InputBinding::Bytes31`, the most
+/// that always fits below a 254 bit modulus.
+pub const NUM_BYTES_PER_INPUT_ELEMENT: usize = 31;
 pub const NUM_BYTES_TO_ABSORB: usize = NUM_WORDS_TO_ABSORB * NUM_BYTES_PER_WORD;
 pub const NUM_WORDS_TO_SQUEEZE: usize = 4;
 pub const NUM_BYTES_TO_SQUEEZE: usize = NUM_WORDS_TO_SQUEEZE * NUM_BYTES_PER_WORD;
+pub const NUM_WORDS_TO_SQUEEZE_512: usize = 8;
 pub const ABSORB_WIDTH_PER_ROW: usize = NUM_BITS_PER_WORD;
 pub const ABSORB_WIDTH_PER_ROW_BYTES: usize = ABSORB_WIDTH_PER_ROW / NUM_BITS_PER_BYTE;
 pub const RATE: usize = NUM_WORDS_TO_ABSORB * NUM_BYTES_PER_WORD;
@@ -27,7 +37,8 @@ pub(crate) const RHO_MATRIX: [[usize; 5]; 5] = [
     [28, 55, 25, 21, 56],
     [27, 20, 39, 8, 14],
 ];
-pub(crate) const ROUND_CST: [u64; NUM_ROUNDS + 1] = [
+/// The iota round constants of Keccak, followed by a zero for the absorb round.
+pub const ROUND_CST: [u64; NUM_ROUNDS + 1] = [
     0x0000000000000001,
     0x0000000000008082,
     0x800000000000808a,
@@ -1,126 +1,182 @@
-use super::{param::*, util::*};
+use super::param::*;
 use halo2_proofs::{
-        circuit::{Layouter, Value},
-        plonk::{Error, TableColumn},
+        circuit::{Layouter, Table, Value},
+        halo2curves::ff::PrimeField,
+        plonk::{ConstraintSystem, Error, Expression, TableColumn},
     };
 use crate::{
+    config::LookupLayout,
     util::eth_types::Field,
 };
-use itertools::Itertools;
 
 /// Returns how many bits we can process in a single lookup given the range of
 /// values the bit can have and the height of the circuit.
 pub fn get_num_bits_per_lookup(range: usize, k: u32) -> usize {
     let num_unusable_rows = 31;
     let mut num_bits = 1;
-    while range.pow(num_bits + 1) + num_unusable_rows <= 2usize.pow(k) {
+    // In u64, so that the part sizes, and with them the witness layout, don't depend on the
+    // width of usize
+    while (range as u64).pow(num_bits + 1) + num_unusable_rows <= 1u64 << k {
         num_bits += 1;
     }
     num_bits as usize
 }
 
-/// Loads a normalization table with the given parameters
-pub(crate) fn load_normalize_table<F: Field>(
-    layouter: &mut impl Layouter<F>,
-    name: &str,
-    tables: &[TableColumn; 2],
-    range: u64,
-    k: u32,
-) -> Result<(), Error> {
-    let part_size = get_num_bits_per_lookup(range as usize, k);
-    layouter.assign_table(
-        || format!("{name} table"),
-        |mut table| {
-            for (offset, perm) in
-                (0..part_size).map(|_| 0u64..range).multi_cartesian_product().enumerate()
-            {
-                let mut input = 0u64;
-                let mut output = 0u64;
-                let mut factor = 1u64;
-                for input_part in perm.iter() {
-                    input += input_part * factor;
-                    output += (input_part & 1) * factor;
-                    factor *= BIT_SIZE as u64;
-                }
-                table.assign_cell(
-                    || format!("{name} input"),
-                    tables[0],
-                    offset,
-                    || Value::known(F::from(input)),
-                )?;
-                table.assign_cell(
-                    || format!("{name} output"),
-                    tables[1],
-                    offset,
-                    || Value::known(F::from(output)),
-                )?;
+/// The `(input, output)` entries of a table over `part_size` parts, the part values being
+/// `0..range` and `f` mapping each to its output. Parts are packed with base `BIT_SIZE`, the first
+/// being the least significant, and entries come in the order of
+/// `(0..part_size).map(|_| 0..range).multi_cartesian_product()`, on which the fixed columns and so
+/// the keys depend.
+///
+/// The table is built a part at a time from the one over the previous parts, with a single
+/// precomputed power per part, instead of repacking every entry.
+pub(crate) fn table_entries(part_size: usize, range: u64, f: impl Fn(u64) -> u64) -> Vec<(u64, u64)> {
+    let part_outputs = (0..range).map(&f).collect::<Vec<_>>();
+    let mut entries = vec![(0u64, 0u64)];
+    let mut factor = 1u64;
+    for _ in 0..part_size {
+        entries = entries
+            .iter()
+            .flat_map(|(input, output)| {
+                part_outputs
+                    .iter()
+                    .enumerate()
+                    .map(move |(part, part_output)| {
+                        (input + part as u64 * factor, output + part_output * factor)
+                    })
+            })
+            .collect();
+        factor *= BIT_SIZE as u64;
+    }
+    entries
+}
+
+/// A two column `(input, output)` lookup table, in its own table columns or tagged within table
+/// columns shared with the other tables.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LookupTable {
+    pub(crate) columns: [TableColumn; 2],
+    /// The shared tag column and the tag of the table in it.
+    pub(crate) tag: Option<(TableColumn, u64)>,
+}
+
+impl LookupTable {
+    /// Allocates `N` tables with the given layout, the tags following the order of the tables.
+    pub(crate) fn configure<F: PrimeField, const N: usize>(
+        meta: &mut ConstraintSystem<F>,
+        layout: LookupLayout,
+    ) -> [Self; N] {
+        match layout {
+            LookupLayout::Split => array_init::array_init(|_| LookupTable {
+                columns: array_init::array_init(|_| meta.lookup_table_column()),
+                tag: None,
+            }),
+            LookupLayout::Tagged => {
+                let tag = meta.lookup_table_column();
+                let columns = array_init::array_init(|_| meta.lookup_table_column());
+                array_init::array_init(|idx| LookupTable { columns, tag: Some((tag, idx as u64)) })
             }
-            Ok(())
-        },
-    )
+        }
+    }
+
+    /// The same table with the input and output columns swapped.
+    pub(crate) fn reversed(self) -> Self {
+        LookupTable { columns: [self.columns[1], self.columns[0]], tag: self.tag }
+    }
+
+    /// The lookup of `input`, and of `output` if any, into the table.
+    pub(crate) fn lookup<F: PrimeField>(
+        &self,
+        input: Expression<F>,
+        output: Option<Expression<F>>,
+    ) -> Vec<(Expression<F>, TableColumn)> {
+        let tag = self.tag.map(|(column, tag)| (Expression::Constant(F::from(tag)), column));
+        let output = output.map(|output| (output, self.columns[1]));
+        tag.into_iter().chain([(input, self.columns[0])]).chain(output).collect()
+    }
 }
 
-/// Loads the byte packing table
-pub(crate) fn load_pack_table<F: Field>(
+/// The entries of a normalization table with the given parameters
+pub(crate) fn normalize_table_entries(range: u64, k: u32) -> Vec<(u64, u64)> {
+    let part_size = get_num_bits_per_lookup(range as usize, k);
+    table_entries(part_size, range, |part| part & 1)
+}
+
+/// The entries of the byte packing table, from bytes to their packed bits
+pub(crate) fn pack_table_entries() -> Vec<(u64, u64)> {
+    // Every bit becomes a part of BIT_COUNT bits, so a packed byte fits in a u64
+    let powers = (0..NUM_BITS_PER_BYTE).map(|i| (BIT_SIZE as u64).pow(i as u32)).collect::<Vec<_>>();
+    (0u64..256)
+        .map(|idx| {
+            let packed =
+                powers.iter().enumerate().map(|(i, power)| ((idx >> i) & 1) * power).sum::<u64>();
+            (idx, packed)
+        })
+        .collect()
+}
+
+/// The entries of a lookup table applying `lookup_table` to every part
+pub(crate) fn lookup_table_entries(part_size: usize, lookup_table: &[u8]) -> Vec<(u64, u64)> {
+    table_entries(part_size, lookup_table.len() as u64, |part| lookup_table[part as usize] as u64)
+}
+
+/// Loads the tables with their entries. The tables of the tagged layout share their columns, so
+/// they are loaded in a single table region, one after the other.
+pub(crate) fn load_tables<F: Field>(
     layouter: &mut impl Layouter<F>,
-    tables: &[TableColumn; 2],
+    tables: &[(&str, LookupTable, Vec<(u64, u64)>)],
 ) -> Result<(), Error> {
+    if tables.iter().all(|(_, table, _)| table.tag.is_none()) {
+        for (name, table, entries) in tables {
+            layouter.assign_table(
+                || format!("{name} table"),
+                |mut region| {
+                    for (offset, (input, output)) in entries.iter().enumerate() {
+                        assign_entry(&mut region, name, table, offset, *input, *output)?;
+                    }
+                    Ok(())
+                },
+            )?;
+        }
+        return Ok(());
+    }
     layouter.assign_table(
-        || "pack table",
-        |mut table| {
-            for (offset, idx) in (0u64..256).enumerate() {
-                table.assign_cell(
-                    || "unpacked",
-                    tables[0],
-                    offset,
-                    || Value::known(F::from(idx)),
-                )?;
-                let packed: F = pack(&into_bits(&[idx as u8]));
-                table.assign_cell(|| "packed", tables[1], offset, || Value::known(packed))?;
+        || "tagged table",
+        |mut region| {
+            let mut offset = 0;
+            for (name, table, entries) in tables {
+                for (input, output) in entries {
+                    assign_entry(&mut region, name, table, offset, *input, *output)?;
+                    offset += 1;
+                }
             }
             Ok(())
         },
     )
 }
 
-/// Loads a lookup table
-pub(crate) fn load_lookup_table<F: Field>(
-    layouter: &mut impl Layouter<F>,
+fn assign_entry<F: Field>(
+    region: &mut Table<'_, F>,
     name: &str,
-    tables: &[TableColumn; 2],
-    part_size: usize,
-    lookup_table: &[u8],
+    table: &LookupTable,
+    offset: usize,
+    input: u64,
+    output: u64,
 ) -> Result<(), Error> {
-    layouter.assign_table(
-        || format!("{name} table"),
-        |mut table| {
-            for (offset, perm) in (0..part_size)
-                .map(|_| 0..lookup_table.len() as u64)
-                .multi_cartesian_product()
-                .enumerate()
-            {
-                let mut input = 0u64;
-                let mut output = 0u64;
-                let mut factor = 1u64;
-                for input_part in perm.iter() {
-                    input += input_part * factor;
-                    output += (lookup_table[*input_part as usize] as u64) * factor;
-                    factor *= BIT_SIZE as u64;
-                }
-                table.assign_cell(
-                    || format!("{name} input"),
-                    tables[0],
-                    offset,
-                    || Value::known(F::from(input)),
-                )?;
-                table.assign_cell(
-                    || format!("{name} output"),
-                    tables[1],
-                    offset,
-                    || Value::known(F::from(output)),
-                )?;
-            }
-            Ok(())
-        },
-    )
+    if let Some((column, tag)) = table.tag {
+        region.assign_cell(|| format!("{name} tag"), column, offset, || Value::known(F::from(tag)))?;
+    }
+    region.assign_cell(
+        || format!("{name} input"),
+        table.columns[0],
+        offset,
+        || Value::known(F::from(input)),
+    )?;
+    region.assign_cell(
+        || format!("{name} output"),
+        table.columns[1],
+        offset,
+        || Value::known(F::from(output)),
+    )?;
+    Ok(())
 }
@@ -10,12 +10,12 @@ pub fn rotate<T>(parts: Vec<T>, count: usize, part_size: usize) -> Vec<T> {
     rotated_parts
 }
 
-/// The words that absorb data
-pub fn get_absorb_positions() -> Vec<(usize, usize)> {
+/// The state positions of the first `num_words` words, which absorb data
+pub fn get_absorb_positions(num_words: usize) -> Vec<(usize, usize)> {
     let mut absorb_positions = Vec::new();
     for j in 0..5 {
         for i in 0..5 {
-            if i + j * 5 < 17 {
+            if i + j * 5 < num_words {
                 absorb_positions.push((i, j));
             }
         }
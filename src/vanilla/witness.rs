@@ -1,12 +1,52 @@
 // This file is moved out from mod.rs.
 use super::*;
+use super::padding::PaddingRule;
 
-/// Witness generation for multiple keccak hashes of little-endian `bytes`.
-pub fn multi_keccak<F: Field>(
-    bytes: &[Vec<u8>],
+/// Witness generation for multiple keccak hashes of little-endian `bytes`, padded with the
+/// config's [`Padding`](crate::config::Padding).
+pub fn multi_keccak<F: Field, B: AsRef<[u8]> + Sync>(
+    bytes: &[B],
     capacity: Option<usize>,
     parameters: KeccakConfigParams,
-) -> (Vec<KeccakRow<F>>, Vec<[F; NUM_WORDS_TO_SQUEEZE]>) {
+) -> (Vec<KeccakRow<F>>, Vec<Vec<F>>) {
+    multi_keccak_with_padding(bytes, capacity, parameters, &parameters.padding.rule())
+}
+
+/// The dense state after every round of a keccak_f, `NUM_ROUNDS` of them, lane `(x, y)` at
+/// index `x + 5 * y` as in FIPS 202.
+pub type RoundStates = Vec<[u64; KECCAK_WIDTH]>;
+
+/// Witness generation as [`multi_keccak`], also returning the [`RoundStates`] of every keccak_f
+/// of every input, in order, for cross-checking against reference implementations. The padding
+/// hashes up to `capacity` are left out.
+pub fn multi_keccak_with_states<F: Field, B: AsRef<[u8]> + Sync>(
+    bytes: &[B],
+    capacity: Option<usize>,
+    parameters: KeccakConfigParams,
+) -> (Vec<KeccakRow<F>>, Vec<Vec<F>>, Vec<RoundStates>) {
+    multi_keccak_impl(bytes, capacity, parameters, &parameters.padding.rule(), true)
+}
+
+/// Witness generation as [`multi_keccak`], padding the messages with `padding`. The padding
+/// hashes filling up to `capacity` are padded with the config's padding, like the circuit
+/// constrains its unused slots.
+pub(crate) fn multi_keccak_with_padding<F: Field, B: AsRef<[u8]> + Sync>(
+    bytes: &[B],
+    capacity: Option<usize>,
+    parameters: KeccakConfigParams,
+    padding: &impl PaddingRule,
+) -> (Vec<KeccakRow<F>>, Vec<Vec<F>>) {
+    let (rows, squeeze_digests, _) = multi_keccak_impl(bytes, capacity, parameters, padding, false);
+    (rows, squeeze_digests)
+}
+
+fn multi_keccak_impl<F: Field, B: AsRef<[u8]> + Sync>(
+    bytes: &[B],
+    capacity: Option<usize>,
+    parameters: KeccakConfigParams,
+    padding: &impl PaddingRule,
+    with_states: bool,
+) -> (Vec<KeccakRow<F>>, Vec<Vec<F>>, Vec<RoundStates>) {
     let num_rows_per_round = parameters.rows_per_round;
     let mut rows =
         Vec::with_capacity((1 + capacity.unwrap_or(0) * (NUM_ROUNDS + 1)) * num_rows_per_round);
@@ -17,66 +57,85 @@ pub fn multi_keccak<F: Field>(
     let artifacts = bytes
         .par_iter()
         .map(|bytes| {
-            let num_keccak_f = get_num_keccak_f(bytes.len());
+            let bytes = bytes.as_ref();
+            let num_keccak_f = parameters.num_keccak_f(bytes.len());
             let mut squeeze_digests = Vec::with_capacity(num_keccak_f);
             let mut rows = Vec::with_capacity(num_keccak_f * (NUM_ROUNDS + 1) * num_rows_per_round);
-            keccak(&mut rows, &mut squeeze_digests, bytes, parameters);
-            (rows, squeeze_digests)
+            let mut states = Vec::with_capacity(if with_states { num_keccak_f } else { 0 });
+            keccak(&mut rows, &mut squeeze_digests, bytes, parameters, padding, with_states.then_some(&mut states));
+            (rows, squeeze_digests, states)
         })
         .collect::<Vec<_>>();
 
     let mut squeeze_digests = Vec::with_capacity(capacity.unwrap_or(0));
-    for (rows_part, squeezes) in artifacts {
+    let mut round_states = vec![];
+    for (rows_part, squeezes, states) in artifacts {
         rows.extend(rows_part);
         squeeze_digests.extend(squeezes);
+        round_states.extend(states);
     }
 
     if let Some(capacity) = capacity {
-        // Pad with no data hashes to the expected capacity
-        while rows.len() < (1 + capacity * (NUM_ROUNDS + 1)) * num_rows_per_round {
-            keccak(&mut rows, &mut squeeze_digests, &[], parameters);
+        // Pad with no data hashes to the expected capacity, all with the same rows
+        let num_rows = (1 + capacity * (NUM_ROUNDS + 1)) * num_rows_per_round;
+        if rows.len() < num_rows {
+            let mut padding_rows = Vec::with_capacity((NUM_ROUNDS + 1) * num_rows_per_round);
+            let mut padding_digests = Vec::with_capacity(1);
+            keccak(&mut padding_rows, &mut padding_digests, &[], parameters, &parameters.padding.rule(), None);
+            while rows.len() < num_rows {
+                rows.extend_from_slice(&padding_rows);
+                squeeze_digests.extend_from_slice(&padding_digests);
+            }
         }
         // Check that we are not over capacity
-        if rows.len() > (1 + capacity * (NUM_ROUNDS + 1)) * num_rows_per_round {
+        if rows.len() > num_rows {
             panic!("{:?}", Error::BoundsFailure);
         }
     }
-    (rows, squeeze_digests)
+    (rows, squeeze_digests, round_states)
+}
+
+/// The lanes of a state of normalized packed words.
+fn dense_state<F: Field>(s: &[[F; 5]; 5]) -> [u64; KECCAK_WIDTH] {
+    array_init::array_init(|idx| {
+        unpack(s[idx % 5][idx / 5]).iter().rev().fold(0u64, |lane, bit| lane << 1 | *bit as u64)
+    })
 }
 /// Witness generation for keccak hash of little-endian `bytes`.
 fn keccak<F: Field>(
     rows: &mut Vec<KeccakRow<F>>,
-    squeeze_digests: &mut Vec<[F; NUM_WORDS_TO_SQUEEZE]>,
+    squeeze_digests: &mut Vec<Vec<F>>,
     bytes: &[u8],
     parameters: KeccakConfigParams,
+    padding: &impl PaddingRule,
+    mut states: Option<&mut Vec<RoundStates>>,
 ) {
-    let k = parameters.k;
+    let k = parameters.lookup_k();
     let num_rows_per_round = parameters.rows_per_round;
+    let round_cst_table = parameters.round_cst_table();
+    let words_to_absorb = parameters.digest_size.words_to_absorb();
+    let rate = parameters.digest_size.rate();
 
     let mut bits = into_bits(bytes);
     let mut s = [[F::ZERO; 5]; 5];
-    let absorb_positions = get_absorb_positions();
-    let num_bytes_in_last_block = bytes.len() % RATE;
+    let absorb_positions = get_absorb_positions(words_to_absorb);
+    let num_bytes_in_last_block = bytes.len() % rate;
     let two = F::from(2u64);
 
-    // Padding
-    bits.push(1);
-    while (bits.len() + 1) % RATE_IN_BITS != 0 {
-        bits.push(0);
-    }
-    bits.push(1);
+    padding.pad(&mut bits, rate * NUM_BITS_PER_BYTE);
 
     // running length of absorbed input in bytes
     let mut length = 0;
-    let chunks = bits.chunks(RATE_IN_BITS);
+    let chunks = bits.chunks(rate * NUM_BITS_PER_BYTE);
     let num_chunks = chunks.len();
 
     let mut cell_managers = Vec::with_capacity(NUM_ROUNDS + 1);
     let mut regions = Vec::with_capacity(NUM_ROUNDS + 1);
     // keeps track of running lengths over all rounds in an absorb step
     let mut round_lengths = Vec::with_capacity(NUM_ROUNDS + 1);
-    let mut hash_words = [F::ZERO; NUM_WORDS_TO_SQUEEZE];
+    let mut hash_words = vec![F::ZERO; parameters.digest_size.words_to_squeeze()];
     let mut hash = Word::default();
+    let mut hash_ext = Word::default();
 
     for (idx, chunk) in chunks.enumerate() {
         let is_final_block = idx == num_chunks - 1;
@@ -94,13 +153,14 @@ fn keccak<F: Field>(
         cell_managers.clear();
         regions.clear();
         round_lengths.clear();
+        let mut round_states = Vec::with_capacity(if states.is_some() { NUM_ROUNDS } else { 0 });
 
         for round in 0..NUM_ROUNDS + 1 {
             let mut cell_manager = CellManager::new(num_rows_per_round);
             let mut region = KeccakRegion::new();
 
             let mut absorb_row = AbsorbData::default();
-            if round < NUM_WORDS_TO_ABSORB {
+            if round < words_to_absorb {
                 absorb_row = absorb_rows[round].clone();
             }
 
@@ -130,7 +190,7 @@ fn keccak<F: Field>(
             let _absorb_result = transform::value(
                 &mut cell_manager,
                 &mut region,
-                absorb_fat.clone(),
+                absorb_fat,
                 true,
                 |v| v & 1,
                 true,
@@ -149,7 +209,7 @@ fn keccak<F: Field>(
             let is_paddings =
                 input_bytes.iter().map(|_| cell_manager.query_cell_value()).collect::<Vec<_>>();
             debug_assert_eq!(is_paddings.len(), NUM_BYTES_PER_WORD);
-            if round < NUM_WORDS_TO_ABSORB {
+            if round < words_to_absorb {
                 for (padding_idx, is_padding) in is_paddings.iter().enumerate() {
                     let byte_idx = round * NUM_BYTES_PER_WORD + padding_idx;
                     let padding = if is_final_block && byte_idx >= num_bytes_in_last_block {
@@ -179,7 +239,7 @@ fn keccak<F: Field>(
                     let bc_norm = transform::value(
                         &mut cell_manager,
                         &mut region,
-                        bc_fat.clone(),
+                        bc_fat,
                         true,
                         |v| v & 1,
                         true,
@@ -238,11 +298,11 @@ fn keccak<F: Field>(
                         let s_parts = transform_to::value(
                             &rho_pi_chi_cells[1][j][(2 * i + 3 * j) % 5],
                             &mut region,
-                            s_parts.clone(),
+                            s_parts,
                             true,
                             |v| v & 1,
                         );
-                        os_part[(2 * i + 3 * j) % 5] = s_parts.clone();
+                        os_part[(2 * i + 3 * j) % 5] = s_parts;
                     }
                 }
                 cell_manager.start_region();
@@ -270,7 +330,7 @@ fn keccak<F: Field>(
                         os[i][j] = decode::value(transform_to::value(
                             &rho_pi_chi_cells[2][i][j],
                             &mut region,
-                            s_parts.clone(),
+                            s_parts,
                             true,
                             |v| CHI_BASE_LOOKUP_TABLE[*v as usize],
                         ));
@@ -281,7 +341,7 @@ fn keccak<F: Field>(
 
                 // iota
                 let part_size = get_num_bits_per_absorb_lookup(k);
-                let input = s[0][0] + pack_u64::<F>(ROUND_CST[round]);
+                let input = s[0][0] + pack_u64::<F>(round_cst_table[round]);
                 let iota_parts = split::value::<F>(
                     &mut cell_manager,
                     &mut region,
@@ -295,43 +355,51 @@ fn keccak<F: Field>(
                 s[0][0] = decode::value(transform::value(
                     &mut cell_manager,
                     &mut region,
-                    iota_parts.clone(),
+                    iota_parts,
                     true,
                     |v| v & 1,
                     true,
                 ));
+                if states.is_some() {
+                    round_states.push(dense_state(&s));
+                }
             }
 
-            // Assign the hash result
-            let is_final = is_final_block && round == NUM_ROUNDS;
-            hash = if is_final {
-                let hash_bytes_le = s
-                    .into_iter()
-                    .take(4)
-                    .flat_map(|a| to_bytes::value(&unpack(a[0])))
-                    .rev()
-                    .collect::<Vec<_>>();
-
-                let word: Word<Value<F>> =
-                    Word::from(eth_types::Word::from_little_endian(hash_bytes_le.as_slice()))
-                        .map(Value::known);
-                word
-            } else {
-                Word::default().into_value()
-            };
-
             // The words to squeeze out: this is the hash digest as words with
-            // NUM_BYTES_PER_WORD (=8) bytes each
-            for (hash_word, a) in hash_words.iter_mut().zip(s.iter()) {
-                *hash_word = a[0];
+            // NUM_BYTES_PER_WORD (=8) bytes each, in lane order
+            for (idx, hash_word) in hash_words.iter_mut().enumerate() {
+                *hash_word = s[idx % 5][idx / 5];
             }
 
+            // Assign the hash result, a word per 32 bytes of the digest
+            let is_final = is_final_block && round == NUM_ROUNDS;
+            let mut digest_words = hash_words
+                .chunks(NUM_WORDS_TO_SQUEEZE)
+                .filter(|_| is_final)
+                .map(|words| {
+                    let hash_bytes_le = words
+                        .iter()
+                        .flat_map(|word| to_bytes::value(&unpack(*word)))
+                        .rev()
+                        .collect::<Vec<_>>();
+                    let word: Word<Value<F>> =
+                        Word::from(eth_types::Word::from_little_endian(hash_bytes_le.as_slice()))
+                            .map(Value::known);
+                    word
+                });
+            hash = digest_words.next().unwrap_or_else(|| Word::default().into_value());
+            hash_ext = digest_words.next().unwrap_or_else(|| Word::default().into_value());
+
             round_lengths.push(length);
 
             cell_managers.push(cell_manager);
             regions.push(region);
         }
 
+        if let Some(states) = states.as_deref_mut() {
+            states.push(round_states);
+        }
+
         // Now that we know the state at the end of the rounds, set the squeeze data
         let num_rounds = cell_managers.len();
         for (idx, word) in hash_words.iter().enumerate() {
@@ -347,47 +415,69 @@ fn keccak<F: Field>(
             cell_manager.start_region();
             transform::value(cell_manager, region, packed, false, |v| *v, true);
         }
-        squeeze_digests.push(hash_words);
+        squeeze_digests.push(hash_words.clone());
 
+        let words = (0..words_to_absorb)
+            .map(|round| {
+                let byte_idx = (idx * words_to_absorb + round) * NUM_BYTES_PER_WORD;
+                if byte_idx >= bytes.len() {
+                    0
+                } else {
+                    let end = std::cmp::min(byte_idx + NUM_BYTES_PER_WORD, bytes.len());
+                    let mut word_bytes = bytes[byte_idx..end].to_vec();
+                    word_bytes.resize(NUM_BYTES_PER_WORD, 0);
+                    u64::from_le_bytes(word_bytes.try_into().unwrap())
+                }
+            })
+            .collect::<Vec<_>>();
+        let input_elements = (parameters.input_binding == InputBinding::Bytes31).then(|| {
+            let block = words.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>();
+            input_element_column::<F>(&block)
+        });
         for round in 0..NUM_ROUNDS + 1 {
-            let round_cst = pack_u64(ROUND_CST[round]);
+            let round_cst = pack_u64(round_cst_table[round]);
 
             for row_idx in 0..num_rows_per_round {
-                let word_value = if round < NUM_WORDS_TO_ABSORB && row_idx == 0 {
-                    let byte_idx = (idx * NUM_WORDS_TO_ABSORB + round) * NUM_BYTES_PER_WORD;
-                    if byte_idx >= bytes.len() {
-                        0
-                    } else {
-                        let end = std::cmp::min(byte_idx + NUM_BYTES_PER_WORD, bytes.len());
-                        let mut word_bytes = bytes[byte_idx..end].to_vec().clone();
-                        word_bytes.resize(NUM_BYTES_PER_WORD, 0);
-                        u64::from_le_bytes(word_bytes.try_into().unwrap())
-                    }
-                } else {
-                    0
+                let is_input = round < words_to_absorb && row_idx == 0;
+                let word_value = if is_input { words[round] } else { 0 };
+                let chunk_end = ((round / NUM_WORDS_PER_INPUT_CHUNK + 1) * NUM_WORDS_PER_INPUT_CHUNK)
+                    .min(words_to_absorb);
+                let input_acc = match (is_input, &input_elements) {
+                    (false, _) => F::ZERO,
+                    (true, Some(input_elements)) => input_elements[round],
+                    (true, None) => words[round..chunk_end]
+                        .iter()
+                        .rev()
+                        .fold(F::ZERO, |acc, word| {
+                            acc * F::from_u128(1 << NUM_BITS_PER_WORD) + F::from(*word)
+                        }),
                 };
-                let byte_idx = if round < NUM_WORDS_TO_ABSORB {
+                let byte_idx = if round < words_to_absorb {
                     round * NUM_BYTES_PER_WORD + std::cmp::min(row_idx, NUM_BYTES_PER_WORD - 1)
                 } else {
-                    NUM_WORDS_TO_ABSORB * NUM_BYTES_PER_WORD
-                } + idx * NUM_WORDS_TO_ABSORB * NUM_BYTES_PER_WORD;
+                    rate
+                } + idx * rate;
                 let bytes_left = if byte_idx >= bytes.len() { 0 } else { bytes.len() - byte_idx };
                 rows.push(KeccakRow {
                     q_enable: row_idx == 0,
                     q_round: row_idx == 0 && round < NUM_ROUNDS,
                     q_absorb: row_idx == 0 && round == NUM_ROUNDS,
                     q_round_last: row_idx == 0 && round == NUM_ROUNDS,
-                    q_input: row_idx == 0 && round < NUM_WORDS_TO_ABSORB,
-                    q_input_last: row_idx == 0 && round == NUM_WORDS_TO_ABSORB - 1,
+                    q_input: row_idx == 0 && round < words_to_absorb,
+                    q_input_last: row_idx == 0 && round == words_to_absorb - 1,
+                    q_input_chunk_last: is_input && round + 1 == chunk_end,
                     round_cst,
                     is_final: is_final_block && round == NUM_ROUNDS && row_idx == 0,
-                    cell_values: regions[round].rows.get(row_idx).unwrap_or(&vec![]).clone(),
+                    // Moved out, every row of a region is read once
+                    cell_values: regions[round].rows.get_mut(row_idx).map(std::mem::take).unwrap_or_default(),
                     hash,
+                    hash_ext,
                     bytes_left: F::from_u128(bytes_left as u128),
                     word_value: F::from_u128(word_value as u128),
+                    input_acc,
                 });
                 #[cfg(debug_assertions)]
-                {
+                if log::log_enabled!(log::Level::Trace) {
                     let mut r = rows.last().unwrap().clone();
                     r.cell_values.clear();
                     log::trace!("offset {:?} row idx {} row {:?}", rows.len() - 1, row_idx, r);
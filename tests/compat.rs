@@ -0,0 +1,135 @@
+//! Proofs of earlier releases, verified against the current circuit, so that a change to the
+//! constraint system or the transcript that would break deployed verifiers fails the tests.
+//!
+//! Every directory `tests/fixtures/compat/<version>/<name>/` holds a `config.toml`, the
+//! verifying key `vk`, a `proof` and its `instances.json`. The SRS isn't stored: it is set up
+//! from `SRS_SEED`, with a portable RNG so every version derives the same one. Fixtures of the
+//! current version are written before a release with
+//!
+//! ```bash
+//! cargo test --release --test compat -- --ignored
+//! ```
+//!
+//! and committed. A change breaking them on purpose removes them in the same commit, which
+//! makes the break visible in review.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use halo2_keccak_256::io::read_vk;
+use halo2_keccak_256::{
+    prove_keccak, verify_keccak, InstanceMode, KeccakCircuit, KeccakConfigParams, TranscriptKind,
+};
+use halo2_proofs::halo2curves::bn256::{Bn256, Fr};
+use halo2_proofs::halo2curves::ff::PrimeField;
+use halo2_proofs::plonk::{keygen_pk, keygen_vk};
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use halo2_proofs::SerdeFormat::RawBytes;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
+const SRS_SEED: u64 = 0x6b656363616b;
+
+/// The configs fixtures are written for: the defaults at a small `k`, and the EVM facing
+/// setup exposing the digests with a keccak transcript. Both lay out the instance by keccak_f
+/// slot, so the keys generated without inputs bind the inputs of the proofs.
+fn fixture_configs() -> Vec<(&'static str, KeccakConfigParams)> {
+    let base = KeccakConfigParams { k: 12, rows_per_round: 5, ..Default::default() };
+    vec![
+        ("default", base),
+        (
+            "evm",
+            KeccakConfigParams {
+                instance_mode: InstanceMode::InputOutput,
+                transcript: TranscriptKind::Keccak256,
+                fixed_instance: true,
+                ..base
+            },
+        ),
+    ]
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/compat")
+}
+
+fn srs(k: u32) -> ParamsKZG<Bn256> {
+    ParamsKZG::setup(k, XorShiftRng::seed_from_u64(SRS_SEED))
+}
+
+fn encode_instances(instances: &[Vec<Fr>]) -> String {
+    let columns = instances
+        .iter()
+        .map(|column| column.iter().map(|value| hex::encode(value.to_repr())).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    serde_json::to_string_pretty(&columns).unwrap()
+}
+
+fn decode_instances(json: &str) -> Vec<Vec<Fr>> {
+    let columns: Vec<Vec<String>> = serde_json::from_str(json).unwrap();
+    columns
+        .iter()
+        .map(|column| {
+            column
+                .iter()
+                .map(|value| {
+                    let repr = <[u8; 32]>::try_from(hex::decode(value).unwrap()).unwrap();
+                    Option::from(Fr::from_repr(repr)).expect("non canonical instance value")
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn compat_fixtures_verify() {
+    let versions = fs::read_dir(fixtures_dir()).unwrap_or_else(|e| {
+        panic!("can't read the fixtures at {}: {e}", fixtures_dir().display())
+    });
+    let mut num_fixtures = 0;
+    for fixture in versions.flat_map(|version| fs::read_dir(version.unwrap().path()).unwrap()) {
+        num_fixtures += 1;
+        let dir = fixture.unwrap().path();
+        let config = KeccakConfigParams::from_toml_str(&fs::read_to_string(dir.join("config.toml")).unwrap())
+            .unwrap_or_else(|e| panic!("{}: {e}", dir.display()));
+        let vk = read_vk::<KeccakCircuit<Fr>>(&dir.join("vk"), config);
+        let proof = fs::read(dir.join("proof")).unwrap();
+        let instances = decode_instances(&fs::read_to_string(dir.join("instances.json")).unwrap());
+
+        // The key must also still be the one the current circuit generates
+        let circuit = KeccakCircuit::builder().config(config).capacity_rows(2usize.pow(config.k)).build().unwrap();
+        let srs = srs(config.k);
+        let current_vk = keygen_vk(&srs, &circuit).unwrap();
+        assert_eq!(
+            current_vk.to_bytes(RawBytes),
+            vk.to_bytes(RawBytes),
+            "{}: the verifying key changed",
+            dir.display()
+        );
+        if let Err(e) = verify_keccak(&proof, &instances, &srs, &vk, config) {
+            panic!("{}: the proof no longer verifies: {e}", dir.display());
+        }
+    }
+    assert!(num_fixtures > 0, "no fixtures in {}, write them with `--ignored`", fixtures_dir().display());
+}
+
+#[test]
+#[ignore = "writes the fixtures of the current version, run before a release"]
+fn write_compat_fixtures() {
+    let inputs = vec![b"".to_vec(), b"abc".to_vec(), (0u8..=200).collect()];
+    for (name, config) in fixture_configs() {
+        let dir = fixtures_dir().join(env!("CARGO_PKG_VERSION")).join(name);
+        fs::create_dir_all(&dir).unwrap();
+
+        let circuit = KeccakCircuit::builder().config(config).capacity_rows(2usize.pow(config.k)).build().unwrap();
+        let srs = srs(config.k);
+        let vk = keygen_vk(&srs, &circuit).unwrap();
+        let pk = keygen_pk(&srs, vk, &circuit).unwrap();
+        let (instances, proof) = prove_keccak(inputs.clone(), &srs, &pk, config).unwrap();
+
+        fs::write(dir.join("config.toml"), toml::to_string(&config).unwrap()).unwrap();
+        fs::write(dir.join("vk"), pk.get_vk().to_bytes(RawBytes)).unwrap();
+        fs::write(dir.join("proof"), proof).unwrap();
+        fs::write(dir.join("instances.json"), encode_instances(&instances)).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+//! Fingerprints of the constraint system of a few configs, pinned in
+//! `tests/fixtures/constraint_system.txt`, so that any change to the columns, gates or lookups
+//! shows up as a diff of that file in review instead of slipping in with a refactor.
+//!
+//! A change to the constraints on purpose rewrites the file with
+//!
+//! ```bash
+//! cargo test --test constraint_system -- --ignored
+//! ```
+//!
+//! and commits it along with the change.
+
+use std::fs;
+use std::path::PathBuf;
+
+use halo2_keccak_256::encoding::{digest_from_hex, to_hex};
+use halo2_keccak_256::{InputBinding, InstanceMode, KeccakConfigParams, LookupLayout, DEFAULT_CONFIG};
+
+/// The configs whose constraint systems are pinned: the defaults, the EVM facing setup, and
+/// the input bindings and lookup layout that add columns and gates.
+fn presets() -> Vec<(&'static str, KeccakConfigParams)> {
+    let small = KeccakConfigParams { k: 12, rows_per_round: 5, ..DEFAULT_CONFIG };
+    vec![
+        ("default", DEFAULT_CONFIG),
+        ("evm", KeccakConfigParams { instance_mode: InstanceMode::InputOutput, ..small }),
+        ("packed", KeccakConfigParams { input_binding: InputBinding::Packed, ..small }),
+        ("bytes31", KeccakConfigParams { input_binding: InputBinding::Bytes31, ..small }),
+        ("tagged_lookups", KeccakConfigParams { lookup_layout: LookupLayout::Tagged, ..small }),
+    ]
+}
+
+fn fixture_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/constraint_system.txt")
+}
+
+#[test]
+fn constraint_systems_match_fingerprints() {
+    let pinned = fs::read_to_string(fixture_path()).unwrap_or_else(|e| {
+        panic!(
+            "can't read the pinned fingerprints at {}: {e}; write them with `--ignored` and commit the file",
+            fixture_path().display()
+        )
+    });
+    let pinned = pinned
+        .lines()
+        .map(|line| {
+            let (name, hex) = line.split_once(' ').expect("lines are `<preset> <fingerprint>`");
+            (name.to_string(), digest_from_hex(hex).unwrap())
+        })
+        .collect::<Vec<_>>();
+    for (name, config) in presets() {
+        let expected = pinned
+            .iter()
+            .find(|(pinned_name, _)| pinned_name == name)
+            .unwrap_or_else(|| panic!("no fingerprint pinned for `{name}`"))
+            .1;
+        assert_eq!(
+            to_hex(&config.constraint_system_fingerprint()),
+            to_hex(&expected),
+            "the constraint system of `{name}` changed, rewrite the fingerprints if that is on purpose"
+        );
+    }
+}
+
+#[test]
+#[ignore = "rewrites the pinned fingerprints, run when changing the constraints on purpose"]
+fn write_constraint_system_fingerprints() {
+    let lines = presets()
+        .into_iter()
+        .map(|(name, config)| format!("{} {}\n", name, to_hex(&config.constraint_system_fingerprint())))
+        .collect::<String>();
+    fs::write(fixture_path(), lines).unwrap();
+}
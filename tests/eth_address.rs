@@ -0,0 +1,97 @@
+//! Proving that an Ethereum address is derived from a public key the verifier never sees, end
+//! to end: keygen, proving, verification, and the calldata of the EVM verifier.
+//!
+//! The address is the last 20 bytes of the keccak256 of the uncompressed public key without
+//! its `0x04` tag. The circuit hashes the 64 byte key in [`InstanceMode::Output`] with the
+//! fixed instance layout, so the instance only holds `(hi, lo)` digest cells, one pair per
+//! keccak_f slot, and the verifier checks the address against the tail of the first digest. Integrators start from this test: the parts worth changing
+//! are the config and where the address comes from.
+
+use ethers_signers::{LocalWallet, Signer};
+use halo2_keccak_256::proof_system::{KeccakTranscriptWrite, KzgShplonk, ProofSystem};
+use halo2_keccak_256::solidity::evm_calldata;
+use halo2_keccak_256::{
+    digest_from_instance, verify_keccak, InstanceMode, KeccakCircuit, KeccakConfigParams, KeccakProver,
+    TranscriptKind, DEFAULT_CONFIG, DIGEST_INSTANCE_STRIDE,
+};
+use halo2_proofs::halo2curves::bn256::{Bn256, Fr};
+use halo2_proofs::halo2curves::ff::Field;
+use halo2_proofs::plonk::{keygen_pk, keygen_vk};
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
+const PRIVATE_KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+const OTHER_PRIVATE_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+/// Hides the key and exposes its digest, with the keccak256 transcript the EVM verifier reads.
+const CONFIG: KeccakConfigParams = KeccakConfigParams {
+    k: 12,
+    rows_per_round: 5,
+    instance_mode: InstanceMode::Output,
+    transcript: TranscriptKind::Keccak256,
+    fixed_instance: true,
+    ..DEFAULT_CONFIG
+};
+
+/// The uncompressed public key of `wallet`, without its `0x04` tag.
+fn public_key(wallet: &LocalWallet) -> Vec<u8> {
+    let point = wallet.signer().verifying_key().to_encoded_point(false);
+    point.as_bytes()[1..].to_vec()
+}
+
+/// The address a proof is for: the last 20 bytes of the digest of the first keccak_f slot,
+/// which the 64 byte key fits in.
+fn address_from_instance(instances: &[Vec<Fr>]) -> Option<[u8; 20]> {
+    let digest = digest_from_instance(instances.last()?, 0)?;
+    digest[12..].try_into().ok()
+}
+
+#[test]
+fn test_prove_address_of_private_public_key() {
+    let wallet = PRIVATE_KEY.parse::<LocalWallet>().unwrap();
+    let address: [u8; 20] = wallet.address().into();
+
+    // Keygen, once per config
+    let srs = ParamsKZG::<Bn256>::setup(CONFIG.k, XorShiftRng::seed_from_u64(0));
+    let builder = KeccakCircuit::builder().config(CONFIG).capacity_rows(2usize.pow(CONFIG.k));
+    let circuit = builder.clone().build().unwrap();
+    let vk = keygen_vk(&srs, &circuit).unwrap();
+    let pk = keygen_pk(&srs, vk.clone(), &circuit).unwrap();
+
+    // Proving, by the holder of the public key
+    let prover = KeccakProver::new(srs.clone(), pk.clone(), CONFIG);
+    let (instances, proof) = prover.prove(vec![public_key(&wallet)]).unwrap();
+
+    // The instance is digests alone, as many as the keys were generated with, the first one
+    // ending with the address
+    let (output, inputs) = instances.split_last().unwrap();
+    assert!(inputs.iter().all(Vec::is_empty));
+    assert_eq!(output.len(), circuit.instances().last().unwrap().len());
+    assert_eq!(output.len() % DIGEST_INSTANCE_STRIDE, 0);
+    assert_eq!(address_from_instance(&instances), Some(address));
+
+    // Verification, by anyone knowing the address
+    verify_keccak(&proof, &instances, &srs, &vk, CONFIG).unwrap();
+    let mut other = instances.clone();
+    *other.last_mut().unwrap().last_mut().unwrap() += Fr::ONE;
+    assert_ne!(address_from_instance(&other), Some(address));
+    assert!(verify_keccak(&proof, &other, &srs, &vk, CONFIG).is_err());
+
+    // A proof hashing another key, with the address forced into its instance
+    let other_wallet = OTHER_PRIVATE_KEY.parse::<LocalWallet>().unwrap();
+    let forged = builder.inputs(vec![public_key(&other_wallet)]).build().unwrap();
+    let instance_refs = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let forged_proof =
+        KzgShplonk::<Bn256>::prove_with_transcript::<KeccakTranscriptWrite<_>>(&srs, &pk, forged, &instance_refs)
+            .unwrap();
+    assert!(verify_keccak(&forged_proof, &instances, &srs, &vk, CONFIG).is_err());
+
+    // The EVM verifier takes the digest cells as big-endian words, then the proof
+    let calldata = evm_calldata(&instances, &proof);
+    assert_eq!(calldata.len(), 32 * output.len() + proof.len());
+    let (hi, lo) = (&calldata[..32], &calldata[32..64]);
+    assert!(hi[..16].iter().chain(&lo[..16]).all(|b| *b == 0));
+    assert_eq!([&hi[28..], &lo[16..]].concat(), address);
+    assert_eq!(&calldata[32 * output.len()..], &proof[..]);
+}